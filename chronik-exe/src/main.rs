@@ -1,22 +1,43 @@
-use std::{io::Read, net::SocketAddr, path::PathBuf, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    io::Read,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use bitcoinsuite_bitcoind::rpc_client::{BitcoindRpcClient, BitcoindRpcClientConf};
 use bitcoinsuite_bitcoind_nng::{PubInterface, RpcInterface};
-use bitcoinsuite_core::Network;
+use bitcoinsuite_core::{Network, Script};
 use bitcoinsuite_ecc_secp256k1::EccSecp256k1;
 use bitcoinsuite_error::{ErrorMeta, Result, WrapErr};
-use chronik_http::ChronikServer;
-use chronik_indexer::{run_transient_data_catchup, SlpIndexer};
-use chronik_rocksdb::{Db, IndexDb, IndexMemData, ScriptTxsConf, TransientData};
+use chronik_http::{
+    ChronikGrpcServer, ChronikServer, PayloadLengths, ScriptTypes, DEFAULT_MAX_TOKEN_LISTINGS,
+    DEFAULT_MAX_VALIDATE_UTXOS, DEFAULT_REQUEST_TIMEOUT,
+};
+use chronik_indexer::{
+    run_transient_data_catchup, SlpIndexer, DEFAULT_MAX_REORG_DEPTH, DEFAULT_VERIFY_SAMPLE_INTERVAL,
+};
+use chronik_rocksdb::{
+    script_payloads, BlockHeight, Db, DbCompactor, DbConf, IndexDb, IndexMemData, LokadTxsConf,
+    PayloadPrefix, ScriptFilter, ScriptTxsConf, SlpConf, TransientData,
+    DEFAULT_LOKAD_TXS_PAGE_SIZE,
+};
 use serde::Deserialize;
 use thiserror::Error;
-use tokio::sync::RwLock;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::RwLock,
+};
 
 const SCRIPT_TXS_PAGE_SIZE: usize = 1000;
 
 #[derive(Deserialize, Debug, Clone)]
 struct ChronikConf {
     host: SocketAddr,
+    /// Address of the gRPC server; it's only started if set
+    grpc_host: Option<SocketAddr>,
     nng_pub_url: String,
     nng_rpc_url: String,
     bitcoind_rpc: BitcoindRpcClientConf,
@@ -24,12 +45,101 @@ struct ChronikConf {
     transient_data_path: PathBuf,
     cache_script_history: usize,
     network: Network,
+    /// Hex-encoded LOKAD IDs of OP_RETURN protocols to index, e.g. "6d02" for memo posts.
+    #[serde(default)]
+    lokad_ids: Vec<String>,
+    #[serde(default)]
+    stall_watchdog: StallWatchdogConf,
+    /// Enable bloom filters for the script_txs and utxos CFs
+    #[serde(default)]
+    payload_prefix_bloom: bool,
+    /// Also write every batch to a second DB here, a warm standby; see `DbConf::replica_path`
+    replica_db_path: Option<PathBuf>,
+    /// Hex-encoded output scripts; if set, only these get a script history and UTXOs indexed
+    watched_scripts: Option<Vec<String>>,
+    /// Treat SLP txs of unknown token types as non-SLP instead of indexing them
+    #[serde(default)]
+    ignore_unknown_slp_token_types: bool,
+    /// Don't index which inputs spend confirmed outputs, saving disk space and write work
+    #[serde(default)]
+    disable_spends_index: bool,
+    /// Max number of blocks disconnected in a row before indexing aborts
+    max_reorg_depth: Option<usize>,
+    /// With `--verify`, every how many blocks to recompute a block's stats from the node
+    verify_sample_interval: Option<BlockHeight>,
+    /// How long an HTTP request may take until its response starts
+    request_timeout_secs: Option<u64>,
+    /// Serve the `/debug/...` endpoints exposing internal index data, `/admin/growth`,
+    /// `/export/utxos` and `/dormant-utxos`
+    #[serde(default)]
+    enable_debug_endpoints: bool,
+    /// Max number of outpoints a single `/validate-utxos` request may query
+    max_validate_utxos: Option<usize>,
+    /// Max number of tokens a single `/tokens` or `/tokens/recent` page may list
+    max_token_listings: Option<usize>,
+    /// If set, WebSocket messages are sent in batches collected over this many milliseconds
+    ws_batch_interval_ms: Option<u64>,
+    /// Serve a minimal bitcoind-style JSON-RPC interface on `POST /rpc`
+    #[serde(default)]
+    enable_rpc: bool,
+    /// Max number of heavy queries (exports, block ranges, scans) served at once
+    max_heavy_queries: Option<usize>,
+    /// Leave the (constant) `network` field of `Tx` and `Utxo` unset in HTTP responses
+    #[serde(default)]
+    strip_network: bool,
+    /// The node's blocks directory; if set, raw txs are read from the block files directly
+    blocks_dir: Option<PathBuf>,
+    /// Script types accepted in script endpoints, added to (or replacing) the default ones
+    #[serde(default)]
+    script_types: HashMap<String, Vec<PayloadLengthConf>>,
+}
+
+/// Payload length accepted for a script type and the payload prefix it maps to. Without
+/// `length`, payloads of any length are accepted, and it must be the script type's only entry.
+#[derive(Deserialize, Debug, Clone)]
+struct PayloadLengthConf {
+    length: Option<usize>,
+    payload_prefix: PayloadPrefix,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+struct StallWatchdogConf {
+    /// How often to compare the index tip to the node's block count, must be at least 1
+    check_interval_secs: u64,
+    /// How many blocks the index may lag behind the node before it's considered lagging
+    max_block_lag: i32,
+    /// How long the index may be lagging before it's reported as stalled
+    max_lag_secs: u64,
+}
+
+impl Default for StallWatchdogConf {
+    fn default() -> Self {
+        StallWatchdogConf {
+            check_interval_secs: 60,
+            max_block_lag: 2,
+            max_lag_secs: 600,
+        }
+    }
+}
+
+impl StallWatchdogConf {
+    fn validate(&self) -> Result<()> {
+        // A zero interval would query the node's block count in a busy loop
+        if self.check_interval_secs == 0 {
+            return Err(InvalidStallCheckInterval.into());
+        }
+        Ok(())
+    }
 }
 
 #[derive(Error, ErrorMeta, Debug)]
 pub enum ChronikExeError {
     #[critical()]
-    #[error("No configuration file provided. Specify like this: cargo run -- <config path>")]
+    #[error(
+        "No configuration file provided. Specify like this: cargo run -- <config path> [--verify] \
+         [--compact-on-start]"
+    )]
     NoConfigFile,
 
     #[critical()]
@@ -43,6 +153,40 @@ pub enum ChronikExeError {
     #[critical()]
     #[error("Invalid configuration file {0}")]
     InvalidConfigFail(String),
+
+    #[critical()]
+    #[error("Invalid LOKAD ID in configuration: {0}")]
+    InvalidLokadId(String),
+
+    #[critical()]
+    #[error("Invalid watched script in configuration: {0}")]
+    InvalidWatchedScript(String),
+
+    #[critical()]
+    #[error(
+        "Invalid script type {0} in configuration, it needs either a single entry without length \
+         or entries that all have a length"
+    )]
+    InvalidScriptTypeConf(String),
+
+    #[critical()]
+    #[error(
+        "Payload prefix {payload_prefix:?} is used by both script types {first} and {second} in \
+         configuration (including the defaults), each prefix may only be used once"
+    )]
+    DuplicatePayloadPrefixConf {
+        payload_prefix: PayloadPrefix,
+        first: String,
+        second: String,
+    },
+
+    #[critical()]
+    #[error("Invalid configuration: stall_watchdog.check_interval_secs must be at least 1")]
+    InvalidStallCheckInterval,
+
+    #[critical()]
+    #[error("Index verification found {0} discrepancies")]
+    IndexVerificationFailed(usize),
 }
 
 use self::ChronikExeError::*;
@@ -51,21 +195,55 @@ use self::ChronikExeError::*;
 async fn main() -> Result<()> {
     bitcoinsuite_error::install()?;
 
-    let conf_path = std::env::args().nth(1).ok_or(NoConfigFile)?;
-    let mut file =
-        std::fs::File::open(&conf_path).wrap_err_with(|| OpenConfigFail(conf_path.clone()))?;
-    let mut conf_contents = String::new();
-    file.read_to_string(&mut conf_contents)
-        .wrap_err_with(|| ReadConfigFail(conf_path.clone()))?;
-    let conf: ChronikConf =
-        toml::from_str(&conf_contents).wrap_err_with(|| InvalidConfigFail(conf_path.clone()))?;
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let verify = args.iter().any(|arg| arg == "--verify");
+    let compact_on_start = args.iter().any(|arg| arg == "--compact-on-start");
+    let conf_path = args
+        .iter()
+        .find(|arg| !arg.starts_with("--"))
+        .cloned()
+        .ok_or(NoConfigFile)?;
+    let conf = read_conf(&conf_path)?;
 
     let client = BitcoindRpcClient::new(conf.bitcoind_rpc);
     let pub_interface = PubInterface::open(&conf.nng_pub_url)?;
     let rpc_interface = RpcInterface::open(&conf.nng_rpc_url)?;
 
-    let db = Db::open(&conf.db_path)?;
+    let db = Db::open_with_conf(
+        &conf.db_path,
+        &DbConf {
+            payload_prefix_bloom: conf.payload_prefix_bloom,
+            replica_path: conf.replica_db_path.clone(),
+        },
+    )?;
     let transient_data = TransientData::open(&conf.transient_data_path)?;
+    if compact_on_start {
+        spawn_compaction(db.compactor());
+    }
+
+    let lokad_ids = conf
+        .lokad_ids
+        .iter()
+        .map(|lokad_id| hex::decode(lokad_id).wrap_err_with(|| InvalidLokadId(lokad_id.clone())))
+        .collect::<Result<_>>()?;
+
+    let script_filter = match &conf.watched_scripts {
+        Some(watched_scripts) => {
+            let mut payloads = HashSet::new();
+            for script_hex in watched_scripts {
+                let script = hex::decode(script_hex)
+                    .wrap_err_with(|| InvalidWatchedScript(script_hex.clone()))?;
+                let script = Script::from_slice(&script);
+                payloads.extend(
+                    script_payloads(&script)
+                        .into_iter()
+                        .map(|payload_state| payload_state.payload),
+                );
+            }
+            ScriptFilter::Watched(payloads)
+        }
+        None => ScriptFilter::All,
+    };
 
     let db = IndexDb::new(
         db,
@@ -73,7 +251,16 @@ async fn main() -> Result<()> {
         ScriptTxsConf {
             page_size: SCRIPT_TXS_PAGE_SIZE,
         },
-    );
+        LokadTxsConf {
+            lokad_ids,
+            page_size: DEFAULT_LOKAD_TXS_PAGE_SIZE,
+        },
+    )
+    .with_script_filter(script_filter)
+    .with_slp_conf(SlpConf {
+        ignore_unknown_token_types: conf.ignore_unknown_slp_token_types,
+    })
+    .with_spends_index(!conf.disable_spends_index);
     let data = IndexMemData::new(conf.cache_script_history);
     let mut slp_indexer = SlpIndexer::new(
         db,
@@ -84,18 +271,66 @@ async fn main() -> Result<()> {
         conf.network,
         Arc::new(EccSecp256k1::default()),
     )?;
+    slp_indexer.set_max_reorg_depth(conf.max_reorg_depth.unwrap_or(DEFAULT_MAX_REORG_DEPTH));
+    if let Some(blocks_dir) = conf.blocks_dir {
+        slp_indexer.set_blocks_dir(blocks_dir);
+    }
+
+    if verify {
+        let sample_interval = conf
+            .verify_sample_interval
+            .unwrap_or(DEFAULT_VERIFY_SAMPLE_INTERVAL);
+        let report = slp_indexer.verifier().verify_index(sample_interval)?;
+        println!(
+            "Verified {} blocks ({} sampled), UTXO set value: {} sats",
+            report.num_blocks, report.num_sampled_blocks, report.utxo_total,
+        );
+        for discrepancy in &report.discrepancies {
+            println!("Discrepancy: {}", discrepancy);
+        }
+        if !report.discrepancies.is_empty() {
+            return Err(IndexVerificationFailed(report.discrepancies.len()).into());
+        }
+    }
 
     while !slp_indexer.catchup_step().await? {}
     slp_indexer.leave_catchup()?;
+    slp_indexer.repair_block_stats()?;
 
     let slp_indexer = Arc::new(RwLock::new(slp_indexer));
 
+    let script_types = Arc::new(script_types_from_conf(&conf.script_types)?);
     let server = ChronikServer {
         addr: conf.host,
         slp_indexer: Arc::clone(&slp_indexer),
+        request_timeout: conf
+            .request_timeout_secs
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_REQUEST_TIMEOUT),
+        script_types: Arc::clone(&script_types),
+        enable_debug_endpoints: conf.enable_debug_endpoints,
+        max_validate_utxos: conf
+            .max_validate_utxos
+            .unwrap_or(DEFAULT_MAX_VALIDATE_UTXOS),
+        max_token_listings: conf
+            .max_token_listings
+            .unwrap_or(DEFAULT_MAX_TOKEN_LISTINGS),
+        ws_batch_interval: conf.ws_batch_interval_ms.map(Duration::from_millis),
+        enable_rpc: conf.enable_rpc,
+        max_heavy_queries: conf.max_heavy_queries,
+        strip_network: conf.strip_network,
     };
     tokio::spawn(server.run());
 
+    if let Some(grpc_host) = conf.grpc_host {
+        let grpc_server = ChronikGrpcServer {
+            addr: grpc_host,
+            slp_indexer: Arc::clone(&slp_indexer),
+            script_types,
+        };
+        tokio::spawn(grpc_server.run());
+    }
+
     tokio::spawn({
         let slp_indexer = Arc::clone(&slp_indexer);
         async move {
@@ -103,6 +338,13 @@ async fn main() -> Result<()> {
         }
     });
 
+    tokio::spawn(run_stall_watchdog(
+        Arc::clone(&slp_indexer),
+        conf.stall_watchdog.clone(),
+    ));
+
+    tokio::spawn(run_conf_reload(Arc::clone(&slp_indexer), conf_path));
+
     loop {
         let msg = tokio::task::spawn_blocking({
             let pub_interface = pub_interface.clone();
@@ -112,3 +354,251 @@ async fn main() -> Result<()> {
         slp_indexer.write().await.process_msg(msg)?;
     }
 }
+
+/// The default script types, with the configured ones added or replaced.
+fn script_types_from_conf(
+    script_types_conf: &HashMap<String, Vec<PayloadLengthConf>>,
+) -> Result<ScriptTypes> {
+    let mut script_types = ScriptTypes::default();
+    for (script_type, lengths_conf) in script_types_conf {
+        let invalid_conf = || InvalidScriptTypeConf(script_type.clone());
+        let lengths = match lengths_conf.as_slice() {
+            [] => return Err(invalid_conf().into()),
+            [PayloadLengthConf {
+                length: None,
+                payload_prefix,
+            }] => PayloadLengths::Any(*payload_prefix),
+            _ => PayloadLengths::Exact(
+                lengths_conf
+                    .iter()
+                    .map(|length_conf| Some((length_conf.length?, length_conf.payload_prefix)))
+                    .collect::<Option<_>>()
+                    .ok_or_else(invalid_conf)?,
+            ),
+        };
+        script_types = script_types.with_script_type(script_type.clone(), lengths);
+    }
+    if let Some((payload_prefix, first, second)) = script_types.duplicate_payload_prefix() {
+        return Err(DuplicatePayloadPrefixConf {
+            payload_prefix,
+            first: first.to_string(),
+            second: second.to_string(),
+        }
+        .into());
+    }
+    Ok(script_types)
+}
+
+fn read_conf(conf_path: &str) -> Result<ChronikConf> {
+    let mut file =
+        std::fs::File::open(conf_path).wrap_err_with(|| OpenConfigFail(conf_path.to_string()))?;
+    let mut conf_contents = String::new();
+    file.read_to_string(&mut conf_contents)
+        .wrap_err_with(|| ReadConfigFail(conf_path.to_string()))?;
+    let conf: ChronikConf = toml::from_str(&conf_contents)
+        .wrap_err_with(|| InvalidConfigFail(conf_path.to_string()))?;
+    conf.stall_watchdog.validate()?;
+    Ok(conf)
+}
+
+/// Compacts the DB on a separate thread, so indexing and serving requests continue meanwhile.
+fn spawn_compaction(compactor: DbCompactor) {
+    std::thread::spawn(move || {
+        println!("Compacting DB in the background");
+        let started_at = Instant::now();
+        let result = compactor.compact_all(|cf_name| {
+            println!(
+                "Compacted CF {} after {:.1}s",
+                cf_name,
+                started_at.elapsed().as_secs_f64(),
+            );
+        });
+        match result {
+            Ok(()) => println!(
+                "Finished compacting DB in {:.1}s",
+                started_at.elapsed().as_secs_f64(),
+            ),
+            Err(report) => println!("Compacting DB failed: {:?}", report),
+        }
+    });
+}
+
+/// Re-reads the configuration file on SIGHUP and applies the settings that can change at
+/// runtime, currently only `cache_script_history`; everything else requires a restart.
+async fn run_conf_reload(slp_indexer: Arc<RwLock<SlpIndexer>>, conf_path: String) {
+    let mut sighup = match signal(SignalKind::hangup()) {
+        Ok(sighup) => sighup,
+        Err(err) => {
+            println!(
+                "Failed to listen for SIGHUP, config reload disabled: {}",
+                err
+            );
+            return;
+        }
+    };
+    while sighup.recv().await.is_some() {
+        let conf = match read_conf(&conf_path) {
+            Ok(conf) => conf,
+            Err(report) => {
+                println!("Config reload failed, keeping current config: {:?}", report);
+                continue;
+            }
+        };
+        let mut slp_indexer = slp_indexer.write().await;
+        slp_indexer.resize_script_history_cache(conf.cache_script_history);
+        println!(
+            "Reloaded config, cache_script_history = {}",
+            conf.cache_script_history,
+        );
+    }
+}
+
+/// Periodically compares the index tip to bitcoind's block count and flags the indexer as
+/// stalled if it lags behind for too long, e.g. because NNG messages were silently dropped.
+async fn run_stall_watchdog(slp_indexer: Arc<RwLock<SlpIndexer>>, conf: StallWatchdogConf) {
+    let mut lagging_since: Option<Instant> = None;
+    loop {
+        tokio::time::sleep(Duration::from_secs(conf.check_interval_secs)).await;
+        let (index_height, bitcoind_rpc, node_status) = {
+            let slp_indexer = slp_indexer.read().await;
+            let index_height = match slp_indexer.blocks().height() {
+                Ok(index_height) => index_height,
+                Err(report) => {
+                    println!("Stall watchdog failed to read index height: {:?}", report);
+                    continue;
+                }
+            };
+            (
+                index_height,
+                slp_indexer.bitcoind_rpc().clone(),
+                Arc::clone(slp_indexer.node_status()),
+            )
+        };
+        // Also probes bitcoind's reachability regularly, even if nothing else calls it
+        let block_count = node_status.call(bitcoind_rpc.cmd_json("getblockcount", &[]));
+        let node_height = match block_count.await {
+            Ok(block_count) => match block_count.as_i32() {
+                Some(node_height) => node_height,
+                None => {
+                    println!("Stall watchdog got invalid getblockcount: {}", block_count);
+                    continue;
+                }
+            },
+            Err(report) => {
+                println!("Stall watchdog failed to query node: {:?}", report);
+                continue;
+            }
+        };
+        let is_stalled = if node_height - index_height > conf.max_block_lag {
+            let lagging_since = *lagging_since.get_or_insert_with(Instant::now);
+            lagging_since.elapsed() >= Duration::from_secs(conf.max_lag_secs)
+        } else {
+            lagging_since = None;
+            false
+        };
+        if is_stalled {
+            println!(
+                "ERROR: Indexing stalled; index height is {}, node height is {}",
+                index_height, node_height,
+            );
+        }
+        if slp_indexer.read().await.is_stalled() != is_stalled {
+            slp_indexer.write().await.set_stalled(is_stalled);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bitcoinsuite_error::Result;
+    use chronik_http::PayloadLengths;
+    use chronik_rocksdb::PayloadPrefix;
+
+    use crate::{script_types_from_conf, ChronikExeError, PayloadLengthConf, StallWatchdogConf};
+
+    #[test]
+    fn test_script_types_from_conf() -> Result<()> {
+        let script_types_conf: HashMap<String, Vec<PayloadLengthConf>> = toml::from_str(
+            r#"
+            [[p2pk]]
+            length = 33
+            payload_prefix = "P2PK"
+            [[p2pk]]
+            length = 64
+            payload_prefix = "P2PKLegacy"
+
+            [[p2sh]]
+            payload_prefix = "P2SH"
+            "#,
+        )?;
+        let script_types = script_types_from_conf(&script_types_conf)?;
+        assert_eq!(
+            script_types.lengths("p2pk"),
+            Some(&PayloadLengths::Exact(vec![
+                (33, PayloadPrefix::P2PK),
+                (64, PayloadPrefix::P2PKLegacy),
+            ])),
+        );
+        assert_eq!(
+            script_types.lengths("p2sh"),
+            Some(&PayloadLengths::Any(PayloadPrefix::P2SH)),
+        );
+        assert_eq!(script_types.script_type(PayloadPrefix::P2SH), Some("p2sh"));
+        // Script types that aren't configured keep their defaults
+        assert_eq!(
+            script_types.lengths("p2pkh"),
+            Some(&PayloadLengths::Exact(vec![(20, PayloadPrefix::P2PKH)])),
+        );
+
+        // A prefix used by two script types would make the script type of a payload ambiguous
+        let script_types_conf: HashMap<String, Vec<PayloadLengthConf>> = toml::from_str(
+            r#"
+            [[pkh]]
+            length = 20
+            payload_prefix = "P2PKH"
+            "#,
+        )?;
+        let err = script_types_from_conf(&script_types_conf)
+            .unwrap_err()
+            .downcast::<ChronikExeError>()?;
+        assert!(matches!(
+            err,
+            ChronikExeError::DuplicatePayloadPrefixConf {
+                payload_prefix: PayloadPrefix::P2PKH,
+                ref first,
+                ref second,
+            } if first == "p2pkh" && second == "pkh"
+        ));
+
+        let script_types_conf: HashMap<String, Vec<PayloadLengthConf>> = toml::from_str(
+            r#"
+            [[mixed]]
+            length = 20
+            payload_prefix = "P2PKH"
+            [[mixed]]
+            payload_prefix = "Other"
+            "#,
+        )?;
+        let err = script_types_from_conf(&script_types_conf)
+            .unwrap_err()
+            .downcast::<ChronikExeError>()?;
+        assert!(
+            matches!(err, ChronikExeError::InvalidScriptTypeConf(script_type) if script_type == "mixed")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_stall_watchdog_conf() -> Result<()> {
+        let conf: StallWatchdogConf = toml::from_str("max_block_lag = 5")?;
+        conf.validate()?;
+        assert_eq!(conf.check_interval_secs, 60);
+
+        let conf: StallWatchdogConf = toml::from_str("check_interval_secs = 0")?;
+        let err = conf.validate().unwrap_err().downcast::<ChronikExeError>()?;
+        assert!(matches!(err, ChronikExeError::InvalidStallCheckInterval));
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    prost_build::compile_protos(&["proto/chronik.proto"], &["proto"])?;
+    tonic_build::configure().compile(&["proto/chronik.proto"], &["proto"])?;
     Ok(())
 }
@@ -1,12 +1,22 @@
-use bitcoinsuite_core::{ecc::PUBKEY_LENGTH, Hashed, Network, Sha256, ShaRmd160};
-use bitcoinsuite_slp::{RichTx, SlpToken, SlpTokenType, SlpTxData, SlpTxType};
+use bitcoinsuite_core::Network;
+use bitcoinsuite_slp::{
+    RichTx, SlpError, SlpGenesisInfo, SlpOutput, SlpToken, SlpTokenType, SlpTxData, SlpTxType,
+    SlpTxTypeVariant,
+};
 
 use bitcoinsuite_error::{ErrorMeta, Report};
 
-use chronik_rocksdb::{Block, BlockStats, PayloadPrefix};
+use chronik_indexer::{
+    broadcast::{BroadcastError, SlpBurnPolicy},
+    TokenListing,
+};
+use chronik_rocksdb::{Block, BlockHeight, BlockStats, PayloadPrefix, TokenStats};
 use thiserror::Error;
 
-use crate::proto;
+use crate::{
+    proto,
+    validation::{PayloadLengths, ScriptTypes},
+};
 
 #[derive(Debug, Error, ErrorMeta)]
 pub enum ChronikConvertError {
@@ -94,19 +104,38 @@ pub fn rich_tx_to_proto(rich_tx: RichTx) -> proto::Tx {
         size: rich_tx.tx.raw().len() as u32,
         is_coinbase: rich_tx.tx.inputs()[0].prev_out.is_coinbase(),
         network: network_to_proto(rich_tx.network) as i32,
+        raw: vec![],
+        confirmations: 0,
     }
 }
 
+/// Number of blocks confirming a tx in the block at `block_height` (including that block) when
+/// the tip is at `tip_height`; 0 for mempool txs (i.e. no block).
+pub fn num_confirmations(block_height: Option<BlockHeight>, tip_height: BlockHeight) -> u32 {
+    match block_height {
+        Some(block_height) => (tip_height - block_height + 1).max(0) as u32,
+        None => 0,
+    }
+}
+
+/// Clears the scriptSig of all inputs, for clients that don't need them, as they can make up
+/// most of the size of a tx.
+pub fn strip_input_scripts(tx: &mut proto::Tx) {
+    for input in &mut tx.inputs {
+        input.input_script.clear();
+    }
+}
+
+/// Leaves `network` unset (which decodes as `BCH`), for servers configured with `strip_network`.
+pub fn strip_network(tx: &mut proto::Tx) {
+    tx.network = 0;
+}
+
 #[allow(clippy::boxed_local)]
 pub fn slp_tx_data_to_proto(slp_tx_data: Box<SlpTxData>) -> proto::SlpTxData {
     proto::SlpTxData {
         slp_meta: Some(proto::SlpMeta {
-            token_type: match slp_tx_data.slp_token_type {
-                SlpTokenType::Fungible => proto::SlpTokenType::Fungible as i32,
-                SlpTokenType::Nft1Group => proto::SlpTokenType::Nft1Group as i32,
-                SlpTokenType::Nft1Child => proto::SlpTokenType::Nft1Child as i32,
-                SlpTokenType::Unknown => proto::SlpTokenType::UnknownTokenType as i32,
-            },
+            token_type: token_type_to_proto(slp_tx_data.slp_token_type),
             tx_type: match &slp_tx_data.slp_tx_type {
                 SlpTxType::Genesis(_) => proto::SlpTxType::Genesis as i32,
                 SlpTxType::Send => proto::SlpTxType::Send as i32,
@@ -121,21 +150,69 @@ pub fn slp_tx_data_to_proto(slp_tx_data: Box<SlpTxData>) -> proto::SlpTxData {
                 .unwrap_or_default(),
         }),
         genesis_info: match slp_tx_data.slp_tx_type {
-            SlpTxType::Genesis(genesis_info) => Some(proto::SlpGenesisInfo {
-                token_ticker: genesis_info.token_ticker.to_vec(),
-                token_name: genesis_info.token_name.to_vec(),
-                token_document_url: genesis_info.token_document_url.to_vec(),
-                token_document_hash: genesis_info
-                    .token_document_hash
-                    .map(|arr| arr.to_vec())
-                    .unwrap_or_default(),
-                decimals: genesis_info.decimals,
-            }),
+            SlpTxType::Genesis(genesis_info) => Some(genesis_info_to_proto(&genesis_info)),
             _ => None,
         },
     }
 }
 
+pub fn token_type_to_proto(token_type: SlpTokenType) -> i32 {
+    match token_type {
+        SlpTokenType::Fungible => proto::SlpTokenType::Fungible as i32,
+        SlpTokenType::Nft1Group => proto::SlpTokenType::Nft1Group as i32,
+        SlpTokenType::Nft1Child => proto::SlpTokenType::Nft1Child as i32,
+        SlpTokenType::Unknown => proto::SlpTokenType::UnknownTokenType as i32,
+    }
+}
+
+pub fn genesis_info_to_proto(genesis_info: &SlpGenesisInfo) -> proto::SlpGenesisInfo {
+    proto::SlpGenesisInfo {
+        token_ticker: genesis_info.token_ticker.to_vec(),
+        token_name: genesis_info.token_name.to_vec(),
+        token_document_url: genesis_info.token_document_url.to_vec(),
+        token_document_hash: genesis_info
+            .token_document_hash
+            .map(|arr| arr.to_vec())
+            .unwrap_or_default(),
+        decimals: genesis_info.decimals,
+    }
+}
+
+pub fn token_listing_to_proto(token: &TokenListing) -> proto::TokenListing {
+    proto::TokenListing {
+        token_id: token.token_id.as_slice_be().to_vec(),
+        genesis_info: Some(genesis_info_to_proto(&token.genesis_info)),
+        genesis_height: token.genesis_height,
+    }
+}
+
+pub fn slp_output_meta_to_proto(slp_output: &SlpOutput) -> proto::SlpMeta {
+    proto::SlpMeta {
+        token_type: token_type_to_proto(slp_output.token_type),
+        tx_type: match &slp_output.tx_type {
+            SlpTxTypeVariant::Genesis => proto::SlpTxType::Genesis as i32,
+            SlpTxTypeVariant::Send => proto::SlpTxType::Send as i32,
+            SlpTxTypeVariant::Mint => proto::SlpTxType::Mint as i32,
+            SlpTxTypeVariant::Burn => proto::SlpTxType::Burn as i32,
+            SlpTxTypeVariant::Unknown => proto::SlpTxType::UnknownTxType as i32,
+        },
+        token_id: slp_output.token_id.as_slice_be().to_vec(),
+        group_token_id: slp_output
+            .group_token_id
+            .as_ref()
+            .map(|token_id| token_id.as_slice_be().to_vec())
+            .unwrap_or_default(),
+    }
+}
+
+pub fn token_stats_to_proto(token_stats: &TokenStats) -> proto::TokenStats {
+    proto::TokenStats {
+        total_minted: token_stats.total_minted.to_string(),
+        total_burned: token_stats.total_burned.to_string(),
+        supply_overflowed: token_stats.supply_overflowed,
+    }
+}
+
 pub fn network_to_proto(network: Network) -> proto::Network {
     match network {
         Network::BCH => proto::Network::Bch,
@@ -155,26 +232,85 @@ pub fn slp_token_to_proto(slp_token: SlpToken) -> Option<proto::SlpToken> {
     })
 }
 
+pub fn broadcast_error_to_slp_rejection(err: &BroadcastError) -> Option<proto::SlpRejection> {
+    match err {
+        BroadcastError::InvalidSlpTx(slp_error, culprits) => Some(proto::SlpRejection {
+            slp_error: slp_error_name(slp_error).to_string(),
+            input_idxs: culprits.input_idxs.clone(),
+            output_idxs: culprits.output_idxs.clone(),
+        }),
+        BroadcastError::InvalidSlpBurns(slp_burns) => Some(proto::SlpRejection {
+            slp_error: String::new(),
+            input_idxs: slp_burns
+                .0
+                .iter()
+                .enumerate()
+                .filter(|(_, burn)| matches!(burn, Some(burn) if burn.token != SlpToken::EMPTY))
+                .map(|(input_idx, _)| input_idx as u32)
+                .collect(),
+            output_idxs: vec![],
+        }),
+        BroadcastError::BitcoindRejectedTx(_) => None,
+    }
+}
+
+/// Variant name of the error, e.g. "OutputSumExceedInputSum".
+fn slp_error_name(slp_error: &SlpError) -> &'static str {
+    match slp_error {
+        SlpError::BytesError { .. } => "BytesError",
+        SlpError::NoOpcodes => "NoOpcodes",
+        SlpError::MissingOpReturn { .. } => "MissingOpReturn",
+        SlpError::InvalidLokadId { .. } => "InvalidLokadId",
+        SlpError::NonPushOp { .. } => "NonPushOp",
+        SlpError::DisallowedPush { .. } => "DisallowedPush",
+        SlpError::InvalidFieldSize { .. } => "InvalidFieldSize",
+        SlpError::InvalidDecimals { .. } => "InvalidDecimals",
+        SlpError::InvalidMintBatonIdx { .. } => "InvalidMintBatonIdx",
+        SlpError::Nft1ChildCannotHaveMintBaton => "Nft1ChildCannotHaveMintBaton",
+        SlpError::Nft1ChildInvalidInitialQuantity { .. } => "Nft1ChildInvalidInitialQuantity",
+        SlpError::Nft1ChildInvalidDecimals { .. } => "Nft1ChildInvalidDecimals",
+        SlpError::TooFewPushes { .. } => "TooFewPushes",
+        SlpError::TooFewPushesExact { .. } => "TooFewPushesExact",
+        SlpError::SuperfluousPushes { .. } => "SuperfluousPushes",
+        SlpError::UnknownTokenType { .. } => "UnknownTokenType",
+        SlpError::InvalidTxType { .. } => "InvalidTxType",
+        SlpError::OutputSumExceedInputSum { .. } => "OutputSumExceedInputSum",
+        SlpError::HasNoNft1Group => "HasNoNft1Group",
+    }
+}
+
+pub fn parse_slp_burn_policy(slp_burn_policy: i32) -> Result<SlpBurnPolicy, Report> {
+    match proto::SlpBurnPolicy::from_i32(slp_burn_policy) {
+        Some(proto::SlpBurnPolicy::RejectAnyBurn) => Ok(SlpBurnPolicy::RejectAnyBurn),
+        Some(proto::SlpBurnPolicy::AllowExplicitBurn) => Ok(SlpBurnPolicy::AllowExplicitBurn),
+        Some(proto::SlpBurnPolicy::AllowAllBurns) => Ok(SlpBurnPolicy::AllowAll),
+        None => Err(InvalidField {
+            name: "slp_burn_policy",
+            value: slp_burn_policy.to_string(),
+        }
+        .into()),
+    }
+}
+
 pub fn parse_payload_prefix(
+    script_types: &ScriptTypes,
     script_type: String,
     payload_len: usize,
 ) -> Result<PayloadPrefix, Report> {
-    fn pl_err(expected: Vec<usize>, actual: usize) -> Report {
-        InvalidScriptPayloadLength { expected, actual }.into()
-    }
-    match script_type.as_str() {
-        "other" => Ok(PayloadPrefix::Other),
-        "p2pk" if payload_len == PUBKEY_LENGTH => Ok(PayloadPrefix::P2PK),
-        "p2pk" if payload_len == 65 => Ok(PayloadPrefix::P2PKLegacy),
-        "p2pk" => Err(pl_err(vec![PUBKEY_LENGTH, 65], payload_len)),
-        "p2pkh" if payload_len == ShaRmd160::size() => Ok(PayloadPrefix::P2PKH),
-        "p2sh" if payload_len == ShaRmd160::size() => Ok(PayloadPrefix::P2SH),
-        "p2pkh" | "p2sh" => Err(pl_err(vec![ShaRmd160::size()], payload_len)),
-        "p2tr-commitment" if payload_len == PUBKEY_LENGTH => Ok(PayloadPrefix::P2TRCommitment),
-        "p2tr-commitment" => Err(pl_err(vec![PUBKEY_LENGTH], payload_len)),
-        "p2tr-state" if payload_len == Sha256::size() => Ok(PayloadPrefix::P2TRState),
-        "p2tr-state" => Err(pl_err(vec![Sha256::size()], payload_len)),
-        _ => Err(InvalidField {
+    match script_types.lengths(&script_type) {
+        Some(PayloadLengths::Any(payload_prefix)) => Ok(*payload_prefix),
+        Some(PayloadLengths::Exact(lengths)) => lengths
+            .iter()
+            .find(|&&(len, _)| len == payload_len)
+            .map(|&(_, payload_prefix)| payload_prefix)
+            .ok_or_else(|| {
+                InvalidScriptPayloadLength {
+                    expected: lengths.iter().map(|&(len, _)| len).collect(),
+                    actual: payload_len,
+                }
+                .into()
+            }),
+        None => Err(InvalidField {
             name: "script_type",
             value: script_type,
         }
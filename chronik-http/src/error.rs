@@ -3,9 +3,12 @@ use axum::{
     response::{IntoResponse, Response},
 };
 use bitcoinsuite_error::{report_to_details, ErrorMeta, ErrorSeverity, Report};
+use prost::Message;
+
+use chronik_indexer::{broadcast::BroadcastError, NodeStatusError};
 
 use crate::{
-    convert::ChronikConvertError,
+    convert::{broadcast_error_to_slp_rejection, ChronikConvertError},
     proto,
     protobuf::{ChronikProtobufError, Protobuf},
     server::ChronikServerError,
@@ -45,6 +48,18 @@ impl From<ChronikServerError> for ReportError {
 }
 
 pub fn report_to_status_proto(report: &Report) -> (StatusCode, Protobuf<proto::Error>) {
+    // Not the client's fault, but unlike other critical errors worth telling them about
+    if let Some(err) = report.downcast_ref::<NodeStatusError>() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Protobuf(proto::Error {
+                error_code: "node-unavailable".to_string(),
+                msg: err.to_string(),
+                is_user_error: false,
+                slp_rejection: None,
+            }),
+        );
+    }
     let details = report_to_details(report, self::report_to_error_meta);
     match details.severity {
         ErrorSeverity::NotFound => (
@@ -53,6 +68,7 @@ pub fn report_to_status_proto(report: &Report) -> (StatusCode, Protobuf<proto::E
                 error_code: details.error_code.to_string(),
                 msg: details.msg,
                 is_user_error: true,
+                slp_rejection: None,
             }),
         ),
         ErrorSeverity::InvalidUserInput => (
@@ -61,16 +77,20 @@ pub fn report_to_status_proto(report: &Report) -> (StatusCode, Protobuf<proto::E
                 error_code: details.error_code.to_string(),
                 msg: details.msg,
                 is_user_error: true,
+                slp_rejection: report
+                    .downcast_ref::<BroadcastError>()
+                    .and_then(broadcast_error_to_slp_rejection),
             }),
         ),
         ErrorSeverity::InvalidClientInput => {
             println!("Invalid client input: {}", details.msg);
             (
-                StatusCode::BAD_REQUEST,
+                client_input_status_code(report),
                 Protobuf(proto::Error {
                     error_code: details.error_code.to_string(),
                     msg: details.msg,
                     is_user_error: false,
+                    slp_rejection: None,
                 }),
             )
         }
@@ -86,12 +106,45 @@ pub fn report_to_status_proto(report: &Report) -> (StatusCode, Protobuf<proto::E
                     error_code: "internal-server-error".into(),
                     msg: "Internal server error".to_string(),
                     is_user_error: false,
+                    slp_rejection: None,
                 }),
             )
         }
     }
 }
 
+/// Like `report_to_status_proto`, with the `proto::Error` attached as the status details.
+pub fn report_to_grpc_status(report: &Report) -> tonic::Status {
+    let (status_code, Protobuf(error_proto)) = report_to_status_proto(report);
+    let code = match status_code {
+        StatusCode::NOT_FOUND => tonic::Code::NotFound,
+        StatusCode::BAD_REQUEST
+        | StatusCode::UNSUPPORTED_MEDIA_TYPE
+        | StatusCode::NOT_ACCEPTABLE => tonic::Code::InvalidArgument,
+        StatusCode::SERVICE_UNAVAILABLE => tonic::Code::Unavailable,
+        _ => tonic::Code::Internal,
+    };
+    tonic::Status::with_details(
+        code,
+        error_proto.msg.clone(),
+        error_proto.encode_to_vec().into(),
+    )
+}
+
+/// Content negotiation failures get their dedicated status code, other bad client input is a
+/// plain 400.
+fn client_input_status_code(report: &Report) -> StatusCode {
+    match report.downcast_ref::<ChronikValidationError>() {
+        Some(
+            ChronikValidationError::NoContentTypeSet { .. }
+            | ChronikValidationError::BadContentType(_)
+            | ChronikValidationError::WrongContentType { .. },
+        ) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        Some(ChronikValidationError::NotAcceptable { .. }) => StatusCode::NOT_ACCEPTABLE,
+        None => StatusCode::BAD_REQUEST,
+    }
+}
+
 impl IntoResponse for ReportError {
     fn into_response(self) -> Response {
         report_to_status_proto(&self.0).into_response()
@@ -0,0 +1,102 @@
+use std::{net::SocketAddr, pin::Pin, sync::Arc};
+
+use bitcoinsuite_core::Sha256d;
+use bitcoinsuite_error::Report;
+use futures::Stream;
+use tokio::sync::broadcast;
+use tonic::{Request, Response, Status};
+
+use crate::{
+    error::report_to_grpc_status,
+    proto::{
+        self,
+        chronik_server::{Chronik, ChronikServer as ChronikGrpcService},
+    },
+    server::{ChronikServerError::*, SlpIndexerRef},
+    service::{script_msg_to_proto, script_payload, ChronikService},
+    validation::ScriptTypes,
+};
+
+/// Serves the `Chronik` gRPC service, alongside the HTTP server.
+#[derive(Clone)]
+pub struct ChronikGrpcServer {
+    pub addr: SocketAddr,
+    pub slp_indexer: SlpIndexerRef,
+    pub script_types: Arc<ScriptTypes>,
+}
+
+type SubscribeMsgStream =
+    Pin<Box<dyn Stream<Item = Result<proto::SubscribeMsg, Status>> + Send + 'static>>;
+
+impl ChronikGrpcServer {
+    pub async fn run(self) -> Result<(), Report> {
+        let service = ChronikService {
+            slp_indexer: Arc::clone(&self.slp_indexer),
+            script_types: Arc::clone(&self.script_types),
+        };
+        tonic::transport::Server::builder()
+            .add_service(ChronikGrpcService::new(service))
+            .serve(self.addr)
+            .await?;
+        Ok(())
+    }
+}
+
+#[tonic::async_trait]
+impl Chronik for ChronikService {
+    async fn get_tx(
+        &self,
+        request: Request<proto::GetTxRequest>,
+    ) -> Result<Response<proto::Tx>, Status> {
+        let request = request.into_inner();
+        let txid = Sha256d::from_slice(&request.txid).map_err(|err| {
+            report_to_grpc_status(
+                &InvalidField {
+                    name: "txid",
+                    value: err.to_string(),
+                }
+                .into(),
+            )
+        })?;
+        let tx = self
+            .tx(txid, request.include_raw)
+            .await
+            .map_err(|report| report_to_grpc_status(&report))?;
+        Ok(Response::new(tx))
+    }
+
+    async fn get_block(
+        &self,
+        request: Request<proto::GetBlockRequest>,
+    ) -> Result<Response<proto::Block>, Status> {
+        let block = self
+            .block(request.into_inner().hash_or_height)
+            .await
+            .map_err(|report| report_to_grpc_status(&report))?;
+        Ok(Response::new(block))
+    }
+
+    type SubscribeScriptStream = SubscribeMsgStream;
+
+    async fn subscribe_script(
+        &self,
+        request: Request<proto::SubscribeScriptRequest>,
+    ) -> Result<Response<Self::SubscribeScriptStream>, Status> {
+        let request = request.into_inner();
+        let script_payload =
+            script_payload(&self.script_types, request.script_type, request.payload)
+                .map_err(|report| report_to_grpc_status(&report))?;
+        // Dropping the receiver when the client disconnects unsubscribes it on the next message
+        let receiver = self.subscribe_to_script(&script_payload).await;
+        let stream = futures::stream::unfold(receiver, |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(script_msg) => return Some((Ok(script_msg_to_proto(script_msg)), receiver)),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
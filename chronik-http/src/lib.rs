@@ -1,12 +1,21 @@
 mod convert;
 mod error;
+mod grpc;
+mod merkle;
 mod protobuf;
+mod rpc;
 mod server;
+mod service;
 mod validation;
 
 pub mod proto {
     include!(concat!(env!("OUT_DIR"), "/chronik.rs"));
 }
 
+pub use grpc::ChronikGrpcServer;
 pub use protobuf::CONTENT_TYPE_PROTOBUF;
-pub use server::ChronikServer;
+pub use server::{
+    ChronikServer, DEFAULT_MAX_TOKEN_LISTINGS, DEFAULT_MAX_VALIDATE_UTXOS, DEFAULT_REQUEST_TIMEOUT,
+};
+pub use service::ChronikService;
+pub use validation::{PayloadLengths, ScriptTypes};
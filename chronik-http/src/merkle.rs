@@ -0,0 +1,103 @@
+use bitcoinsuite_core::{Bytes, Hashed, Sha256d};
+
+/// Merkle branch of the tx at `index` among `txids` (all txids of a block, in block order),
+/// i.e. the sibling hashes from the leaf up to the root.
+///
+/// Uses the Bitcoin merkle tree as used by eCash, where a level with an odd number of hashes
+/// pairs its last hash with itself.
+pub(crate) fn merkle_branch(txids: &[Sha256d], mut index: usize) -> Vec<Sha256d> {
+    let mut branch = Vec::new();
+    let mut level = txids.to_vec();
+    while level.len() > 1 {
+        let sibling_idx = (index ^ 1).min(level.len() - 1);
+        branch.push(level[sibling_idx].clone());
+        level = level
+            .chunks(2)
+            .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+            .collect();
+        index /= 2;
+    }
+    branch
+}
+
+fn hash_pair(left: &Sha256d, right: &Sha256d) -> Sha256d {
+    let mut data = Vec::with_capacity(64);
+    data.extend_from_slice(left.as_slice());
+    data.extend_from_slice(right.as_slice());
+    Sha256d::digest(Bytes::from_bytes(data))
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoinsuite_core::Sha256d;
+    use pretty_assertions::assert_eq;
+
+    use crate::merkle::{hash_pair, merkle_branch};
+
+    fn txids(num_txs: u8) -> Vec<Sha256d> {
+        (0..num_txs).map(|i| Sha256d::new([i; 32])).collect()
+    }
+
+    /// Root computed level by level, independently of the branch.
+    fn merkle_root(txids: &[Sha256d]) -> Sha256d {
+        let mut level = txids.to_vec();
+        while level.len() > 1 {
+            if level.len() % 2 == 1 {
+                level.push(level.last().unwrap().clone());
+            }
+            level = level
+                .chunks(2)
+                .map(|pair| hash_pair(&pair[0], &pair[1]))
+                .collect();
+        }
+        level.remove(0)
+    }
+
+    /// Root obtained by hashing the leaf with its branch.
+    fn fold_branch(txid: &Sha256d, mut index: usize, branch: &[Sha256d]) -> Sha256d {
+        let mut hash = txid.clone();
+        for sibling in branch {
+            hash = match index % 2 {
+                0 => hash_pair(&hash, sibling),
+                _ => hash_pair(sibling, &hash),
+            };
+            index /= 2;
+        }
+        hash
+    }
+
+    #[test]
+    fn test_merkle_branch_small() {
+        let txids = txids(3);
+        assert_eq!(merkle_branch(&txids[..1], 0), vec![]);
+        assert_eq!(merkle_branch(&txids[..2], 0), vec![txids[1].clone()],);
+        assert_eq!(merkle_branch(&txids[..2], 1), vec![txids[0].clone()],);
+        // Odd level: the last tx is paired with itself
+        assert_eq!(
+            merkle_branch(&txids, 2),
+            vec![txids[2].clone(), hash_pair(&txids[0], &txids[1])],
+        );
+        assert_eq!(
+            merkle_branch(&txids, 0),
+            vec![txids[1].clone(), hash_pair(&txids[2], &txids[2])],
+        );
+    }
+
+    #[test]
+    fn test_merkle_branch_folds_to_root() {
+        // Covers multi-level trees with odd levels at different depths
+        for num_txs in 1..=13 {
+            let txids = txids(num_txs);
+            let root = merkle_root(&txids);
+            let mut expected_depth = 0;
+            while 1 << expected_depth < txids.len() {
+                expected_depth += 1;
+            }
+            for (index, txid) in txids.iter().enumerate() {
+                let branch = merkle_branch(&txids, index);
+                assert_eq!(branch.len(), expected_depth);
+                assert_eq!(fold_branch(txid, index, &branch), root);
+            }
+        }
+    }
+}
@@ -9,7 +9,10 @@ use hyper::{body::to_bytes, header::CONTENT_TYPE, Body};
 use prost::Message;
 use thiserror::Error;
 
-use crate::{error::ReportError, validation::check_content_type};
+use crate::{
+    error::ReportError,
+    validation::{check_accept, check_content_type},
+};
 
 pub struct Protobuf<P: Message + Default>(pub P);
 
@@ -35,6 +38,7 @@ impl<P: Message + Default> FromRequest<Body> for Protobuf<P> {
     async fn from_request(req: &mut RequestParts<Body>) -> Result<Self, Self::Rejection> {
         let headers = req.headers();
         check_content_type(headers, CONTENT_TYPE_PROTOBUF)?;
+        check_accept(headers, CONTENT_TYPE_PROTOBUF)?;
         let mut body = req.take_body().expect("Body taken");
         let mut body_bytes = to_bytes(&mut body)
             .await
@@ -0,0 +1,188 @@
+//! Minimal bitcoind-style JSON-RPC interface on `POST /rpc`, so scripts written against
+//! bitcoind can read blocks and txs from Chronik. Only a few read-only methods are supported,
+//! with a subset of bitcoind's result fields.
+
+use axum::{body::Bytes, extract::Extension, Json};
+use bitcoinsuite_core::{Hashed, Sha256d};
+use bitcoinsuite_error::Report;
+use chronik_indexer::SlpIndexer;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{convert, server::ChronikServer};
+
+// Error codes as used by bitcoind
+const RPC_PARSE_ERROR: i32 = -32700;
+const RPC_METHOD_NOT_FOUND: i32 = -32601;
+const RPC_INTERNAL_ERROR: i32 = -32603;
+const RPC_INVALID_PARAMETER: i32 = -8;
+const RPC_INVALID_ADDRESS_OR_KEY: i32 = -5;
+
+#[derive(Debug, Deserialize)]
+struct RpcRequest {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Vec<Value>,
+}
+
+#[derive(Debug)]
+struct RpcError {
+    code: i32,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i32, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<Report> for RpcError {
+    fn from(report: Report) -> Self {
+        RpcError::new(RPC_INTERNAL_ERROR, report.to_string())
+    }
+}
+
+/// Responds with a JSON-RPC response; errors are reported in its `error` field, not via the
+/// HTTP status.
+pub(crate) async fn handle_rpc(
+    Extension(server): Extension<ChronikServer>,
+    body: Bytes,
+) -> Json<Value> {
+    let request = match serde_json::from_slice::<RpcRequest>(&body) {
+        Ok(request) => request,
+        Err(err) => {
+            let err = RpcError::new(RPC_PARSE_ERROR, format!("Parse error: {}", err));
+            return rpc_response(Value::Null, Err(err));
+        }
+    };
+    let indexer = server.slp_indexer.read().await;
+    let result = match request.method.as_str() {
+        "getblockcount" => getblockcount(&indexer),
+        "getblock" => getblock(&indexer, &request.params),
+        "gettransaction" => gettransaction(&indexer, &request.params),
+        _ => Err(RpcError::new(RPC_METHOD_NOT_FOUND, "Method not found")),
+    };
+    rpc_response(request.id, result)
+}
+
+fn rpc_response(id: Value, result: Result<Value, RpcError>) -> Json<Value> {
+    Json(match result {
+        Ok(result) => json!({ "result": result, "error": null, "id": id }),
+        Err(err) => json!({
+            "result": null,
+            "error": { "code": err.code, "message": err.message },
+            "id": id,
+        }),
+    })
+}
+
+fn getblockcount(indexer: &SlpIndexer) -> Result<Value, RpcError> {
+    Ok(json!(indexer.blocks().height()?))
+}
+
+/// `getblock "blockhash" ( verbosity )`, only verbosity 1 (txids only) is supported.
+fn getblock(indexer: &SlpIndexer, params: &[Value]) -> Result<Value, RpcError> {
+    let hash = hash_param(params, 0, "blockhash")?;
+    match params.get(1) {
+        None | Some(Value::Bool(true)) => {}
+        Some(verbosity) if verbosity.as_u64() == Some(1) => {}
+        Some(verbosity) => {
+            return Err(RpcError::new(
+                RPC_INVALID_PARAMETER,
+                format!("Verbosity {} not supported, only 1 is", verbosity),
+            ))
+        }
+    }
+    let blocks = indexer.blocks();
+    let block = blocks
+        .by_hash(&hash)?
+        .ok_or_else(|| RpcError::new(RPC_INVALID_ADDRESS_OR_KEY, "Block not found"))?;
+    let tip_height = blocks.height()?;
+    let block_stats = blocks.block_stats(&block)?;
+    let raw_header = blocks
+        .raw_header(&block)?
+        .ok_or_else(|| RpcError::new(RPC_INTERNAL_ERROR, "Block header not found"))?;
+    let header_details = blocks.header_details(&block, &raw_header)?;
+    let txids = blocks
+        .block_txs_by_hash(&block.hash)?
+        .into_iter()
+        .map(|tx| tx.txid.to_hex_be())
+        .collect::<Vec<_>>();
+    let mut result = json!({
+        "hash": block.hash.to_hex_be(),
+        "confirmations": convert::num_confirmations(Some(block.height), tip_height),
+        "size": block_stats.block_size,
+        "height": block.height,
+        "version": header_details.version,
+        "versionHex": format!("{:08x}", header_details.version),
+        "merkleroot": header_details.merkle_root.to_hex_be(),
+        "tx": txids,
+        "time": block.timestamp,
+        "mediantime": header_details.median_timestamp,
+        "nonce": header_details.nonce,
+        "bits": format!("{:08x}", block.n_bits),
+        "nTx": block_stats.num_txs,
+    });
+    if block.height > 0 {
+        result["previousblockhash"] = json!(block.prev_hash.to_hex_be());
+    }
+    if let Some(next_block) = blocks.by_height(block.height + 1)? {
+        result["nextblockhash"] = json!(next_block.hash.to_hex_be());
+    }
+    Ok(result)
+}
+
+/// `gettransaction "txid"`, for any tx in the mempool or a block, not just wallet txs.
+fn gettransaction(indexer: &SlpIndexer, params: &[Value]) -> Result<Value, RpcError> {
+    let txid = hash_param(params, 0, "txid")?;
+    let txs = indexer.txs();
+    let rich_tx = txs.rich_tx_by_txid(&txid)?.ok_or_else(|| {
+        RpcError::new(
+            RPC_INVALID_ADDRESS_OR_KEY,
+            "No such mempool or blockchain transaction",
+        )
+    })?;
+    let raw_tx = txs
+        .raw_tx_by_id(&txid)?
+        .ok_or_else(|| RpcError::new(RPC_INTERNAL_ERROR, "Raw tx not found"))?;
+    let mut result = json!({
+        "txid": rich_tx.txid.to_hex_be(),
+        "hash": rich_tx.txid.to_hex_be(),
+        "time": rich_tx.time_first_seen,
+        "hex": hex::encode(raw_tx.to_vec()),
+    });
+    let confirmations = match &rich_tx.block {
+        Some(block) => {
+            let tip_height = indexer.blocks().height()?;
+            result["blockhash"] = json!(block.hash.to_hex_be());
+            result["blockheight"] = json!(block.height);
+            result["blocktime"] = json!(block.timestamp);
+            if let Some(tx_location) = txs.tx_location(&txid)? {
+                result["blockindex"] = json!(tx_location.index_in_block);
+            }
+            convert::num_confirmations(Some(block.height), tip_height)
+        }
+        None => 0,
+    };
+    result["confirmations"] = json!(confirmations);
+    Ok(result)
+}
+
+fn hash_param(params: &[Value], idx: usize, name: &str) -> Result<Sha256d, RpcError> {
+    let param = params
+        .get(idx)
+        .and_then(Value::as_str)
+        .ok_or_else(|| RpcError::new(RPC_INVALID_PARAMETER, format!("Missing {}", name)))?;
+    Sha256d::from_hex_be(param).map_err(|_| {
+        RpcError::new(
+            RPC_INVALID_PARAMETER,
+            format!("{} must be a 64 character hex string", name),
+        )
+    })
+}
@@ -1,40 +1,151 @@
-use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap, future::Future, net::SocketAddr, pin::Pin, sync::Arc, time::Duration,
+};
 
 use axum::{
+    body::StreamBody,
+    error_handling::HandleErrorLayer,
     extract::{
         ws::{self, WebSocket, WebSocketUpgrade},
         Extension, Path, Query,
     },
-    response::IntoResponse,
-    routing::{self, MethodFilter},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{self, MethodFilter, MethodRouter},
     Router,
 };
-use bitcoinsuite_core::{BitcoinCode, BitcoinSuiteError, Hashed, OutPoint, Sha256d, UnhashedTx};
-use bitcoinsuite_error::{ErrorMeta, Report, WrapErr};
-use bitcoinsuite_slp::{SlpTokenType, SlpTxTypeVariant, TokenId};
+use bitcoinsuite_core::{
+    BitcoinCode, BitcoinSuiteError, CashAddress, Hashed, OutPoint, Sha256d, UnhashedTx,
+};
+use bitcoinsuite_error::{ErrorMeta, Report};
+use bitcoinsuite_slp::{RichTx, RichUtxo, SlpToken, TokenId};
 use chronik_indexer::{
-    subscribers::{SubscribeBlockMessage, SubscribeScriptMessage},
+    dormant_utxos, export_blocks, export_utxos, format_token_amount, parse_token_amount, raw_block,
+    subscribers::{
+        SubscribeBlockMessage, SubscribeOutpointMessage, SubscribeScriptMessage,
+        SubscribeTokenMessage,
+    },
+    BlocksError::InconsistentNoSuchRawHeader,
     SlpIndexer, UtxoStateVariant,
 };
-use chronik_rocksdb::ScriptPayload;
-use futures::future::select_all;
+use chronik_rocksdb::{script_payloads, BlockHeight, PayloadPrefix, ScriptFilter, ScriptPayload};
+use futures::{future::select_all, FutureExt, StreamExt};
+use hyper::header::{CACHE_CONTROL, CONTENT_TYPE};
 use itertools::Itertools;
 use prost::Message;
 use rand::SeedableRng;
 use thiserror::Error;
-use tokio::sync::{broadcast, RwLock};
+use tokio::sync::{broadcast, OwnedSemaphorePermit, RwLock, Semaphore};
+use tower::{limit::GlobalConcurrencyLimitLayer, BoxError, ServiceBuilder};
 use tower_http::compression::CompressionLayer;
 
 pub const DEFAULT_PAGE_SIZE: usize = 25;
 pub const MAX_HISTORY_PAGE_SIZE: usize = 200;
 pub const MAX_BLOCKS_PAGE_SIZE: usize = 500;
+pub const MAX_BLOCK_INPUTS_PAGE_SIZE: usize = 1000;
+pub const MAX_TOKENS_STATS: usize = 200;
+pub const MAX_HEADERS_SINCE: usize = 2000;
+pub const MAX_DORMANT_UTXOS: usize = 1000;
+/// Max number of confirmed txs a script subscription with `from_height` replays.
+pub const MAX_REPLAY_TXS: usize = 10_000;
+/// Max `min_confirmations` of a script subscription.
+pub const MAX_MIN_CONFIRMATIONS: u32 = 100;
+/// Max number of `MsgConfirmed`s a WebSocket connection holds back for `min_confirmations`.
+pub const MAX_PENDING_CONFIRMED: usize = 10_000;
+/// How long a request may take until its response starts, after which it fails with a 504.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// How many outpoints a single `/validate-utxos` request may query by default.
+pub const DEFAULT_MAX_VALIDATE_UTXOS: usize = 1000;
+/// How many tokens a single `/tokens` or `/tokens/recent` page may list by default.
+pub const DEFAULT_MAX_TOKEN_LISTINGS: usize = 200;
 
 pub type SlpIndexerRef = Arc<RwLock<SlpIndexer>>;
 
+/// Endpoints listed in `GET /`; keep in sync with the routes in `ChronikServer::run`.
+const ENDPOINTS: &[&str] = &[
+    "GET /",
+    "POST /broadcast-tx",
+    "POST /broadcast-txs",
+    "GET /blockchain-info",
+    "GET /tip",
+    "GET /status",
+    "GET /metrics",
+    "GET /blocks/:start/:end",
+    "GET /blocks-in-time/:start_ts/:end_ts",
+    "GET /block/:hash_or_height",
+    "GET /block/:hash_or_height/info",
+    "GET /block/:hash_or_height/inputs",
+    "GET /block/:hash_or_height/coinbase-script",
+    "GET /block/:hash_or_height/burns",
+    "GET /block/:hash_or_height/tx/:index",
+    "GET /export/blocks/:start/:end",
+    "POST /headers-since",
+    "GET /tx/:txid",
+    "GET /tx/:txid/conflict-status",
+    "GET /tx/:txid/location",
+    "GET /tx/:txid/merkle-proof",
+    "GET /raw-tx/:txid",
+    "GET /raw-block/:hash_or_height",
+    "GET /token/:token_id",
+    "GET /token/:token_id/genesis",
+    "GET /token/:token_id/spends",
+    "GET /token/:token_id/history",
+    "GET /token/:token_id/mints",
+    "GET /token/:token_id/holders",
+    "GET /token/:token_id/parse-amount",
+    "GET /tokens",
+    "GET /tokens/recent",
+    "POST /tokens-stats",
+    "GET /mint-baton/:txid/:out_idx",
+    "GET /slp-stats",
+    "GET /protocol/:lokad_id/history",
+    "GET /address/:address/scripts",
+    "GET /script/:type/:payload/history",
+    "GET /script/:type/:payload/utxos",
+    "GET /script/:type/:payload/summary",
+    "GET /script/:type/:payload/value",
+    "GET /script/:type/:payload/token-history",
+    "GET /script/:type/:payload/spent-outputs",
+    "GET /script/:type/:payload/token-flows",
+    "POST /validate-utxos",
+    "GET /ws",
+];
+const DEBUG_ENDPOINTS: &[&str] = &[
+    "GET /debug/tx-num/:txid",
+    "GET /export/utxos",
+    "GET /dormant-utxos",
+    "GET /admin/growth",
+];
+
 #[derive(Clone)]
 pub struct ChronikServer {
     pub addr: SocketAddr,
     pub slp_indexer: SlpIndexerRef,
+    /// Only bounds the time until the response starts, so streamed responses like
+    /// `/export/blocks` and WebSocket connections on `/ws` aren't cut off.
+    pub request_timeout: Duration,
+    pub script_types: Arc<ScriptTypes>,
+    /// Serve the `/debug/...` endpoints exposing internal index data, `/admin/growth`, and
+    /// `/export/utxos` and `/dormant-utxos`, which go through the whole UTXO set; meant for
+    /// operators only.
+    pub enable_debug_endpoints: bool,
+    /// Max number of outpoints per `/validate-utxos` request, more fail with `PageSizeTooLarge`.
+    pub max_validate_utxos: usize,
+    /// Max `count` of a `/tokens` or `/tokens/recent` page, more fail with `PageSizeTooLarge`.
+    pub max_token_listings: usize,
+    /// If set, `SubscribeMsg`s on `/ws` are collected for this long and sent together as one
+    /// `SubscribeMsgBatch` frame, instead of one frame per message.
+    pub ws_batch_interval: Option<Duration>,
+    /// Serve a minimal bitcoind-style JSON-RPC interface on `POST /rpc` (`getblockcount`,
+    /// `getblock` and `gettransaction`), for tooling written against bitcoind.
+    pub enable_rpc: bool,
+    /// Max number of heavy queries (exports, block ranges and scans over many txs) served at
+    /// once; further ones fail with 503 right away. Streamed exports count until their stream
+    /// ends. Other endpoints aren't limited.
+    pub max_heavy_queries: Option<usize>,
+    /// Leave `network` of `Tx` and `Utxo` unset to save bytes, as it's the same for every tx of
+    /// a deployment; clients then get it once from `GET /`.
+    pub strip_network: bool,
 }
 
 #[derive(Debug, Error, ErrorMeta)]
@@ -47,6 +158,13 @@ pub enum ChronikServerError {
     #[error("Block not found: {0}")]
     BlockNotFound(String),
 
+    #[not_found()]
+    #[error("Block {hash_or_height} has no tx at index {index}")]
+    BlockTxNotFound {
+        hash_or_height: String,
+        index: usize,
+    },
+
     #[not_found()]
     #[error("Token txid not found: {0}")]
     TokenTxidNotFound(Sha256d),
@@ -55,6 +173,18 @@ pub enum ChronikServerError {
     #[error("Token txid is not a GENESIS tx: {0}")]
     TokenTxNotGenesis(Sha256d),
 
+    #[not_found()]
+    #[error("Output not found: {0}")]
+    OutputNotFound(String),
+
+    #[invalid_user_input()]
+    #[error("Output is not a mint baton: {0}")]
+    NotAMintBaton(String),
+
+    #[invalid_user_input()]
+    #[error("Mint baton already spent: {0}")]
+    MintBatonSpent(String),
+
     #[invalid_user_input()]
     #[error("Invalid hash or height: {0}")]
     InvalidHashOrHeight(String),
@@ -71,31 +201,95 @@ pub enum ChronikServerError {
     #[error("Page size too large")]
     PageSizeTooLarge,
 
+    #[invalid_user_input()]
+    #[error("Too many txs to replay from height {0}, query the script's history instead")]
+    ReplayTooLarge(BlockHeight),
+
+    #[invalid_user_input()]
+    #[error("Too many txs awaiting confirmations, dropped Confirmed of {0}")]
+    TooManyPendingConfirmed(String),
+
     #[invalid_user_input()]
     #[error("Invalid tx encoding: {0}")]
     InvalidTxEncoding(BitcoinSuiteError),
 
-    #[critical()]
-    #[error("Unexpected JSON from bitcoind: {0}")]
-    BitcoindBadJson(&'static str),
+    #[invalid_user_input()]
+    #[error("Set exactly one of display and base_units")]
+    InvalidAmountQuery,
 }
 
 use crate::{
     convert::{
-        block_to_info_proto, network_to_proto, parse_payload_prefix, rich_tx_to_proto,
-        slp_token_to_proto, slp_tx_data_to_proto,
+        self, block_to_info_proto, network_to_proto, parse_payload_prefix, parse_slp_burn_policy,
+        rich_tx_to_proto, slp_output_meta_to_proto, slp_token_to_proto, slp_tx_data_to_proto,
+        token_listing_to_proto, token_stats_to_proto, token_type_to_proto,
     },
     error::{report_to_status_proto, ReportError},
-    proto,
-    protobuf::Protobuf,
+    merkle, proto,
+    protobuf::{Protobuf, CONTENT_TYPE_PROTOBUF},
+    rpc::handle_rpc,
+    service::{
+        block_by_hash_or_height, outpoint_msg_to_proto, script_msg_to_proto, script_payload,
+        token_msg_to_proto, ChronikService,
+    },
+    validation::{parse_height_range, PayloadLengths, ScriptTypes},
 };
 
 use self::ChronikServerError::*;
 
 impl ChronikServer {
+    pub fn service(&self) -> ChronikService {
+        ChronikService {
+            slp_indexer: Arc::clone(&self.slp_indexer),
+            script_types: Arc::clone(&self.script_types),
+        }
+    }
+
+    /// Parts of `GET /` that don't change while the server is running.
+    async fn static_server_info(&self) -> proto::ServerInfo {
+        let slp_indexer = self.slp_indexer.read().await;
+        let mut endpoints = ENDPOINTS.to_vec();
+        if self.enable_debug_endpoints {
+            endpoints.extend_from_slice(DEBUG_ENDPOINTS);
+        }
+        if self.enable_rpc {
+            endpoints.push("POST /rpc");
+        }
+        proto::ServerInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            network: network_to_proto(slp_indexer.network()) as i32,
+            tip_height: -1,
+            is_watch_only: matches!(slp_indexer.db().script_filter(), ScriptFilter::Watched(_)),
+            ignores_unknown_token_types: slp_indexer.db().slp_conf().ignore_unknown_token_types,
+            has_spends_index: slp_indexer.db().has_spends_index(),
+            endpoints: endpoints.into_iter().map(str::to_string).collect(),
+        }
+    }
+
     pub async fn run(self) -> Result<(), Report> {
         let addr = self.addr;
-        let app = Router::new()
+        let request_timeout = self.request_timeout;
+        let server_info = Arc::new(self.static_server_info().await);
+        // Shared by all heavy routes, so they're limited together. Streaming routes take their
+        // permit themselves, as the layer would release it once the response headers are sent.
+        let heavy_queries = HeavyQueries(
+            self.max_heavy_queries
+                .map(|max_heavy_queries| Arc::new(Semaphore::new(max_heavy_queries))),
+        );
+        let heavy_query_limit = heavy_queries.0.as_ref().map(|semaphore| {
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(handle_overloaded_error))
+                .load_shed()
+                .layer(GlobalConcurrencyLimitLayer::with_semaphore(Arc::clone(
+                    semaphore,
+                )))
+        });
+        let heavy = |method_router: MethodRouter| match &heavy_query_limit {
+            Some(heavy_query_limit) => method_router.layer(heavy_query_limit.clone()),
+            None => method_router,
+        };
+        let mut router = Router::new()
+            .route("/", routing::get(handle_server_info))
             .route(
                 "/broadcast-tx",
                 routing::post(handle_broadcast_tx).on(MethodFilter::OPTIONS, handle_post_options),
@@ -105,11 +299,96 @@ impl ChronikServer {
                 routing::post(handle_broadcast_txs).on(MethodFilter::OPTIONS, handle_post_options),
             )
             .route("/blockchain-info", routing::get(handle_blockchain_info))
-            .route("/blocks/:start/:end", routing::get(handle_blocks))
+            .route("/tip", routing::get(handle_tip))
+            .route("/status", routing::get(handle_status))
+            .route("/metrics", routing::get(handle_metrics))
+            .route("/blocks/:start/:end", heavy(routing::get(handle_blocks)))
+            .route(
+                "/blocks-in-time/:start_ts/:end_ts",
+                heavy(routing::get(handle_blocks_in_time)),
+            )
             .route("/block/:hash_or_height", routing::get(handle_block))
+            .route(
+                "/block/:hash_or_height/info",
+                routing::get(handle_block_info),
+            )
+            .route(
+                "/block/:hash_or_height/inputs",
+                heavy(routing::get(handle_block_inputs)),
+            )
+            .route(
+                "/block/:hash_or_height/coinbase-script",
+                routing::get(handle_block_coinbase_script),
+            )
+            .route(
+                "/block/:hash_or_height/burns",
+                routing::get(handle_block_burns),
+            )
+            .route(
+                "/block/:hash_or_height/tx/:index",
+                routing::get(handle_block_tx),
+            )
+            .route(
+                "/export/blocks/:start/:end",
+                routing::get(handle_export_blocks),
+            )
+            .route(
+                "/headers-since",
+                routing::post(handle_headers_since).on(MethodFilter::OPTIONS, handle_post_options),
+            )
             .route("/tx/:txid", routing::get(handle_tx))
+            .route(
+                "/tx/:txid/conflict-status",
+                routing::get(handle_tx_conflict_status),
+            )
+            .route("/tx/:txid/location", routing::get(handle_tx_location))
+            .route(
+                "/tx/:txid/merkle-proof",
+                routing::get(handle_tx_merkle_proof),
+            )
             .route("/raw-tx/:txid", routing::get(handle_raw_tx))
+            .route("/raw-block/:hash_or_height", routing::get(handle_raw_block))
             .route("/token/:token_id", routing::get(handle_token))
+            .route(
+                "/token/:token_id/genesis",
+                routing::get(handle_token_genesis),
+            )
+            .route("/token/:token_id/spends", routing::get(handle_token_spends))
+            .route(
+                "/token/:token_id/history",
+                routing::get(handle_token_history),
+            )
+            .route(
+                "/token/:token_id/mints",
+                heavy(routing::get(handle_token_mints)),
+            )
+            .route(
+                "/token/:token_id/holders",
+                heavy(routing::get(handle_token_holders)),
+            )
+            .route(
+                "/token/:token_id/parse-amount",
+                routing::get(handle_token_parse_amount),
+            )
+            .route("/tokens", routing::get(handle_tokens))
+            .route("/tokens/recent", routing::get(handle_recent_tokens))
+            .route(
+                "/tokens-stats",
+                routing::post(handle_tokens_stats).on(MethodFilter::OPTIONS, handle_post_options),
+            )
+            .route(
+                "/mint-baton/:txid/:out_idx",
+                routing::get(handle_mint_baton),
+            )
+            .route("/slp-stats", routing::get(handle_slp_stats))
+            .route(
+                "/protocol/:lokad_id/history",
+                routing::get(handle_protocol_history),
+            )
+            .route(
+                "/address/:address/scripts",
+                routing::get(handle_address_scripts),
+            )
             .route(
                 "/script/:type/:payload/history",
                 routing::get(handle_script_history),
@@ -118,13 +397,54 @@ impl ChronikServer {
                 "/script/:type/:payload/utxos",
                 routing::get(handle_script_utxos),
             )
+            .route(
+                "/script/:type/:payload/summary",
+                routing::get(handle_script_summary),
+            )
+            .route(
+                "/script/:type/:payload/value",
+                routing::get(handle_script_value),
+            )
+            .route(
+                "/script/:type/:payload/token-history",
+                routing::get(handle_script_token_history),
+            )
+            .route(
+                "/script/:type/:payload/spent-outputs",
+                heavy(routing::get(handle_script_spent_outputs)),
+            )
+            .route(
+                "/script/:type/:payload/token-flows",
+                heavy(routing::get(handle_script_token_flows)),
+            )
             .route(
                 "/validate-utxos",
                 routing::post(handle_validate_utxos).on(MethodFilter::OPTIONS, handle_post_options),
             )
-            .route("/ws", routing::get(handle_subscribe))
+            .route("/ws", routing::get(handle_subscribe));
+        if self.enable_debug_endpoints {
+            router = router
+                .route("/debug/tx-num/:txid", routing::get(handle_debug_tx_num))
+                .route("/export/utxos", routing::get(handle_export_utxos))
+                .route("/dormant-utxos", heavy(routing::get(handle_dormant_utxos)))
+                .route("/admin/growth", routing::get(handle_admin_growth));
+        }
+        if self.enable_rpc {
+            router = router.route(
+                "/rpc",
+                routing::post(handle_rpc).on(MethodFilter::OPTIONS, handle_post_options),
+            );
+        }
+        let app = router
             .layer(Extension(self))
-            .layer(CompressionLayer::new());
+            .layer(Extension(server_info))
+            .layer(Extension(heavy_queries))
+            .layer(CompressionLayer::new())
+            .layer(
+                ServiceBuilder::new()
+                    .layer(HandleErrorLayer::new(handle_timeout_error))
+                    .timeout(request_timeout),
+            );
 
         axum::Server::bind(&addr)
             .serve(app.into_make_service())
@@ -134,6 +454,55 @@ impl ChronikServer {
     }
 }
 
+async fn handle_timeout_error(_: BoxError) -> (StatusCode, Protobuf<proto::Error>) {
+    // The routes themselves are infallible, so the only error reaching here is the timeout
+    (
+        StatusCode::GATEWAY_TIMEOUT,
+        Protobuf(proto::Error {
+            error_code: "request-timeout".to_string(),
+            msg: "Request timed out".to_string(),
+            is_user_error: false,
+            slp_rejection: None,
+        }),
+    )
+}
+
+async fn handle_overloaded_error(_: BoxError) -> (StatusCode, Protobuf<proto::Error>) {
+    // Only the load shedding of heavy routes can fail, when `max_heavy_queries` are running
+    too_many_heavy_queries()
+}
+
+/// Slots of the `max_heavy_queries` limit, if any.
+#[derive(Clone)]
+struct HeavyQueries(Option<Arc<Semaphore>>);
+
+impl HeavyQueries {
+    /// Takes a slot for a streaming route, which holds it for as long as its stream lives.
+    fn try_acquire(
+        &self,
+    ) -> Result<Option<OwnedSemaphorePermit>, (StatusCode, Protobuf<proto::Error>)> {
+        match &self.0 {
+            Some(semaphore) => match Arc::clone(semaphore).try_acquire_owned() {
+                Ok(permit) => Ok(Some(permit)),
+                Err(_) => Err(too_many_heavy_queries()),
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+fn too_many_heavy_queries() -> (StatusCode, Protobuf<proto::Error>) {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Protobuf(proto::Error {
+            error_code: "too-many-heavy-queries".to_string(),
+            msg: "Too many heavy queries in progress, try again later".to_string(),
+            is_user_error: false,
+            slp_rejection: None,
+        }),
+    )
+}
+
 async fn handle_post_options() -> Result<http::Response<axum::body::Body>, ReportError> {
     http::Response::builder()
         .header("Allow", "OPTIONS, HEAD, POST")
@@ -148,7 +517,12 @@ async fn handle_broadcast_tx(
     let tx = UnhashedTx::deser(&mut broadcast_request.raw_tx.into()).map_err(InvalidTxEncoding)?;
     let slp_indexer = server.slp_indexer.read().await;
     let check_slp = !broadcast_request.skip_slp_check;
-    let txid = slp_indexer.broadcast().broadcast_tx(&tx, check_slp).await?;
+    let burn_policy = parse_slp_burn_policy(broadcast_request.slp_burn_policy)?;
+    let txid = slp_indexer
+        .broadcast()
+        .with_burn_policy(burn_policy)
+        .broadcast_tx(&tx, check_slp)
+        .await?;
     Ok(Protobuf(proto::BroadcastTxResponse {
         txid: txid.as_slice().to_vec(),
     }))
@@ -159,8 +533,9 @@ async fn handle_broadcast_txs(
     Extension(server): Extension<ChronikServer>,
 ) -> Result<Protobuf<proto::BroadcastTxsResponse>, ReportError> {
     let check_slp = !broadcast_request.skip_slp_check;
+    let burn_policy = parse_slp_burn_policy(broadcast_request.slp_burn_policy)?;
     let slp_indexer = server.slp_indexer.read().await;
-    let broadcast = slp_indexer.broadcast();
+    let broadcast = slp_indexer.broadcast().with_burn_policy(burn_policy);
     let mut txs = Vec::new();
     for raw_tx in broadcast_request.raw_txs {
         let tx = UnhashedTx::deser(&mut raw_tx.into()).map_err(InvalidTxEncoding)?;
@@ -172,7 +547,7 @@ async fn handle_broadcast_txs(
     }
     let mut txids = Vec::new();
     for tx in txs {
-        let txid = slp_indexer.broadcast().broadcast_tx(&tx, check_slp).await?;
+        let txid = broadcast.broadcast_tx(&tx, check_slp).await?;
         txids.push(txid);
     }
     Ok(Protobuf(proto::BroadcastTxsResponse {
@@ -180,6 +555,17 @@ async fn handle_broadcast_txs(
     }))
 }
 
+async fn handle_server_info(
+    Extension(server): Extension<ChronikServer>,
+    Extension(server_info): Extension<Arc<proto::ServerInfo>>,
+) -> Result<Protobuf<proto::ServerInfo>, ReportError> {
+    let tip_height = server.slp_indexer.read().await.blocks().height()?;
+    Ok(Protobuf(proto::ServerInfo {
+        tip_height,
+        ..server_info.as_ref().clone()
+    }))
+}
+
 async fn handle_blockchain_info(
     Extension(server): Extension<ChronikServer>,
 ) -> Result<Protobuf<proto::BlockchainInfo>, ReportError> {
@@ -194,6 +580,79 @@ async fn handle_blockchain_info(
     }))
 }
 
+/// Tip info, raw header and coinbase tx in one response, for clients polling the chain head.
+async fn handle_tip(
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::Tip>, ReportError> {
+    let slp_indexer = server.slp_indexer.read().await;
+    let block = match slp_indexer.blocks().tip()? {
+        Some(block) => block,
+        None => {
+            return Ok(Protobuf(proto::Tip {
+                blockchain_info: Some(proto::BlockchainInfo {
+                    tip_hash: vec![0; 32],
+                    tip_height: -1,
+                }),
+                raw_header: vec![],
+                raw_coinbase_tx: vec![],
+            }))
+        }
+    };
+    // Kept in memory by the indexer, so polling this never queries the node
+    let (raw_header, raw_coinbase_tx) = match slp_indexer.tip_raw() {
+        Some(tip_raw) if tip_raw.hash == block.hash => {
+            (tip_raw.raw_header.clone(), tip_raw.raw_coinbase_tx.clone())
+        }
+        _ => (vec![], vec![]),
+    };
+    Ok(Protobuf(proto::Tip {
+        blockchain_info: Some(proto::BlockchainInfo {
+            tip_hash: block.hash.as_slice().to_vec(),
+            tip_height: block.height,
+        }),
+        raw_header,
+        raw_coinbase_tx,
+    }))
+}
+
+async fn handle_status(
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::Status>, ReportError> {
+    let slp_indexer = server.slp_indexer.read().await;
+    let (tip_hash, tip_height, tip_timestamp) = match slp_indexer.blocks().tip()? {
+        Some(block) => (block.hash, block.height, block.timestamp),
+        None => (Sha256d::new([0; 32]), -1, 0),
+    };
+    Ok(Protobuf(proto::Status {
+        tip_hash: tip_hash.as_slice().to_vec(),
+        tip_height,
+        is_stalled: slp_indexer.is_stalled(),
+        last_processed_at: slp_indexer.last_processed_at().unwrap_or_default(),
+        last_block_at: tip_timestamp,
+        is_node_reachable: slp_indexer.node_status().is_reachable(),
+    }))
+}
+
+async fn handle_metrics(
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::Metrics>, ReportError> {
+    let slp_indexer = server.slp_indexer.read().await;
+    let cache_stats = slp_indexer.script_history_cache_stats();
+    let num_queries = cache_stats.num_hits + cache_stats.num_misses;
+    Ok(Protobuf(proto::Metrics {
+        script_history_cache: Some(proto::CacheMetrics {
+            capacity: cache_stats.capacity as u64,
+            num_entries: cache_stats.num_entries as u64,
+            num_hits: cache_stats.num_hits,
+            num_misses: cache_stats.num_misses,
+            hit_ratio: match num_queries {
+                0 => 0.0,
+                _ => cache_stats.num_hits as f64 / num_queries as f64,
+            },
+        }),
+    }))
+}
+
 async fn handle_blocks(
     Path((start_height, end_height)): Path<(i32, i32)>,
     Extension(server): Extension<ChronikServer>,
@@ -217,99 +676,520 @@ async fn handle_blocks(
         return Err(PageSizeTooLarge.into());
     }
     let slp_indexer = server.slp_indexer.read().await;
-    let block_stats_reader = slp_indexer.db().block_stats()?;
     let block_reader = slp_indexer.db().blocks()?;
     let mut blocks = Vec::new();
     for block_height in start_height..=end_height {
-        let block = block_reader.by_height(block_height)?;
-        let block_stats = block_stats_reader.by_height(block_height)?;
-        let (block, block_stats) = match block.zip(block_stats) {
-            Some(tuple) => tuple,
+        let block = match block_reader.by_height(block_height)? {
+            Some(block) => block,
             None => break,
         };
+        let block_stats = slp_indexer.blocks().block_stats(&block)?;
         blocks.push(block_to_info_proto(&block, &block_stats));
     }
-    Ok(Protobuf(proto::Blocks { blocks }))
+    Ok(Protobuf(proto::Blocks {
+        blocks,
+        next_start_height: None,
+    }))
 }
 
-async fn handle_block(
-    Path(hash_or_height): Path<String>,
+async fn handle_blocks_in_time(
+    Path((start_timestamp, end_timestamp)): Path<(i64, i64)>,
+    Query(query_params): Query<HashMap<String, String>>,
     Extension(server): Extension<ChronikServer>,
-) -> Result<Protobuf<proto::Block>, ReportError> {
+) -> Result<Protobuf<proto::Blocks>, ReportError> {
+    if end_timestamp < start_timestamp {
+        return Err(InvalidField {
+            name: "end_ts",
+            value: end_timestamp.to_string(),
+        }
+        .into());
+    }
+    let min_start_height: BlockHeight = match query_params.get("start_height") {
+        Some(start_height) => match start_height.parse() {
+            Ok(start_height) if start_height >= 0 => start_height,
+            _ => {
+                return Err(InvalidField {
+                    name: "start_height",
+                    value: start_height.clone(),
+                }
+                .into())
+            }
+        },
+        None => 0,
+    };
     let slp_indexer = server.slp_indexer.read().await;
+    let height_range = slp_indexer
+        .blocks()
+        .height_range_by_median_timestamp(start_timestamp, end_timestamp)?;
+    let (start_height, end_height) = match height_range {
+        Some(height_range) => (
+            (*height_range.start()).max(min_start_height),
+            *height_range.end(),
+        ),
+        None => return Ok(Protobuf(proto::Blocks::default())),
+    };
+    let page_end_height =
+        end_height.min(start_height.saturating_add(MAX_BLOCKS_PAGE_SIZE as BlockHeight - 1));
     let block_reader = slp_indexer.db().blocks()?;
-    let block_stats_reader = slp_indexer.db().block_stats()?;
-    let block = match hash_or_height.parse::<i32>() {
-        Ok(height) => block_reader.by_height(height)?,
-        Err(_) => {
-            let hash = Sha256d::from_hex_be(&hash_or_height)
-                .map_err(|_| InvalidHashOrHeight(hash_or_height.clone()))?;
-            block_reader.by_hash(&hash)?
+    let mut blocks = Vec::new();
+    for block_height in start_height..=page_end_height {
+        let block = match block_reader.by_height(block_height)? {
+            Some(block) => block,
+            None => break,
+        };
+        let block_stats = slp_indexer.blocks().block_stats(&block)?;
+        blocks.push(block_to_info_proto(&block, &block_stats));
+    }
+    Ok(Protobuf(proto::Blocks {
+        blocks,
+        next_start_height: (page_end_height < end_height).then(|| page_end_height + 1),
+    }))
+}
+
+/// Streams length-delimited `proto::Block`s, without `block_details`.
+async fn handle_export_blocks(
+    Path((start_height, end_height)): Path<(i32, i32)>,
+    Extension(server): Extension<ChronikServer>,
+    Extension(heavy_queries): Extension<HeavyQueries>,
+) -> Result<Response, ReportError> {
+    if start_height < 0 {
+        return Err(InvalidField {
+            name: "start_height",
+            value: start_height.to_string(),
+        }
+        .into());
+    }
+    if end_height < start_height {
+        return Err(InvalidField {
+            name: "end_height",
+            value: end_height.to_string(),
         }
+        .into());
+    }
+    let permit = match heavy_queries.try_acquire() {
+        Ok(permit) => permit,
+        Err(overloaded) => return Ok(overloaded.into_response()),
     };
-    let block = match block {
-        Some(block) => block,
-        None => return Err(BlockNotFound(hash_or_height).into()),
+    let blocks = export_blocks(Arc::clone(&server.slp_indexer), start_height, end_height);
+    let strip_network = server.strip_network;
+    let blocks = blocks.map(move |exported_block| {
+        // Keeps the heavy query slot taken until the stream is dropped
+        let _permit = &permit;
+        let exported_block = exported_block.map_err(|report| {
+            println!("Exporting blocks failed: {:?}", report);
+            std::io::Error::new(std::io::ErrorKind::Other, "Exporting blocks failed")
+        })?;
+        let block_proto = proto::Block {
+            block_info: Some(block_to_info_proto(
+                &exported_block.block,
+                &exported_block.block_stats,
+            )),
+            block_details: None,
+            raw_header: exported_block.raw_header,
+            txs: exported_block
+                .txs
+                .into_iter()
+                .map(|rich_tx| server_tx_to_proto(rich_tx, strip_network))
+                .collect(),
+        };
+        Ok::<_, std::io::Error>(block_proto.encode_length_delimited_to_vec())
+    });
+    Ok((
+        [(CONTENT_TYPE, CONTENT_TYPE_PROTOBUF)],
+        StreamBody::new(blocks),
+    )
+        .into_response())
+}
+
+/// Streams length-delimited `proto::ExportedUtxo`s of all confirmed UTXOs, optionally only of
+/// scripts of the type given via `?script_type=`.
+async fn handle_export_utxos(
+    Query(query_params): Query<HashMap<String, String>>,
+    Extension(server): Extension<ChronikServer>,
+    Extension(heavy_queries): Extension<HeavyQueries>,
+) -> Result<Response, ReportError> {
+    let prefixes = match query_params.get("script_type") {
+        Some(script_type) => match server.script_types.lengths(script_type) {
+            Some(PayloadLengths::Any(prefix)) => vec![*prefix],
+            Some(PayloadLengths::Exact(lengths)) => {
+                lengths.iter().map(|&(_, prefix)| prefix).collect()
+            }
+            None => {
+                return Err(InvalidField {
+                    name: "script_type",
+                    value: script_type.clone(),
+                }
+                .into())
+            }
+        },
+        None => PayloadPrefix::ALL.to_vec(),
+    };
+    let permit = match heavy_queries.try_acquire() {
+        Ok(permit) => permit,
+        Err(overloaded) => return Ok(overloaded.into_response()),
+    };
+    let script_types = Arc::clone(&server.script_types);
+    let utxos = export_utxos(Arc::clone(&server.slp_indexer), prefixes);
+    let utxos = utxos.map(move |utxos| {
+        // Keeps the heavy query slot taken until the stream is dropped
+        let _permit = &permit;
+        let utxos = utxos.map_err(|report| {
+            println!("Exporting UTXOs failed: {:?}", report);
+            std::io::Error::new(std::io::ErrorKind::Other, "Exporting UTXOs failed")
+        })?;
+        let mut chunk = Vec::new();
+        for utxo in utxos {
+            let utxo_proto = proto::ExportedUtxo {
+                script_type: script_types
+                    .script_type(utxo.script_payload.payload_prefix)
+                    .unwrap_or_default()
+                    .to_string(),
+                payload: utxo.script_payload.payload_data,
+                outpoint: Some(proto::OutPoint {
+                    txid: utxo.outpoint.txid.as_slice().to_vec(),
+                    out_idx: utxo.outpoint.out_idx,
+                }),
+                value: utxo.value,
+            };
+            chunk.extend(utxo_proto.encode_length_delimited_to_vec());
+        }
+        Ok::<_, std::io::Error>(chunk)
+    });
+    Ok((
+        [(CONTENT_TYPE, CONTENT_TYPE_PROTOBUF)],
+        StreamBody::new(utxos),
+    )
+        .into_response())
+}
+
+/// The oldest confirmed UTXOs created before `?older_than_height=`, up to `?limit=`, e.g. to
+/// estimate lost coins.
+async fn handle_dormant_utxos(
+    Query(query_params): Query<HashMap<String, String>>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::DormantUtxos>, ReportError> {
+    let older_than_height = query_params
+        .get("older_than_height")
+        .map(String::as_str)
+        .unwrap_or_default();
+    let older_than_height: BlockHeight = match older_than_height.parse() {
+        Ok(height) if height >= 0 => height,
+        _ => {
+            return Err(InvalidField {
+                name: "older_than_height",
+                value: older_than_height.to_string(),
+            }
+            .into())
+        }
+    };
+    let limit: usize = match query_params.get("limit") {
+        Some(limit) => match limit.parse() {
+            Ok(limit) if limit > 0 => limit,
+            _ => {
+                return Err(InvalidField {
+                    name: "limit",
+                    value: limit.clone(),
+                }
+                .into())
+            }
+        },
+        None => DEFAULT_PAGE_SIZE,
     };
-    let block_stats = block_stats_reader
-        .by_height(block.height)?
-        .expect("Inconsistent index");
-    let block_info = Some(block_to_info_proto(&block, &block_stats));
+    if limit > MAX_DORMANT_UTXOS {
+        return Err(PageSizeTooLarge.into());
+    }
+    let utxos = dormant_utxos(Arc::clone(&server.slp_indexer), older_than_height, limit).await?;
+    Ok(Protobuf(proto::DormantUtxos {
+        utxos: utxos
+            .into_iter()
+            .map(|utxo| proto::DormantUtxo {
+                script_type: server
+                    .script_types
+                    .script_type(utxo.script_payload.payload_prefix)
+                    .unwrap_or_default()
+                    .to_string(),
+                payload: utxo.script_payload.payload_data,
+                outpoint: Some(proto::OutPoint {
+                    txid: utxo.outpoint.txid.as_slice().to_vec(),
+                    out_idx: utxo.outpoint.out_idx,
+                }),
+                value: utxo.value,
+                created_height: utxo.height,
+            })
+            .collect(),
+    }))
+}
+
+async fn handle_headers_since(
+    Extension(server): Extension<ChronikServer>,
+    Protobuf(request): Protobuf<proto::HeadersSinceRequest>,
+) -> Result<Protobuf<proto::HeadersSinceResponse>, ReportError> {
+    let locator = request
+        .locator
+        .iter()
+        .map(|hash| {
+            Sha256d::from_slice(hash).map_err(|_| InvalidField {
+                name: "locator",
+                value: hex::encode(hash),
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let slp_indexer = server.slp_indexer.read().await;
+    let raw_headers = slp_indexer
+        .blocks()
+        .headers_since(&locator, MAX_HEADERS_SINCE)?;
+    Ok(Protobuf(proto::HeadersSinceResponse { raw_headers }))
+}
+
+async fn handle_block(
+    Path(hash_or_height): Path<String>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::Block>, ReportError> {
+    let mut block = server.service().block(hash_or_height).await?;
+    if server.strip_network {
+        block.txs.iter_mut().for_each(convert::strip_network);
+    }
+    Ok(Protobuf(block))
+}
+
+/// Like `handle_block`, but without txs.
+async fn handle_block_info(
+    Path(hash_or_height): Path<String>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::BlockSummary>, ReportError> {
+    let slp_indexer = server.slp_indexer.read().await;
+    let block = block_by_hash_or_height(&slp_indexer, hash_or_height)?;
+    let block_stats = slp_indexer.blocks().block_stats(&block)?;
     let raw_header = slp_indexer
         .blocks()
         .raw_header(&block)?
-        .expect("Inconsistent index");
+        .ok_or_else(|| Report::from(InconsistentNoSuchRawHeader(block.hash.clone())))?;
+    let header_details = slp_indexer.blocks().header_details(&block, &raw_header)?;
+    Ok(Protobuf(proto::BlockSummary {
+        block_info: Some(block_to_info_proto(&block, &block_stats)),
+        block_details: Some(proto::BlockDetails {
+            version: header_details.version,
+            merkle_root: header_details.merkle_root.as_slice().to_vec(),
+            nonce: header_details.nonce,
+            median_timestamp: header_details.median_timestamp,
+        }),
+        raw_header,
+    }))
+}
+
+async fn handle_block_inputs(
+    Path(hash_or_height): Path<String>,
+    Query(query_params): Query<HashMap<String, String>>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::BlockInputs>, ReportError> {
+    let (page_num, page_size) = parse_page_params(
+        &query_params,
+        MAX_BLOCK_INPUTS_PAGE_SIZE,
+        MAX_BLOCK_INPUTS_PAGE_SIZE,
+    )?;
+    let slp_indexer = server.slp_indexer.read().await;
+    let block = block_by_hash_or_height(&slp_indexer, hash_or_height)?;
     let txs = slp_indexer.blocks().block_txs_by_height(block.height)?;
-    let txs = txs.into_iter().map(rich_tx_to_proto).collect();
-    let bitcoind_rpc = slp_indexer.bitcoind_rpc().clone();
     std::mem::drop(slp_indexer);
-    let block_header_json = bitcoind_rpc
-        .cmd_json("getblockheader", &[block.hash.to_string().into()])
-        .await?;
-    let version = block_header_json["version"]
-        .as_i32()
-        .ok_or(BitcoindBadJson("Missing/ill-typed version"))?;
-    let merkle_root = block_header_json["merkleroot"]
-        .as_str()
-        .ok_or(BitcoindBadJson("Missing/ill-typed merkleroot"))?;
-    let merkle_root =
-        Sha256d::from_hex_be(merkle_root).wrap_err(BitcoindBadJson("Invalid merkleroot length"))?;
-    let nonce = block_header_json["nonce"]
-        .as_u64()
-        .ok_or(BitcoindBadJson("Missing/ill-typed nonce"))?;
-    let median_timestamp = block_header_json["mediantime"]
-        .as_i64()
-        .ok_or(BitcoindBadJson("Missing/ill-typed mediantime"))?;
-    let block_details = Some(proto::BlockDetails {
-        version,
-        merkle_root: merkle_root.as_slice().to_vec(),
-        nonce,
-        median_timestamp,
-    });
-    Ok(Protobuf(proto::Block {
-        block_info,
-        block_details,
-        raw_header,
-        txs,
+    let page_start = page_num.saturating_mul(page_size);
+    let page_end = page_start.saturating_add(page_size);
+    let mut inputs = Vec::new();
+    let mut num_inputs = 0;
+    // Skip the coinbase tx, it doesn't spend any coins
+    for tx in txs.into_iter().skip(1) {
+        let tx_start = num_inputs;
+        num_inputs += tx.tx.inputs().len();
+        // Only convert the txs with inputs on the requested page
+        if num_inputs <= page_start || tx_start >= page_end {
+            continue;
+        }
+        let tx = rich_tx_to_proto(tx);
+        let txid = tx.txid;
+        inputs.extend(
+            tx.inputs
+                .into_iter()
+                .enumerate()
+                .skip(page_start.saturating_sub(tx_start))
+                .take(page_end.min(num_inputs) - page_start.max(tx_start))
+                .map(|(input_idx, input)| proto::BlockInput {
+                    txid: txid.clone(),
+                    input_idx: input_idx as u32,
+                    input: Some(input),
+                }),
+        );
+    }
+    Ok(Protobuf(proto::BlockInputs {
+        inputs,
+        num_pages: ((num_inputs + page_size - 1) / page_size) as u32,
+    }))
+}
+
+async fn handle_block_burns(
+    Path(hash_or_height): Path<String>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::BlockTokenBurns>, ReportError> {
+    let slp_indexer = server.slp_indexer.read().await;
+    let block = block_by_hash_or_height(&slp_indexer, hash_or_height)?;
+    let burns = slp_indexer.blocks().token_burns(block.height)?;
+    Ok(Protobuf(proto::BlockTokenBurns {
+        burns: burns
+            .into_iter()
+            .map(|burn| proto::BlockTokenBurn {
+                token_id: burn.token_id.as_slice_be().to_vec(),
+                amount: burn.amount.to_string(),
+                num_mint_batons: burn.num_mint_batons as u32,
+                txids: burn
+                    .txids
+                    .iter()
+                    .map(|txid| txid.as_slice().to_vec())
+                    .collect(),
+            })
+            .collect(),
+    }))
+}
+
+async fn handle_block_coinbase_script(
+    Path(hash_or_height): Path<String>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::CoinbaseScript>, ReportError> {
+    let slp_indexer = server.slp_indexer.read().await;
+    let block = block_by_hash_or_height(&slp_indexer, hash_or_height)?;
+    let script = slp_indexer.blocks().coinbase_script(&block)?;
+    let script = script.bytecode().to_vec();
+    Ok(Protobuf(proto::CoinbaseScript {
+        utf8: String::from_utf8(script.clone()).unwrap_or_default(),
+        script,
     }))
 }
 
+async fn handle_block_tx(
+    Path((hash_or_height, index)): Path<(String, usize)>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::Tx>, ReportError> {
+    let slp_indexer = server.slp_indexer.read().await;
+    let block = block_by_hash_or_height(&slp_indexer, hash_or_height.clone())?;
+    let rich_tx = slp_indexer
+        .blocks()
+        .block_tx_by_index(block.height, index)?
+        .ok_or(BlockTxNotFound {
+            hash_or_height,
+            index,
+        })?;
+    Ok(Protobuf(server_tx_to_proto(rich_tx, server.strip_network)))
+}
+
 async fn handle_tx(
     Path(txid): Path<String>,
+    Query(query_params): Query<HashMap<String, String>>,
     Extension(server): Extension<ChronikServer>,
 ) -> Result<Protobuf<proto::Tx>, ReportError> {
     let txid = Sha256d::from_hex_be(&txid).map_err(|err| InvalidField {
         name: "txid",
         value: err.to_string(),
     })?;
-    let indexer = server.slp_indexer.read().await;
-    let rich_tx = indexer
-        .txs()
-        .rich_tx_by_txid(&txid)
-        .map_err(ReportError)?
-        .ok_or(TxNotFound(txid))?;
-    Ok(Protobuf(rich_tx_to_proto(rich_tx)))
-}
+    let include_raw = match query_params.get("include_raw") {
+        Some(include_raw) => include_raw.parse().map_err(|_| InvalidField {
+            name: "include_raw",
+            value: include_raw.clone(),
+        })?,
+        None => false,
+    };
+    let strip_input_scripts = parse_strip_input_scripts(&query_params)?;
+    let mut tx = server.service().tx(txid, include_raw).await?;
+    if strip_input_scripts {
+        convert::strip_input_scripts(&mut tx);
+    }
+    if server.strip_network {
+        convert::strip_network(&mut tx);
+    }
+    Ok(Protobuf(tx))
+}
+
+async fn handle_tx_conflict_status(
+    Path(txid): Path<String>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::ConflictStatus>, ReportError> {
+    let txid = Sha256d::from_hex_be(&txid).map_err(|err| InvalidField {
+        name: "txid",
+        value: err.to_string(),
+    })?;
+    let indexer = server.slp_indexer.read().await;
+    if indexer.db_mempool().tx(&txid).is_none() {
+        let tx_reader = indexer.db().txs().map_err(ReportError)?;
+        if tx_reader
+            .tx_num_by_txid(&txid)
+            .map_err(ReportError)?
+            .is_none()
+        {
+            return Err(TxNotFound(txid).into());
+        }
+    }
+    let conflicting_txid = indexer.mempool_conflict(&txid);
+    Ok(Protobuf(proto::ConflictStatus {
+        is_conflicting: conflicting_txid.is_some(),
+        conflicting_txid: conflicting_txid
+            .map(|txid| txid.as_slice().to_vec())
+            .unwrap_or_default(),
+    }))
+}
+
+async fn handle_tx_location(
+    Path(txid): Path<String>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::TxLocation>, ReportError> {
+    let txid = Sha256d::from_hex_be(&txid).map_err(|err| InvalidField {
+        name: "txid",
+        value: err.to_string(),
+    })?;
+    let indexer = server.slp_indexer.read().await;
+    match indexer.txs().tx_location(&txid)? {
+        Some(tx_location) => Ok(Protobuf(proto::TxLocation {
+            block_height: tx_location.block.height,
+            block_hash: tx_location.block.hash.as_slice().to_vec(),
+            index_in_block: tx_location.index_in_block as u32,
+            tx_num: tx_location.tx_num,
+        })),
+        None if indexer.db_mempool().tx(&txid).is_some() => Ok(Protobuf(proto::TxLocation {
+            block_height: -1,
+            ..Default::default()
+        })),
+        None => Err(TxNotFound(txid).into()),
+    }
+}
+
+async fn handle_tx_merkle_proof(
+    Path(txid): Path<String>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::MerkleProof>, ReportError> {
+    let txid = Sha256d::from_hex_be(&txid).map_err(|err| InvalidField {
+        name: "txid",
+        value: err.to_string(),
+    })?;
+    let indexer = server.slp_indexer.read().await;
+    let block = indexer
+        .txs()
+        .rich_tx_by_txid(&txid)?
+        .and_then(|rich_tx| rich_tx.block)
+        .ok_or_else(|| TxNotFound(txid.clone()))?;
+    let block_txids = indexer
+        .blocks()
+        .block_txs_by_height(block.height)?
+        .into_iter()
+        .map(|tx| tx.txid)
+        .collect::<Vec<_>>();
+    let index = block_txids
+        .iter()
+        .position(|block_txid| block_txid == &txid)
+        .ok_or_else(|| TxNotFound(txid.clone()))?;
+    Ok(Protobuf(proto::MerkleProof {
+        block_hash: block.hash.as_slice().to_vec(),
+        index: index as u32,
+        branch: merkle::merkle_branch(&block_txids, index)
+            .iter()
+            .map(|hash| hash.as_slice().to_vec())
+            .collect(),
+    }))
+}
 
 async fn handle_raw_tx(
     Path(txid): Path<String>,
@@ -328,20 +1208,557 @@ async fn handle_raw_tx(
     Ok(raw_tx.to_vec())
 }
 
+async fn handle_raw_block(
+    Path(hash_or_height): Path<String>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Vec<u8>, ReportError> {
+    let slp_indexer = server.slp_indexer.read().await;
+    let block = block_by_hash_or_height(&slp_indexer, hash_or_height)?;
+    let node_status = Arc::clone(slp_indexer.node_status());
+    let bitcoind = slp_indexer.bitcoind_rpc().clone();
+    // Don't hold up indexing while waiting for bitcoind
+    std::mem::drop(slp_indexer);
+    Ok(raw_block(&node_status, &bitcoind, &block.hash).await?)
+}
+
+async fn handle_debug_tx_num(
+    Path(txid): Path<String>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::DebugTxNum>, ReportError> {
+    let txid = Sha256d::from_hex_be(&txid).map_err(|err| InvalidField {
+        name: "txid",
+        value: err.to_string(),
+    })?;
+    let indexer = server.slp_indexer.read().await;
+    let (tx_num, block_tx) = indexer
+        .db()
+        .txs()?
+        .tx_and_num_by_txid(&txid)?
+        .ok_or_else(|| TxNotFound(txid.clone()))?;
+    let slp_reader = indexer.db().slp()?;
+    let token_num = match slp_reader.slp_data_by_tx_num(tx_num)? {
+        Some(slp_data) => slp_reader.token_num_by_id(&slp_data.slp_tx_data.token_id)?,
+        None => None,
+    };
+    let entry = block_tx.entry;
+    Ok(Protobuf(proto::DebugTxNum {
+        tx_num,
+        block_height: block_tx.block_height,
+        data_pos: entry.data_pos,
+        tx_size: entry.tx_size,
+        undo_pos: entry.undo_pos,
+        undo_size: entry.undo_size,
+        time_first_seen: entry.time_first_seen,
+        is_coinbase: entry.is_coinbase,
+        token_num: token_num.map(i64::from).unwrap_or(-1),
+    }))
+}
+
+async fn handle_admin_growth(
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::DbGrowth>, ReportError> {
+    let indexer = server.slp_indexer.read().await;
+    let write_sizes = indexer.db().block_write_sizes();
+    Ok(Protobuf(proto::DbGrowth {
+        num_blocks: write_sizes.num_blocks() as u32,
+        avg_bytes_per_block: write_sizes.avg_bytes_per_block(),
+        writers: write_sizes
+            .avg_bytes_per_writer()
+            .into_iter()
+            .map(|(name, avg_bytes_per_block)| proto::DbGrowthWriter {
+                name: name.to_string(),
+                avg_bytes_per_block,
+            })
+            .collect(),
+    }))
+}
+
 async fn handle_token(
     Path(token_id): Path<String>,
+    Query(query_params): Query<HashMap<String, String>>,
     Extension(server): Extension<ChronikServer>,
 ) -> Result<Protobuf<proto::Token>, ReportError> {
     let token_id = TokenId::from_token_id_hex(&token_id).map_err(|err| InvalidField {
         name: "token_id",
         value: err.to_string(),
     })?;
+    let include_genesis_raw = match query_params.get("include_genesis_raw") {
+        Some(include_genesis_raw) => include_genesis_raw.parse().map_err(|_| InvalidField {
+            name: "include_genesis_raw",
+            value: include_genesis_raw.clone(),
+        })?,
+        None => false,
+    };
     let indexer = server.slp_indexer.read().await;
     let rich_tx = indexer
         .txs()
         .rich_tx_by_txid(token_id.hash())
         .map_err(ReportError)?
         .ok_or_else(|| TokenTxidNotFound(token_id.hash().clone()))?;
+    let mut token = token_to_proto(&indexer, rich_tx)?;
+    if include_genesis_raw {
+        let genesis_raw = indexer
+            .txs()
+            .raw_tx_by_id(token_id.hash())?
+            .ok_or_else(|| TokenTxidNotFound(token_id.hash().clone()))?;
+        token.genesis_raw = genesis_raw.to_vec();
+    }
+    Ok(Protobuf(token))
+}
+
+/// Only the token type and GENESIS info of a token, e.g. for wallets rendering token amounts.
+/// Cheaper than `/token/:token_id`, as it skips the token's stats; the GENESIS info of a token
+/// never changes, so clients may cache it.
+async fn handle_token_genesis(
+    Path(token_id): Path<String>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<impl IntoResponse, ReportError> {
+    let token_id = TokenId::from_token_id_hex(&token_id).map_err(|err| InvalidField {
+        name: "token_id",
+        value: err.to_string(),
+    })?;
+    let indexer = server.slp_indexer.read().await;
+    let slp_tx_data = indexer
+        .tokens()
+        .genesis_slp_tx_data(&token_id)?
+        .ok_or_else(|| TokenTxidNotFound(token_id.hash().clone()))?;
+    // The GENESIS can only be cached once no reorg can drop it anymore
+    let is_final = match indexer.db().txs()?.by_txid(token_id.hash())? {
+        Some(block_tx) => {
+            let tip_height = indexer.blocks().height()?;
+            (tip_height - block_tx.block_height) as usize >= indexer.max_reorg_depth()
+        }
+        None => false,
+    };
+    let cache_control = match is_final {
+        true => "public, max-age=31536000, immutable",
+        false => "no-cache",
+    };
+    Ok((
+        [(CACHE_CONTROL, cache_control)],
+        Protobuf(slp_tx_data_to_proto(Box::new(slp_tx_data))),
+    ))
+}
+
+/// Token whose mint baton is at the given outpoint, e.g. for minting UIs to check a baton
+/// before minting with it.
+async fn handle_mint_baton(
+    Path((txid, out_idx)): Path<(String, String)>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::Token>, ReportError> {
+    let outpoint = OutPoint {
+        txid: Sha256d::from_hex_be(&txid).map_err(|err| InvalidField {
+            name: "txid",
+            value: err.to_string(),
+        })?,
+        out_idx: out_idx.parse().map_err(|_| InvalidField {
+            name: "out_idx",
+            value: out_idx.clone(),
+        })?,
+    };
+    let outpoint_str = format!("{}:{}", outpoint.txid, outpoint.out_idx);
+    let indexer = server.slp_indexer.read().await;
+    let utxos = indexer.utxos();
+    let utxo_state = utxos
+        .utxo_states(std::slice::from_ref(&outpoint))?
+        .remove(0);
+    match utxo_state.state {
+        UtxoStateVariant::NoSuchTx => return Err(TxNotFound(outpoint.txid).into()),
+        UtxoStateVariant::NoSuchOutput => return Err(OutputNotFound(outpoint_str).into()),
+        _ => {}
+    }
+    let slp_output = match utxos.slp_output(&outpoint)? {
+        Some(slp_output) if slp_output.token.is_mint_baton => slp_output,
+        _ => return Err(NotAMintBaton(outpoint_str).into()),
+    };
+    // Without the spends index, batons spent in a block can't be detected
+    if utxo_state.state == UtxoStateVariant::Spent {
+        return Err(MintBatonSpent(outpoint_str).into());
+    }
+    let rich_tx = indexer
+        .txs()
+        .rich_tx_by_txid(slp_output.token_id.hash())
+        .map_err(ReportError)?
+        .ok_or_else(|| TokenTxidNotFound(slp_output.token_id.hash().clone()))?;
+    Ok(Protobuf(token_to_proto(&indexer, rich_tx)?))
+}
+
+/// Converts between human-readable amounts (`?display=`) and base units (`?base_units=`) of
+/// the token, using the decimals of its GENESIS.
+async fn handle_token_parse_amount(
+    Path(token_id): Path<String>,
+    Query(query_params): Query<HashMap<String, String>>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::TokenAmount>, ReportError> {
+    let token_id = TokenId::from_token_id_hex(&token_id).map_err(|err| InvalidField {
+        name: "token_id",
+        value: err.to_string(),
+    })?;
+    let indexer = server.slp_indexer.read().await;
+    let decimals = indexer
+        .tokens()
+        .decimals(&token_id)?
+        .ok_or_else(|| TokenTxidNotFound(token_id.hash().clone()))?;
+    let base_units = match (query_params.get("display"), query_params.get("base_units")) {
+        (Some(display), None) => parse_token_amount(display, decimals)?,
+        (None, Some(base_units)) => base_units.parse().map_err(|_| InvalidField {
+            name: "base_units",
+            value: base_units.clone(),
+        })?,
+        _ => return Err(InvalidAmountQuery.into()),
+    };
+    Ok(Protobuf(proto::TokenAmount {
+        base_units,
+        display: format_token_amount(base_units, decimals)?,
+        decimals,
+    }))
+}
+
+async fn handle_token_spends(
+    Path(token_id): Path<String>,
+    Query(query_params): Query<HashMap<String, String>>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::TokenSpendsPage>, ReportError> {
+    let token_id = TokenId::from_token_id_hex(&token_id).map_err(|err| InvalidField {
+        name: "token_id",
+        value: err.to_string(),
+    })?;
+    let (page_num, page_size) =
+        parse_page_params(&query_params, DEFAULT_PAGE_SIZE, MAX_HISTORY_PAGE_SIZE)?;
+    let indexer = server.slp_indexer.read().await;
+    if indexer.txs().rich_tx_by_txid(token_id.hash())?.is_none() {
+        return Err(TokenTxidNotFound(token_id.hash().clone()).into());
+    }
+    let tokens = indexer.tokens();
+    let spends = tokens.rev_spends_page(&token_id, page_num, page_size)?;
+    let num_pages = tokens.rev_spends_num_pages(&token_id, page_size)?;
+    Ok(Protobuf(proto::TokenSpendsPage {
+        spends: spends
+            .into_iter()
+            .map(|spend| proto::TokenSpend {
+                tx: Some(server_tx_to_proto(spend.tx, server.strip_network)),
+                is_burn: spend.is_burn,
+            })
+            .collect(),
+        num_pages: num_pages as u32,
+    }))
+}
+
+async fn handle_token_history(
+    Path(token_id): Path<String>,
+    Query(query_params): Query<HashMap<String, String>>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::TxHistoryPage>, ReportError> {
+    let token_id = TokenId::from_token_id_hex(&token_id).map_err(|err| InvalidField {
+        name: "token_id",
+        value: err.to_string(),
+    })?;
+    let (page_num, page_size) =
+        parse_page_params(&query_params, DEFAULT_PAGE_SIZE, MAX_HISTORY_PAGE_SIZE)?;
+    let indexer = server.slp_indexer.read().await;
+    if indexer.txs().rich_tx_by_txid(token_id.hash())?.is_none() {
+        return Err(TokenTxidNotFound(token_id.hash().clone()).into());
+    }
+    let (txs, num_pages) = indexer
+        .tokens()
+        .rev_history_page(&token_id, page_num, page_size)?;
+    Ok(Protobuf(proto::TxHistoryPage {
+        txs: txs
+            .into_iter()
+            .map(|tx| server_tx_to_proto(tx, server.strip_network))
+            .collect(),
+        num_pages: num_pages as u32,
+    }))
+}
+
+async fn handle_token_mints(
+    Path(token_id): Path<String>,
+    Query(query_params): Query<HashMap<String, String>>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::TokenMintsPage>, ReportError> {
+    let token_id = TokenId::from_token_id_hex(&token_id).map_err(|err| InvalidField {
+        name: "token_id",
+        value: err.to_string(),
+    })?;
+    let (page_num, page_size) =
+        parse_page_params(&query_params, DEFAULT_PAGE_SIZE, MAX_HISTORY_PAGE_SIZE)?;
+    let indexer = server.slp_indexer.read().await;
+    if indexer.txs().rich_tx_by_txid(token_id.hash())?.is_none() {
+        return Err(TokenTxidNotFound(token_id.hash().clone()).into());
+    }
+    let (mints, num_pages) = indexer
+        .tokens()
+        .rev_mints_page(&token_id, page_num, page_size)?;
+    Ok(Protobuf(proto::TokenMintsPage {
+        mints: mints
+            .into_iter()
+            .map(|mint| proto::TokenMint {
+                tx: Some(server_tx_to_proto(mint.tx, server.strip_network)),
+                amount: mint.amount.to_string(),
+            })
+            .collect(),
+        num_pages: num_pages as u32,
+    }))
+}
+
+/// Scripts holding the token, by the sum of their confirmed token UTXOs. Aggregates all of the
+/// token's UTXOs for every page, so this is a heavy query for widely held tokens.
+async fn handle_token_holders(
+    Path(token_id): Path<String>,
+    Query(query_params): Query<HashMap<String, String>>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::TokenHoldersPage>, ReportError> {
+    let token_id = TokenId::from_token_id_hex(&token_id).map_err(|err| InvalidField {
+        name: "token_id",
+        value: err.to_string(),
+    })?;
+    let (page_num, page_size) =
+        parse_page_params(&query_params, DEFAULT_PAGE_SIZE, MAX_HISTORY_PAGE_SIZE)?;
+    let indexer = server.slp_indexer.read().await;
+    if indexer.txs().rich_tx_by_txid(token_id.hash())?.is_none() {
+        return Err(TokenTxidNotFound(token_id.hash().clone()).into());
+    }
+    let (holders, num_pages) = indexer
+        .tokens()
+        .holders_page(&token_id, page_num, page_size)?;
+    Ok(Protobuf(proto::TokenHoldersPage {
+        holders: holders
+            .into_iter()
+            .map(|holder| proto::TokenHolder {
+                script_type: server
+                    .script_types
+                    .script_type(holder.script_payload.payload_prefix)
+                    .unwrap_or_default()
+                    .to_string(),
+                payload: holder.script_payload.payload_data,
+                amount: holder.amount.to_string(),
+            })
+            .collect(),
+        num_pages: num_pages as u32,
+    }))
+}
+
+async fn handle_tokens(
+    Query(query_params): Query<HashMap<String, String>>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::TokenListings>, ReportError> {
+    let count: usize = match query_params.get("count") {
+        Some(count) => match count.parse() {
+            Ok(count) if count > 0 => count,
+            _ => {
+                return Err(InvalidField {
+                    name: "count",
+                    value: count.clone(),
+                }
+                .into())
+            }
+        },
+        None => DEFAULT_PAGE_SIZE,
+    };
+    if count > server.max_token_listings {
+        return Err(PageSizeTooLarge.into());
+    }
+    let start_token_num = match query_params.get("start") {
+        Some(start) => start.parse().map_err(|_| InvalidField {
+            name: "start",
+            value: start.clone(),
+        })?,
+        None => 0,
+    };
+    let indexer = server.slp_indexer.read().await;
+    let (tokens, next_start) = indexer.tokens().tokens_page(start_token_num, count)?;
+    Ok(Protobuf(proto::TokenListings {
+        tokens: tokens.iter().map(token_listing_to_proto).collect(),
+        next_start,
+    }))
+}
+
+async fn handle_recent_tokens(
+    Query(query_params): Query<HashMap<String, String>>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::Tokens>, ReportError> {
+    let count: usize = match query_params.get("count") {
+        Some(count) => match count.parse() {
+            Ok(count) if count > 0 => count,
+            _ => {
+                return Err(InvalidField {
+                    name: "count",
+                    value: count.clone(),
+                }
+                .into())
+            }
+        },
+        None => DEFAULT_PAGE_SIZE,
+    };
+    if count > server.max_token_listings {
+        return Err(PageSizeTooLarge.into());
+    }
+    let before_token_num = match query_params.get("before") {
+        Some(before) => Some(before.parse().map_err(|_| InvalidField {
+            name: "before",
+            value: before.clone(),
+        })?),
+        None => None,
+    };
+    let indexer = server.slp_indexer.read().await;
+    let (genesis_txs, next_before) = indexer.tokens().recent_tokens(before_token_num, count)?;
+    let tokens = genesis_txs
+        .into_iter()
+        .map(|rich_tx| token_to_proto(&indexer, rich_tx))
+        .collect::<Result<Vec<_>, Report>>()?;
+    Ok(Protobuf(proto::Tokens {
+        tokens,
+        next_before,
+    }))
+}
+
+async fn handle_tokens_stats(
+    Extension(server): Extension<ChronikServer>,
+    Protobuf(request): Protobuf<proto::TokensStatsRequest>,
+) -> Result<Protobuf<proto::TokensStatsResponse>, ReportError> {
+    if request.token_ids.len() > MAX_TOKENS_STATS {
+        return Err(PageSizeTooLarge.into());
+    }
+    let token_ids = request
+        .token_ids
+        .iter()
+        .map(|token_id| {
+            TokenId::from_slice_be(token_id).map_err(|err| InvalidField {
+                name: "token_id",
+                value: err.to_string(),
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let slp_indexer = server.slp_indexer.read().await;
+    let tokens = slp_indexer.tokens();
+    let all_token_stats = tokens.token_stats_by_token_ids(&token_ids)?;
+    let mut response = proto::TokensStatsResponse::default();
+    for (token_id, token_stats) in token_ids.iter().zip(all_token_stats) {
+        match token_stats {
+            Some(token_stats) => response.token_stats.push(proto::TokenStatsEntry {
+                token_id: token_id.as_slice_be().to_vec(),
+                total_minted: token_stats.total_minted.to_string(),
+                total_burned: token_stats.total_burned.to_string(),
+                circulating_supply: (token_stats.total_minted - token_stats.total_burned)
+                    .to_string(),
+                supply_overflowed: token_stats.supply_overflowed,
+                num_holders: tokens.num_holders(token_id)? as u64,
+            }),
+            None => response
+                .missing_token_ids
+                .push(token_id.as_slice_be().to_vec()),
+        }
+    }
+    Ok(Protobuf(response))
+}
+
+async fn handle_slp_stats(
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::SlpStats>, ReportError> {
+    let slp_stats = server.slp_indexer.read().await.tokens().slp_stats()?;
+    Ok(Protobuf(proto::SlpStats {
+        num_tokens: slp_stats.num_tokens,
+        num_token_types: slp_stats.num_token_types,
+        num_genesis_txs: slp_stats.num_genesis_txs,
+        num_mint_txs: slp_stats.num_mint_txs,
+        num_send_txs: slp_stats.num_send_txs,
+        num_burn_txs: slp_stats.num_burn_txs,
+    }))
+}
+
+/// `rich_tx_to_proto`, leaving out `network` if the server is configured with `strip_network`.
+fn server_tx_to_proto(rich_tx: RichTx, strip_network: bool) -> proto::Tx {
+    let mut tx = rich_tx_to_proto(rich_tx);
+    if strip_network {
+        convert::strip_network(&mut tx);
+    }
+    tx
+}
+
+/// Converts the txs of a history page; `tip_height` is only set if confirmations are requested.
+fn txs_to_proto(
+    txs: Vec<RichTx>,
+    strip_input_scripts: bool,
+    strip_network: bool,
+    tip_height: Option<BlockHeight>,
+) -> Vec<proto::Tx> {
+    txs.into_iter()
+        .map(|rich_tx| {
+            let block_height = rich_tx.block.as_ref().map(|block| block.height);
+            let mut tx = server_tx_to_proto(rich_tx, strip_network);
+            if strip_input_scripts {
+                convert::strip_input_scripts(&mut tx);
+            }
+            if let Some(tip_height) = tip_height {
+                tx.confirmations = convert::num_confirmations(block_height, tip_height);
+            }
+            tx
+        })
+        .collect()
+}
+
+/// `(page_num, page_size)` from the `page` and `page_size` query params.
+fn parse_page_params(
+    query_params: &HashMap<String, String>,
+    default_page_size: usize,
+    max_page_size: usize,
+) -> Result<(usize, usize), ReportError> {
+    let page_size: usize = match query_params.get("page_size") {
+        Some(page_size) => match page_size.parse() {
+            Ok(page_size) if page_size > 0 => page_size,
+            _ => {
+                return Err(InvalidField {
+                    name: "page_size",
+                    value: page_size.clone(),
+                }
+                .into())
+            }
+        },
+        None => default_page_size,
+    };
+    if page_size > max_page_size {
+        return Err(PageSizeTooLarge.into());
+    }
+    let page_num: usize = match query_params.get("page") {
+        Some(page_num) => page_num.parse().map_err(|_| InvalidField {
+            name: "page",
+            value: page_num.clone(),
+        })?,
+        None => 0,
+    };
+    Ok((page_num, page_size))
+}
+
+fn parse_strip_input_scripts(query_params: &HashMap<String, String>) -> Result<bool, ReportError> {
+    match query_params.get("strip_input_scripts") {
+        Some(strip_input_scripts) => Ok(strip_input_scripts.parse().map_err(|_| InvalidField {
+            name: "strip_input_scripts",
+            value: strip_input_scripts.clone(),
+        })?),
+        None => Ok(false),
+    }
+}
+
+/// Tip height to compute confirmations against if `?include_confirmations=true` is set.
+fn parse_include_confirmations(
+    query_params: &HashMap<String, String>,
+    slp_indexer: &SlpIndexer,
+) -> Result<Option<BlockHeight>, ReportError> {
+    let include_confirmations = match query_params.get("include_confirmations") {
+        Some(include_confirmations) => include_confirmations.parse().map_err(|_| InvalidField {
+            name: "include_confirmations",
+            value: include_confirmations.clone(),
+        })?,
+        None => false,
+    };
+    match include_confirmations {
+        true => Ok(Some(slp_indexer.blocks().height()?)),
+        false => Ok(None),
+    }
+}
+
+fn token_to_proto(indexer: &SlpIndexer, rich_tx: RichTx) -> Result<proto::Token, Report> {
+    let token_id = TokenId::new(rich_tx.txid.clone());
     let slp_tx_data = rich_tx
         .slp_tx_data
         .ok_or_else(|| TokenTxNotGenesis(token_id.hash().clone()))?;
@@ -358,12 +1775,13 @@ async fn handle_token(
         .output_tokens
         .iter()
         .any(|token| token.is_mint_baton);
-    Ok(Protobuf(proto::Token {
+    let (first_active_height, last_active_height) = indexer
+        .tokens()
+        .active_heights(&token_id)?
+        .unwrap_or((-1, -1));
+    Ok(proto::Token {
         slp_tx_data: Some(slp_tx_data_to_proto(slp_tx_data)),
-        token_stats: Some(proto::TokenStats {
-            total_minted: token_stats.total_minted.to_string(),
-            total_burned: token_stats.total_burned.to_string(),
-        }),
+        token_stats: Some(token_stats_to_proto(&token_stats)),
         block: rich_tx.block.map(|block| proto::BlockMetadata {
             height: block.height,
             hash: block.hash.as_slice().to_vec(),
@@ -373,7 +1791,10 @@ async fn handle_token(
         initial_token_quantity,
         contains_baton,
         network: network_to_proto(rich_tx.network).into(),
-    }))
+        genesis_raw: vec![],
+        first_active_height,
+        last_active_height,
+    })
 }
 
 async fn handle_script_history(
@@ -385,48 +1806,158 @@ async fn handle_script_history(
         name: "script payload",
         value: payload.clone(),
     })?;
-    let prefix = parse_payload_prefix(script_type, payload.len())?;
-    let page_size: usize = match query_params.get("page_size") {
-        Some(page_size) => page_size.parse().map_err(|_| InvalidField {
-            name: "page_size",
-            value: page_size.clone(),
-        })?,
-        None => DEFAULT_PAGE_SIZE,
-    };
-    if page_size > MAX_HISTORY_PAGE_SIZE {
-        return Err(PageSizeTooLarge.into());
-    }
-    let page_num: usize = match query_params.get("page") {
-        Some(page_num) => page_num.parse().map_err(|_| InvalidField {
-            name: "page",
-            value: page_num.clone(),
-        })?,
-        None => 0,
-    };
+    let prefix = parse_payload_prefix(&server.script_types, script_type, payload.len())?;
+    let (page_num, page_size) =
+        parse_page_params(&query_params, DEFAULT_PAGE_SIZE, MAX_HISTORY_PAGE_SIZE)?;
+    let (from_height, to_height) = parse_height_range(&query_params)?;
+    let strip_input_scripts = parse_strip_input_scripts(&query_params)?;
     let slp_indexer = server.slp_indexer.read().await;
+    let tip_height = parse_include_confirmations(&query_params, &slp_indexer)?;
     let script_history = slp_indexer.script_history();
-    let txs = script_history.rev_history_page(prefix, &payload, page_num, page_size)?;
-    let num_pages = script_history.rev_history_num_pages(prefix, &payload, page_size)?;
+    let (txs, num_pages) = match (from_height, to_height) {
+        (None, None) => (
+            script_history.rev_history_page(prefix, &payload, page_num, page_size)?,
+            script_history.rev_history_num_pages(prefix, &payload, page_size)?,
+        ),
+        (from_height, to_height) => {
+            let from_height = from_height.unwrap_or(0);
+            let to_height = to_height.unwrap_or(BlockHeight::MAX);
+            script_history.rev_history_page_in_height_range(
+                prefix,
+                &payload,
+                from_height,
+                to_height,
+                page_num,
+                page_size,
+            )?
+        }
+    };
+    Ok(Protobuf(proto::TxHistoryPage {
+        txs: txs_to_proto(txs, strip_input_scripts, server.strip_network, tip_height),
+        num_pages: num_pages as u32,
+    }))
+}
+
+async fn handle_protocol_history(
+    Path(lokad_id): Path<String>,
+    Query(query_params): Query<HashMap<String, String>>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::TxHistoryPage>, ReportError> {
+    let lokad_id = hex::decode(&lokad_id).map_err(|_| InvalidField {
+        name: "lokad_id",
+        value: lokad_id.clone(),
+    })?;
+    let (page_num, page_size) =
+        parse_page_params(&query_params, DEFAULT_PAGE_SIZE, MAX_HISTORY_PAGE_SIZE)?;
+    let strip_input_scripts = parse_strip_input_scripts(&query_params)?;
+    let slp_indexer = server.slp_indexer.read().await;
+    let tip_height = parse_include_confirmations(&query_params, &slp_indexer)?;
+    let lokad_history = slp_indexer.lokad_history();
+    let txs = lokad_history.rev_history_page(&lokad_id, page_num, page_size)?;
+    let num_pages = lokad_history.rev_history_num_pages(&lokad_id, page_size)?;
     Ok(Protobuf(proto::TxHistoryPage {
-        txs: txs.into_iter().map(rich_tx_to_proto).collect(),
+        txs: txs_to_proto(txs, strip_input_scripts, server.strip_network, tip_height),
         num_pages: num_pages as u32,
     }))
 }
 
+/// Scripts (as type and payload) a P2PKH or P2SH CashAddress is indexed under, so clients can
+/// query them without decoding addresses themselves.
+async fn handle_address_scripts(
+    Path(address): Path<String>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::AddressScripts>, ReportError> {
+    let cash_address =
+        CashAddress::parse_cow(address.as_str().into()).map_err(|_| InvalidField {
+            name: "address",
+            value: address.clone(),
+        })?;
+    let script = cash_address.to_script();
+    let slp_indexer = server.slp_indexer.read().await;
+    let scripts = slp_indexer
+        .db()
+        .script_filter()
+        .script_payloads(&script)
+        .into_iter()
+        .filter_map(|payload_state| {
+            let payload = payload_state.payload;
+            let script_type = server.script_types.script_type(payload.payload_prefix)?;
+            let path = format!(
+                "/script/{}/{}",
+                script_type,
+                hex::encode(&payload.payload_data),
+            );
+            Some(proto::AddressScript {
+                script_type: script_type.to_string(),
+                history_path: format!("{}/history", path),
+                utxos_path: format!("{}/utxos", path),
+                payload: payload.payload_data,
+            })
+        })
+        .collect();
+    Ok(Protobuf(proto::AddressScripts { scripts }))
+}
+
 async fn handle_script_utxos(
     Path((script_type, payload)): Path<(String, String)>,
+    Query(query_params): Query<HashMap<String, String>>,
     Extension(server): Extension<ChronikServer>,
 ) -> Result<Protobuf<proto::Utxos>, ReportError> {
     let payload = hex::decode(&payload).map_err(|_| InvalidField {
         name: "payload",
         value: payload.clone(),
     })?;
-    let prefix = parse_payload_prefix(script_type, payload.len())?;
+    let prefix = parse_payload_prefix(&server.script_types, script_type, payload.len())?;
+    let min_confirmations: u32 = match query_params.get("min_confirmations") {
+        Some(min_confirmations) => min_confirmations.parse().map_err(|_| InvalidField {
+            name: "min_confirmations",
+            value: min_confirmations.clone(),
+        })?,
+        None => 0,
+    };
+    let has_token: Option<bool> = match query_params.get("has_token") {
+        Some(has_token) => Some(has_token.parse().map_err(|_| InvalidField {
+            name: "has_token",
+            value: has_token.clone(),
+        })?),
+        None => None,
+    };
+    let token_id = match query_params.get("token_id") {
+        Some(token_id) => {
+            Some(
+                TokenId::from_token_id_hex(token_id).map_err(|err| InvalidField {
+                    name: "token_id",
+                    value: err.to_string(),
+                })?,
+            )
+        }
+        None => None,
+    };
     let slp_indexer = server.slp_indexer.read().await;
     let mut utxos = slp_indexer.utxos().utxos(&ScriptPayload {
         payload_prefix: prefix,
         payload_data: payload,
     })?;
+    // Non-token outputs of valid SLP txs have an empty token and hold no tokens. Mint batons
+    // count as token UTXOs, as coin selection for fees has to leave them alone too.
+    let utxo_token_id = |utxo: &RichUtxo| match &utxo.slp_output {
+        Some(slp_output) if slp_output.token != SlpToken::EMPTY => Some(&slp_output.token_id),
+        _ => None,
+    };
+    if let Some(has_token) = has_token {
+        utxos.retain(|utxo| utxo_token_id(utxo).is_some() == has_token);
+    }
+    if let Some(token_id) = &token_id {
+        utxos.retain(|utxo| utxo_token_id(utxo) == Some(token_id));
+    }
+    if min_confirmations > 0 {
+        let tip_height = slp_indexer.blocks().height()?;
+        // Mempool UTXOs have 0 confirmations and are therefore always excluded here
+        utxos.retain(|utxo| {
+            let block_height = utxo.block.as_ref().map(|block| block.height);
+            convert::num_confirmations(block_height, tip_height) >= min_confirmations
+        });
+    }
     utxos.sort_by_key(|utxo| utxo.output.script.bytecode().clone());
 
     let groups = Itertools::group_by(utxos.into_iter(), |utxo| {
@@ -448,27 +1979,11 @@ async fn handle_script_utxos(
                         .slp_output
                         .as_ref()
                         .and_then(|slp_output| slp_token_to_proto(slp_output.token)),
-                    slp_meta: utxo.slp_output.map(|slp_output| proto::SlpMeta {
-                        token_type: match slp_output.token_type {
-                            SlpTokenType::Fungible => proto::SlpTokenType::Fungible as i32,
-                            SlpTokenType::Nft1Group => proto::SlpTokenType::Nft1Group as i32,
-                            SlpTokenType::Nft1Child => proto::SlpTokenType::Nft1Child as i32,
-                            SlpTokenType::Unknown => proto::SlpTokenType::UnknownTokenType as i32,
-                        },
-                        tx_type: match &slp_output.tx_type {
-                            SlpTxTypeVariant::Genesis => proto::SlpTxType::Genesis as i32,
-                            SlpTxTypeVariant::Send => proto::SlpTxType::Send as i32,
-                            SlpTxTypeVariant::Mint => proto::SlpTxType::Mint as i32,
-                            SlpTxTypeVariant::Burn => proto::SlpTxType::Burn as i32,
-                            SlpTxTypeVariant::Unknown => proto::SlpTxType::UnknownTxType as i32,
-                        },
-                        token_id: slp_output.token_id.as_slice_be().to_vec(),
-                        group_token_id: slp_output
-                            .group_token_id
-                            .map(|token_id| token_id.as_slice_be().to_vec())
-                            .unwrap_or_default(),
-                    }),
-                    network: network_to_proto(utxo.network) as i32,
+                    slp_meta: utxo.slp_output.as_deref().map(slp_output_meta_to_proto),
+                    network: match server.strip_network {
+                        true => 0,
+                        false => network_to_proto(utxo.network) as i32,
+                    },
                 })
                 .collect();
             proto::ScriptUtxos {
@@ -480,19 +1995,275 @@ async fn handle_script_utxos(
     Ok(Protobuf(proto::Utxos { script_utxos }))
 }
 
+async fn handle_script_summary(
+    Path((script_type, payload)): Path<(String, String)>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::ScriptSummary>, ReportError> {
+    let payload = hex::decode(&payload).map_err(|_| InvalidField {
+        name: "payload",
+        value: payload.clone(),
+    })?;
+    let prefix = parse_payload_prefix(&server.script_types, script_type, payload.len())?;
+    let slp_indexer = server.slp_indexer.read().await;
+    let summary = slp_indexer.script_history().summary(&ScriptPayload {
+        payload_prefix: prefix,
+        payload_data: payload,
+    })?;
+    Ok(Protobuf(proto::ScriptSummary {
+        balance: summary.balance,
+        num_txs: summary.num_txs as u64,
+        num_utxos: summary.num_utxos as u64,
+        first_activity: summary.first_activity.unwrap_or_default(),
+        last_activity: summary.last_activity.unwrap_or_default(),
+        token_holdings: summary
+            .token_holdings
+            .into_iter()
+            .map(|holding| proto::TokenHolding {
+                token_id: holding.token_id.as_slice_be().to_vec(),
+                token_type: token_type_to_proto(holding.token_type),
+                amount: holding.amount.to_string(),
+                num_mint_batons: holding.num_mint_batons as u32,
+            })
+            .collect(),
+    }))
+}
+
+async fn handle_script_value(
+    Path((script_type, payload)): Path<(String, String)>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::ScriptValue>, ReportError> {
+    let payload = hex::decode(&payload).map_err(|_| InvalidField {
+        name: "payload",
+        value: payload.clone(),
+    })?;
+    let prefix = parse_payload_prefix(&server.script_types, script_type, payload.len())?;
+    let slp_indexer = server.slp_indexer.read().await;
+    let value = slp_indexer.utxos().total_value(&ScriptPayload {
+        payload_prefix: prefix,
+        payload_data: payload,
+    })?;
+    Ok(Protobuf(proto::ScriptValue { value }))
+}
+
+async fn handle_script_token_history(
+    Path((script_type, payload)): Path<(String, String)>,
+    Query(query_params): Query<HashMap<String, String>>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::ScriptTokenHistory>, ReportError> {
+    let payload = hex::decode(&payload).map_err(|_| InvalidField {
+        name: "payload",
+        value: payload.clone(),
+    })?;
+    let prefix = parse_payload_prefix(&server.script_types, script_type, payload.len())?;
+    let (page_num, page_size) =
+        parse_page_params(&query_params, DEFAULT_PAGE_SIZE, MAX_HISTORY_PAGE_SIZE)?;
+    let slp_indexer = server.slp_indexer.read().await;
+    let tokens = slp_indexer
+        .script_history()
+        .token_history(prefix, &payload)?;
+    let num_pages = (tokens.len() + page_size - 1) / page_size;
+    Ok(Protobuf(proto::ScriptTokenHistory {
+        tokens: tokens
+            .into_iter()
+            .skip(page_num.saturating_mul(page_size))
+            .take(page_size)
+            .map(|token| proto::ScriptToken {
+                token_id: token.token_id.as_slice_be().to_vec(),
+                token_type: token_type_to_proto(token.token_type),
+                first_seen_height: token.first_seen_height.unwrap_or(-1),
+            })
+            .collect(),
+        num_pages: num_pages as u32,
+    }))
+}
+
+async fn handle_script_spent_outputs(
+    Path((script_type, payload)): Path<(String, String)>,
+    Query(query_params): Query<HashMap<String, String>>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::SpentOutputsPage>, ReportError> {
+    let payload = hex::decode(&payload).map_err(|_| InvalidField {
+        name: "payload",
+        value: payload.clone(),
+    })?;
+    let prefix = parse_payload_prefix(&server.script_types, script_type, payload.len())?;
+    let (page_num, page_size) =
+        parse_page_params(&query_params, DEFAULT_PAGE_SIZE, MAX_HISTORY_PAGE_SIZE)?;
+    let parse_ts = |name: &'static str| -> Result<Option<i64>, ReportError> {
+        match query_params.get(name) {
+            Some(value) => match value.parse::<i64>() {
+                Ok(value) if value >= 0 => Ok(Some(value)),
+                _ => Err(InvalidField {
+                    name,
+                    value: value.clone(),
+                }
+                .into()),
+            },
+            None => Ok(None),
+        }
+    };
+    let (from_height, to_height) = parse_height_range(&query_params)?;
+    let mut from_height = from_height.unwrap_or(0);
+    let mut to_height = to_height.unwrap_or(BlockHeight::MAX);
+    let from_ts = parse_ts("from_ts")?;
+    let to_ts = parse_ts("to_ts")?;
+    let slp_indexer = server.slp_indexer.read().await;
+    // A time range further restricts the height range to blocks with a median timestamp in it
+    if from_ts.is_some() || to_ts.is_some() {
+        let height_range = slp_indexer
+            .blocks()
+            .height_range_by_median_timestamp(from_ts.unwrap_or(0), to_ts.unwrap_or(i64::MAX))?;
+        match height_range {
+            Some(height_range) => {
+                from_height = from_height.max(*height_range.start());
+                to_height = to_height.min(*height_range.end());
+            }
+            None => {
+                return Ok(Protobuf(proto::SpentOutputsPage {
+                    spent_outputs: vec![],
+                    num_pages: 0,
+                }))
+            }
+        }
+    }
+    if to_height < from_height {
+        return Ok(Protobuf(proto::SpentOutputsPage {
+            spent_outputs: vec![],
+            num_pages: 0,
+        }));
+    }
+    let (spent_outputs, num_pages) = slp_indexer
+        .script_history()
+        .spent_outputs_page_in_height_range(
+            prefix,
+            &payload,
+            from_height,
+            to_height,
+            page_num,
+            page_size,
+        )?;
+    Ok(Protobuf(proto::SpentOutputsPage {
+        spent_outputs: spent_outputs
+            .into_iter()
+            .map(|spent_output| proto::SpentOutput {
+                outpoint: Some(proto::OutPoint {
+                    txid: spent_output.outpoint.txid.as_slice().to_vec(),
+                    out_idx: spent_output.outpoint.out_idx,
+                }),
+                value: spent_output.output.value,
+                output_script: spent_output.output.script.bytecode().to_vec(),
+                slp_token: slp_token_to_proto(spent_output.slp_token),
+                created_height: spent_output.created_height,
+                spending_txid: spent_output.spending_txid.as_slice().to_vec(),
+                input_idx: spent_output.input_idx,
+                spending_block: Some(proto::BlockMetadata {
+                    height: spent_output.spending_block.height,
+                    hash: spent_output.spending_block.hash.as_slice().to_vec(),
+                    timestamp: spent_output.spending_block.timestamp,
+                }),
+            })
+            .collect(),
+        num_pages: num_pages as u32,
+    }))
+}
+
+async fn handle_script_token_flows(
+    Path((script_type, payload)): Path<(String, String)>,
+    Query(query_params): Query<HashMap<String, String>>,
+    Extension(server): Extension<ChronikServer>,
+) -> Result<Protobuf<proto::TokenFlowsPage>, ReportError> {
+    let payload = hex::decode(&payload).map_err(|_| InvalidField {
+        name: "payload",
+        value: payload.clone(),
+    })?;
+    let prefix = parse_payload_prefix(&server.script_types, script_type, payload.len())?;
+    let token_id = query_params
+        .get("token_id")
+        .map(String::as_str)
+        .unwrap_or_default();
+    let token_id = TokenId::from_token_id_hex(token_id).map_err(|err| InvalidField {
+        name: "token_id",
+        value: err.to_string(),
+    })?;
+    let (page_num, page_size) =
+        parse_page_params(&query_params, DEFAULT_PAGE_SIZE, MAX_HISTORY_PAGE_SIZE)?;
+    let (from_height, to_height) = parse_height_range(&query_params)?;
+    let from_height = from_height.unwrap_or(0);
+    let to_height = to_height.unwrap_or(BlockHeight::MAX);
+    let slp_indexer = server.slp_indexer.read().await;
+    if slp_indexer
+        .txs()
+        .rich_tx_by_txid(token_id.hash())?
+        .is_none()
+    {
+        return Err(TokenTxidNotFound(token_id.hash().clone()).into());
+    }
+    let (flows, num_pages) = slp_indexer
+        .script_history()
+        .token_flows_page_in_height_range(
+            prefix,
+            &payload,
+            &token_id,
+            from_height,
+            to_height,
+            page_num,
+            page_size,
+        )?;
+    Ok(Protobuf(proto::TokenFlowsPage {
+        flows: flows
+            .into_iter()
+            .map(|flow| proto::TokenFlow {
+                txid: flow.txid.as_slice().to_vec(),
+                block: Some(proto::BlockMetadata {
+                    height: flow.block.height,
+                    hash: flow.block.hash.as_slice().to_vec(),
+                    timestamp: flow.block.timestamp,
+                }),
+                received: flow.received.to_string(),
+                sent: flow.sent.to_string(),
+                net: (flow.received - flow.sent).to_string(),
+            })
+            .collect(),
+        num_pages: num_pages as u32,
+    }))
+}
+
 async fn handle_validate_utxos(
-    Protobuf(request): Protobuf<proto::ValidateUtxoRequest>,
+    Query(query_params): Query<HashMap<String, String>>,
     Extension(server): Extension<ChronikServer>,
+    Protobuf(request): Protobuf<proto::ValidateUtxoRequest>,
 ) -> Result<Protobuf<proto::ValidateUtxoResponse>, ReportError> {
-    let slp_indexer = server.slp_indexer.read().await;
-    let utxo_states = request
+    let include_slp = match query_params.get("include_slp") {
+        Some(include_slp) => include_slp.parse().map_err(|_| InvalidField {
+            name: "include_slp",
+            value: include_slp.clone(),
+        })?,
+        None => false,
+    };
+    if request.outpoints.len() > server.max_validate_utxos {
+        return Err(PageSizeTooLarge.into());
+    }
+    let outpoints = request
         .outpoints
         .iter()
         .map(|outpoint| {
-            let utxo_state = slp_indexer.utxos().utxo_state(&OutPoint {
+            Ok(OutPoint {
                 txid: Sha256d::from_slice(&outpoint.txid)?,
                 out_idx: outpoint.out_idx,
-            })?;
+            })
+        })
+        .collect::<Result<Vec<_>, Report>>()?;
+    let slp_indexer = server.slp_indexer.read().await;
+    let utxos = slp_indexer.utxos();
+    let utxo_states = utxos
+        .utxo_states(&outpoints)?
+        .into_iter()
+        .zip(&outpoints)
+        .map(|(utxo_state, outpoint)| {
+            let slp_output = match include_slp {
+                true => utxos.slp_output(outpoint)?,
+                false => None,
+            };
             Ok(proto::UtxoState {
                 height: utxo_state.height.unwrap_or(-1),
                 is_confirmed: utxo_state.height.is_some(),
@@ -501,7 +2272,12 @@ async fn handle_validate_utxos(
                     UtxoStateVariant::Spent => proto::UtxoStateVariant::Spent,
                     UtxoStateVariant::NoSuchTx => proto::UtxoStateVariant::NoSuchTx,
                     UtxoStateVariant::NoSuchOutput => proto::UtxoStateVariant::NoSuchOutput,
+                    UtxoStateVariant::Unknown => proto::UtxoStateVariant::Unknown,
                 } as i32,
+                slp_token: slp_output
+                    .as_ref()
+                    .and_then(|slp_output| slp_token_to_proto(slp_output.token)),
+                slp_meta: slp_output.as_ref().map(slp_output_meta_to_proto),
             })
         })
         .collect::<Result<Vec<_>, Report>>()?;
@@ -518,14 +2294,107 @@ async fn handle_subscribe(
 enum SubscribeAction {
     Close,
     Message(ws::Message),
+    SubscribeMsg(proto::SubscribeMsg),
+    FlushBatch,
     Subscribe {
         script_payload: ScriptPayload,
         is_subscribe: bool,
+        from_height: Option<BlockHeight>,
+        min_confirmations: u32,
     },
+    /// `MsgConfirmed` of a script subscription held back until the tx is confirmed deep enough
+    DelayConfirmed {
+        script_payload: ScriptPayload,
+        txid: Sha256d,
+    },
+    BlockConnected {
+        height: BlockHeight,
+        msg: proto::SubscribeMsg,
+    },
+    SubscribeOutpoint {
+        outpoint: OutPoint,
+        is_subscribe: bool,
+    },
+    SubscribeToken {
+        token_id: TokenId,
+        include_stats: bool,
+        is_subscribe: bool,
+    },
+    GetSubscriptions,
     Nothing,
 }
 
+/// Sends the `SubscribeMsg`s of a WebSocket connection, either each in its own frame, or with
+/// batching enabled collected into `SubscribeMsgBatch` frames, keeping their order.
+struct SubscribeMsgSender {
+    batch_interval: Option<Duration>,
+    batch: Vec<proto::SubscribeMsg>,
+    /// When the current batch has to be sent, set while it's not empty.
+    flush_at: Option<tokio::time::Instant>,
+}
+
+impl SubscribeMsgSender {
+    fn new(batch_interval: Option<Duration>) -> Self {
+        SubscribeMsgSender {
+            batch_interval,
+            batch: Vec::new(),
+            flush_at: None,
+        }
+    }
+
+    async fn send(
+        &mut self,
+        socket: &mut WebSocket,
+        msg: proto::SubscribeMsg,
+    ) -> Result<(), axum::Error> {
+        let batch_interval = match self.batch_interval {
+            Some(batch_interval) => batch_interval,
+            None => return socket.send(ws::Message::Binary(msg.encode_to_vec())).await,
+        };
+        if self.batch.is_empty() {
+            self.flush_at = Some(tokio::time::Instant::now() + batch_interval);
+        }
+        self.batch.push(msg);
+        Ok(())
+    }
+
+    async fn flush(&mut self, socket: &mut WebSocket) -> Result<(), axum::Error> {
+        self.flush_at = None;
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        let batch = proto::SubscribeMsgBatch {
+            msgs: std::mem::take(&mut self.batch),
+        };
+        socket
+            .send(ws::Message::Binary(batch.encode_to_vec()))
+            .await
+    }
+}
+
+struct ScriptSubscription {
+    min_confirmations: u32,
+    receiver: broadcast::Receiver<SubscribeScriptMessage>,
+}
+
+/// A tx of a script subscription whose `MsgConfirmed` hasn't been sent yet, because it doesn't
+/// have the subscription's `min_confirmations` yet.
+struct PendingConfirmed {
+    script_payload: ScriptPayload,
+    txid: Sha256d,
+}
+
+struct TokenSubscription {
+    token_id: TokenId,
+    include_stats: bool,
+    receiver: broadcast::Receiver<SubscribeTokenMessage>,
+}
+
+type SubscribeActionFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<SubscribeAction, Report>> + Send + 'a>>;
+
 fn subscribe_client_msg_action(
+    script_types: &ScriptTypes,
     client_msg: Option<Result<ws::Message, axum::Error>>,
 ) -> Result<SubscribeAction, Report> {
     let client_msg = match client_msg {
@@ -535,14 +2404,48 @@ fn subscribe_client_msg_action(
     match client_msg {
         Ok(ws::Message::Binary(client_msg)) => {
             let subscription = proto::Subscription::decode(client_msg.as_slice())?;
-            let payload_prefix =
-                parse_payload_prefix(subscription.script_type, subscription.payload.len())?;
+            if subscription.get_subscriptions.is_some() {
+                return Ok(SubscribeAction::GetSubscriptions);
+            }
+            if let Some(outpoint) = subscription.outpoint {
+                return Ok(SubscribeAction::SubscribeOutpoint {
+                    outpoint: OutPoint {
+                        txid: Sha256d::from_slice(&outpoint.txid)?,
+                        out_idx: outpoint.out_idx,
+                    },
+                    is_subscribe: subscription.is_subscribe,
+                });
+            }
+            if !subscription.token_id.is_empty() {
+                let token_id =
+                    TokenId::from_slice_be(&subscription.token_id).map_err(|err| InvalidField {
+                        name: "token_id",
+                        value: err.to_string(),
+                    })?;
+                return Ok(SubscribeAction::SubscribeToken {
+                    token_id,
+                    include_stats: subscription.include_token_stats,
+                    is_subscribe: subscription.is_subscribe,
+                });
+            }
+            let script_payload = match subscription.address.is_empty() {
+                true => {
+                    script_payload(script_types, subscription.script_type, subscription.payload)?
+                }
+                false => address_script_payload(script_types, &subscription.address)?,
+            };
+            if subscription.min_confirmations > MAX_MIN_CONFIRMATIONS {
+                return Err(InvalidField {
+                    name: "min_confirmations",
+                    value: subscription.min_confirmations.to_string(),
+                }
+                .into());
+            }
             Ok(SubscribeAction::Subscribe {
-                script_payload: ScriptPayload {
-                    payload_prefix,
-                    payload_data: subscription.payload,
-                },
+                script_payload,
                 is_subscribe: subscription.is_subscribe,
+                from_height: (subscription.from_height > 0).then(|| subscription.from_height),
+                min_confirmations: subscription.min_confirmations,
             })
         }
         Ok(ws::Message::Ping(ping)) => Ok(SubscribeAction::Message(ws::Message::Pong(ping))),
@@ -552,35 +2455,79 @@ fn subscribe_client_msg_action(
     }
 }
 
+/// Payload of the script of a P2PKH or P2SH CashAddress, if its script type is served.
+fn address_script_payload(
+    script_types: &ScriptTypes,
+    address: &str,
+) -> Result<ScriptPayload, Report> {
+    let invalid_address = || InvalidField {
+        name: "address",
+        value: address.to_string(),
+    };
+    let cash_address = CashAddress::parse_cow(address.into()).map_err(|_| invalid_address())?;
+    let payload = script_payloads(&cash_address.to_script())
+        .into_iter()
+        .next()
+        .ok_or_else(invalid_address)?
+        .payload;
+    if script_types.script_type(payload.payload_prefix).is_none() {
+        return Err(invalid_address().into());
+    }
+    Ok(payload)
+}
+
 fn subscribe_script_msg_action(
+    script_payload: &ScriptPayload,
+    min_confirmations: u32,
     script_msg: Result<SubscribeScriptMessage, broadcast::error::RecvError>,
 ) -> Result<SubscribeAction, Report> {
-    use proto::subscribe_msg::MsgType;
     let script_msg = match script_msg {
+        Ok(SubscribeScriptMessage::Confirmed(txid)) if min_confirmations > 1 => {
+            return Ok(SubscribeAction::DelayConfirmed {
+                script_payload: script_payload.clone(),
+                txid,
+            })
+        }
         Ok(script_msg) => script_msg,
         Err(_) => return Ok(SubscribeAction::Nothing),
     };
-    let msg_type = Some(match script_msg {
-        SubscribeScriptMessage::AddedToMempool(txid) => {
-            MsgType::AddedToMempool(proto::MsgAddedToMempool {
-                txid: txid.as_slice().to_vec(),
+    let msg_proto = script_msg_to_proto(script_msg);
+    Ok(SubscribeAction::SubscribeMsg(msg_proto))
+}
+
+fn subscribe_outpoint_msg_action(
+    outpoint: &OutPoint,
+    outpoint_msg: Result<SubscribeOutpointMessage, broadcast::error::RecvError>,
+) -> Result<SubscribeAction, Report> {
+    let outpoint_msg = match outpoint_msg {
+        Ok(outpoint_msg) => outpoint_msg,
+        // Channel is closed after the spend has been sent
+        Err(broadcast::error::RecvError::Closed) => {
+            return Ok(SubscribeAction::SubscribeOutpoint {
+                outpoint: outpoint.clone(),
+                is_subscribe: false,
             })
         }
-        SubscribeScriptMessage::RemovedFromMempool(txid) => {
-            MsgType::RemovedFromMempool(proto::MsgRemovedFromMempool {
-                txid: txid.as_slice().to_vec(),
-            })
+        Err(broadcast::error::RecvError::Lagged(_)) => return Ok(SubscribeAction::Nothing),
+    };
+    let msg_proto = outpoint_msg_to_proto(outpoint, outpoint_msg);
+    Ok(SubscribeAction::SubscribeMsg(msg_proto))
+}
+
+fn subscribe_token_msg_action(
+    token_id: &TokenId,
+    include_stats: bool,
+    token_msg: Result<SubscribeTokenMessage, broadcast::error::RecvError>,
+) -> Result<SubscribeAction, Report> {
+    let token_msg = match token_msg {
+        Ok(SubscribeTokenMessage::Stats { .. }) if !include_stats => {
+            return Ok(SubscribeAction::Nothing)
         }
-        SubscribeScriptMessage::Confirmed(txid) => MsgType::Confirmed(proto::MsgConfirmed {
-            txid: txid.as_slice().to_vec(),
-        }),
-        SubscribeScriptMessage::Reorg(txid) => MsgType::Reorg(proto::MsgReorg {
-            txid: txid.as_slice().to_vec(),
-        }),
-    });
-    let msg_proto = proto::SubscribeMsg { msg_type };
-    let msg = ws::Message::Binary(msg_proto.encode_to_vec());
-    Ok(SubscribeAction::Message(msg))
+        Ok(token_msg) => token_msg,
+        Err(_) => return Ok(SubscribeAction::Nothing),
+    };
+    let msg_proto = token_msg_to_proto(token_id, token_msg);
+    Ok(SubscribeAction::SubscribeMsg(msg_proto))
 }
 
 fn subscribe_block_msg_action(
@@ -591,21 +2538,191 @@ fn subscribe_block_msg_action(
         Ok(script_msg) => script_msg,
         Err(_) => return Ok(SubscribeAction::Nothing),
     };
+    let connected_height = match &script_msg {
+        SubscribeBlockMessage::BlockConnected { height, .. } => Some(*height),
+        _ => None,
+    };
     let msg_type = Some(match script_msg {
-        SubscribeBlockMessage::BlockConnected(block_hash) => {
-            MsgType::BlockConnected(proto::MsgBlockConnected {
-                block_hash: block_hash.as_slice().to_vec(),
-            })
-        }
+        SubscribeBlockMessage::BlockConnected {
+            block_hash,
+            height,
+            coinbase_outputs,
+            subsidy,
+            fees,
+        } => MsgType::BlockConnected(proto::MsgBlockConnected {
+            block_hash: block_hash.as_slice().to_vec(),
+            block_height: height,
+            coinbase_outputs: coinbase_outputs
+                .into_iter()
+                .map(|output| proto::TxOutput {
+                    value: output.value,
+                    output_script: output.script.bytecode().to_vec(),
+                    slp_token: None,
+                    spent_by: None,
+                })
+                .collect(),
+            subsidy,
+            fees,
+        }),
         SubscribeBlockMessage::BlockDisconnected(block_hash) => {
             MsgType::BlockDisconnected(proto::MsgBlockDisconnected {
                 block_hash: block_hash.as_slice().to_vec(),
             })
         }
+        SubscribeBlockMessage::ReorgStarted {
+            from_height,
+            to_height,
+        } => MsgType::ReorgStarted(proto::MsgReorgStarted {
+            from_height,
+            to_height,
+        }),
+        SubscribeBlockMessage::ReorgFinished { new_tip_height } => {
+            MsgType::ReorgFinished(proto::MsgReorgFinished { new_tip_height })
+        }
     });
     let msg_proto = proto::SubscribeMsg { msg_type };
-    let msg = ws::Message::Binary(msg_proto.encode_to_vec());
-    Ok(SubscribeAction::Message(msg))
+    match connected_height {
+        Some(height) => Ok(SubscribeAction::BlockConnected {
+            height,
+            msg: msg_proto,
+        }),
+        None => Ok(SubscribeAction::SubscribeMsg(msg_proto)),
+    }
+}
+
+fn subscriptions_msg<'a>(
+    script_types: &ScriptTypes,
+    subbed_scripts: impl Iterator<Item = (&'a ScriptPayload, &'a ScriptSubscription)>,
+    subbed_outpoints: impl Iterator<Item = &'a OutPoint>,
+    subbed_tokens: impl Iterator<Item = &'a TokenSubscription>,
+) -> proto::SubscribeMsg {
+    use proto::subscribe_msg::MsgType;
+    let subscription = proto::Subscription {
+        is_subscribe: true,
+        ..Default::default()
+    };
+    let script_subs = subbed_scripts.map(|(script_payload, script_sub)| proto::Subscription {
+        script_type: script_types
+            .script_type(script_payload.payload_prefix)
+            .unwrap_or_default()
+            .to_string(),
+        payload: script_payload.payload_data.clone(),
+        min_confirmations: script_sub.min_confirmations,
+        ..subscription.clone()
+    });
+    let outpoint_subs = subbed_outpoints.map(|outpoint| proto::Subscription {
+        outpoint: Some(proto::OutPoint {
+            txid: outpoint.txid.as_slice().to_vec(),
+            out_idx: outpoint.out_idx,
+        }),
+        ..subscription.clone()
+    });
+    let token_subs = subbed_tokens.map(|token_sub| proto::Subscription {
+        token_id: token_sub.token_id.as_slice_be().to_vec(),
+        include_token_stats: token_sub.include_stats,
+        ..subscription.clone()
+    });
+    proto::SubscribeMsg {
+        msg_type: Some(MsgType::Subscriptions(proto::MsgSubscriptions {
+            subscriptions: script_subs.chain(outpoint_subs).chain(token_subs).collect(),
+        })),
+    }
+}
+
+/// Sends `MsgConfirmed` for the script's confirmed txs in blocks at or above `from_height`,
+/// page by page, so blocks can still be indexed in between.
+async fn replay_script_txs(
+    socket: &mut WebSocket,
+    msg_sender: &mut SubscribeMsgSender,
+    server: &ChronikServer,
+    script_payload: &ScriptPayload,
+    from_height: BlockHeight,
+) -> Result<(), Report> {
+    use proto::subscribe_msg::MsgType;
+    let mut page_num = 0;
+    loop {
+        let (txs, num_pages) = {
+            let slp_indexer = server.slp_indexer.read().await;
+            slp_indexer.script_history().history_page_in_height_range(
+                script_payload.payload_prefix,
+                &script_payload.payload_data,
+                from_height,
+                BlockHeight::MAX,
+                page_num,
+                MAX_HISTORY_PAGE_SIZE,
+            )?
+        };
+        if page_num == 0 && num_pages * MAX_HISTORY_PAGE_SIZE > MAX_REPLAY_TXS {
+            return Err(ReplayTooLarge(from_height).into());
+        }
+        for tx in txs {
+            let msg_proto = proto::SubscribeMsg {
+                msg_type: Some(MsgType::Confirmed(proto::MsgConfirmed {
+                    txid: tx.txid.as_slice().to_vec(),
+                })),
+            };
+            if msg_sender.send(socket, msg_proto).await.is_err() {
+                // A closed socket is handled on the next receive
+                return Ok(());
+            }
+        }
+        page_num += 1;
+        if page_num >= num_pages {
+            return Ok(());
+        }
+    }
+}
+
+/// Sends the held back `MsgConfirmed`s whose txs have enough confirmations once the block at
+/// `tip_height` is connected. Txs no longer in a block (i.e. reorged) are dropped, they're held
+/// back again once they're confirmed again.
+async fn send_pending_confirmed(
+    socket: &mut WebSocket,
+    msg_sender: &mut SubscribeMsgSender,
+    server: &ChronikServer,
+    subbed_scripts: &HashMap<ScriptPayload, ScriptSubscription>,
+    pending_confirmed: &mut Vec<PendingConfirmed>,
+    tip_height: BlockHeight,
+) -> Result<(), axum::Error> {
+    use proto::subscribe_msg::MsgType;
+    if pending_confirmed.is_empty() {
+        return Ok(());
+    }
+    let mut due_txids = Vec::new();
+    {
+        let slp_indexer = server.slp_indexer.read().await;
+        let txs = slp_indexer.txs();
+        pending_confirmed.retain(|pending| {
+            let script_sub = match subbed_scripts.get(&pending.script_payload) {
+                Some(script_sub) => script_sub,
+                None => return false,
+            };
+            let block_height = match txs.tx_location(&pending.txid) {
+                Ok(Some(tx_location)) => tx_location.block.height,
+                Ok(None) => return false,
+                Err(report) => {
+                    // Kept, so it's retried on the next block
+                    println!("Looking up pending confirmed tx failed: {:?}", report);
+                    return true;
+                }
+            };
+            let num_confirmations = convert::num_confirmations(Some(block_height), tip_height);
+            if num_confirmations < script_sub.min_confirmations {
+                return true;
+            }
+            due_txids.push(pending.txid.clone());
+            false
+        });
+    }
+    for txid in due_txids {
+        let msg_proto = proto::SubscribeMsg {
+            msg_type: Some(MsgType::Confirmed(proto::MsgConfirmed {
+                txid: txid.as_slice().to_vec(),
+            })),
+        };
+        msg_sender.send(socket, msg_proto).await?;
+    }
+    Ok(())
 }
 
 fn subscribe_ping_msg_action(rng: &mut impl rand::Rng) -> Result<SubscribeAction, Report> {
@@ -618,28 +2735,94 @@ async fn handle_subscribe_socket(mut socket: WebSocket, server: ChronikServer) {
     // 45s is a decent value to keep the connection alive in practice
     const PING_INTERVAL: Duration = Duration::from_secs(45);
 
-    let mut subbed_scripts =
-        HashMap::<ScriptPayload, broadcast::Receiver<SubscribeScriptMessage>>::new();
+    let mut subbed_scripts = HashMap::<ScriptPayload, ScriptSubscription>::new();
+    let mut pending_confirmed = Vec::<PendingConfirmed>::new();
+    let mut subbed_outpoints =
+        HashMap::<OutPoint, broadcast::Receiver<SubscribeOutpointMessage>>::new();
+    let mut subbed_tokens = HashMap::<[u8; 32], TokenSubscription>::new();
     let mut blocks_receiver = {
         let mut slp_indexer = server.slp_indexer.write().await;
         slp_indexer.subscribers_mut().subscribe_to_blocks()
     };
     let mut rng = rand::rngs::StdRng::from_entropy();
+    let mut msg_sender = SubscribeMsgSender::new(server.ws_batch_interval);
+    // Block message received but not forwarded yet, see below
+    let mut held_block_msg = None;
     loop {
-        let subscribe_action = if subbed_scripts.is_empty() {
-            let client_msg = socket.recv().await;
-            subscribe_client_msg_action(client_msg)
+        let subscribe_action = if subbed_scripts.is_empty()
+            && subbed_outpoints.is_empty()
+            && subbed_tokens.is_empty()
+        {
+            match held_block_msg.take() {
+                Some(block_msg) => subscribe_block_msg_action(block_msg),
+                None => {
+                    // Nothing else would send the last batch; a closed socket is handled below
+                    let _ = msg_sender.flush(&mut socket).await;
+                    let client_msg = socket.recv().await;
+                    subscribe_client_msg_action(&server.script_types, client_msg)
+                }
+            }
         } else {
-            let script_receivers = select_all(
-                subbed_scripts
-                    .values_mut()
-                    .map(|receiver| Box::pin(receiver.recv())),
+            let script_receivers = subbed_scripts
+                .iter_mut()
+                .map(|(script_payload, script_sub)| {
+                    let ScriptSubscription {
+                        min_confirmations,
+                        receiver,
+                    } = script_sub;
+                    Box::pin(async move {
+                        subscribe_script_msg_action(
+                            script_payload,
+                            *min_confirmations,
+                            receiver.recv().await,
+                        )
+                    }) as SubscribeActionFuture
+                });
+            let outpoint_receivers = subbed_outpoints.iter_mut().map(|(outpoint, receiver)| {
+                Box::pin(
+                    async move { subscribe_outpoint_msg_action(outpoint, receiver.recv().await) },
+                ) as SubscribeActionFuture
+            });
+            let token_receivers = subbed_tokens.values_mut().map(|token_sub| {
+                let TokenSubscription {
+                    token_id,
+                    include_stats,
+                    receiver,
+                } = token_sub;
+                Box::pin(async move {
+                    subscribe_token_msg_action(token_id, *include_stats, receiver.recv().await)
+                }) as SubscribeActionFuture
+            });
+            let receivers = select_all(
+                script_receivers
+                    .chain(outpoint_receivers)
+                    .chain(token_receivers),
             );
-            tokio::select! {
-                client_msg = socket.recv() => subscribe_client_msg_action(client_msg),
-                block_msg = blocks_receiver.recv() => subscribe_block_msg_action(block_msg),
-                (script_msg, _, _) = script_receivers => subscribe_script_msg_action(script_msg),
-                _ = tokio::time::sleep(PING_INTERVAL) => subscribe_ping_msg_action(&mut rng),
+            let flush_at = msg_sender.flush_at;
+            match held_block_msg.take() {
+                // The indexer sends the block message after all other messages of the block, so
+                // these are already in the subscription channels; drain them before forwarding
+                // the block message, one per iteration.
+                Some(block_msg) => match receivers.now_or_never() {
+                    Some((subscribe_action, _, _)) => {
+                        held_block_msg = Some(block_msg);
+                        subscribe_action
+                    }
+                    None => subscribe_block_msg_action(block_msg),
+                },
+                None => tokio::select! {
+                    biased;
+                    client_msg = socket.recv() => subscribe_client_msg_action(&server.script_types, client_msg),
+                    // Before the subscriptions, so a busy subscription can't hold back the batch
+                    _ = tokio::time::sleep_until(flush_at.unwrap_or_else(tokio::time::Instant::now)),
+                        if flush_at.is_some() => Ok(SubscribeAction::FlushBatch),
+                    (subscribe_action, _, _) = receivers => subscribe_action,
+                    block_msg = blocks_receiver.recv() => {
+                        held_block_msg = Some(block_msg);
+                        Ok(SubscribeAction::Nothing)
+                    }
+                    _ = tokio::time::sleep(PING_INTERVAL) => subscribe_ping_msg_action(&mut rng),
+                },
             }
         };
 
@@ -658,40 +2841,187 @@ async fn handle_subscribe_socket(mut socket: WebSocket, server: ChronikServer) {
                 Ok(()) => SubscribeAction::Nothing,
                 Err(_) => SubscribeAction::Close,
             },
+            SubscribeAction::SubscribeMsg(msg) => match msg_sender.send(&mut socket, msg).await {
+                Ok(()) => SubscribeAction::Nothing,
+                Err(_) => SubscribeAction::Close,
+            },
+            SubscribeAction::FlushBatch => match msg_sender.flush(&mut socket).await {
+                Ok(()) => SubscribeAction::Nothing,
+                Err(_) => SubscribeAction::Close,
+            },
+            // Confirmations that became deep enough with this block are sent before it
+            SubscribeAction::BlockConnected { height, msg } => {
+                let result = send_pending_confirmed(
+                    &mut socket,
+                    &mut msg_sender,
+                    &server,
+                    &subbed_scripts,
+                    &mut pending_confirmed,
+                    height,
+                )
+                .await;
+                match result {
+                    Ok(()) => match msg_sender.send(&mut socket, msg).await {
+                        Ok(()) => SubscribeAction::Nothing,
+                        Err(_) => SubscribeAction::Close,
+                    },
+                    Err(_) => SubscribeAction::Close,
+                }
+            }
             other => other,
         };
 
         match subscribe_action {
             SubscribeAction::Close => {
-                if !subbed_scripts.is_empty() {
+                if !subbed_scripts.is_empty()
+                    || !subbed_outpoints.is_empty()
+                    || !subbed_tokens.is_empty()
+                {
                     let mut slp_indexer = server.slp_indexer.write().await;
-                    for (script_payload, receiver) in subbed_scripts {
-                        std::mem::drop(receiver);
+                    for (script_payload, script_sub) in subbed_scripts {
+                        std::mem::drop(script_sub.receiver);
                         slp_indexer
                             .subscribers_mut()
                             .unsubscribe_from_script(&script_payload);
                     }
+                    for (outpoint, receiver) in subbed_outpoints {
+                        std::mem::drop(receiver);
+                        slp_indexer
+                            .subscribers_mut()
+                            .unsubscribe_from_outpoint(&outpoint);
+                    }
+                    for token_sub in subbed_tokens.into_values() {
+                        std::mem::drop(token_sub.receiver);
+                        slp_indexer
+                            .subscribers_mut()
+                            .unsubscribe_from_token(&token_sub.token_id);
+                    }
                 }
                 return;
             }
-            SubscribeAction::Message(_) => unreachable!(),
+            SubscribeAction::Message(_)
+            | SubscribeAction::SubscribeMsg(_)
+            | SubscribeAction::FlushBatch
+            | SubscribeAction::BlockConnected { .. } => unreachable!(),
+            SubscribeAction::DelayConfirmed {
+                script_payload,
+                txid,
+            } => {
+                let is_pending = pending_confirmed.iter().any(|pending| {
+                    pending.script_payload == script_payload && pending.txid == txid
+                });
+                if !is_pending && pending_confirmed.len() >= MAX_PENDING_CONFIRMED {
+                    let report = Report::from(TooManyPendingConfirmed(txid.to_hex_be()));
+                    let (_, Protobuf(error_proto)) = report_to_status_proto(&report);
+                    let msg = ws::Message::Binary(error_proto.encode_to_vec());
+                    // A closed socket is handled on the next receive
+                    let _ = socket.send(msg).await;
+                } else if !is_pending {
+                    pending_confirmed.push(PendingConfirmed {
+                        script_payload,
+                        txid,
+                    });
+                }
+            }
             SubscribeAction::Subscribe {
                 script_payload,
                 is_subscribe,
+                from_height,
+                min_confirmations,
             } => {
-                let mut slp_indexer = server.slp_indexer.write().await;
                 if is_subscribe {
-                    let receiver = slp_indexer
-                        .subscribers_mut()
-                        .subscribe_to_script(&script_payload);
-                    subbed_scripts.insert(script_payload, receiver);
+                    // Subscribe before replaying, so no tx is missed in between; a tx confirmed
+                    // during the replay may be sent twice.
+                    let receiver = server.service().subscribe_to_script(&script_payload).await;
+                    subbed_scripts.insert(
+                        script_payload.clone(),
+                        ScriptSubscription {
+                            min_confirmations,
+                            receiver,
+                        },
+                    );
+                    if let Some(from_height) = from_height {
+                        let result = replay_script_txs(
+                            &mut socket,
+                            &mut msg_sender,
+                            &server,
+                            &script_payload,
+                            from_height,
+                        )
+                        .await;
+                        if let Err(report) = result {
+                            let (_, Protobuf(error_proto)) = report_to_status_proto(&report);
+                            let msg = ws::Message::Binary(error_proto.encode_to_vec());
+                            // A closed socket is handled on the next receive
+                            let _ = socket.send(msg).await;
+                        }
+                    }
                 } else {
                     std::mem::drop(subbed_scripts.remove(&script_payload));
+                    pending_confirmed.retain(|pending| pending.script_payload != script_payload);
+                    let mut slp_indexer = server.slp_indexer.write().await;
                     slp_indexer
                         .subscribers_mut()
                         .unsubscribe_from_script(&script_payload);
                 }
             }
+            SubscribeAction::SubscribeOutpoint {
+                outpoint,
+                is_subscribe,
+            } => {
+                if is_subscribe {
+                    match server.service().subscribe_to_outpoint(&outpoint).await {
+                        Ok(receiver) => {
+                            subbed_outpoints.insert(outpoint, receiver);
+                        }
+                        Err(report) => {
+                            let (_, Protobuf(error_proto)) = report_to_status_proto(&report);
+                            let msg = ws::Message::Binary(error_proto.encode_to_vec());
+                            // A closed socket is handled on the next receive
+                            let _ = socket.send(msg).await;
+                        }
+                    }
+                } else {
+                    std::mem::drop(subbed_outpoints.remove(&outpoint));
+                    let mut slp_indexer = server.slp_indexer.write().await;
+                    slp_indexer
+                        .subscribers_mut()
+                        .unsubscribe_from_outpoint(&outpoint);
+                }
+            }
+            SubscribeAction::SubscribeToken {
+                token_id,
+                include_stats,
+                is_subscribe,
+            } => {
+                if is_subscribe {
+                    let receiver = server.service().subscribe_to_token(&token_id).await;
+                    subbed_tokens.insert(
+                        token_id.token_id_be(),
+                        TokenSubscription {
+                            token_id,
+                            include_stats,
+                            receiver,
+                        },
+                    );
+                } else {
+                    std::mem::drop(subbed_tokens.remove(&token_id.token_id_be()));
+                    let mut slp_indexer = server.slp_indexer.write().await;
+                    slp_indexer
+                        .subscribers_mut()
+                        .unsubscribe_from_token(&token_id);
+                }
+            }
+            SubscribeAction::GetSubscriptions => {
+                let msg = subscriptions_msg(
+                    &server.script_types,
+                    subbed_scripts.iter(),
+                    subbed_outpoints.keys(),
+                    subbed_tokens.values(),
+                );
+                // A closed socket is handled on the next receive
+                let _ = msg_sender.send(&mut socket, msg).await;
+            }
             SubscribeAction::Nothing => {}
         }
     }
@@ -0,0 +1,203 @@
+use std::sync::Arc;
+
+use bitcoinsuite_core::{OutPoint, Sha256d};
+use bitcoinsuite_error::Report;
+use bitcoinsuite_slp::TokenId;
+use chronik_indexer::{
+    subscribers::{SubscribeOutpointMessage, SubscribeScriptMessage, SubscribeTokenMessage},
+    BlocksError::InconsistentNoSuchRawHeader,
+    SlpIndexer,
+};
+use chronik_rocksdb::{Block, ScriptPayload};
+use tokio::sync::broadcast;
+
+use crate::{
+    convert::{block_to_info_proto, parse_payload_prefix, rich_tx_to_proto, token_stats_to_proto},
+    proto,
+    server::{ChronikServerError::*, SlpIndexerRef},
+    validation::ScriptTypes,
+};
+
+/// Request handling shared by the HTTP and the gRPC server, independent of the transport.
+#[derive(Clone)]
+pub struct ChronikService {
+    pub slp_indexer: SlpIndexerRef,
+    pub script_types: Arc<ScriptTypes>,
+}
+
+impl ChronikService {
+    pub async fn tx(&self, txid: Sha256d, include_raw: bool) -> Result<proto::Tx, Report> {
+        let indexer = self.slp_indexer.read().await;
+        let rich_tx = indexer
+            .txs()
+            .rich_tx_by_txid(&txid)?
+            .ok_or_else(|| TxNotFound(txid.clone()))?;
+        let mut tx = rich_tx_to_proto(rich_tx);
+        if include_raw {
+            let raw_tx = indexer.txs().raw_tx_by_id(&txid)?.ok_or(TxNotFound(txid))?;
+            tx.raw = raw_tx.to_vec();
+        }
+        Ok(tx)
+    }
+
+    pub async fn block(&self, hash_or_height: String) -> Result<proto::Block, Report> {
+        let slp_indexer = self.slp_indexer.read().await;
+        let block = block_by_hash_or_height(&slp_indexer, hash_or_height)?;
+        let block_stats = slp_indexer.blocks().block_stats(&block)?;
+        let block_info = Some(block_to_info_proto(&block, &block_stats));
+        let raw_header = slp_indexer
+            .blocks()
+            .raw_header(&block)?
+            .ok_or_else(|| InconsistentNoSuchRawHeader(block.hash.clone()))?;
+        let txs = slp_indexer.blocks().block_txs_by_height(block.height)?;
+        let txs = txs.into_iter().map(rich_tx_to_proto).collect();
+        // Parsed from the raw header instead of querying bitcoind, so this works while it's down
+        let header_details = slp_indexer.blocks().header_details(&block, &raw_header)?;
+        let block_details = Some(proto::BlockDetails {
+            version: header_details.version,
+            merkle_root: header_details.merkle_root.as_slice().to_vec(),
+            nonce: header_details.nonce,
+            median_timestamp: header_details.median_timestamp,
+        });
+        Ok(proto::Block {
+            block_info,
+            block_details,
+            raw_header,
+            txs,
+        })
+    }
+
+    pub async fn subscribe_to_script(
+        &self,
+        script_payload: &ScriptPayload,
+    ) -> broadcast::Receiver<SubscribeScriptMessage> {
+        let mut slp_indexer = self.slp_indexer.write().await;
+        slp_indexer
+            .subscribers_mut()
+            .subscribe_to_script(script_payload)
+    }
+
+    pub async fn subscribe_to_outpoint(
+        &self,
+        outpoint: &OutPoint,
+    ) -> Result<broadcast::Receiver<SubscribeOutpointMessage>, Report> {
+        let mut slp_indexer = self.slp_indexer.write().await;
+        slp_indexer.subscribe_to_outpoint(outpoint)
+    }
+
+    pub async fn subscribe_to_token(
+        &self,
+        token_id: &TokenId,
+    ) -> broadcast::Receiver<SubscribeTokenMessage> {
+        let mut slp_indexer = self.slp_indexer.write().await;
+        slp_indexer.subscribers_mut().subscribe_to_token(token_id)
+    }
+}
+
+pub fn script_payload(
+    script_types: &ScriptTypes,
+    script_type: String,
+    payload: Vec<u8>,
+) -> Result<ScriptPayload, Report> {
+    let payload_prefix = parse_payload_prefix(script_types, script_type, payload.len())?;
+    Ok(ScriptPayload {
+        payload_prefix,
+        payload_data: payload,
+    })
+}
+
+pub fn script_msg_to_proto(script_msg: SubscribeScriptMessage) -> proto::SubscribeMsg {
+    use proto::subscribe_msg::MsgType;
+    let msg_type = Some(match script_msg {
+        SubscribeScriptMessage::AddedToMempool(txid) => {
+            MsgType::AddedToMempool(proto::MsgAddedToMempool {
+                txid: txid.as_slice().to_vec(),
+            })
+        }
+        SubscribeScriptMessage::RemovedFromMempool(txid) => {
+            MsgType::RemovedFromMempool(proto::MsgRemovedFromMempool {
+                txid: txid.as_slice().to_vec(),
+            })
+        }
+        SubscribeScriptMessage::Confirmed(txid) => MsgType::Confirmed(proto::MsgConfirmed {
+            txid: txid.as_slice().to_vec(),
+        }),
+        SubscribeScriptMessage::Reorg(txid) => MsgType::Reorg(proto::MsgReorg {
+            txid: txid.as_slice().to_vec(),
+        }),
+        SubscribeScriptMessage::Conflicting(txid) => MsgType::Conflicting(proto::MsgConflicting {
+            txid: txid.as_slice().to_vec(),
+        }),
+    });
+    proto::SubscribeMsg { msg_type }
+}
+
+pub fn outpoint_msg_to_proto(
+    outpoint: &OutPoint,
+    outpoint_msg: SubscribeOutpointMessage,
+) -> proto::SubscribeMsg {
+    use proto::subscribe_msg::MsgType;
+    let msg_type = Some(match outpoint_msg {
+        SubscribeOutpointMessage::Spent {
+            spending_txid,
+            input_idx,
+            is_confirmed,
+        } => MsgType::OutpointSpent(proto::MsgOutpointSpent {
+            spending_txid: spending_txid.as_slice().to_vec(),
+            input_idx,
+            is_confirmed,
+            outpoint: Some(proto::OutPoint {
+                txid: outpoint.txid.as_slice().to_vec(),
+                out_idx: outpoint.out_idx,
+            }),
+        }),
+    });
+    proto::SubscribeMsg { msg_type }
+}
+
+pub fn token_msg_to_proto(
+    token_id: &TokenId,
+    token_msg: SubscribeTokenMessage,
+) -> proto::SubscribeMsg {
+    use proto::subscribe_msg::MsgType;
+    let msg_type = Some(match token_msg {
+        SubscribeTokenMessage::AddedToMempool(txid) => {
+            MsgType::AddedToMempool(proto::MsgAddedToMempool {
+                txid: txid.as_slice().to_vec(),
+            })
+        }
+        SubscribeTokenMessage::Confirmed(txid) => MsgType::Confirmed(proto::MsgConfirmed {
+            txid: txid.as_slice().to_vec(),
+        }),
+        SubscribeTokenMessage::Stats {
+            token_stats,
+            unconfirmed_delta,
+        } => MsgType::TokenStats(proto::MsgTokenStats {
+            token_id: token_id.as_slice_be().to_vec(),
+            total_minted: token_stats.total_minted.to_string(),
+            total_burned: token_stats.total_burned.to_string(),
+            unconfirmed_delta: Some(token_stats_to_proto(&unconfirmed_delta)),
+            supply_overflowed: token_stats.supply_overflowed,
+        }),
+    });
+    proto::SubscribeMsg { msg_type }
+}
+
+pub(crate) fn block_by_hash_or_height(
+    slp_indexer: &SlpIndexer,
+    hash_or_height: String,
+) -> Result<Block, Report> {
+    let block_reader = slp_indexer.db().blocks()?;
+    let block = match hash_or_height.parse::<i32>() {
+        Ok(height) => block_reader.by_height(height)?,
+        Err(_) => {
+            let hash = Sha256d::from_hex_be(&hash_or_height)
+                .map_err(|_| InvalidHashOrHeight(hash_or_height.clone()))?;
+            block_reader.by_hash(&hash)?
+        }
+    };
+    match block {
+        Some(block) => Ok(block),
+        None => Err(BlockNotFound(hash_or_height).into()),
+    }
+}
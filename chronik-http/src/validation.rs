@@ -1,14 +1,21 @@
-use hyper::{header::CONTENT_TYPE, HeaderMap};
+use std::collections::{BTreeMap, HashMap};
 
+use hyper::{
+    header::{ACCEPT, CONTENT_TYPE},
+    HeaderMap,
+};
+
+use bitcoinsuite_core::{ecc::PUBKEY_LENGTH, Hashed, Sha256, ShaRmd160};
 use bitcoinsuite_error::{ErrorMeta, Report};
+use chronik_rocksdb::{BlockHeight, PayloadPrefix};
 
 use thiserror::Error;
 
 #[derive(Debug, Error, ErrorMeta)]
 pub enum ChronikValidationError {
     #[invalid_client_input()]
-    #[error("No Content-Type set")]
-    NoContentTypeSet,
+    #[error("No Content-Type set, must be {expected}")]
+    NoContentTypeSet { expected: &'static str },
 
     #[invalid_client_input()]
     #[error("Content-Type bad encoding: {0}")]
@@ -20,12 +27,25 @@ pub enum ChronikValidationError {
         expected: &'static str,
         actual: String,
     },
+
+    #[invalid_client_input()]
+    #[error("Accept must allow {supported}, got {actual}")]
+    NotAcceptable {
+        supported: &'static str,
+        actual: String,
+    },
+
+    #[invalid_user_input()]
+    #[error("Invalid {name}: {value}")]
+    InvalidField { name: &'static str, value: String },
 }
 
 use self::ChronikValidationError::*;
 
 pub fn check_content_type(headers: &HeaderMap, expected: &'static str) -> Result<(), Report> {
-    let content_type = headers.get(CONTENT_TYPE).ok_or(NoContentTypeSet)?;
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .ok_or(NoContentTypeSet { expected })?;
     let content_type = content_type
         .to_str()
         .map_err(|err| BadContentType(err.to_string()))?;
@@ -38,3 +58,168 @@ pub fn check_content_type(headers: &HeaderMap, expected: &'static str) -> Result
     }
     Ok(())
 }
+
+/// Parses the optional `from_height` and `to_height` query params of endpoints restricted to a
+/// height range; both are inclusive, and `to_height` must not be below `from_height`.
+pub fn parse_height_range(
+    query_params: &HashMap<String, String>,
+) -> Result<(Option<BlockHeight>, Option<BlockHeight>), Report> {
+    let parse_height = |name: &'static str| -> Result<Option<BlockHeight>, Report> {
+        match query_params.get(name) {
+            Some(height) => match height.parse::<BlockHeight>() {
+                Ok(height) if height >= 0 => Ok(Some(height)),
+                _ => Err(InvalidField {
+                    name,
+                    value: height.clone(),
+                }
+                .into()),
+            },
+            None => Ok(None),
+        }
+    };
+    let from_height = parse_height("from_height")?;
+    let to_height = parse_height("to_height")?;
+    if let (Some(from_height), Some(to_height)) = (from_height, to_height) {
+        if to_height < from_height {
+            return Err(InvalidField {
+                name: "to_height",
+                value: to_height.to_string(),
+            }
+            .into());
+        }
+    }
+    Ok((from_height, to_height))
+}
+
+/// Checks that the `Accept` header, if set, allows responses of type `supported`, either
+/// exactly or via a `type/*` or `*/*` wildcard. Parameters like `q=` are ignored.
+pub fn check_accept(headers: &HeaderMap, supported: &'static str) -> Result<(), Report> {
+    let accept = match headers.get(ACCEPT) {
+        Some(accept) => accept,
+        None => return Ok(()),
+    };
+    let not_acceptable = || NotAcceptable {
+        supported,
+        actual: String::from_utf8_lossy(accept.as_bytes()).into_owned(),
+    };
+    let accept = accept.to_str().map_err(|_| not_acceptable())?;
+    let supported_type = supported.split('/').next().unwrap_or_default();
+    let is_acceptable = accept.split(',').any(|media_range| {
+        let media_range = media_range.split(';').next().unwrap_or_default().trim();
+        let media_range = media_range.to_ascii_lowercase();
+        media_range == supported
+            || media_range == "*/*"
+            || media_range == format!("{}/*", supported_type)
+    });
+    if !is_acceptable {
+        return Err(not_acceptable().into());
+    }
+    Ok(())
+}
+
+/// Payload lengths accepted for a script type, and the payload prefix each of them maps to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PayloadLengths {
+    /// Payloads of any length are accepted.
+    Any(PayloadPrefix),
+    /// Only the listed lengths are accepted.
+    Exact(Vec<(usize, PayloadPrefix)>),
+}
+
+/// Script types accepted in script endpoints and subscriptions, e.g. "p2pkh" in
+/// `/script/p2pkh/:payload/history`. Defaults to the script types Chronik indexes.
+#[derive(Debug, Clone)]
+pub struct ScriptTypes {
+    lengths_by_type: BTreeMap<String, PayloadLengths>,
+}
+
+impl ScriptTypes {
+    pub fn empty() -> Self {
+        ScriptTypes {
+            lengths_by_type: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a script type, or replaces the accepted lengths of an existing one.
+    pub fn with_script_type(
+        mut self,
+        script_type: impl Into<String>,
+        lengths: PayloadLengths,
+    ) -> Self {
+        self.lengths_by_type.insert(script_type.into(), lengths);
+        self
+    }
+
+    pub fn lengths(&self, script_type: &str) -> Option<&PayloadLengths> {
+        self.lengths_by_type.get(script_type)
+    }
+
+    /// Script type whose payloads get the given prefix, i.e. the reverse of `lengths`. Only
+    /// unambiguous if `duplicate_payload_prefix` is None; otherwise the script type that sorts
+    /// first is returned.
+    pub fn script_type(&self, payload_prefix: PayloadPrefix) -> Option<&str> {
+        self.prefixes()
+            .find(|&(_, prefix)| prefix == payload_prefix)
+            .map(|(script_type, _)| script_type)
+    }
+
+    /// A payload prefix that more than one payload length maps to, with the script types of the
+    /// first two of them (which can be the same). Such script types can't be told apart when
+    /// reporting the script type of a payload.
+    pub fn duplicate_payload_prefix(&self) -> Option<(PayloadPrefix, &str, &str)> {
+        let mut script_types_by_prefix = BTreeMap::new();
+        for (script_type, prefix) in self.prefixes() {
+            if let Some(first) = script_types_by_prefix.insert(prefix, script_type) {
+                return Some((prefix, first, script_type));
+            }
+        }
+        None
+    }
+
+    fn prefixes(&self) -> impl Iterator<Item = (&str, PayloadPrefix)> {
+        self.lengths_by_type
+            .iter()
+            .flat_map(|(script_type, lengths)| {
+                let prefixes = match lengths {
+                    PayloadLengths::Any(prefix) => vec![*prefix],
+                    PayloadLengths::Exact(lengths) => {
+                        lengths.iter().map(|&(_, prefix)| prefix).collect()
+                    }
+                };
+                prefixes
+                    .into_iter()
+                    .map(move |prefix| (script_type.as_str(), prefix))
+            })
+    }
+}
+
+impl Default for ScriptTypes {
+    fn default() -> Self {
+        use PayloadLengths::*;
+        ScriptTypes::empty()
+            .with_script_type("other", Any(PayloadPrefix::Other))
+            .with_script_type(
+                "p2pk",
+                Exact(vec![
+                    (PUBKEY_LENGTH, PayloadPrefix::P2PK),
+                    (65, PayloadPrefix::P2PKLegacy),
+                ]),
+            )
+            .with_script_type(
+                "p2pkh",
+                Exact(vec![(ShaRmd160::size(), PayloadPrefix::P2PKH)]),
+            )
+            .with_script_type(
+                "p2sh",
+                Exact(vec![(ShaRmd160::size(), PayloadPrefix::P2SH)]),
+            )
+            .with_script_type(
+                "p2tr-commitment",
+                Exact(vec![(PUBKEY_LENGTH, PayloadPrefix::P2TRCommitment)]),
+            )
+            .with_script_type(
+                "p2tr-state",
+                Exact(vec![(Sha256::size(), PayloadPrefix::P2TRState)]),
+            )
+    }
+}
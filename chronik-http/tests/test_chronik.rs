@@ -1,26 +1,37 @@
-use std::{ffi::OsString, str::FromStr, sync::Arc, time::Duration};
+use std::{collections::HashMap, ffi::OsString, str::FromStr, sync::Arc, time::Duration};
 
 use bitcoinsuite_bitcoind::instance::{BitcoindChain, BitcoindConf, BitcoindInstance};
 use bitcoinsuite_bitcoind_nng::{PubInterface, RpcInterface};
 use bitcoinsuite_core::{
-    lotus_txid, AddressType, BitcoinCode, Bytes, CashAddress, Hashed, Network, Script, Sha256d,
-    ShaRmd160, TxOutput, BCHREG,
+    lotus_txid, AddressType, BitcoinCode, Bytes, CashAddress, Hashed, Network, OutPoint, Script,
+    Sha256d, ShaRmd160, TxOutput, UnhashedTx, BCHREG,
 };
 use bitcoinsuite_ecc_secp256k1::EccSecp256k1;
 use bitcoinsuite_error::Result;
-use bitcoinsuite_slp::{genesis_opreturn, SlpGenesisInfo, SlpTokenType};
+use bitcoinsuite_slp::{
+    genesis_opreturn, send_opreturn, SlpAmount, SlpGenesisInfo, SlpTokenType, TokenId,
+};
 use bitcoinsuite_test_utils::{bin_folder, is_free_tcp, pick_ports};
 use bitcoinsuite_test_utils_blockchain::build_tx;
-use chronik_http::{proto, ChronikServer, CONTENT_TYPE_PROTOBUF};
-use chronik_indexer::SlpIndexer;
+use chronik_http::{
+    proto::{self, chronik_client::ChronikClient},
+    ChronikGrpcServer, ChronikServer, ScriptTypes, CONTENT_TYPE_PROTOBUF,
+    DEFAULT_MAX_TOKEN_LISTINGS, DEFAULT_MAX_VALIDATE_UTXOS, DEFAULT_REQUEST_TIMEOUT,
+};
+use chronik_indexer::{SlpIndexer, UtxoStateVariant};
 use chronik_rocksdb::{
-    Db, IndexDb, IndexMemData, PayloadPrefix, ScriptPayload, ScriptTxsConf, TransientData,
+    Db, IndexDb, IndexMemData, LokadTxsConf, PayloadPrefix, ScriptPayload, ScriptTxsConf,
+    TransientData,
 };
 use futures::{SinkExt, StreamExt};
-use hyper::{header::CONTENT_TYPE, StatusCode};
+use hyper::{
+    header::{ACCEPT, CACHE_CONTROL, CONTENT_TYPE},
+    StatusCode,
+};
 use pretty_assertions::assert_eq;
 use prost::Message;
-use reqwest::Response;
+use reqwest::{Client, Response};
+use serde_json::json;
 use tempdir::TempDir;
 use tokio::{sync::RwLock, time::timeout};
 use tokio_tungstenite::{connect_async, tungstenite::Message as WsMessage};
@@ -51,7 +62,7 @@ async fn test_server() -> Result<()> {
     let outputs_conf = ScriptTxsConf { page_size: 7 };
     let db = Db::open(dir.path().join("index.rocksdb"))?;
     let transient_data = TransientData::open(&dir.path().join("transient.rocksdb"))?;
-    let db = IndexDb::new(db, transient_data, outputs_conf);
+    let db = IndexDb::new(db, transient_data, outputs_conf, LokadTxsConf::default());
     let bitcoind = instance.cli();
     let cache = IndexMemData::new(10);
     let slp_indexer = SlpIndexer::new(
@@ -66,14 +77,45 @@ async fn test_server() -> Result<()> {
     bitcoind.cmd_string("setmocktime", &["2100000000"])?;
 
     let slp_indexer = Arc::new(RwLock::new(slp_indexer));
-    let port = pick_ports(1)?[0];
+    let script_types = Arc::new(ScriptTypes::default());
+    let ports = pick_ports(3)?;
+    let (port, grpc_port, timeout_port) = (ports[0], ports[1], ports[2]);
     let server = ChronikServer {
         addr: ([127, 0, 0, 1], port).into(),
         slp_indexer: Arc::clone(&slp_indexer),
+        request_timeout: DEFAULT_REQUEST_TIMEOUT,
+        script_types: Arc::clone(&script_types),
+        enable_debug_endpoints: true,
+        max_validate_utxos: 4,
+        max_token_listings: 150,
+        ws_batch_interval: None,
+        enable_rpc: true,
+        max_heavy_queries: Some(1),
+        strip_network: false,
     };
     tokio::spawn(server.run());
+    let timeout_server = ChronikServer {
+        addr: ([127, 0, 0, 1], timeout_port).into(),
+        slp_indexer: Arc::clone(&slp_indexer),
+        request_timeout: Duration::from_millis(100),
+        script_types: Arc::clone(&script_types),
+        enable_debug_endpoints: false,
+        max_validate_utxos: DEFAULT_MAX_VALIDATE_UTXOS,
+        max_token_listings: DEFAULT_MAX_TOKEN_LISTINGS,
+        ws_batch_interval: Some(Duration::from_millis(50)),
+        enable_rpc: false,
+        max_heavy_queries: None,
+        strip_network: true,
+    };
+    tokio::spawn(timeout_server.run());
+    let grpc_server = ChronikGrpcServer {
+        addr: ([127, 0, 0, 1], grpc_port).into(),
+        slp_indexer: Arc::clone(&slp_indexer),
+        script_types,
+    };
+    tokio::spawn(grpc_server.run());
     let mut attempt = 0i32;
-    while is_free_tcp(port) {
+    while is_free_tcp(port) || is_free_tcp(grpc_port) || is_free_tcp(timeout_port) {
         if attempt == 100 {
             panic!("Unable to start Chronik server");
         }
@@ -83,6 +125,7 @@ async fn test_server() -> Result<()> {
     let client = reqwest::Client::new();
     let url = format!("http://127.0.0.1:{}", port);
     let ws_url = format!("ws://127.0.0.1:{}", port);
+    let mut grpc_client = ChronikClient::connect(format!("http://127.0.0.1:{}", grpc_port)).await?;
 
     let response = client
         .get(format!("{}/blockchain-info", url))
@@ -98,6 +141,36 @@ async fn test_server() -> Result<()> {
         }
     );
 
+    let response = client.get(format!("{}/status", url)).send().await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], CONTENT_TYPE_PROTOBUF);
+    assert_eq!(
+        proto::Status::decode(response.bytes().await?)?,
+        proto::Status {
+            tip_hash: vec![0; 32],
+            tip_height: -1,
+            is_stalled: false,
+            last_processed_at: 0,
+            last_block_at: 0,
+            is_node_reachable: true,
+        }
+    );
+
+    let response = client.get(format!("{}/tip", url)).send().await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], CONTENT_TYPE_PROTOBUF);
+    assert_eq!(
+        proto::Tip::decode(response.bytes().await?)?,
+        proto::Tip {
+            blockchain_info: Some(proto::BlockchainInfo {
+                tip_hash: vec![0; 32],
+                tip_height: -1,
+            }),
+            raw_header: vec![],
+            raw_coinbase_tx: vec![],
+        }
+    );
+
     let anyone1_script = Script::from_slice(&[0x51]);
     let anyone1_hash = ShaRmd160::digest(anyone1_script.bytecode().clone());
     let anyone1_slice = anyone1_hash.as_slice();
@@ -115,6 +188,43 @@ async fn test_server() -> Result<()> {
     })?;
     assert_eq!(utxos.len(), 10);
 
+    // Replays the coinbase txs of blocks 8 to 10, oldest first
+    let (replay_txs, _) = slp_indexer
+        .read()
+        .await
+        .script_history()
+        .history_page_in_height_range(PayloadPrefix::P2SH, anyone1_slice, 8, 10, 0, 10)?;
+    assert_eq!(replay_txs.len(), 3);
+    let (mut ws_replay_client, _) = connect_async(format!("{}/ws", ws_url)).await?;
+    ws_replay_client
+        .send(WsMessage::binary(
+            proto::Subscription {
+                script_type: "p2sh".to_string(),
+                payload: anyone1_slice.to_vec(),
+                is_subscribe: true,
+                from_height: 8,
+                ..Default::default()
+            }
+            .encode_to_vec(),
+        ))
+        .await?;
+    for replay_tx in &replay_txs {
+        let msg = timeout(Duration::from_millis(50), ws_replay_client.next())
+            .await?
+            .unwrap()?;
+        let msg = proto::SubscribeMsg::decode(msg.into_data().as_slice())?;
+        match msg.msg_type.unwrap() {
+            proto::subscribe_msg::MsgType::Confirmed(confirmed) => {
+                assert_eq!(confirmed.txid, replay_tx.txid.as_slice());
+            }
+            msg => panic!("Unexpected message: {:?}", msg),
+        }
+    }
+    assert!(timeout(Duration::from_millis(50), ws_replay_client.next())
+        .await
+        .is_err());
+    ws_replay_client.close(None).await?;
+
     let anyone2_script = Script::from_slice(&[0x52]);
     let anyone2_hash = ShaRmd160::digest(anyone2_script.bytecode().clone());
     let anyone2_slice = anyone2_hash.as_slice();
@@ -128,12 +238,146 @@ async fn test_server() -> Result<()> {
                 script_type: "p2sh".to_string(),
                 payload: anyone1_slice.to_vec(),
                 is_subscribe: true,
+                outpoint: None,
+                token_id: vec![],
+                include_token_stats: false,
+                get_subscriptions: None,
+                from_height: 0,
+                min_confirmations: 0,
+                address: String::new(),
+            }
+            .encode_to_vec(),
+        ))
+        .await?;
+    // Same subscription, but only confirming txs with 2 confirmations
+    let (mut ws_deep_client, _) = connect_async(format!("{}/ws", ws_url)).await?;
+    ws_deep_client
+        .send(WsMessage::binary(
+            proto::Subscription {
+                script_type: "p2sh".to_string(),
+                payload: anyone1_slice.to_vec(),
+                is_subscribe: true,
+                min_confirmations: 2,
+                ..Default::default()
+            }
+            .encode_to_vec(),
+        ))
+        .await?;
+    // Same subscription on the server batching WS messages
+    let (mut ws_batch_client, _) =
+        connect_async(format!("ws://127.0.0.1:{}/ws", timeout_port)).await?;
+    ws_batch_client
+        .send(WsMessage::binary(
+            proto::Subscription {
+                script_type: "p2sh".to_string(),
+                payload: anyone1_slice.to_vec(),
+                is_subscribe: true,
+                ..Default::default()
+            }
+            .encode_to_vec(),
+        ))
+        .await?;
+    // Same subscription, but by address instead of script type and payload
+    let (mut ws_address_client, _) = connect_async(format!("{}/ws", ws_url)).await?;
+    for address in ["bork", anyone1_address.as_str()] {
+        ws_address_client
+            .send(WsMessage::binary(
+                proto::Subscription {
+                    address: address.to_string(),
+                    is_subscribe: true,
+                    ..Default::default()
+                }
+                .encode_to_vec(),
+            ))
+            .await?;
+    }
+    let msg = timeout(Duration::from_millis(50), ws_address_client.next())
+        .await?
+        .unwrap()?;
+    assert_eq!(
+        proto::Error::decode(msg.into_data().as_slice())?,
+        proto::Error {
+            error_code: "invalid-field".to_string(),
+            msg: "Invalid address: bork".to_string(),
+            is_user_error: true,
+            slp_rejection: None,
+        },
+    );
+    ws_address_client
+        .send(WsMessage::binary(
+            proto::Subscription {
+                address: anyone1_address.as_str().to_string(),
+                is_subscribe: true,
+                min_confirmations: 101,
+                ..Default::default()
             }
             .encode_to_vec(),
         ))
         .await?;
+    let msg = timeout(Duration::from_millis(50), ws_address_client.next())
+        .await?
+        .unwrap()?;
+    assert_eq!(
+        proto::Error::decode(msg.into_data().as_slice())?,
+        proto::Error {
+            error_code: "invalid-field".to_string(),
+            msg: "Invalid min_confirmations: 101".to_string(),
+            is_user_error: true,
+            slp_rejection: None,
+        },
+    );
+    let mut grpc_script_msgs = grpc_client
+        .subscribe_script(proto::SubscribeScriptRequest {
+            script_type: "p2sh".to_string(),
+            payload: anyone1_slice.to_vec(),
+        })
+        .await?
+        .into_inner();
 
     let utxo = utxos.pop().unwrap();
+    let outpoint_subscription = proto::Subscription {
+        script_type: String::new(),
+        payload: vec![],
+        is_subscribe: true,
+        outpoint: Some(proto::OutPoint {
+            txid: utxo.outpoint.txid.as_slice().to_vec(),
+            out_idx: utxo.outpoint.out_idx,
+        }),
+        token_id: vec![],
+        include_token_stats: false,
+        get_subscriptions: None,
+        from_height: 0,
+        min_confirmations: 0,
+        address: String::new(),
+    };
+    let (mut ws_outpoint_client, _) = connect_async(format!("{}/ws", ws_url)).await?;
+    ws_outpoint_client
+        .send(WsMessage::binary(outpoint_subscription.encode_to_vec()))
+        .await?;
+
+    // Only lists the subscriptions of this connection, not the script one of `ws_client`
+    ws_outpoint_client
+        .send(WsMessage::binary(
+            proto::Subscription {
+                get_subscriptions: Some(proto::GetSubscriptions {}),
+                ..Default::default()
+            }
+            .encode_to_vec(),
+        ))
+        .await?;
+    let msg = timeout(Duration::from_millis(50), ws_outpoint_client.next())
+        .await?
+        .unwrap()?;
+    let msg = proto::SubscribeMsg::decode(msg.into_data().as_slice())?;
+    match msg.msg_type.unwrap() {
+        proto::subscribe_msg::MsgType::Subscriptions(subscriptions) => {
+            assert_eq!(
+                subscriptions.subscriptions,
+                vec![outpoint_subscription.clone()]
+            );
+        }
+        msg => panic!("Unexpected message: {:?}", msg),
+    }
     let leftover_value = utxo.output.value - 20_000;
     let tx = build_tx(
         utxo.outpoint.clone(),
@@ -150,16 +394,54 @@ async fn test_server() -> Result<()> {
         ],
     );
 
+    let broadcast_request = proto::BroadcastTxRequest {
+        raw_tx: tx.ser().to_vec(),
+        skip_slp_check: false,
+        slp_burn_policy: proto::SlpBurnPolicy::RejectAnyBurn as i32,
+    }
+    .encode_to_vec();
+    for (content_type, accept, status, error_code, msg) in [
+        (
+            None,
+            None,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "no-content-type-set",
+            "No Content-Type set, must be application/x-protobuf",
+        ),
+        (
+            Some("application/json"),
+            None,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "wrong-content-type",
+            "Content-Type must be application/x-protobuf, got application/json",
+        ),
+        (
+            Some(CONTENT_TYPE_PROTOBUF),
+            Some("application/json, text/html;q=0.9"),
+            StatusCode::NOT_ACCEPTABLE,
+            "not-acceptable",
+            "Accept must allow application/x-protobuf, got application/json, text/html;q=0.9",
+        ),
+    ] {
+        let mut request = client
+            .post(format!("{}/broadcast-tx", url))
+            .body(broadcast_request.clone());
+        if let Some(content_type) = content_type {
+            request = request.header(CONTENT_TYPE, content_type);
+        }
+        if let Some(accept) = accept {
+            request = request.header(ACCEPT, accept);
+        }
+        let response = request.send().await?;
+        assert_eq!(response.status(), status);
+        check_proto_error(response, error_code, msg, false).await?;
+    }
+
     let response = client
         .post(format!("{}/broadcast-tx", url))
         .header(CONTENT_TYPE, CONTENT_TYPE_PROTOBUF)
-        .body(
-            proto::BroadcastTxRequest {
-                raw_tx: tx.ser().to_vec(),
-                skip_slp_check: false,
-            }
-            .encode_to_vec(),
-        )
+        .header(ACCEPT, "application/*;q=0.5, application/json")
+        .body(broadcast_request)
         .send()
         .await?;
     assert_eq!(response.status(), StatusCode::OK);
@@ -181,6 +463,53 @@ async fn test_server() -> Result<()> {
         msg => panic!("Unexpected message: {:?}", msg),
     }
 
+    // Same msg for the subscription by address
+    let msg = timeout(Duration::from_millis(50), ws_address_client.next())
+        .await?
+        .unwrap()?;
+    let msg = proto::SubscribeMsg::decode(msg.into_data().as_slice())?;
+    match msg.msg_type.unwrap() {
+        proto::subscribe_msg::MsgType::AddedToMempool(added_to_mempool) => {
+            assert_eq!(added_to_mempool.txid, txid.as_slice());
+        }
+        msg => panic!("Unexpected message: {:?}", msg),
+    }
+
+    // Same msg from the gRPC stream
+    let msg = timeout(Duration::from_millis(50), grpc_script_msgs.message())
+        .await??
+        .unwrap();
+    match msg.msg_type.unwrap() {
+        proto::subscribe_msg::MsgType::AddedToMempool(added_to_mempool) => {
+            assert_eq!(added_to_mempool.txid, txid.as_slice());
+        }
+        msg => panic!("Unexpected message: {:?}", msg),
+    }
+
+    // Spend of the subscribed outpoint; sent right away if subscribing after the spend
+    let expected_outpoint_spent = proto::MsgOutpointSpent {
+        spending_txid: txid.as_slice().to_vec(),
+        input_idx: 0,
+        outpoint: outpoint_subscription.outpoint.clone(),
+        is_confirmed: false,
+    };
+    let (mut ws_late_outpoint_client, _) = connect_async(format!("{}/ws", ws_url)).await?;
+    ws_late_outpoint_client
+        .send(WsMessage::binary(outpoint_subscription.encode_to_vec()))
+        .await?;
+    for ws_client in [&mut ws_outpoint_client, &mut ws_late_outpoint_client] {
+        let msg = timeout(Duration::from_millis(50), ws_client.next())
+            .await?
+            .unwrap()?;
+        let msg = proto::SubscribeMsg::decode(msg.into_data().as_slice())?;
+        match msg.msg_type.unwrap() {
+            proto::subscribe_msg::MsgType::OutpointSpent(outpoint_spent) => {
+                assert_eq!(outpoint_spent, expected_outpoint_spent);
+            }
+            msg => panic!("Unexpected message: {:?}", msg),
+        }
+    }
+
     let response = client.get(format!("{}/tx/ab", url)).send().await?;
     assert_eq!(response.status(), StatusCode::BAD_REQUEST);
     check_proto_error(
@@ -242,142 +571,401 @@ async fn test_server() -> Result<()> {
         size: 117,
         is_coinbase: false,
         network: proto::Network::Xpi as i32,
+        raw: vec![],
+        confirmations: 0,
     };
 
     assert_eq!(proto_tx, expected_tx.clone());
 
-    let coinbase_utxo = utxos.pop().unwrap();
     let response = client
-        .get(format!("{}/tx/{}", url, coinbase_utxo.outpoint.txid))
+        .get(format!("{}/tx/{}?include_raw=true", url, txid))
         .send()
         .await?;
     assert_eq!(response.status(), StatusCode::OK);
     let proto_tx = proto::Tx::decode(response.bytes().await?)?;
-    assert!(proto_tx.is_coinbase);
+    assert_eq!(
+        proto_tx,
+        proto::Tx {
+            raw: tx.ser().to_vec(),
+            ..expected_tx.clone()
+        },
+    );
 
     let response = client
-        .get(format!("{}/raw-tx/{}", url, txid))
+        .get(format!("{}/tx/{}?include_raw=yes", url, txid))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    check_proto_error(response, "invalid-field", "Invalid include_raw: yes", true).await?;
+
+    let response = client.get(format!("{}/tx/{}", url, txid)).send().await?;
+    let full_size = response.bytes().await?.len();
+    let response = client
+        .get(format!("{}/tx/{}?strip_input_scripts=true", url, txid))
         .send()
         .await?;
     assert_eq!(response.status(), StatusCode::OK);
-    assert_eq!(response.headers()[CONTENT_TYPE], "application/octet-stream");
-    let raw_tx = response.bytes().await?;
-    assert_eq!(Bytes::from_bytes(raw_tx), tx.ser());
+    let stripped_response = response.bytes().await?;
+    let mut stripped_tx = expected_tx.clone();
+    stripped_tx.inputs[0].input_script = vec![];
+    assert_eq!(proto::Tx::decode(stripped_response.clone())?, stripped_tx);
+    assert_eq!(full_size, expected_tx.encoded_len());
+    assert_eq!(stripped_response.len(), stripped_tx.encoded_len());
+    assert!(stripped_response.len() + tx.inputs[0].script.bytecode().len() <= full_size);
 
     let response = client
         .get(format!(
-            "{}/script/bork/{}/history",
+            "{}/script/p2sh/{}/history?strip_input_scripts=true",
             url,
             hex::encode(anyone2_slice),
         ))
         .send()
         .await?;
-    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
-    check_proto_error(response, "invalid-field", "Invalid script_type: bork", true).await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let proto_page = proto::TxHistoryPage::decode(response.bytes().await?)?;
+    assert_eq!(proto_page.txs, vec![stripped_tx]);
 
+    // Mempool txs have 0 confirmations
     let response = client
         .get(format!(
-            "{}/script/p2sh/{}/history",
+            "{}/script/p2sh/{}/history?include_confirmations=true",
             url,
-            hex::encode(&[0; 19]),
+            hex::encode(anyone2_slice),
         ))
         .send()
         .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let proto_page = proto::TxHistoryPage::decode(response.bytes().await?)?;
+    assert_eq!(proto_page.txs, vec![expected_tx.clone()]);
+
+    let response = client
+        .get(format!("{}/tx/{}?strip_input_scripts=1", url, txid))
+        .send()
+        .await?;
     assert_eq!(response.status(), StatusCode::BAD_REQUEST);
     check_proto_error(
         response,
-        "invalid-script-payload-length",
-        "Invalid script payload: length expected to be one of [20], got 19",
-        false,
+        "invalid-field",
+        "Invalid strip_input_scripts: 1",
+        true,
     )
     .await?;
 
+    let grpc_tx = grpc_client
+        .get_tx(proto::GetTxRequest {
+            txid: txid.as_slice().to_vec(),
+            include_raw: false,
+        })
+        .await?
+        .into_inner();
+    assert_eq!(grpc_tx, expected_tx.clone());
+
+    let unknown_txid = Sha256d::new([0x42; 32]);
+    let status = grpc_client
+        .get_tx(proto::GetTxRequest {
+            txid: unknown_txid.as_slice().to_vec(),
+            include_raw: false,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::NotFound);
+    assert_eq!(
+        proto::Error::decode(status.details())?,
+        proto::Error {
+            error_code: "tx-not-found".to_string(),
+            msg: format!("Txid not found: {}", unknown_txid),
+            is_user_error: true,
+            slp_rejection: None,
+        },
+    );
+
+    let coinbase_utxo = utxos.pop().unwrap();
     let response = client
-        .get(format!(
-            "{}/script/p2sh/{}/history?page=ab&page_num=10",
-            url,
-            hex::encode(anyone2_slice),
-        ))
+        .get(format!("{}/tx/{}", url, coinbase_utxo.outpoint.txid))
         .send()
         .await?;
-    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
-    check_proto_error(response, "invalid-field", "Invalid page: ab", true).await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let proto_tx = proto::Tx::decode(response.bytes().await?)?;
+    assert!(proto_tx.is_coinbase);
+    assert_eq!(proto_tx.network, proto::Network::Xpi as i32);
 
+    // The timeout server is configured with strip_network, so `network` is left unset
     let response = client
         .get(format!(
-            "{}/script/p2sh/{}/history?page=0&page_size=cd",
-            url,
-            hex::encode(anyone2_slice),
+            "http://127.0.0.1:{}/tx/{}",
+            timeout_port, coinbase_utxo.outpoint.txid,
         ))
         .send()
         .await?;
-    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
-    check_proto_error(response, "invalid-field", "Invalid page_size: cd", true).await?;
-
+    assert_eq!(response.status(), StatusCode::OK);
+    let stripped_tx = proto::Tx::decode(response.bytes().await?)?;
+    assert_eq!(stripped_tx.network, 0);
+    assert_eq!(
+        stripped_tx,
+        proto::Tx {
+            network: 0,
+            ..proto_tx.clone()
+        },
+    );
     let response = client
         .get(format!(
-            "{}/script/p2sh/{}/history?page=0&page_size=10",
-            url,
-            hex::encode(anyone2_slice),
+            "http://127.0.0.1:{}/script/p2sh/{}/utxos",
+            timeout_port,
+            hex::encode(anyone1_slice),
         ))
         .send()
         .await?;
     assert_eq!(response.status(), StatusCode::OK);
-    assert_eq!(response.headers()[CONTENT_TYPE], CONTENT_TYPE_PROTOBUF);
-    let proto_page = proto::TxHistoryPage::decode(response.bytes().await?)?;
-    assert_eq!(proto_page.txs, vec![expected_tx.clone()]);
+    let proto_utxos = proto::Utxos::decode(response.bytes().await?)?;
+    assert!(proto_utxos
+        .script_utxos
+        .iter()
+        .flat_map(|script_utxos| &script_utxos.utxos)
+        .all(|utxo| utxo.network == 0));
 
     let response = client
-        .get(format!(
-            "{}/script/p2sh/{}/utxos",
-            url,
-            hex::encode(anyone2_slice),
-        ))
+        .get(format!("{}/tx/{}/conflict-status", url, txid))
         .send()
         .await?;
     assert_eq!(response.status(), StatusCode::OK);
-    assert_eq!(response.headers()[CONTENT_TYPE], CONTENT_TYPE_PROTOBUF);
-    let proto_utxos = proto::Utxos::decode(response.bytes().await?)?;
+    let conflict_status = proto::ConflictStatus::decode(response.bytes().await?)?;
     assert_eq!(
-        proto_utxos,
-        proto::Utxos {
-            script_utxos: vec![proto::ScriptUtxos {
-                output_script: anyone2_address.to_script().bytecode().to_vec(),
-                utxos: vec![proto::Utxo {
-                    outpoint: Some(proto::OutPoint {
-                        txid: txid.as_slice().to_vec(),
-                        out_idx: 1,
-                    }),
-                    block_height: -1,
-                    is_coinbase: false,
-                    value: leftover_value,
-                    slp_meta: None,
-                    slp_token: None,
-                    network: proto::Network::Xpi as i32,
-                }],
-            }],
-        }
+        conflict_status,
+        proto::ConflictStatus {
+            is_conflicting: false,
+            conflicting_txid: vec![],
+        },
     );
 
+    // Not mined yet
     let response = client
-        .post(format!("{}/validate-utxos", url))
-        .header(CONTENT_TYPE, CONTENT_TYPE_PROTOBUF)
-        .body(
-            proto::ValidateUtxoRequest {
-                outpoints: vec![
-                    proto::OutPoint {
-                        txid: utxo.outpoint.txid.as_slice().to_vec(),
-                        out_idx: utxo.outpoint.out_idx,
-                    },
-                    proto::OutPoint {
-                        txid: txid.as_slice().to_vec(),
-                        out_idx: 1,
-                    },
-                    proto::OutPoint {
-                        txid: txid.as_slice().to_vec(),
-                        out_idx: 2,
-                    },
-                    proto::OutPoint {
+        .get(format!("{}/tx/{}/location", url, txid))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        proto::TxLocation::decode(response.bytes().await?)?,
+        proto::TxLocation {
+            block_height: -1,
+            ..Default::default()
+        },
+    );
+    // No merkle proof for mempool txs
+    let response = client
+        .get(format!("{}/tx/{}/merkle-proof", url, txid))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    check_proto_error(
+        response,
+        "tx-not-found",
+        &format!("Txid not found: {}", txid),
+        true,
+    )
+    .await?;
+
+    let response = client
+        .get(format!("{}/tx/{}/location", url, unknown_txid))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    check_proto_error(
+        response,
+        "tx-not-found",
+        &format!("Txid not found: {}", unknown_txid),
+        true,
+    )
+    .await?;
+
+    let response = client
+        .get(format!("{}/tx/{}/conflict-status", url, unknown_txid))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    check_proto_error(
+        response,
+        "tx-not-found",
+        &format!("Txid not found: {}", unknown_txid),
+        true,
+    )
+    .await?;
+
+    let response = client
+        .get(format!("{}/raw-tx/{}", url, txid))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], "application/octet-stream");
+    let raw_tx = response.bytes().await?;
+    assert_eq!(Bytes::from_bytes(raw_tx), tx.ser());
+
+    let response = client
+        .get(format!(
+            "{}/script/bork/{}/history",
+            url,
+            hex::encode(anyone2_slice),
+        ))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    check_proto_error(response, "invalid-field", "Invalid script_type: bork", true).await?;
+
+    let response = client
+        .get(format!(
+            "{}/script/p2sh/{}/history",
+            url,
+            hex::encode(&[0; 19]),
+        ))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    check_proto_error(
+        response,
+        "invalid-script-payload-length",
+        "Invalid script payload: length expected to be one of [20], got 19",
+        false,
+    )
+    .await?;
+
+    let response = client
+        .get(format!(
+            "{}/script/p2pk/{}/utxos",
+            url,
+            hex::encode(&[0; 20]),
+        ))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    check_proto_error(
+        response,
+        "invalid-script-payload-length",
+        "Invalid script payload: length expected to be one of [33, 65], got 20",
+        false,
+    )
+    .await?;
+
+    let response = client
+        .get(format!(
+            "{}/script/p2sh/{}/history?page=ab&page_num=10",
+            url,
+            hex::encode(anyone2_slice),
+        ))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    check_proto_error(response, "invalid-field", "Invalid page: ab", true).await?;
+
+    let response = client
+        .get(format!(
+            "{}/script/p2sh/{}/history?page=0&page_size=cd",
+            url,
+            hex::encode(anyone2_slice),
+        ))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    check_proto_error(response, "invalid-field", "Invalid page_size: cd", true).await?;
+
+    let response = client
+        .get(format!(
+            "{}/script/p2sh/{}/history?page=0&page_size=10",
+            url,
+            hex::encode(anyone2_slice),
+        ))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], CONTENT_TYPE_PROTOBUF);
+    let proto_page = proto::TxHistoryPage::decode(response.bytes().await?)?;
+    assert_eq!(proto_page.txs, vec![expected_tx.clone()]);
+
+    let response = client
+        .get(format!(
+            "{}/script/p2sh/{}/history?from_height=0&to_height=1000",
+            url,
+            hex::encode(anyone2_slice),
+        ))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let proto_page = proto::TxHistoryPage::decode(response.bytes().await?)?;
+    // Mempool txs are excluded from height ranges
+    assert_eq!(proto_page.txs, vec![]);
+    assert_eq!(proto_page.num_pages, 0);
+
+    let response = client
+        .get(format!(
+            "{}/script/p2sh/{}/history?from_height=5&to_height=4",
+            url,
+            hex::encode(anyone2_slice),
+        ))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    check_proto_error(response, "invalid-field", "Invalid to_height: 4", true).await?;
+
+    let response = client
+        .get(format!(
+            "{}/script/p2sh/{}/history?from_height=0&page_size=0",
+            url,
+            hex::encode(anyone2_slice),
+        ))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    check_proto_error(response, "invalid-field", "Invalid page_size: 0", true).await?;
+
+    let response = client
+        .get(format!(
+            "{}/script/p2sh/{}/utxos",
+            url,
+            hex::encode(anyone2_slice),
+        ))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], CONTENT_TYPE_PROTOBUF);
+    let proto_utxos = proto::Utxos::decode(response.bytes().await?)?;
+    assert_eq!(
+        proto_utxos,
+        proto::Utxos {
+            script_utxos: vec![proto::ScriptUtxos {
+                output_script: anyone2_address.to_script().bytecode().to_vec(),
+                utxos: vec![proto::Utxo {
+                    outpoint: Some(proto::OutPoint {
+                        txid: txid.as_slice().to_vec(),
+                        out_idx: 1,
+                    }),
+                    block_height: -1,
+                    is_coinbase: false,
+                    value: leftover_value,
+                    slp_meta: None,
+                    slp_token: None,
+                    network: proto::Network::Xpi as i32,
+                }],
+            }],
+        }
+    );
+
+    let response = client
+        .post(format!("{}/validate-utxos", url))
+        .header(CONTENT_TYPE, CONTENT_TYPE_PROTOBUF)
+        .body(
+            proto::ValidateUtxoRequest {
+                outpoints: vec![
+                    proto::OutPoint {
+                        txid: utxo.outpoint.txid.as_slice().to_vec(),
+                        out_idx: utxo.outpoint.out_idx,
+                    },
+                    proto::OutPoint {
+                        txid: txid.as_slice().to_vec(),
+                        out_idx: 1,
+                    },
+                    proto::OutPoint {
+                        txid: txid.as_slice().to_vec(),
+                        out_idx: 2,
+                    },
+                    proto::OutPoint {
                         txid: vec![3; 32],
                         out_idx: 0,
                     },
@@ -398,34 +986,62 @@ async fn test_server() -> Result<()> {
                     height: 10,
                     is_confirmed: true,
                     state: proto::UtxoStateVariant::Spent as i32,
+                    slp_meta: None,
+                    slp_token: None,
                 },
                 proto::UtxoState {
                     height: -1,
                     is_confirmed: false,
                     state: proto::UtxoStateVariant::Unspent as i32,
+                    slp_meta: None,
+                    slp_token: None,
                 },
                 proto::UtxoState {
                     height: -1,
                     is_confirmed: false,
                     state: proto::UtxoStateVariant::NoSuchOutput as i32,
+                    slp_meta: None,
+                    slp_token: None,
                 },
                 proto::UtxoState {
                     height: -1,
                     is_confirmed: false,
                     state: proto::UtxoStateVariant::NoSuchTx as i32,
+                    slp_meta: None,
+                    slp_token: None,
                 }
             ],
         }
     );
 
+    // More outpoints than `max_validate_utxos` allows
+    let response = client
+        .post(format!("{}/validate-utxos", url))
+        .header(CONTENT_TYPE, CONTENT_TYPE_PROTOBUF)
+        .body(
+            proto::ValidateUtxoRequest {
+                outpoints: (0..5)
+                    .map(|out_idx| proto::OutPoint {
+                        txid: txid.as_slice().to_vec(),
+                        out_idx,
+                    })
+                    .collect(),
+            }
+            .encode_to_vec(),
+        )
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    check_proto_error(response, "page-size-too-large", "Page size too large", true).await?;
+
     let hashes = bitcoind.cmd_json("generatetoaddress", &["1", burn_address.as_str()])?;
     slp_indexer.write().await.process_next_msg()?;
 
-    let mut n_attempt = 0;
-    loop {
-        n_attempt += 1;
-        if n_attempt > 100 {
-            panic!("Too many attempts");
+    // All Confirmed messages of the block arrive before its BlockConnected message
+    let mut confirmed_txids = Vec::new();
+    let block_connected = loop {
+        if confirmed_txids.len() > 100 {
+            panic!("Too many messages");
         }
         // msg from ws (within 50ms)
         let msg = timeout(Duration::from_millis(50), ws_client.next())
@@ -439,31 +1055,452 @@ async fn test_server() -> Result<()> {
                     Sha256d::from_slice(&block_connected.block_hash)?,
                     Sha256d::from_hex_be(hashes[0].as_str().unwrap())?,
                 );
-                break;
+                break block_connected;
+            }
+            proto::subscribe_msg::MsgType::Confirmed(confirmed) => {
+                confirmed_txids.push(confirmed.txid);
             }
-            proto::subscribe_msg::MsgType::Confirmed(_) => {}
             msg => panic!("Unexpected message: {:?}", msg),
         }
+    };
+    assert!(confirmed_txids.contains(&txid.as_slice().to_vec()));
+    // The reward matches the coinbase tx and the stats of the block
+    {
+        let slp_indexer = slp_indexer.read().await;
+        let block = slp_indexer.blocks().tip()?.unwrap();
+        // None of the block's Confirmed messages came after the BlockConnected message
+        let (block_script_txs, _) = slp_indexer.script_history().history_page_in_height_range(
+            PayloadPrefix::P2SH,
+            anyone1_slice,
+            block.height,
+            block.height,
+            0,
+            200,
+        )?;
+        let mut block_script_txids = block_script_txs
+            .iter()
+            .map(|tx| tx.txid.as_slice().to_vec())
+            .collect::<Vec<_>>();
+        block_script_txids.sort();
+        let mut sorted_confirmed_txids = confirmed_txids.clone();
+        sorted_confirmed_txids.sort();
+        assert_eq!(sorted_confirmed_txids, block_script_txids);
+        let block_stats = slp_indexer.blocks().block_stats(&block)?;
+        let coinbase_tx = &slp_indexer.blocks().block_txs_by_hash(&block.hash)?[0];
+        assert_eq!(block_connected.block_height, block.height);
+        assert_eq!(
+            block_connected.coinbase_outputs,
+            coinbase_tx
+                .tx
+                .outputs()
+                .iter()
+                .map(|output| proto::TxOutput {
+                    value: output.value,
+                    output_script: output.script.bytecode().to_vec(),
+                    slp_token: None,
+                    spent_by: None,
+                })
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(
+            block_connected.fees,
+            block_stats.sum_input_sats - block_stats.sum_normal_output_sats,
+        );
+        assert!(block_connected.fees > 0);
+        assert_eq!(
+            block_connected.subsidy + block_connected.fees,
+            block_stats.sum_coinbase_output_sats,
+        );
     }
+    assert!(timeout(Duration::from_millis(50), ws_client.next())
+        .await
+        .is_err());
 
-    for (path, error_code, msg) in [
-        ("/blocks/-1/10", "invalid-field", "Invalid start_height: -1"),
-        ("/blocks/10/-1", "invalid-field", "Invalid end_height: -1"),
-        ("/blocks/10/5", "invalid-field", "Invalid end_height: 5"),
-        (
-            "/blocks/10/510",
-            "page-size-too-large",
-            "Page size too large",
-        ),
-    ] {
-        let response = client.get(format!("{}{}", url, path)).send().await?;
-        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
-        check_proto_error(response, error_code, msg, true).await?;
+    // The outpoint subscriptions stayed open after the mempool spend and get the confirmed one
+    let expected_outpoint_spent = proto::MsgOutpointSpent {
+        is_confirmed: true,
+        ..expected_outpoint_spent
+    };
+    for ws_client in [&mut ws_outpoint_client, &mut ws_late_outpoint_client] {
+        let msg = timeout(Duration::from_millis(50), ws_client.next())
+            .await?
+            .unwrap()?;
+        let msg = proto::SubscribeMsg::decode(msg.into_data().as_slice())?;
+        match msg.msg_type.unwrap() {
+            proto::subscribe_msg::MsgType::OutpointSpent(outpoint_spent) => {
+                assert_eq!(outpoint_spent, expected_outpoint_spent);
+            }
+            msg => panic!("Unexpected message: {:?}", msg),
+        }
     }
 
-    let response = client.get(format!("{}/blocks/0/200", url)).send().await?;
-    assert_eq!(response.status(), StatusCode::OK);
-    assert_eq!(response.headers()[CONTENT_TYPE], CONTENT_TYPE_PROTOBUF);
+    // With 1 confirmation, the txs of the block are held back from the deep subscription
+    loop {
+        let msg = timeout(Duration::from_millis(50), ws_deep_client.next())
+            .await?
+            .unwrap()?;
+        let msg = proto::SubscribeMsg::decode(msg.into_data().as_slice())?;
+        match msg.msg_type.unwrap() {
+            proto::subscribe_msg::MsgType::BlockConnected(deep_block_connected) => {
+                assert_eq!(deep_block_connected, block_connected);
+                break;
+            }
+            proto::subscribe_msg::MsgType::AddedToMempool(_) => {}
+            msg => panic!("Unexpected message: {:?}", msg),
+        }
+    }
+
+    // The batching server sends the same messages in the same order, but in fewer frames
+    let mut batched_msgs = Vec::new();
+    let mut num_frames = 0;
+    while let Ok(msg) = timeout(Duration::from_millis(200), ws_batch_client.next()).await {
+        let batch = proto::SubscribeMsgBatch::decode(msg.unwrap()?.into_data().as_slice())?;
+        batched_msgs.extend(batch.msgs.into_iter().map(|msg| msg.msg_type.unwrap()));
+        num_frames += 1;
+    }
+    use proto::subscribe_msg::MsgType;
+    let mut expected_msgs = vec![MsgType::AddedToMempool(proto::MsgAddedToMempool {
+        txid: txid.as_slice().to_vec(),
+    })];
+    expected_msgs.extend(
+        confirmed_txids
+            .iter()
+            .map(|txid| MsgType::Confirmed(proto::MsgConfirmed { txid: txid.clone() })),
+    );
+    expected_msgs.push(MsgType::BlockConnected(block_connected));
+    assert_eq!(batched_msgs, expected_msgs);
+    assert!(num_frames < expected_msgs.len());
+    ws_batch_client.close(None).await?;
+
+    let response = client.get(format!("{}/status", url)).send().await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let status = proto::Status::decode(response.bytes().await?)?;
+    assert_eq!(status.tip_height, 111);
+    assert_eq!(status.last_block_at, 2_100_000_020);
+    assert!(status.last_processed_at > 0);
+
+    // Confirmations are computed against the tip at height 111
+    let response = client
+        .get(format!(
+            "{}/script/p2sh/{}/history?include_confirmations=true&page_size=200",
+            url,
+            hex::encode(anyone2_slice),
+        ))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let proto_page = proto::TxHistoryPage::decode(response.bytes().await?)?;
+    assert!(!proto_page.txs.is_empty());
+    for tx in &proto_page.txs {
+        let block = tx.block.as_ref().unwrap();
+        assert_eq!(tx.confirmations, (111 - block.height + 1) as u32);
+    }
+    let proto_tx = proto_page
+        .txs
+        .iter()
+        .find(|tx| tx.txid == txid.as_slice())
+        .unwrap();
+    assert_eq!(proto_tx.confirmations, 1);
+    let response = client
+        .get(format!(
+            "{}/script/p2sh/{}/history?include_confirmations=1",
+            url,
+            hex::encode(anyone2_slice),
+        ))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    check_proto_error(
+        response,
+        "invalid-field",
+        "Invalid include_confirmations: 1",
+        true,
+    )
+    .await?;
+
+    // The output of anyone1 spent by the broadcast tx in block 111
+    for query in ["from_height=111&to_height=111", "from_height=111&from_ts=0"] {
+        let response = client
+            .get(format!(
+                "{}/script/p2sh/{}/spent-outputs?{}",
+                url,
+                hex::encode(anyone1_slice),
+                query,
+            ))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let proto_page = proto::SpentOutputsPage::decode(response.bytes().await?)?;
+        assert_eq!(proto_page.num_pages, 1);
+        let spent_output = &proto_page.spent_outputs[0];
+        assert_eq!(
+            spent_output,
+            &proto::SpentOutput {
+                outpoint: Some(proto::OutPoint {
+                    txid: utxo.outpoint.txid.as_slice().to_vec(),
+                    out_idx: utxo.outpoint.out_idx,
+                }),
+                value: utxo.output.value,
+                output_script: anyone1_script.to_p2sh().bytecode().to_vec(),
+                slp_token: None,
+                created_height: utxo.block.as_ref().unwrap().height,
+                spending_txid: txid.as_slice().to_vec(),
+                input_idx: 0,
+                spending_block: spent_output.spending_block.clone(),
+            },
+        );
+        assert_eq!(spent_output.spending_block.as_ref().unwrap().height, 111);
+    }
+    // No blocks beyond the tip, and none with a median timestamp of 0
+    for query in ["from_height=112", "to_ts=0"] {
+        let response = client
+            .get(format!(
+                "{}/script/p2sh/{}/spent-outputs?{}",
+                url,
+                hex::encode(anyone1_slice),
+                query,
+            ))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            proto::SpentOutputsPage::decode(response.bytes().await?)?,
+            proto::SpentOutputsPage {
+                spent_outputs: vec![],
+                num_pages: 0,
+            },
+        );
+    }
+    let response = client
+        .get(format!(
+            "{}/script/p2sh/{}/spent-outputs?page_size=0",
+            url,
+            hex::encode(anyone1_slice),
+        ))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    check_proto_error(response, "invalid-field", "Invalid page_size: 0", true).await?;
+    // Height ranges are validated the same way for every endpoint
+    for endpoint in ["spent-outputs", "token-flows"] {
+        let response = client
+            .get(format!(
+                "{}/script/p2sh/{}/{}?token_id={}&from_height=5&to_height=4",
+                url,
+                hex::encode(anyone1_slice),
+                endpoint,
+                hex::encode([7; 32]),
+            ))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        check_proto_error(response, "invalid-field", "Invalid to_height: 4", true).await?;
+    }
+    // Token flows need the GENESIS of the token
+    let unknown_token_id = Sha256d::new([7; 32]);
+    let response = client
+        .get(format!(
+            "{}/script/p2sh/{}/token-flows?token_id={}",
+            url,
+            hex::encode(anyone1_slice),
+            unknown_token_id,
+        ))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    check_proto_error(
+        response,
+        "token-txid-not-found",
+        &format!("Token txid not found: {}", unknown_token_id),
+        true,
+    )
+    .await?;
+
+    let response = client.get(format!("{}/", url)).send().await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], CONTENT_TYPE_PROTOBUF);
+    let server_info = proto::ServerInfo::decode(response.bytes().await?)?;
+    assert_eq!(server_info.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(server_info.network, proto::Network::Xpi as i32);
+    assert_eq!(server_info.tip_height, 111);
+    assert!(!server_info.is_watch_only);
+    assert!(!server_info.ignores_unknown_token_types);
+    assert!(server_info.has_spends_index);
+    for endpoint in ["GET /", "GET /tx/:txid", "POST /broadcast-tx", "GET /ws"] {
+        assert!(server_info.endpoints.contains(&endpoint.to_string()));
+    }
+    // Debug endpoints are enabled for this server
+    assert!(server_info
+        .endpoints
+        .contains(&"GET /debug/tx-num/:txid".to_string()));
+    assert!(server_info.endpoints.contains(&"POST /rpc".to_string()));
+
+    // The JSON-RPC shim answers like the node for the fields it supports
+    assert_eq!(
+        rpc_call(&client, &url, "getblockcount", json!([])).await?,
+        json!(111)
+    );
+    let block_hash = hashes[0].as_str().unwrap();
+    let node_block = bitcoind.cmd_json("getblock", &[block_hash])?;
+    let rpc_block = rpc_call(&client, &url, "getblock", json!([block_hash])).await?;
+    assert_eq!(rpc_block["hash"].as_str(), Some(block_hash));
+    assert_eq!(rpc_block["height"].as_i64(), Some(111));
+    assert_eq!(rpc_block["confirmations"].as_i64(), Some(1));
+    assert_eq!(rpc_block["time"].as_i64(), node_block["time"].as_i64());
+    assert_eq!(rpc_block["nTx"].as_i64(), node_block["nTx"].as_i64());
+    assert_eq!(
+        rpc_block["previousblockhash"].as_str(),
+        node_block["previousblockhash"].as_str(),
+    );
+    assert_eq!(rpc_block.get("nextblockhash"), None);
+    let num_txs = node_block["nTx"].as_u64().unwrap() as usize;
+    assert_eq!(rpc_block["tx"].as_array().unwrap().len(), num_txs);
+    for tx_idx in 0..num_txs {
+        assert_eq!(
+            rpc_block["tx"][tx_idx].as_str(),
+            node_block["tx"][tx_idx].as_str(),
+        );
+    }
+    let rpc_tx = rpc_call(&client, &url, "gettransaction", json!([txid.to_hex_be()])).await?;
+    let raw_tx = slp_indexer.read().await.txs().raw_tx_by_id(&txid)?.unwrap();
+    assert_eq!(rpc_tx["txid"], json!(txid.to_hex_be()));
+    assert_eq!(rpc_tx["blockhash"], json!(block_hash));
+    assert_eq!(rpc_tx["blockheight"], json!(111));
+    assert_eq!(rpc_tx["confirmations"], json!(1));
+    assert_eq!(rpc_tx["hex"], json!(hex::encode(raw_tx.to_vec())));
+    // Errors are reported with bitcoind's error codes
+    let rpc_error = |code: i32, message: &str| json!({ "code": code, "message": message });
+    let response = client
+        .post(format!("{}/rpc", url))
+        .body(json!({ "id": 1, "method": "getbestblockhash" }).to_string())
+        .send()
+        .await?;
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(&response.bytes().await?)?,
+        json!({ "result": null, "error": rpc_error(-32601, "Method not found"), "id": 1 }),
+    );
+    for (method, params, expected_error) in [
+        (
+            "getblock",
+            json!([Sha256d::new([7; 32]).to_hex_be()]),
+            rpc_error(-5, "Block not found"),
+        ),
+        (
+            "getblock",
+            json!([block_hash, 2]),
+            rpc_error(-8, "Verbosity 2 not supported, only 1 is"),
+        ),
+        (
+            "gettransaction",
+            json!(["abc"]),
+            rpc_error(-8, "txid must be a 64 character hex string"),
+        ),
+        (
+            "gettransaction",
+            json!([Sha256d::new([7; 32]).to_hex_be()]),
+            rpc_error(-5, "No such mempool or blockchain transaction"),
+        ),
+    ] {
+        let response = client
+            .post(format!("{}/rpc", url))
+            .body(json!({ "id": 1, "method": method, "params": params }).to_string())
+            .send()
+            .await?;
+        let response = serde_json::from_slice::<serde_json::Value>(&response.bytes().await?)?;
+        assert_eq!(response["error"], expected_error);
+        assert_eq!(response["result"], json!(null));
+    }
+    let response = client.post(format!("{}/rpc", url)).body("{").send().await?;
+    let response = serde_json::from_slice::<serde_json::Value>(&response.bytes().await?)?;
+    assert_eq!(response["error"]["code"], json!(-32700));
+    // Not served on the other server
+    let response = client
+        .post(format!("http://127.0.0.1:{}/rpc", timeout_port))
+        .body(json!({ "id": 1, "method": "getblockcount" }).to_string())
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Addresses resolve to the scripts they're indexed under
+    let p2pkh_hash = ShaRmd160::new([1; 20]);
+    let p2pkh_address = CashAddress::from_hash(BCHREG, AddressType::P2PKH, p2pkh_hash.clone());
+    for (address, script_type, payload) in [
+        (&anyone1_address, "p2sh", anyone1_slice),
+        (&p2pkh_address, "p2pkh", p2pkh_hash.as_slice()),
+    ] {
+        let response = client
+            .get(format!("{}/address/{}/scripts", url, address.as_str()))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let path = format!("/script/{}/{}", script_type, hex::encode(payload));
+        assert_eq!(
+            proto::AddressScripts::decode(response.bytes().await?)?,
+            proto::AddressScripts {
+                scripts: vec![proto::AddressScript {
+                    script_type: script_type.to_string(),
+                    payload: payload.to_vec(),
+                    history_path: format!("{}/history", path),
+                    utxos_path: format!("{}/utxos", path),
+                }],
+            },
+        );
+    }
+    let response = client
+        .get(format!("{}/address/bchreg:xyz/scripts", url))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    check_proto_error(
+        response,
+        "invalid-field",
+        "Invalid address: bchreg:xyz",
+        true,
+    )
+    .await?;
+
+    let response = client.get(format!("{}/metrics", url)).send().await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let cache_metrics = proto::Metrics::decode(response.bytes().await?)?
+        .script_history_cache
+        .unwrap();
+    let num_queries = cache_metrics.num_hits + cache_metrics.num_misses;
+    assert_eq!(cache_metrics.capacity, 10);
+    assert!(cache_metrics.num_entries > 0 && cache_metrics.num_entries <= 10);
+    assert!(cache_metrics.num_hits > 0);
+    assert_eq!(
+        cache_metrics.hit_ratio,
+        cache_metrics.num_hits as f64 / num_queries as f64,
+    );
+    // Shrinking the cache (as on SIGHUP) evicts entries, but keeps the counters
+    slp_indexer.write().await.resize_script_history_cache(1);
+    let response = client.get(format!("{}/metrics", url)).send().await?;
+    let resized_metrics = proto::Metrics::decode(response.bytes().await?)?
+        .script_history_cache
+        .unwrap();
+    assert_eq!(resized_metrics.capacity, 1);
+    assert_eq!(resized_metrics.num_entries, 1);
+    assert_eq!(resized_metrics.num_hits, cache_metrics.num_hits);
+    assert_eq!(resized_metrics.num_misses, cache_metrics.num_misses);
+
+    for (path, error_code, msg) in [
+        ("/blocks/-1/10", "invalid-field", "Invalid start_height: -1"),
+        ("/blocks/10/-1", "invalid-field", "Invalid end_height: -1"),
+        ("/blocks/10/5", "invalid-field", "Invalid end_height: 5"),
+        (
+            "/blocks/10/510",
+            "page-size-too-large",
+            "Page size too large",
+        ),
+    ] {
+        let response = client.get(format!("{}{}", url, path)).send().await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        check_proto_error(response, error_code, msg, true).await?;
+    }
+
+    let response = client.get(format!("{}/blocks/0/200", url)).send().await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], CONTENT_TYPE_PROTOBUF);
     let proto_blocks = proto::Blocks::decode(response.bytes().await?)?;
     assert_eq!(proto_blocks.blocks.len(), 112);
     {
@@ -568,11 +1605,248 @@ async fn test_server() -> Result<()> {
             proto::Block {
                 block_info: Some(block_info),
                 block_details: Some(block_details),
-                raw_header,
+                raw_header: raw_header.clone(),
                 txs: vec![proto_block.txs[0].clone(), expected_tx],
             }
         );
 
+        // The raw block starts with the header and ends with the last tx
+        for hash_or_height in [cur_hash.to_string(), "111".to_string()] {
+            let response = client
+                .get(format!("{}/raw-block/{}", url, hash_or_height))
+                .send()
+                .await?;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()[CONTENT_TYPE], "application/octet-stream");
+            let raw_block = response.bytes().await?;
+            assert!(raw_block.starts_with(&raw_header));
+            assert!(raw_block.ends_with(&tx.ser().to_vec()));
+        }
+        let response = client.get(format!("{}/raw-block/112", url)).send().await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        check_proto_error(response, "block-not-found", "Block not found: 112", true).await?;
+
+        let grpc_block = grpc_client
+            .get_block(proto::GetBlockRequest {
+                hash_or_height: "111".to_string(),
+            })
+            .await?
+            .into_inner();
+        assert_eq!(grpc_block, proto_block);
+
+        for hash_or_height in [cur_hash.to_string(), "111".to_string()] {
+            let response = client
+                .get(format!("{}/block/{}/info", url, hash_or_height))
+                .send()
+                .await?;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()[CONTENT_TYPE], CONTENT_TYPE_PROTOBUF);
+            assert_eq!(
+                proto::BlockSummary::decode(response.bytes().await?)?,
+                proto::BlockSummary {
+                    block_info: proto_block.block_info.clone(),
+                    block_details: proto_block.block_details.clone(),
+                    raw_header: raw_header.clone(),
+                },
+            );
+        }
+        // Missing block stats fail requests until they're recomputed by the indexer
+        slp_indexer.read().await.db().delete_block_stats(111)?;
+        let response = client.get(format!("{}/block/111/info", url)).send().await?;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        check_proto_error(
+            response,
+            "internal-server-error",
+            "Internal server error",
+            false,
+        )
+        .await?;
+        assert!(slp_indexer
+            .read()
+            .await
+            .db()
+            .block_stats()?
+            .by_height(111)?
+            .is_none());
+        assert_eq!(slp_indexer.write().await.repair_block_stats()?, 1);
+        assert_eq!(slp_indexer.write().await.repair_block_stats()?, 0);
+        let response = client.get(format!("{}/block/111/info", url)).send().await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            proto::BlockSummary::decode(response.bytes().await?)?.block_info,
+            proto_block.block_info,
+        );
+        assert!(slp_indexer
+            .read()
+            .await
+            .db()
+            .block_stats()?
+            .by_height(111)?
+            .is_some());
+        let response = client
+            .get(format!("{}/block/{}/inputs", url, cur_hash))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], CONTENT_TYPE_PROTOBUF);
+        assert_eq!(
+            proto::BlockInputs::decode(response.bytes().await?)?,
+            proto::BlockInputs {
+                inputs: vec![proto::BlockInput {
+                    txid: proto_block.txs[1].txid.clone(),
+                    input_idx: 0,
+                    input: Some(proto_block.txs[1].inputs[0].clone()),
+                }],
+                num_pages: 1,
+            },
+        );
+        let response = client
+            .get(format!("{}/block/111/coinbase-script", url))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], CONTENT_TYPE_PROTOBUF);
+        let coinbase_script = proto_block.txs[0].inputs[0].input_script.clone();
+        assert_eq!(
+            proto::CoinbaseScript::decode(response.bytes().await?)?,
+            proto::CoinbaseScript {
+                utf8: String::from_utf8(coinbase_script.clone()).unwrap_or_default(),
+                script: coinbase_script,
+            },
+        );
+        // With 2 txs, the branch of each tx is just the other txid
+        for (index, proto_tx) in proto_block.txs.iter().enumerate() {
+            let txid = Sha256d::from_slice(&proto_tx.txid)?;
+            let response = client
+                .get(format!("{}/tx/{}/merkle-proof", url, txid))
+                .send()
+                .await?;
+            assert_eq!(response.status(), StatusCode::OK);
+            let merkle_proof = proto::MerkleProof::decode(response.bytes().await?)?;
+            assert_eq!(
+                merkle_proof,
+                proto::MerkleProof {
+                    block_hash: cur_hash.as_slice().to_vec(),
+                    index: index as u32,
+                    branch: vec![proto_block.txs[1 - index].txid.clone()],
+                },
+            );
+            // Hashing the txid up the branch must yield the header's merkle root
+            let mut hash = txid;
+            let mut node_index = merkle_proof.index;
+            for sibling in &merkle_proof.branch {
+                let sibling = Sha256d::from_slice(sibling)?;
+                let (left, right) = match node_index % 2 {
+                    0 => (hash, sibling),
+                    _ => (sibling, hash),
+                };
+                hash = Sha256d::digest(Bytes::from_bytes(
+                    [left.as_slice(), right.as_slice()].concat(),
+                ));
+                node_index /= 2;
+            }
+            assert_eq!(
+                hash.as_slice(),
+                proto_block.block_details.as_ref().unwrap().merkle_root,
+            );
+        }
+        for (index, proto_tx) in proto_block.txs.iter().enumerate() {
+            let response = client
+                .get(format!("{}/block/111/tx/{}", url, index))
+                .send()
+                .await?;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()[CONTENT_TYPE], CONTENT_TYPE_PROTOBUF);
+            assert_eq!(
+                proto::Tx::decode(response.bytes().await?)?.txid,
+                proto_tx.txid
+            );
+            let txid = Sha256d::from_slice(&proto_tx.txid)?;
+            let response = client
+                .get(format!("{}/tx/{}/location", url, txid))
+                .send()
+                .await?;
+            assert_eq!(response.status(), StatusCode::OK);
+            let tx_location = proto::TxLocation::decode(response.bytes().await?)?;
+            assert_eq!(tx_location.block_height, 111);
+            assert_eq!(
+                tx_location.block_hash,
+                proto_block.block_info.as_ref().unwrap().hash,
+            );
+            assert_eq!(tx_location.index_in_block as usize, index);
+            // Every block before has exactly one tx
+            assert_eq!(tx_location.tx_num, 111 + index as u64);
+        }
+        let response = client
+            .get(format!("{}/block/111/tx/{}", url, proto_block.txs.len()))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        check_proto_error(
+            response,
+            "block-tx-not-found",
+            &format!("Block 111 has no tx at index {}", proto_block.txs.len()),
+            true,
+        )
+        .await?;
+        let coinbase_txid = Sha256d::from_slice(&proto_block.txs[0].txid)?;
+        let response = client
+            .get(format!("{}/debug/tx-num/{}", url, coinbase_txid))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], CONTENT_TYPE_PROTOBUF);
+        let debug_tx_num = proto::DebugTxNum::decode(response.bytes().await?)?;
+        // Every block before has exactly one tx
+        assert_eq!(debug_tx_num.tx_num, 111);
+        assert_eq!(debug_tx_num.block_height, 111);
+        assert!(debug_tx_num.is_coinbase);
+        assert_eq!(debug_tx_num.token_num, -1);
+        // Debug endpoints are disabled on the other server
+        let response = client
+            .get(format!(
+                "http://127.0.0.1:{}/debug/tx-num/{}",
+                timeout_port, coinbase_txid,
+            ))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = client
+            .get(format!("http://127.0.0.1:{}/admin/growth", timeout_port))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = client.get(format!("{}/admin/growth", url)).send().await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let db_growth = proto::DbGrowth::decode(response.bytes().await?)?;
+        // All blocks were connected since startup
+        assert_eq!(db_growth.num_blocks, 112);
+        assert!(db_growth.avg_bytes_per_block > 0.0);
+        let writer_total = db_growth
+            .writers
+            .iter()
+            .map(|writer| writer.avg_bytes_per_block)
+            .sum::<f64>();
+        assert!((writer_total - db_growth.avg_bytes_per_block).abs() < 1e-6);
+        assert!(db_growth.writers.iter().any(|writer| writer.name == "txs"));
+        let response = client
+            .get(format!("{}/block/111/inputs?page_size=0", url))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        check_proto_error(response, "invalid-field", "Invalid page_size: 0", true).await?;
+
+        let response = client.get(format!("{}/block/112/info", url)).send().await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        check_proto_error(response, "block-not-found", "Block not found: 112", true).await?;
+        let response = client
+            .get(format!("{}/block/112/coinbase-script", url))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        check_proto_error(response, "block-not-found", "Block not found: 112", true).await?;
+
         let response = client
             .get(format!("{}/blockchain-info", url))
             .send()
@@ -586,6 +1860,24 @@ async fn test_server() -> Result<()> {
                 tip_height: 111,
             }
         );
+
+        let response = client.get(format!("{}/tip", url)).send().await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], CONTENT_TYPE_PROTOBUF);
+        let tip = proto::Tip::decode(response.bytes().await?)?;
+        assert_eq!(
+            tip.blockchain_info,
+            Some(proto::BlockchainInfo {
+                tip_hash: cur_hash.as_slice().to_vec(),
+                tip_height: 111,
+            }),
+        );
+        assert_eq!(tip.raw_header, raw_header);
+        let coinbase_tx = UnhashedTx::deser(&mut Bytes::from_slice(&tip.raw_coinbase_tx))?;
+        assert_eq!(
+            lotus_txid(&coinbase_tx).as_slice(),
+            proto_block.txs[0].txid.as_slice(),
+        );
     }
 
     let response = client.get(format!("{}/blocks/10/20", url)).send().await?;
@@ -594,24 +1886,326 @@ async fn test_server() -> Result<()> {
     let proto_blocks_smaller = proto::Blocks::decode(response.bytes().await?)?;
     assert_eq!(proto_blocks_smaller.blocks, proto_blocks.blocks[10..=20]);
 
-    // Test atomic multi-tx broadcast
-    let utxo = utxos.pop().unwrap();
-    let leftover_value = utxo.output.value - 20_000;
-    let tx1 = build_tx(
-        utxo.outpoint.clone(),
-        &anyone1_script,
-        vec![
-            TxOutput {
-                value: 6_000,
-                script: burn_address.to_script(),
-            },
-            TxOutput {
-                value: leftover_value,
-                script: anyone2_script.to_p2sh(),
-            },
-        ],
-    );
-    let utxo = utxos.pop().unwrap();
+    // Only one heavy query is served at a time: hold the indexer so the first one stays in
+    // progress, then the second one is rejected right away
+    {
+        let indexer_guard = slp_indexer.write().await;
+        let first_query = tokio::spawn(client.get(format!("{}/blocks/10/20", url)).send());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let response = client
+            .get(format!("{}/blocks-in-time/0/1", url))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        check_proto_error(
+            response,
+            "too-many-heavy-queries",
+            "Too many heavy queries in progress, try again later",
+            false,
+        )
+        .await?;
+        std::mem::drop(indexer_guard);
+        let response = first_query.await??;
+        assert_eq!(response.status(), StatusCode::OK);
+        // The limit is free again afterwards
+        let response = client.get(format!("{}/blocks/10/20", url)).send().await?;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    // An export keeps its slot while its body is still streaming, not just until the headers
+    {
+        let indexer_guard = slp_indexer.write().await;
+        let export_response = client
+            .get(format!("{}/export/blocks/0/111", url))
+            .send()
+            .await?;
+        assert_eq!(export_response.status(), StatusCode::OK);
+        let response = client.get(format!("{}/blocks/10/20", url)).send().await?;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        check_proto_error(
+            response,
+            "too-many-heavy-queries",
+            "Too many heavy queries in progress, try again later",
+            false,
+        )
+        .await?;
+        // A second export is limited the same way
+        let response = client
+            .get(format!("{}/export/blocks/0/1", url))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        std::mem::drop(indexer_guard);
+        let mut body = export_response.bytes().await?;
+        let mut num_exported_blocks = 0;
+        while !body.is_empty() {
+            proto::Block::decode_length_delimited(&mut body)?;
+            num_exported_blocks += 1;
+        }
+        assert_eq!(num_exported_blocks, 112);
+        // Released once the stream ended and got dropped by the server
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let response = client.get(format!("{}/blocks/10/20", url)).send().await?;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    let median_timestamps = (0..proto_blocks.blocks.len())
+        .map(|height| {
+            let mut timestamps = proto_blocks.blocks[height.saturating_sub(10)..=height]
+                .iter()
+                .map(|block| block.timestamp)
+                .collect::<Vec<_>>();
+            timestamps.sort_unstable();
+            timestamps[timestamps.len() / 2]
+        })
+        .collect::<Vec<_>>();
+    let (start_ts, end_ts) = (median_timestamps[50], median_timestamps[60]);
+    let response = client
+        .get(format!("{}/blocks-in-time/{}/{}", url, start_ts, end_ts))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let proto_blocks_in_time = proto::Blocks::decode(response.bytes().await?)?;
+    let expected_blocks = proto_blocks
+        .blocks
+        .iter()
+        .zip(&median_timestamps)
+        .filter(|(_, &median_timestamp)| (start_ts..=end_ts).contains(&median_timestamp))
+        .map(|(block, _)| block.clone())
+        .collect::<Vec<_>>();
+    assert!(!expected_blocks.is_empty());
+    assert_eq!(proto_blocks_in_time.blocks, expected_blocks);
+    assert_eq!(proto_blocks_in_time.next_start_height, None);
+    // Continuing from a height within the window skips the blocks below it
+    let next_height = expected_blocks[1].height;
+    let response = client
+        .get(format!(
+            "{}/blocks-in-time/{}/{}?start_height={}",
+            url, start_ts, end_ts, next_height,
+        ))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let proto_blocks_in_time = proto::Blocks::decode(response.bytes().await?)?;
+    assert_eq!(proto_blocks_in_time.blocks, expected_blocks[1..]);
+    assert_eq!(proto_blocks_in_time.next_start_height, None);
+    let response = client
+        .get(format!("{}/blocks-in-time/0/1?start_height=-1", url))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    check_proto_error(response, "invalid-field", "Invalid start_height: -1", true).await?;
+    // Window covering the entire chain
+    let response = client
+        .get(format!("{}/blocks-in-time/0/{}", url, i64::MAX))
+        .send()
+        .await?;
+    let proto_blocks_in_time = proto::Blocks::decode(response.bytes().await?)?;
+    assert_eq!(proto_blocks_in_time.blocks, proto_blocks.blocks);
+    // Window before genesis
+    let response = client
+        .get(format!("{}/blocks-in-time/0/1000", url))
+        .send()
+        .await?;
+    let proto_blocks_in_time = proto::Blocks::decode(response.bytes().await?)?;
+    assert_eq!(proto_blocks_in_time.blocks, vec![]);
+    let response = client
+        .get(format!("{}/blocks-in-time/1000/999", url))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    check_proto_error(response, "invalid-field", "Invalid end_ts: 999", true).await?;
+
+    let response = client
+        .get(format!("{}/export/blocks/108/200", url))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], CONTENT_TYPE_PROTOBUF);
+    let mut body = response.bytes().await?;
+    let mut exported_blocks = Vec::new();
+    while !body.is_empty() {
+        exported_blocks.push(proto::Block::decode_length_delimited(&mut body)?);
+    }
+    // Stops at the tip (height 111)
+    assert_eq!(exported_blocks.len(), 4);
+    for (exported_block, block_info) in exported_blocks.iter().zip(&proto_blocks.blocks[108..]) {
+        assert_eq!(exported_block.block_info.as_ref(), Some(block_info));
+        assert_eq!(exported_block.block_details, None);
+        assert_eq!(exported_block.txs.len() as u64, block_info.num_txs);
+    }
+    let response = client
+        .get(format!("{}/export/blocks/20/10", url))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    check_proto_error(response, "invalid-field", "Invalid end_height: 10", true).await?;
+
+    let response = client
+        .get(format!("{}/export/utxos?script_type=p2sh", url))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], CONTENT_TYPE_PROTOBUF);
+    let mut body = response.bytes().await?;
+    let mut exported_utxos = Vec::new();
+    while !body.is_empty() {
+        exported_utxos.push(proto::ExportedUtxo::decode_length_delimited(&mut body)?);
+    }
+    assert!(exported_utxos.iter().all(|utxo| utxo.script_type == "p2sh"));
+    // Only confirmed UTXOs are exported
+    let db_utxos = slp_indexer
+        .read()
+        .await
+        .db()
+        .utxos()?
+        .utxos(PayloadPrefix::P2SH, anyone1_slice)?;
+    assert_eq!(
+        exported_utxos
+            .iter()
+            .filter(|utxo| utxo.payload == anyone1_slice)
+            .count(),
+        db_utxos.len(),
+    );
+    let response = client
+        .get(format!("{}/export/utxos?script_type=p2x", url))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    check_proto_error(response, "invalid-field", "Invalid script_type: p2x", true).await?;
+    // Only served with debug endpoints enabled
+    let response = client
+        .get(format!("http://127.0.0.1:{}/export/utxos", timeout_port))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Dormant UTXOs are the oldest ones first
+    let response = client
+        .get(format!(
+            "{}/dormant-utxos?older_than_height=11&limit=3",
+            url
+        ))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let old_utxos = proto::DormantUtxos::decode(response.bytes().await?)?.utxos;
+    assert_eq!(old_utxos.len(), 3);
+    for (utxo, next_utxo) in old_utxos.iter().zip(&old_utxos[1..]) {
+        assert!(utxo.created_height <= next_utxo.created_height);
+    }
+    assert!(old_utxos.iter().all(|utxo| utxo.created_height < 11));
+    // Above the tip, recent UTXOs (e.g. coinbases of burn_address) are included too, after the
+    // old ones
+    let response = client
+        .get(format!(
+            "{}/dormant-utxos?older_than_height=1000&limit=1000",
+            url
+        ))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let all_utxos = proto::DormantUtxos::decode(response.bytes().await?)?.utxos;
+    assert_eq!(all_utxos[..3], old_utxos[..]);
+    assert!(all_utxos.iter().any(|utxo| utxo.created_height >= 100));
+    for (query, code, msg) in [
+        ("limit=3", "invalid-field", "Invalid older_than_height: "),
+        (
+            "older_than_height=-1",
+            "invalid-field",
+            "Invalid older_than_height: -1",
+        ),
+        (
+            "older_than_height=11&limit=0",
+            "invalid-field",
+            "Invalid limit: 0",
+        ),
+        (
+            "older_than_height=11&limit=1001",
+            "page-size-too-large",
+            "Page size too large",
+        ),
+    ] {
+        let response = client
+            .get(format!("{}/dormant-utxos?{}", url, query))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        check_proto_error(response, code, msg, true).await?;
+    }
+    let response = client
+        .get(format!(
+            "http://127.0.0.1:{}/dormant-utxos?older_than_height=11",
+            timeout_port
+        ))
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Unknown hashes in the locator are skipped, headers start after the first known block
+    let response = client
+        .post(format!("{}/headers-since", url))
+        .header(CONTENT_TYPE, CONTENT_TYPE_PROTOBUF)
+        .body(
+            proto::HeadersSinceRequest {
+                locator: vec![vec![7; 32], proto_blocks.blocks[108].hash.clone()],
+            }
+            .encode_to_vec(),
+        )
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], CONTENT_TYPE_PROTOBUF);
+    assert_eq!(
+        proto::HeadersSinceResponse::decode(response.bytes().await?)?.raw_headers,
+        exported_blocks[1..]
+            .iter()
+            .map(|exported_block| exported_block.raw_header.clone())
+            .collect::<Vec<_>>(),
+    );
+    // Without any known block, headers start at genesis
+    let response = client
+        .post(format!("{}/headers-since", url))
+        .header(CONTENT_TYPE, CONTENT_TYPE_PROTOBUF)
+        .body(proto::HeadersSinceRequest { locator: vec![] }.encode_to_vec())
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let raw_headers = proto::HeadersSinceResponse::decode(response.bytes().await?)?.raw_headers;
+    assert_eq!(raw_headers.len(), 112);
+    assert_eq!(raw_headers[111], exported_blocks[3].raw_header);
+    let response = client
+        .post(format!("{}/headers-since", url))
+        .header(CONTENT_TYPE, CONTENT_TYPE_PROTOBUF)
+        .body(
+            proto::HeadersSinceRequest {
+                locator: vec![vec![1, 2, 3]],
+            }
+            .encode_to_vec(),
+        )
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    check_proto_error(response, "invalid-field", "Invalid locator: 010203", true).await?;
+
+    // Test atomic multi-tx broadcast
+    let utxo = utxos.pop().unwrap();
+    let leftover_value = utxo.output.value - 20_000;
+    let tx1 = build_tx(
+        utxo.outpoint.clone(),
+        &anyone1_script,
+        vec![
+            TxOutput {
+                value: 6_000,
+                script: burn_address.to_script(),
+            },
+            TxOutput {
+                value: leftover_value,
+                script: anyone2_script.to_p2sh(),
+            },
+        ],
+    );
+    let utxo = utxos.pop().unwrap();
     let leftover_value = utxo.output.value - 20_000;
     let mut tx2 = build_tx(
         utxo.outpoint.clone(),
@@ -634,6 +2228,28 @@ async fn test_server() -> Result<()> {
             proto::BroadcastTxsRequest {
                 raw_txs: vec![tx1.ser().to_vec(), tx2.ser().to_vec()],
                 skip_slp_check: false,
+                slp_burn_policy: 7,
+            }
+            .encode_to_vec(),
+        )
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    check_proto_error(
+        response,
+        "invalid-field",
+        "Invalid slp_burn_policy: 7",
+        true,
+    )
+    .await?;
+    let response = client
+        .post(format!("{}{}", url, "/broadcast-txs"))
+        .header(CONTENT_TYPE, CONTENT_TYPE_PROTOBUF)
+        .body(
+            proto::BroadcastTxsRequest {
+                raw_txs: vec![tx1.ser().to_vec(), tx2.ser().to_vec()],
+                skip_slp_check: false,
+                slp_burn_policy: proto::SlpBurnPolicy::RejectAnyBurn as i32,
             }
             .encode_to_vec(),
         )
@@ -655,6 +2271,7 @@ async fn test_server() -> Result<()> {
             proto::BroadcastTxsRequest {
                 raw_txs: vec![tx1.ser().to_vec(), tx2.ser().to_vec()],
                 skip_slp_check: false,
+                slp_burn_policy: proto::SlpBurnPolicy::RejectAnyBurn as i32,
             }
             .encode_to_vec(),
         )
@@ -674,6 +2291,86 @@ async fn test_server() -> Result<()> {
     slp_indexer.write().await.process_next_msg()?;
     slp_indexer.write().await.process_next_msg()?;
 
+    // Test min_confirmations; tip is at height 111
+    for (script_slice, min_confirmations, expected_num_utxos) in [
+        // 1 UTXO confirmed at height 111, 2 in the mempool
+        (anyone2_slice, None, 3),
+        (anyone2_slice, Some(0), 3),
+        (anyone2_slice, Some(1), 1),
+        (anyone2_slice, Some(2), 0),
+        // 7 UTXOs confirmed at heights 1 to 10
+        (anyone1_slice, Some(1), 7),
+        (anyone1_slice, Some(102), 7),
+        (anyone1_slice, Some(112), 0),
+    ] {
+        let query = match min_confirmations {
+            Some(min_confirmations) => format!("?min_confirmations={}", min_confirmations),
+            None => String::new(),
+        };
+        let response = client
+            .get(format!(
+                "{}/script/p2sh/{}/utxos{}",
+                url,
+                hex::encode(script_slice),
+                query,
+            ))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let proto_utxos = proto::Utxos::decode(response.bytes().await?)?;
+        let utxos = proto_utxos
+            .script_utxos
+            .iter()
+            .flat_map(|script_utxos| &script_utxos.utxos)
+            .collect::<Vec<_>>();
+        assert_eq!(utxos.len(), expected_num_utxos);
+        for utxo in utxos {
+            let num_confirmations = match utxo.block_height {
+                -1 => 0,
+                height => 111 - height + 1,
+            };
+            assert!(num_confirmations >= min_confirmations.unwrap_or_default());
+        }
+    }
+    {
+        // Confirmations depend on the height of each UTXO
+        let response = client
+            .get(format!(
+                "{}/script/p2sh/{}/utxos?min_confirmations=106",
+                url,
+                hex::encode(anyone1_slice),
+            ))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let proto_utxos = proto::Utxos::decode(response.bytes().await?)?;
+        let utxos = proto_utxos
+            .script_utxos
+            .iter()
+            .flat_map(|script_utxos| &script_utxos.utxos)
+            .collect::<Vec<_>>();
+        assert!(!utxos.is_empty());
+        for utxo in utxos {
+            assert!(utxo.block_height <= 6);
+        }
+        let response = client
+            .get(format!(
+                "{}/script/p2sh/{}/utxos?min_confirmations=-1",
+                url,
+                hex::encode(anyone1_slice),
+            ))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        check_proto_error(
+            response,
+            "invalid-field",
+            "Invalid min_confirmations: -1",
+            true,
+        )
+        .await?;
+    }
+
     {
         // Test SLP
         let utxo = utxos.pop().unwrap();
@@ -706,6 +2403,7 @@ async fn test_server() -> Result<()> {
                 proto::BroadcastTxRequest {
                     raw_tx: tx.ser().to_vec(),
                     skip_slp_check: false,
+                    slp_burn_policy: proto::SlpBurnPolicy::RejectAnyBurn as i32,
                 }
                 .encode_to_vec(),
             )
@@ -714,6 +2412,7 @@ async fn test_server() -> Result<()> {
         assert_eq!(response.status(), StatusCode::OK);
         let response = proto::BroadcastTxResponse::decode(response.bytes().await?)?;
         let txid = Sha256d::from_slice(&response.txid)?;
+        let htw_token_id = TokenId::new(txid.clone());
         slp_indexer.write().await.process_next_msg()?;
 
         let response = client.get(format!("{}/token/{}", url, txid)).send().await?;
@@ -742,19 +2441,1096 @@ async fn test_server() -> Result<()> {
                 token_stats: Some(proto::TokenStats {
                     total_minted: "1234".to_string(),
                     total_burned: "0".to_string(),
+                    supply_overflowed: false,
                 }),
                 block: None,
                 time_first_seen: 2_100_000_000,
                 initial_token_quantity: 1234,
                 contains_baton: false,
                 network: proto::Network::Xpi.into(),
+                genesis_raw: vec![],
+                // Only in the mempool
+                first_active_height: -1,
+                last_active_height: -1,
             },
         );
-    }
+        let response = client
+            .get(format!("{}/token/{}?include_genesis_raw=true", url, txid))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let proto_token = proto::Token::decode(response.bytes().await?)?;
+        assert_eq!(proto_token.genesis_raw, tx.ser().to_vec());
+        let response = client
+            .get(format!("{}/token/{}?include_genesis_raw=yes", url, txid))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        check_proto_error(
+            response,
+            "invalid-field",
+            "Invalid include_genesis_raw: yes",
+            true,
+        )
+        .await?;
 
-    instance.cleanup()?;
+        // Amounts converted using the token's 4 decimals, both ways
+        for query in ["display=1.5", "display=1.50000", "base_units=15000"] {
+            let response = client
+                .get(format!("{}/token/{}/parse-amount?{}", url, txid, query))
+                .send()
+                .await?;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                proto::TokenAmount::decode(response.bytes().await?)?,
+                proto::TokenAmount {
+                    base_units: 15000,
+                    display: "1.5000".to_string(),
+                    decimals: 4,
+                },
+            );
+        }
+        for (query, error_code, msg) in [
+            (
+                "display=1.00001",
+                "too-many-decimals",
+                "Token amount 1.00001 has more than 4 decimals",
+            ),
+            (
+                "display=1844674407370955.1616",
+                "token-amount-too-large",
+                "Token amount 1844674407370955.1616 exceeds the max of 2^64-1 base units",
+            ),
+            (
+                "display=1,5",
+                "invalid-token-amount",
+                "Invalid token amount: \"1,5\"",
+            ),
+            (
+                "",
+                "invalid-amount-query",
+                "Set exactly one of display and base_units",
+            ),
+            (
+                "display=1&base_units=10000",
+                "invalid-amount-query",
+                "Set exactly one of display and base_units",
+            ),
+        ] {
+            let response = client
+                .get(format!("{}/token/{}/parse-amount?{}", url, txid, query))
+                .send()
+                .await?;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            check_proto_error(response, error_code, msg, true).await?;
+        }
 
-    Ok(())
+        // SLP state of a token output and a non-SLP output
+        let non_slp_outpoint = utxos.last().unwrap().outpoint.clone();
+        let response = client
+            .post(format!("{}/validate-utxos?include_slp=true", url))
+            .header(CONTENT_TYPE, CONTENT_TYPE_PROTOBUF)
+            .body(
+                proto::ValidateUtxoRequest {
+                    outpoints: vec![
+                        proto::OutPoint {
+                            txid: txid.as_slice().to_vec(),
+                            out_idx: 1,
+                        },
+                        proto::OutPoint {
+                            txid: non_slp_outpoint.txid.as_slice().to_vec(),
+                            out_idx: non_slp_outpoint.out_idx,
+                        },
+                    ],
+                }
+                .encode_to_vec(),
+            )
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let utxo_states = proto::ValidateUtxoResponse::decode(response.bytes().await?)?.utxo_states;
+        assert_eq!(utxo_states.len(), 2);
+        assert_eq!(
+            utxo_states[0].state,
+            proto::UtxoStateVariant::Unspent as i32,
+        );
+        assert_eq!(
+            utxo_states[0].slp_meta,
+            Some(proto::SlpMeta {
+                token_type: proto::SlpTokenType::Fungible as i32,
+                tx_type: proto::SlpTxType::Genesis as i32,
+                token_id: txid.to_vec_be(),
+                group_token_id: vec![],
+            }),
+        );
+        assert_eq!(
+            utxo_states[0].slp_token,
+            Some(proto::SlpToken {
+                amount: 1234,
+                is_mint_baton: false,
+            }),
+        );
+        assert_eq!(
+            utxo_states[1].state,
+            proto::UtxoStateVariant::Unspent as i32,
+        );
+        assert_eq!(utxo_states[1].slp_meta, None);
+        assert_eq!(utxo_states[1].slp_token, None);
+
+        // GENESIS with both a token amount and a mint baton output
+        let baton_genesis_info = SlpGenesisInfo {
+            token_ticker: b"BTN".as_slice().into(),
+            token_name: b"Baton token".as_slice().into(),
+            token_document_url: b"".as_slice().into(),
+            token_document_hash: None,
+            decimals: 0,
+        };
+        let utxo = utxos.pop().unwrap();
+        let leftover_value = utxo.output.value - 20_000;
+        let tx = build_tx(
+            utxo.outpoint,
+            &anyone1_script,
+            vec![
+                TxOutput {
+                    value: 0,
+                    script: genesis_opreturn(
+                        &baton_genesis_info,
+                        SlpTokenType::Fungible,
+                        Some(2),
+                        100,
+                    ),
+                },
+                TxOutput {
+                    value: leftover_value,
+                    script: anyone2_script.to_p2sh(),
+                },
+                TxOutput {
+                    value: 10_000,
+                    script: anyone2_script.to_p2sh(),
+                },
+                TxOutput {
+                    value: 5_000,
+                    script: anyone2_script.to_p2sh(),
+                },
+                TxOutput {
+                    value: 2_000,
+                    script: anyone1_script.to_p2sh(),
+                },
+            ],
+        );
+        let response = client
+            .post(format!("{}/broadcast-tx", url))
+            .header(CONTENT_TYPE, CONTENT_TYPE_PROTOBUF)
+            .body(
+                proto::BroadcastTxRequest {
+                    raw_tx: tx.ser().to_vec(),
+                    skip_slp_check: false,
+                    slp_burn_policy: proto::SlpBurnPolicy::RejectAnyBurn as i32,
+                }
+                .encode_to_vec(),
+            )
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = proto::BroadcastTxResponse::decode(response.bytes().await?)?;
+        let txid = Sha256d::from_slice(&response.txid)?;
+        slp_indexer.write().await.process_next_msg()?;
+
+        let response = client.get(format!("{}/tx/{}", url, txid)).send().await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let proto_tx = proto::Tx::decode(response.bytes().await?)?;
+        assert_eq!(
+            proto_tx
+                .outputs
+                .into_iter()
+                .map(|output| output.slp_token)
+                .collect::<Vec<_>>(),
+            vec![
+                None,
+                Some(proto::SlpToken {
+                    amount: 100,
+                    is_mint_baton: false,
+                }),
+                Some(proto::SlpToken {
+                    amount: 0,
+                    is_mint_baton: true,
+                }),
+                None,
+                None,
+            ],
+        );
+        // Getting only sats from the GENESIS doesn't make anyone1 a holder of the token
+        let response = client
+            .get(format!(
+                "{}/script/p2sh/{}/summary",
+                url,
+                hex::encode(anyone1_slice)
+            ))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let summary = proto::ScriptSummary::decode(response.bytes().await?)?;
+        assert_eq!(summary.token_holdings, vec![]);
+
+        let response = client.get(format!("{}/token/{}", url, txid)).send().await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let proto_token = proto::Token::decode(response.bytes().await?)?;
+        assert_eq!(proto_token.initial_token_quantity, 100);
+        assert!(proto_token.contains_baton);
+
+        // Only the GENESIS part of the token; not cached while it's still in the mempool
+        let response = client
+            .get(format!("{}/token/{}/genesis", url, txid))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CACHE_CONTROL], "no-cache");
+        let genesis_slp_tx_data = proto::SlpTxData::decode(response.bytes().await?)?;
+        assert_eq!(Some(genesis_slp_tx_data), proto_token.slp_tx_data);
+        let unknown_token_id = Sha256d::new([7; 32]);
+        let response = client
+            .get(format!("{}/token/{}/genesis", url, unknown_token_id))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        check_proto_error(
+            response,
+            "token-txid-not-found",
+            &format!("Token txid not found: {}", unknown_token_id),
+            true,
+        )
+        .await?;
+
+        // Filter UTXOs by whether they hold tokens; the BTN token and its mint baton are at
+        // anyone2, next to pure-sat UTXOs (incl. output 3 of the GENESIS)
+        let btn_query = format!("?token_id={}", txid);
+        let mut utxos_by_query = HashMap::new();
+        for query in [
+            "",
+            "?has_token=true",
+            "?has_token=false",
+            btn_query.as_str(),
+        ] {
+            let response = client
+                .get(format!(
+                    "{}/script/p2sh/{}/utxos{}",
+                    url,
+                    hex::encode(anyone2_slice),
+                    query,
+                ))
+                .send()
+                .await?;
+            assert_eq!(response.status(), StatusCode::OK);
+            let proto_utxos = proto::Utxos::decode(response.bytes().await?)?;
+            let utxos = proto_utxos
+                .script_utxos
+                .into_iter()
+                .flat_map(|script_utxos| script_utxos.utxos)
+                .collect::<Vec<_>>();
+            utxos_by_query.insert(query.to_string(), utxos);
+        }
+        let token_utxos = &utxos_by_query["?has_token=true"];
+        let sat_utxos = &utxos_by_query["?has_token=false"];
+        assert!(token_utxos.iter().all(|utxo| utxo.slp_token.is_some()));
+        assert!(sat_utxos.iter().all(|utxo| utxo.slp_token.is_none()));
+        assert!(sat_utxos.iter().any(|utxo| utxo.outpoint
+            == Some(proto::OutPoint {
+                txid: txid.as_slice().to_vec(),
+                out_idx: 3,
+            })));
+        assert_eq!(
+            token_utxos.len() + sat_utxos.len(),
+            utxos_by_query[""].len(),
+        );
+        let mut btn_outpoints = utxos_by_query[&btn_query]
+            .iter()
+            .map(|utxo| utxo.outpoint.clone().unwrap())
+            .collect::<Vec<_>>();
+        btn_outpoints.sort_by_key(|outpoint| outpoint.out_idx);
+        assert_eq!(
+            btn_outpoints,
+            vec![
+                proto::OutPoint {
+                    txid: txid.as_slice().to_vec(),
+                    out_idx: 1,
+                },
+                proto::OutPoint {
+                    txid: txid.as_slice().to_vec(),
+                    out_idx: 2,
+                },
+            ],
+        );
+        for (query, msg) in [
+            ("has_token=yes", "Invalid has_token: yes"),
+            ("token_id=bork", "Invalid token_id: "),
+        ] {
+            let response = client
+                .get(format!(
+                    "{}/script/p2sh/{}/utxos?{}",
+                    url,
+                    hex::encode(anyone2_slice),
+                    query,
+                ))
+                .send()
+                .await?;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            let error = proto::Error::decode(response.bytes().await?)?;
+            assert_eq!(error.error_code, "invalid-field");
+            assert!(error.msg.starts_with(msg));
+        }
+
+        // The GENESIS' mint baton leads back to the token
+        let response = client
+            .get(format!("{}/mint-baton/{}/2", url, txid))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(proto::Token::decode(response.bytes().await?)?, proto_token);
+        let response = client
+            .get(format!("{}/mint-baton/{}/1", url, txid))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        check_proto_error(
+            response,
+            "not-a-mint-baton",
+            &format!("Output is not a mint baton: {}:1", txid),
+            true,
+        )
+        .await?;
+        let response = client
+            .get(format!("{}/mint-baton/{}/4", url, txid))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        check_proto_error(
+            response,
+            "output-not-found",
+            &format!("Output not found: {}:4", txid),
+            true,
+        )
+        .await?;
+        let response = client
+            .get(format!("{}/mint-baton/{}/x", url, txid))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        check_proto_error(response, "invalid-field", "Invalid out_idx: x", true).await?;
+
+        // Overspending a token, or sending a different token than the inputs hold, is rejected
+        // with the SLP error and the inputs at fault
+        let btn_token_id = TokenId::new(txid.clone());
+        for (token_id, expected_input_idxs) in [(&btn_token_id, vec![]), (&htw_token_id, vec![0])] {
+            let tx = build_tx(
+                OutPoint {
+                    txid: txid.clone(),
+                    out_idx: 1,
+                },
+                &anyone2_script,
+                vec![TxOutput {
+                    value: 0,
+                    script: send_opreturn(token_id, SlpTokenType::Fungible, &[SlpAmount::new(150)]),
+                }],
+            );
+            let response = client
+                .post(format!("{}/broadcast-tx", url))
+                .header(CONTENT_TYPE, CONTENT_TYPE_PROTOBUF)
+                .body(
+                    proto::BroadcastTxRequest {
+                        raw_tx: tx.ser().to_vec(),
+                        skip_slp_check: false,
+                        slp_burn_policy: proto::SlpBurnPolicy::RejectAnyBurn as i32,
+                    }
+                    .encode_to_vec(),
+                )
+                .send()
+                .await?;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            let error = proto::Error::decode(response.bytes().await?)?;
+            assert_eq!(error.error_code, "invalid-slp-tx");
+            assert!(error.msg.starts_with("Invalid SLP tx: "));
+            assert!(error.is_user_error);
+            assert_eq!(
+                error.slp_rejection,
+                Some(proto::SlpRejection {
+                    slp_error: "OutputSumExceedInputSum".to_string(),
+                    input_idxs: expected_input_idxs,
+                    output_idxs: vec![],
+                }),
+            );
+        }
+
+        // Both tokens are still in the mempool, and are the only ones so far
+        let response = client.get(format!("{}/tokens/recent", url)).send().await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let proto_tokens = proto::Tokens::decode(response.bytes().await?)?;
+        assert_eq!(proto_tokens.tokens.len(), 2);
+        assert!(proto_tokens.tokens.contains(&proto_token));
+        // No confirmed tokens to page through
+        assert_eq!(proto_tokens.next_before, None);
+        let response = client
+            .get(format!("{}/tokens/recent?before=1", url))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            proto::Tokens::decode(response.bytes().await?)?,
+            proto::Tokens::default(),
+        );
+        let response = client
+            .get(format!("{}/tokens/recent?before=x", url))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        check_proto_error(response, "invalid-field", "Invalid before: x", true).await?;
+        // Only confirmed tokens are listed
+        let response = client.get(format!("{}/tokens", url)).send().await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            proto::TokenListings::decode(response.bytes().await?)?,
+            proto::TokenListings::default(),
+        );
+        let response = client
+            .get(format!("{}/tokens?start=-1", url))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        check_proto_error(response, "invalid-field", "Invalid start: -1", true).await?;
+        let response = client.get(format!("{}/tokens?count=0", url)).send().await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        check_proto_error(response, "invalid-field", "Invalid count: 0", true).await?;
+        let response = client
+            .get(format!("{}/tokens/recent?count=1", url))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let proto_tokens = proto::Tokens::decode(response.bytes().await?)?;
+        assert_eq!(proto_tokens.tokens.len(), 1);
+        let response = client
+            .get(format!("{}/tokens/recent?count=0", url))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        check_proto_error(response, "invalid-field", "Invalid count: 0", true).await?;
+        let response = client
+            .get(format!("{}/tokens/recent?count=151", url))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        check_proto_error(response, "page-size-too-large", "Page size too large", true).await?;
+
+        // Summary agrees with the script's history and UTXOs
+        let response = client
+            .get(format!(
+                "{}/script/p2sh/{}/summary",
+                url,
+                hex::encode(anyone2_slice)
+            ))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let summary = proto::ScriptSummary::decode(response.bytes().await?)?;
+        let response = client
+            .get(format!(
+                "{}/script/p2sh/{}/history?page_size=200",
+                url,
+                hex::encode(anyone2_slice),
+            ))
+            .send()
+            .await?;
+        let history = proto::TxHistoryPage::decode(response.bytes().await?)?;
+        let response = client
+            .get(format!(
+                "{}/script/p2sh/{}/utxos",
+                url,
+                hex::encode(anyone2_slice)
+            ))
+            .send()
+            .await?;
+        let utxos = proto::Utxos::decode(response.bytes().await?)?
+            .script_utxos
+            .into_iter()
+            .flat_map(|script_utxos| script_utxos.utxos)
+            .collect::<Vec<_>>();
+        assert_eq!(summary.num_txs, history.txs.len() as u64);
+        assert_eq!(summary.num_utxos, utxos.len() as u64);
+        assert_eq!(
+            summary.balance,
+            utxos.iter().map(|utxo| utxo.value).sum::<i64>(),
+        );
+        // The total value from the UTXO index matches the summed UTXOs, mempool ones included
+        let response = client
+            .get(format!(
+                "{}/script/p2sh/{}/value",
+                url,
+                hex::encode(anyone2_slice)
+            ))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let script_value = proto::ScriptValue::decode(response.bytes().await?)?;
+        assert_eq!(script_value.value, summary.balance);
+        // Most recent tx is in the mempool, oldest one is confirmed
+        assert_eq!(
+            summary.last_activity,
+            history.txs.first().unwrap().time_first_seen,
+        );
+        assert_eq!(
+            summary.first_activity,
+            history
+                .txs
+                .last()
+                .unwrap()
+                .block
+                .as_ref()
+                .unwrap()
+                .timestamp,
+        );
+        let mut expected_holdings = vec![
+            proto::TokenHolding {
+                token_id: utxo_states[0].slp_meta.as_ref().unwrap().token_id.clone(),
+                token_type: proto::SlpTokenType::Fungible as i32,
+                amount: "1234".to_string(),
+                num_mint_batons: 0,
+            },
+            proto::TokenHolding {
+                token_id: txid.to_vec_be(),
+                token_type: proto::SlpTokenType::Fungible as i32,
+                amount: "100".to_string(),
+                num_mint_batons: 1,
+            },
+        ];
+        expected_holdings.sort_by(|a, b| a.token_id.cmp(&b.token_id));
+        assert_eq!(summary.token_holdings, expected_holdings);
+
+        // Both tokens are in the token history, only in the mempool so far
+        let response = client
+            .get(format!(
+                "{}/script/p2sh/{}/token-history",
+                url,
+                hex::encode(anyone2_slice)
+            ))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let token_history = proto::ScriptTokenHistory::decode(response.bytes().await?)?;
+        assert_eq!(token_history.num_pages, 1);
+        let mut token_ids = token_history
+            .tokens
+            .iter()
+            .map(|token| {
+                assert_eq!(token.token_type, proto::SlpTokenType::Fungible as i32);
+                assert_eq!(token.first_seen_height, -1);
+                token.token_id.clone()
+            })
+            .collect::<Vec<_>>();
+        token_ids.sort();
+        assert_eq!(
+            token_ids,
+            expected_holdings
+                .iter()
+                .map(|holding| holding.token_id.clone())
+                .collect::<Vec<_>>(),
+        );
+        let response = client
+            .get(format!(
+                "{}/script/p2sh/{}/token-history?page=1&page_size=1",
+                url,
+                hex::encode(anyone2_slice)
+            ))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let token_page = proto::ScriptTokenHistory::decode(response.bytes().await?)?;
+        assert_eq!(token_page.num_pages, 2);
+        assert_eq!(token_page.tokens, token_history.tokens[1..]);
+
+        // Stats of several tokens at once, unknown ones listed separately
+        let unknown_token_id = vec![7; 32];
+        let response = client
+            .post(format!("{}/tokens-stats", url))
+            .header(CONTENT_TYPE, CONTENT_TYPE_PROTOBUF)
+            .body(
+                proto::TokensStatsRequest {
+                    token_ids: vec![
+                        htw_token_id.as_slice_be().to_vec(),
+                        unknown_token_id.clone(),
+                        txid.to_vec_be(),
+                    ],
+                }
+                .encode_to_vec(),
+            )
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            proto::TokensStatsResponse::decode(response.bytes().await?)?,
+            proto::TokensStatsResponse {
+                token_stats: vec![
+                    proto::TokenStatsEntry {
+                        token_id: htw_token_id.as_slice_be().to_vec(),
+                        total_minted: "1234".to_string(),
+                        total_burned: "0".to_string(),
+                        circulating_supply: "1234".to_string(),
+                        supply_overflowed: false,
+                        // Only in the mempool so far
+                        num_holders: 0,
+                    },
+                    proto::TokenStatsEntry {
+                        token_id: txid.to_vec_be(),
+                        total_minted: "100".to_string(),
+                        total_burned: "0".to_string(),
+                        circulating_supply: "100".to_string(),
+                        supply_overflowed: false,
+                        // Only in the mempool so far
+                        num_holders: 0,
+                    },
+                ],
+                missing_token_ids: vec![unknown_token_id],
+            },
+        );
+        let response = client
+            .post(format!("{}/tokens-stats", url))
+            .header(CONTENT_TYPE, CONTENT_TYPE_PROTOBUF)
+            .body(
+                proto::TokensStatsRequest {
+                    token_ids: vec![txid.to_vec_be(); 201],
+                }
+                .encode_to_vec(),
+            )
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        check_proto_error(response, "page-size-too-large", "Page size too large", true).await?;
+
+        // Spends only list confirmed txs, and none of the token's outputs are spent yet
+        let response = client
+            .get(format!("{}/token/{}/spends", url, txid))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            proto::TokenSpendsPage::decode(response.bytes().await?)?,
+            proto::TokenSpendsPage::default(),
+        );
+        // The token hasn't been minted after its GENESIS
+        let response = client
+            .get(format!("{}/token/{}/mints", url, txid))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            proto::TokenMintsPage::decode(response.bytes().await?)?,
+            proto::TokenMintsPage::default(),
+        );
+        // Holders only count confirmed UTXOs
+        let response = client
+            .get(format!("{}/token/{}/holders", url, txid))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            proto::TokenHoldersPage::decode(response.bytes().await?)?,
+            proto::TokenHoldersPage::default(),
+        );
+        // History includes mempool txs, so the GENESIS is already in it
+        let response = client
+            .get(format!("{}/token/{}/history", url, txid))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let history = proto::TxHistoryPage::decode(response.bytes().await?)?;
+        assert_eq!(history.num_pages, 1);
+        assert_eq!(history.txs.len(), 1);
+        assert_eq!(history.txs[0].txid, txid.as_slice());
+        assert_eq!(history.txs[0].block, None);
+        let response = client
+            .get(format!("{}/token/{}/history?page_size=201", url, txid))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        check_proto_error(response, "page-size-too-large", "Page size too large", true).await?;
+        let response = client
+            .get(format!("{}/token/{}/spends?page_size=0", url, txid))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        check_proto_error(response, "invalid-field", "Invalid page_size: 0", true).await?;
+        let unknown_token_id = Sha256d::new([7; 32]);
+        let response = client
+            .get(format!("{}/token/{}/spends", url, unknown_token_id))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        check_proto_error(
+            response,
+            "token-txid-not-found",
+            &format!("Token txid not found: {}", unknown_token_id),
+            true,
+        )
+        .await?;
+
+        // SLP stats only count confirmed txs
+        let response = client.get(format!("{}/slp-stats", url)).send().await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], CONTENT_TYPE_PROTOBUF);
+        assert_eq!(
+            proto::SlpStats::decode(response.bytes().await?)?,
+            proto::SlpStats::default(),
+        );
+
+        // Two non-SLP txs burning the BTN tokens and mint baton, mined in block 112
+        let mut burn_txids = Vec::new();
+        for (out_idx, value) in [(1, leftover_value), (2, 10_000)] {
+            let tx = build_tx(
+                OutPoint {
+                    txid: txid.clone(),
+                    out_idx,
+                },
+                &anyone2_script,
+                vec![TxOutput {
+                    value: value - 1_000,
+                    script: anyone2_script.to_p2sh(),
+                }],
+            );
+            let response = client
+                .post(format!("{}/broadcast-tx", url))
+                .header(CONTENT_TYPE, CONTENT_TYPE_PROTOBUF)
+                .body(
+                    proto::BroadcastTxRequest {
+                        raw_tx: tx.ser().to_vec(),
+                        skip_slp_check: false,
+                        slp_burn_policy: proto::SlpBurnPolicy::AllowAllBurns as i32,
+                    }
+                    .encode_to_vec(),
+                )
+                .send()
+                .await?;
+            assert_eq!(response.status(), StatusCode::OK);
+            let response = proto::BroadcastTxResponse::decode(response.bytes().await?)?;
+            burn_txids.push(response.txid);
+            slp_indexer.write().await.process_next_msg()?;
+        }
+        bitcoind.cmd_json("generatetoaddress", &["1", burn_address.as_str()])?;
+        slp_indexer.write().await.process_next_msg()?;
+
+        // Block 112 gives the txs of block 111 their 2nd confirmation, so the deep subscription
+        // sends their Confirmed messages right before the BlockConnected message of block 112
+        let mut deep_confirmed_txids = Vec::new();
+        loop {
+            let msg = timeout(Duration::from_millis(50), ws_deep_client.next())
+                .await?
+                .unwrap()?;
+            let msg = proto::SubscribeMsg::decode(msg.into_data().as_slice())?;
+            match msg.msg_type.unwrap() {
+                proto::subscribe_msg::MsgType::BlockConnected(block_connected) => {
+                    assert_eq!(block_connected.block_height, 112);
+                    break;
+                }
+                proto::subscribe_msg::MsgType::Confirmed(confirmed) => {
+                    deep_confirmed_txids.push(confirmed.txid);
+                }
+                proto::subscribe_msg::MsgType::AddedToMempool(_)
+                | proto::subscribe_msg::MsgType::RemovedFromMempool(_) => {}
+                msg => panic!("Unexpected message: {:?}", msg),
+            }
+        }
+        assert_eq!(deep_confirmed_txids, confirmed_txids);
+        ws_deep_client.close(None).await?;
+
+        let response = client
+            .get(format!("{}/block/112/burns", url))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let mut burns = proto::BlockTokenBurns::decode(response.bytes().await?)?.burns;
+        // Txids are in block order, which doesn't depend on the order they were broadcast in
+        burns[0].txids.sort();
+        burn_txids.sort();
+        assert_eq!(
+            burns,
+            vec![proto::BlockTokenBurn {
+                token_id: txid.to_vec_be(),
+                amount: "100".to_string(),
+                num_mint_batons: 1,
+                txids: burn_txids,
+            }],
+        );
+        // The baton was burned along with the tokens
+        let response = client
+            .get(format!("{}/mint-baton/{}/2", url, txid))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        check_proto_error(
+            response,
+            "mint-baton-spent",
+            &format!("Mint baton already spent: {}:2", txid),
+            true,
+        )
+        .await?;
+        // HTW got confirmed along with the burns, which left no BTN holders
+        let response = client
+            .get(format!("{}/token/{}/holders", url, htw_token_id.hash()))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            proto::TokenHoldersPage::decode(response.bytes().await?)?,
+            proto::TokenHoldersPage {
+                holders: vec![proto::TokenHolder {
+                    script_type: "p2sh".to_string(),
+                    payload: anyone2_slice.to_vec(),
+                    amount: "1234".to_string(),
+                }],
+                num_pages: 1,
+            },
+        );
+        let response = client
+            .post(format!("{}/tokens-stats", url))
+            .header(CONTENT_TYPE, CONTENT_TYPE_PROTOBUF)
+            .body(
+                proto::TokensStatsRequest {
+                    token_ids: vec![htw_token_id.as_slice_be().to_vec()],
+                }
+                .encode_to_vec(),
+            )
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let tokens_stats = proto::TokensStatsResponse::decode(response.bytes().await?)?;
+        assert_eq!(tokens_stats.token_stats[0].num_holders, 1);
+        let response = client
+            .get(format!("{}/token/{}/holders", url, txid))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            proto::TokenHoldersPage::decode(response.bytes().await?)?,
+            proto::TokenHoldersPage::default(),
+        );
+        // The non-SLP burns aren't part of the token's history, only its confirmed GENESIS is
+        let response = client
+            .get(format!("{}/token/{}/history", url, txid))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let history = proto::TxHistoryPage::decode(response.bytes().await?)?;
+        assert_eq!(history.num_pages, 1);
+        assert_eq!(history.txs.len(), 1);
+        assert_eq!(history.txs[0].txid, txid.as_slice());
+        assert_eq!(history.txs[0].block.as_ref().unwrap().height, 112);
+        // Both tokens got confirmed in block 112; page through them one at a time
+        let mut listed_token_ids = Vec::new();
+        for (start, expected_next_start) in [(0, Some(1)), (1, None)] {
+            let response = client
+                .get(format!("{}/tokens?start={}&count=1", url, start))
+                .send()
+                .await?;
+            assert_eq!(response.status(), StatusCode::OK);
+            let listings = proto::TokenListings::decode(response.bytes().await?)?;
+            assert_eq!(listings.next_start, expected_next_start);
+            assert_eq!(listings.tokens.len(), 1);
+            let token = &listings.tokens[0];
+            assert_eq!(token.genesis_height, 112);
+            assert!(token.genesis_info.is_some());
+            listed_token_ids.push(token.token_id.clone());
+        }
+        listed_token_ids.sort();
+        let mut expected_token_ids = vec![htw_token_id.as_slice_be().to_vec(), txid.to_vec_be()];
+        expected_token_ids.sort();
+        assert_eq!(listed_token_ids, expected_token_ids);
+        let response = client
+            .get(format!("{}/tokens?count=151", url))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        check_proto_error(response, "page-size-too-large", "Page size too large", true).await?;
+        // Block 111 didn't burn any tokens
+        let response = client
+            .get(format!("{}/block/111/burns", url))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            proto::BlockTokenBurns::decode(response.bytes().await?)?,
+            proto::BlockTokenBurns::default(),
+        );
+    }
+
+    // An index built without the spends index only knows spends in the mempool
+    {
+        let spent_outpoint = {
+            let slp_indexer = slp_indexer.read().await;
+            let block_txs = slp_indexer.blocks().block_txs_by_height(111)?;
+            let spent_outpoint = block_txs[1].tx.inputs()[0].prev_out.clone();
+            assert_eq!(
+                slp_indexer.utxos().utxo_state(&spent_outpoint)?.state,
+                UtxoStateVariant::Spent,
+            );
+            spent_outpoint
+        };
+        let transient_data = TransientData::open(&dir.path().join("no-spends-transient.rocksdb"))?;
+        let db = IndexDb::new(
+            Db::open(dir.path().join("no-spends.rocksdb"))?,
+            transient_data,
+            ScriptTxsConf { page_size: 7 },
+            LokadTxsConf::default(),
+        )
+        .with_spends_index(false);
+        let mut no_spends_indexer = SlpIndexer::new(
+            db,
+            instance.rpc_client().clone(),
+            RpcInterface::open(&rpc_url)?,
+            PubInterface::open(&pub_url)?,
+            IndexMemData::new(10),
+            Network::XPI,
+            Arc::new(EccSecp256k1::default()),
+        )?;
+        while !no_spends_indexer.catchup_step().await? {}
+        let spent_tx_num = no_spends_indexer
+            .db()
+            .txs()?
+            .tx_num_by_txid(&spent_outpoint.txid)?
+            .unwrap();
+        assert_eq!(
+            no_spends_indexer
+                .db()
+                .spends()?
+                .spends_by_tx_num(spent_tx_num)?,
+            vec![],
+        );
+        let no_spends_indexer = Arc::new(RwLock::new(no_spends_indexer));
+        let no_spends_port = pick_ports(1)?[0];
+        let no_spends_server = ChronikServer {
+            addr: ([127, 0, 0, 1], no_spends_port).into(),
+            slp_indexer: Arc::clone(&no_spends_indexer),
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            script_types: Arc::new(ScriptTypes::default()),
+            enable_debug_endpoints: false,
+            max_validate_utxos: DEFAULT_MAX_VALIDATE_UTXOS,
+            max_token_listings: DEFAULT_MAX_TOKEN_LISTINGS,
+            ws_batch_interval: None,
+            enable_rpc: false,
+            max_heavy_queries: None,
+            strip_network: false,
+        };
+        tokio::spawn(no_spends_server.run());
+        while is_free_tcp(no_spends_port) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let no_spends_url = format!("http://127.0.0.1:{}", no_spends_port);
+
+        let response = client.get(&no_spends_url).send().await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!proto::ServerInfo::decode(response.bytes().await?)?.has_spends_index);
+
+        let response = client
+            .post(format!("{}/validate-utxos", no_spends_url))
+            .header(CONTENT_TYPE, CONTENT_TYPE_PROTOBUF)
+            .body(
+                proto::ValidateUtxoRequest {
+                    outpoints: vec![proto::OutPoint {
+                        txid: spent_outpoint.txid.as_slice().to_vec(),
+                        out_idx: spent_outpoint.out_idx,
+                    }],
+                }
+                .encode_to_vec(),
+            )
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let utxo_states = proto::ValidateUtxoResponse::decode(response.bytes().await?)?;
+        assert_eq!(
+            utxo_states.utxo_states[0].state,
+            proto::UtxoStateVariant::Unknown as i32,
+        );
+
+        let response = client
+            .get(format!(
+                "{}/tx/{}",
+                no_spends_url,
+                spent_outpoint.txid.to_hex_be(),
+            ))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let spent_tx = proto::Tx::decode(response.bytes().await?)?;
+        assert_eq!(
+            spent_tx.outputs[spent_outpoint.out_idx as usize].spent_by,
+            None,
+        );
+    }
+
+    {
+        // Holding the write lock blocks all requests, which then time out
+        let slp_indexer = slp_indexer.write().await;
+        let response = client
+            .get(format!("http://127.0.0.1:{}/blockchain-info", timeout_port))
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        check_proto_error(response, "request-timeout", "Request timed out", false).await?;
+        std::mem::drop(slp_indexer);
+    }
+
+    {
+        // Simulate bitcoind becoming unreachable; endpoints served from the index keep working
+        slp_indexer.read().await.node_status().set_unreachable();
+        let response = client.get(format!("{}/status", url)).send().await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let status = proto::Status::decode(response.bytes().await?)?;
+        assert!(!status.is_node_reachable);
+        let response = client.get(format!("{}/block/111", url)).send().await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = client
+            .post(format!("{}/broadcast-tx", url))
+            .header(CONTENT_TYPE, CONTENT_TYPE_PROTOBUF)
+            .body(
+                proto::BroadcastTxRequest {
+                    raw_tx: tx.ser().to_vec(),
+                    skip_slp_check: true,
+                    slp_burn_policy: proto::SlpBurnPolicy::RejectAnyBurn as i32,
+                }
+                .encode_to_vec(),
+            )
+            .send()
+            .await?;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        check_proto_error(
+            response,
+            "node-unavailable",
+            "Node unavailable: bitcoind can't be reached, try again later",
+            false,
+        )
+        .await?;
+    }
+
+    instance.cleanup()?;
+
+    Ok(())
+}
+
+/// Calls `method` on the JSON-RPC shim and returns its result, which must not be an error.
+async fn rpc_call(
+    client: &Client,
+    url: &str,
+    method: &str,
+    params: serde_json::Value,
+) -> Result<serde_json::Value> {
+    let response = client
+        .post(format!("{}/rpc", url))
+        .body(
+            json!({ "jsonrpc": "1.0", "id": "test", "method": method, "params": params })
+                .to_string(),
+        )
+        .send()
+        .await?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let mut response = serde_json::from_slice::<serde_json::Value>(&response.bytes().await?)?;
+    assert_eq!(response["error"], json!(null));
+    assert_eq!(response["id"], json!("test"));
+    Ok(response["result"].take())
 }
 
 async fn check_proto_error(
@@ -770,6 +3546,7 @@ async fn check_proto_error(
         error_code: error_code.to_string(),
         msg: msg.to_string(),
         is_user_error,
+        slp_rejection: None,
     };
     assert_eq!(actual_error, expected_error);
     Ok(())
@@ -0,0 +1,27 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::PathBuf,
+};
+
+/// Reads slices of the node's `blkXXXXX.dat` files directly from disk, which is much cheaper than
+/// a `get_block_slice` RPC call, but requires the block files to be accessible to the indexer.
+#[derive(Debug, Clone)]
+pub struct BlockFiles {
+    blocks_dir: PathBuf,
+}
+
+impl BlockFiles {
+    pub fn new(blocks_dir: PathBuf) -> Self {
+        BlockFiles { blocks_dir }
+    }
+
+    pub fn read_slice(&self, file_num: u32, data_pos: u32, size: u32) -> std::io::Result<Vec<u8>> {
+        let path = self.blocks_dir.join(format!("blk{:05}.dat", file_num));
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::Start(data_pos.into()))?;
+        let mut data = vec![0; size as usize];
+        file.read_exact(&mut data)?;
+        Ok(data)
+    }
+}
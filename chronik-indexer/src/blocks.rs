@@ -1,26 +1,90 @@
+use std::{
+    collections::BTreeMap,
+    ops::{Range, RangeInclusive},
+    sync::Arc,
+};
+
+use bitcoinsuite_bitcoind::rpc_client::BitcoindRpcClient;
 use bitcoinsuite_bitcoind_nng::BlockIdentifier;
-use bitcoinsuite_core::{BitcoinCode, BitcoinHeader, LotusHeader, Network, Sha256d};
+use bitcoinsuite_core::{
+    BitcoinCode, BitcoinHeader, Bytes, LotusHeader, Network, Script, Sha256d, UnhashedTx,
+};
 use bitcoinsuite_error::{ErrorMeta, Result};
-use bitcoinsuite_slp::RichTx;
-use chronik_rocksdb::{Block, BlockHeight, BlockReader};
+use bitcoinsuite_slp::{RichTx, TokenId};
+use chronik_rocksdb::{Block, BlockHeight, BlockReader, BlockStats, TxNum};
+use futures::Stream;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use thiserror::Error;
+use tokio::sync::RwLock;
 
-use crate::SlpIndexer;
+use crate::{NodeStatus, SlpIndexer};
 
 pub struct Blocks<'a> {
     indexer: &'a SlpIndexer,
 }
 
+#[derive(Debug, Clone)]
+pub struct ExportedBlock {
+    pub block: Block,
+    pub block_stats: BlockStats,
+    pub raw_header: Vec<u8>,
+    pub txs: Vec<RichTx>,
+}
+
+/// Header fields not stored in the index, parsed from the raw header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockHeaderDetails {
+    pub version: i32,
+    pub merkle_root: Sha256d,
+    pub nonce: u64,
+    /// Median of the timestamps of this block and the 10 blocks before it.
+    pub median_timestamp: i64,
+}
+
+/// Amount of a token burned by the txs of a block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockTokenBurn {
+    pub token_id: TokenId,
+    /// Sum of the burned token amounts, in base units
+    pub amount: i128,
+    pub num_mint_batons: usize,
+    /// Txs of the block burning the token, in block order
+    pub txids: Vec<Sha256d>,
+}
+
 #[derive(Debug, Error, ErrorMeta)]
 pub enum BlocksError {
     #[critical()]
     #[error("Inconsistent db, block hash doesn't exist: {0}")]
     InconsistentNoSuchBlock(Sha256d),
 
+    #[critical()]
+    #[error("Inconsistent db, block height doesn't exist: {0}")]
+    InconsistentNoSuchBlockHeight(BlockHeight),
+
     #[critical()]
     #[error("Inconsistent db, txid doesn't exist: {0}")]
     InconsistentNoSuchBlockTx(Sha256d),
+
+    #[critical()]
+    #[error("Inconsistent db, block stats don't exist for height {0}")]
+    InconsistentNoSuchBlockStats(BlockHeight),
+
+    #[critical()]
+    #[error("Inconsistent db, tx_num doesn't exist: {0}")]
+    InconsistentNoSuchBlockTxNum(TxNum),
+
+    #[critical()]
+    #[error("Inconsistent db, raw header doesn't exist for block {0}")]
+    InconsistentNoSuchRawHeader(Sha256d),
+
+    #[critical()]
+    #[error("Invalid raw header size: expected {expected}, got {actual}")]
+    InvalidRawHeaderSize { expected: usize, actual: usize },
+
+    #[critical()]
+    #[error("Node returned invalid raw block hex for {0}")]
+    InvalidRawBlockHex(Sha256d),
 }
 
 use self::BlocksError::*;
@@ -46,17 +110,178 @@ impl<'a> Blocks<'a> {
         self.reader()?.by_height(height)
     }
 
-    pub fn raw_header(&self, block: &Block) -> Result<Option<Vec<u8>>> {
-        let header_size = match self.indexer.network {
-            Network::BCH | Network::XEC | Network::XRG => BitcoinHeader::default().ser().len(),
-            Network::XPI => LotusHeader::default().ser().len(),
+    /// Stats of the indexed `block`. Missing stats are an error here; they're recomputed by
+    /// [`SlpIndexer::repair_block_stats`] on startup.
+    pub fn block_stats(&self, block: &Block) -> Result<BlockStats> {
+        let block_stats = self
+            .indexer
+            .db()
+            .block_stats()?
+            .by_height(block.height)?
+            .ok_or(InconsistentNoSuchBlockStats(block.height))?;
+        Ok(block_stats)
+    }
+
+    /// Range of the tx nums of the blocks in `start_height..=end_height`; `end_height` may
+    /// exceed the tip. None if there's no block at `start_height`.
+    pub fn tx_num_range(
+        &self,
+        start_height: BlockHeight,
+        end_height: BlockHeight,
+    ) -> Result<Option<Range<TxNum>>> {
+        let tx_reader = self.indexer.db().txs()?;
+        let start_tx_num = match tx_reader.first_tx_num_by_block(start_height)? {
+            Some(start_tx_num) => start_tx_num,
+            None => return Ok(None),
+        };
+        let end_tx_num = match tx_reader.first_tx_num_by_block(end_height.saturating_add(1))? {
+            Some(end_tx_num) => end_tx_num,
+            None => match tx_reader.last_tx_num()? {
+                Some(last_tx_num) => last_tx_num + 1,
+                None => return Ok(None),
+            },
         };
-        let header = self.indexer.rpc_interface.get_block_slice(
+        Ok(Some(start_tx_num..end_tx_num))
+    }
+
+    /// Tx at position `index` within the block at `height`, where the coinbase is at 0.
+    /// Only loads that single tx, not the whole block. None if there's no such tx.
+    pub fn block_tx_by_index(&self, height: BlockHeight, index: usize) -> Result<Option<RichTx>> {
+        let txid = match self
+            .indexer
+            .db()
+            .txs()?
+            .txid_by_block_and_index(height, index)?
+        {
+            Some(txid) => txid,
+            None => return Ok(None),
+        };
+        self.indexer.txs().rich_tx_by_txid(&txid)
+    }
+
+    pub fn raw_header(&self, block: &Block) -> Result<Option<Vec<u8>>> {
+        let header =
+            self.indexer
+                .block_slice(block.file_num, block.data_pos, self.header_size() as u32)?;
+        Ok(Some(header))
+    }
+
+    /// Raw headers of the blocks following the first block of `locator` (newest first) that's
+    /// in the index, up to the tip and at most `max_headers`, like the node's `getheaders`.
+    /// Starts at genesis if none of the locator's blocks is in the index.
+    pub fn headers_since(&self, locator: &[Sha256d], max_headers: usize) -> Result<Vec<Vec<u8>>> {
+        let block_reader = self.reader()?;
+        let mut start_height = 0;
+        for hash in locator {
+            if let Some(block) = block_reader.by_hash(hash)? {
+                start_height = block.height + 1;
+                break;
+            }
+        }
+        let mut raw_headers = Vec::new();
+        for height in (start_height..=block_reader.height()?).take(max_headers) {
+            let block = block_reader
+                .by_height(height)?
+                .ok_or(InconsistentNoSuchBlockHeight(height))?;
+            raw_headers.extend(self.raw_header(&block)?);
+        }
+        Ok(raw_headers)
+    }
+
+    /// scriptSig of the coinbase tx of `block`, which usually contains the miner tag.
+    pub fn coinbase_script(&self, block: &Block) -> Result<Script> {
+        let raw_tx = self.raw_coinbase_tx(block)?;
+        let coinbase_tx = UnhashedTx::deser(&mut Bytes::from_bytes(raw_tx))?;
+        Ok(coinbase_tx.inputs[0].script.clone())
+    }
+
+    /// Serialized coinbase tx of `block`, located via the tx index.
+    pub fn raw_coinbase_tx(&self, block: &Block) -> Result<Vec<u8>> {
+        let tx_reader = self.indexer.db().txs()?;
+        let tx_num = tx_reader
+            .first_tx_num_by_block(block.height)?
+            .ok_or(InconsistentNoSuchBlockHeight(block.height))?;
+        let block_tx = tx_reader
+            .by_tx_num(tx_num)?
+            .ok_or(InconsistentNoSuchBlockTxNum(tx_num))?;
+        let raw_tx = self.indexer.block_slice(
             block.file_num,
-            block.data_pos,
-            header_size as u32,
+            block_tx.entry.data_pos,
+            block_tx.entry.tx_size,
         )?;
-        Ok(Some(header))
+        Ok(raw_tx)
+    }
+
+    /// Parses the header fields of `block` which aren't indexed, without querying bitcoind.
+    pub fn header_details(&self, block: &Block, raw_header: &[u8]) -> Result<BlockHeaderDetails> {
+        let header_size = self.header_size();
+        if raw_header.len() != header_size {
+            return Err(InvalidRawHeaderSize {
+                expected: header_size,
+                actual: raw_header.len(),
+            }
+            .into());
+        }
+        // Size is checked above, so converting slices into fixed-size arrays can't fail
+        fn array<const N: usize>(slice: &[u8]) -> [u8; N] {
+            slice.try_into().unwrap()
+        }
+        let (version, merkle_root, nonce) = match self.indexer.network {
+            // version: 4 | prev_hash: 32 | merkle_root: 32 | timestamp: 4 | n_bits: 4 | nonce: 4
+            Network::BCH | Network::XEC | Network::XRG => (
+                i32::from_le_bytes(array(&raw_header[0..4])),
+                Sha256d::new(array(&raw_header[36..68])),
+                u32::from_le_bytes(array(&raw_header[76..80])) as u64,
+            ),
+            // prev_hash: 32 | n_bits: 4 | timestamp: 6 | reserved: 2 | nonce: 8 | version: 1 |
+            // size: 7 | height: 4 | epoch_hash: 32 | merkle_root: 32 | extended_metadata_hash: 32
+            Network::XPI => (
+                raw_header[52] as i32,
+                Sha256d::new(array(&raw_header[96..128])),
+                u64::from_le_bytes(array(&raw_header[44..52])),
+            ),
+        };
+        Ok(BlockHeaderDetails {
+            version,
+            merkle_root,
+            nonce,
+            median_timestamp: self.median_timestamp(&self.reader()?, block.height)?,
+        })
+    }
+
+    /// Heights of the blocks whose median timestamp (see [`BlockHeaderDetails`]) is within
+    /// `start_timestamp..=end_timestamp`, or None if there are no such blocks.
+    ///
+    /// Block timestamps can decrease from one block to the next, but consensus requires each
+    /// timestamp to be greater than the median timestamp of the previous block, so median
+    /// timestamps never decrease. The matching blocks therefore form one contiguous height
+    /// range, found by binary search. Individual blocks in it can still have a timestamp
+    /// outside the window.
+    pub fn height_range_by_median_timestamp(
+        &self,
+        start_timestamp: i64,
+        end_timestamp: i64,
+    ) -> Result<Option<RangeInclusive<BlockHeight>>> {
+        let block_reader = self.reader()?;
+        let tip_height = block_reader.height()?;
+        // Lowest height whose median timestamp satisfies `pred`, or tip_height + 1 if none does
+        let partition_point = |pred: &dyn Fn(i64) -> bool| -> Result<BlockHeight> {
+            let (mut low, mut high) = (0, tip_height + 1);
+            while low < high {
+                let mid = low + (high - low) / 2;
+                match pred(self.median_timestamp(&block_reader, mid)?) {
+                    true => high = mid,
+                    false => low = mid + 1,
+                }
+            }
+            Ok(low)
+        };
+        let start_height = partition_point(&|timestamp| timestamp >= start_timestamp)?;
+        let end_height = partition_point(&|timestamp| timestamp > end_timestamp)? - 1;
+        if start_height > end_height {
+            return Ok(None);
+        }
+        Ok(Some(start_height..=end_height))
     }
 
     pub fn block_txs_by_hash(&self, hash: &Sha256d) -> Result<Vec<RichTx>> {
@@ -67,6 +292,22 @@ impl<'a> Blocks<'a> {
         self.block_txs_by_identifier(BlockIdentifier::Height(height))
     }
 
+    pub fn exported_block_by_height(&self, height: BlockHeight) -> Result<Option<ExportedBlock>> {
+        let block = match self.by_height(height)? {
+            Some(block) => block,
+            None => return Ok(None),
+        };
+        let block_stats = self.block_stats(&block)?;
+        let raw_header = self.raw_header(&block)?.unwrap_or_default();
+        let txs = self.block_txs_by_height(height)?;
+        Ok(Some(ExportedBlock {
+            block,
+            block_stats,
+            raw_header,
+            txs,
+        }))
+    }
+
     fn block_txs_by_identifier(&self, block_id: BlockIdentifier) -> Result<Vec<RichTx>> {
         let nng_block = self.indexer.rpc_interface.get_block(block_id)?;
         let txs = self.indexer.txs();
@@ -93,7 +334,95 @@ impl<'a> Blocks<'a> {
             .collect::<Result<_>>()
     }
 
+    /// Tokens burned by the txs of the block at `height`, ordered by token ID. Computed from the
+    /// SLP burns of the block's txs on every call, so no per-block burn data has to be stored.
+    pub fn token_burns(&self, height: BlockHeight) -> Result<Vec<BlockTokenBurn>> {
+        let null_token_id = TokenId::new(Sha256d::new([0; 32]));
+        let mut burns = BTreeMap::new();
+        for tx in self.block_txs_by_height(height)? {
+            for slp_burn in tx.slp_burns.iter().flatten() {
+                if slp_burn.token_id == null_token_id {
+                    continue;
+                }
+                let burn = burns
+                    .entry(slp_burn.token_id.token_id_be())
+                    .or_insert_with(|| BlockTokenBurn {
+                        token_id: slp_burn.token_id.clone(),
+                        amount: 0,
+                        num_mint_batons: 0,
+                        txids: vec![],
+                    });
+                if slp_burn.token.is_mint_baton {
+                    burn.num_mint_batons += 1;
+                } else {
+                    burn.amount += slp_burn.token.amount.base_amount();
+                }
+                if burn.txids.last() != Some(&tx.txid) {
+                    burn.txids.push(tx.txid.clone());
+                }
+            }
+        }
+        Ok(burns.into_values().collect())
+    }
+
+    /// Median of the timestamps of the block at `height` and the 10 blocks before it.
+    fn median_timestamp(&self, block_reader: &BlockReader, height: BlockHeight) -> Result<i64> {
+        let mut timestamps = Vec::with_capacity(11);
+        for height in (height - 10).max(0)..=height {
+            let past_block = block_reader
+                .by_height(height)?
+                .ok_or(InconsistentNoSuchBlockHeight(height))?;
+            timestamps.push(past_block.timestamp);
+        }
+        timestamps.sort_unstable();
+        Ok(timestamps[timestamps.len() / 2])
+    }
+
+    fn header_size(&self) -> usize {
+        match self.indexer.network {
+            Network::BCH | Network::XEC | Network::XRG => BitcoinHeader::default().ser().len(),
+            Network::XPI => LotusHeader::default().ser().len(),
+        }
+    }
+
     fn reader(&self) -> Result<BlockReader> {
         self.indexer.db.blocks()
     }
 }
+
+/// Serialized block with the given hash, fetched from bitcoind (`getblock` with verbosity 0) on
+/// every call. Doesn't take the indexer, so callers can release its lock while bitcoind answers.
+pub async fn raw_block(
+    node_status: &NodeStatus,
+    bitcoind: &BitcoindRpcClient,
+    block_hash: &Sha256d,
+) -> Result<Vec<u8>> {
+    let raw_block_hex = node_status
+        .call(bitcoind.cmd_text("getblock", &[block_hash.to_hex_be().into(), 0.into()]))
+        .await?;
+    let raw_block =
+        hex::decode(raw_block_hex.trim()).map_err(|_| InvalidRawBlockHex(block_hash.clone()))?;
+    Ok(raw_block)
+}
+
+/// Streams the blocks from `start_height` to `end_height` (inclusive), stopping early at the tip.
+/// The indexer is only locked while a single block is being loaded, so a long export doesn't
+/// hold up indexing, and the next block is only loaded once the consumer polls for it.
+pub fn export_blocks(
+    slp_indexer: Arc<RwLock<SlpIndexer>>,
+    start_height: BlockHeight,
+    end_height: BlockHeight,
+) -> impl Stream<Item = Result<ExportedBlock>> {
+    futures::stream::unfold(Some(start_height), move |height| {
+        let slp_indexer = Arc::clone(&slp_indexer);
+        async move {
+            let height = height.filter(|&height| height <= end_height)?;
+            let slp_indexer = slp_indexer.read().await;
+            match slp_indexer.blocks().exported_block_by_height(height) {
+                Ok(Some(exported_block)) => Some((Ok(exported_block), height.checked_add(1))),
+                Ok(None) => None,
+                Err(report) => Some((Err(report), None)),
+            }
+        }
+    })
+}
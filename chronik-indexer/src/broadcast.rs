@@ -1,19 +1,38 @@
 use bitcoinsuite_bitcoind::BitcoindError;
 use bitcoinsuite_core::{BitcoinCode, Hashed, Sha256d, UnhashedTx};
 use bitcoinsuite_error::{ErrorMeta, Result};
-use bitcoinsuite_slp::{SlpAmount, SlpBurn, SlpError, SlpToken};
+use bitcoinsuite_slp::{parse_slp_tx, SlpAmount, SlpBurn, SlpError, SlpToken, SlpTxType};
 use chronik_rocksdb::is_ignored_error;
 use thiserror::Error;
 
 pub struct Broadcast<'a> {
     indexer: &'a SlpIndexer,
+    burn_policy: SlpBurnPolicy,
+}
+
+/// Which SLP burns are accepted when broadcasting with the SLP check enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SlpBurnPolicy {
+    /// Reject txs burning any tokens (default).
+    RejectAnyBurn,
+    /// Allow a valid SLP tx to burn amounts of its own token, i.e. to send less than its inputs.
+    /// Burns of other tokens or of mint batons are still rejected.
+    AllowExplicitBurn,
+    /// Allow all burns; invalid SLP txs are still rejected.
+    AllowAll,
+}
+
+impl Default for SlpBurnPolicy {
+    fn default() -> Self {
+        SlpBurnPolicy::RejectAnyBurn
+    }
 }
 
 #[derive(Debug, Error, ErrorMeta, PartialEq, Eq)]
 pub enum BroadcastError {
     #[invalid_user_input()]
     #[error("Invalid SLP tx: {0}")]
-    InvalidSlpTx(SlpError),
+    InvalidSlpTx(SlpError, SlpCulprits),
 
     #[invalid_user_input()]
     #[error("Invalid SLP burns: {0}")]
@@ -27,19 +46,35 @@ pub enum BroadcastError {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SlpBurns(pub Vec<Option<Box<SlpBurn>>>);
 
+/// Inputs and outputs of a tx which caused it to be SLP-invalid, so wallets can fix the tx.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SlpCulprits {
+    /// Inputs spending tokens of a different token ID than the one of the tx.
+    pub input_idxs: Vec<u32>,
+    /// Outputs the SLP error refers to, e.g. an invalid mint baton output.
+    pub output_idxs: Vec<u32>,
+}
+
 use crate::SlpIndexer;
 
 use self::BroadcastError::*;
 
 impl<'a> Broadcast<'a> {
     pub fn new(indexer: &'a SlpIndexer) -> Self {
-        Broadcast { indexer }
+        Broadcast {
+            indexer,
+            burn_policy: SlpBurnPolicy::default(),
+        }
     }
 
-    fn check_no_slp_burn(
-        &self,
-        tx: &UnhashedTx,
-    ) -> Result<std::result::Result<(), BroadcastError>> {
+    pub fn with_burn_policy(self, burn_policy: SlpBurnPolicy) -> Self {
+        Broadcast {
+            burn_policy,
+            ..self
+        }
+    }
+
+    fn check_slp_burn(&self, tx: &UnhashedTx) -> Result<std::result::Result<(), BroadcastError>> {
         let dummy_txid = Sha256d::default();
         let result = self
             .indexer
@@ -47,36 +82,86 @@ impl<'a> Broadcast<'a> {
             .validate_slp_tx(&self.indexer.data, &dummy_txid, tx)?;
         match result {
             Ok(valid_tx_data) => {
-                if valid_tx_data.slp_burns.iter().any(Option::is_some) {
+                let slp_tx_data = &valid_tx_data.slp_tx_data;
+                let is_allowed_burn = |burn: &SlpBurn| match self.burn_policy {
+                    SlpBurnPolicy::RejectAnyBurn => false,
+                    SlpBurnPolicy::AllowExplicitBurn => {
+                        slp_tx_data.slp_tx_type == SlpTxType::Send
+                            && burn.token_id == slp_tx_data.token_id
+                            && !burn.token.is_mint_baton
+                    }
+                    SlpBurnPolicy::AllowAll => true,
+                };
+                let has_disallowed_burn = valid_tx_data
+                    .slp_burns
+                    .iter()
+                    .flatten()
+                    .any(|burn| !is_allowed_burn(burn));
+                if has_disallowed_burn {
                     return Ok(Err(InvalidSlpBurns(SlpBurns(valid_tx_data.slp_burns))));
                 }
             }
             Err(slp_error) => {
                 if !is_ignored_error(&slp_error) {
-                    return Ok(Err(InvalidSlpTx(slp_error)));
+                    let culprits = self.slp_culprits(tx, &slp_error)?;
+                    return Ok(Err(InvalidSlpTx(slp_error, culprits)));
                 }
             }
         }
         Ok(Ok(()))
     }
 
+    fn slp_culprits(&self, tx: &UnhashedTx, slp_error: &SlpError) -> Result<SlpCulprits> {
+        let mut culprits = SlpCulprits::default();
+        if let SlpError::InvalidMintBatonIdx { actual } = *slp_error {
+            culprits.output_idxs.push(actual as u32);
+        }
+        let parse_data = match parse_slp_tx(&Sha256d::default(), tx) {
+            Ok(parse_data) => parse_data,
+            Err(_) => return Ok(culprits),
+        };
+        if !matches!(parse_data.slp_tx_type, SlpTxType::Send | SlpTxType::Mint) {
+            return Ok(culprits);
+        }
+        let spent_outputs = self
+            .indexer
+            .db()
+            .slp_spent_outputs(&self.indexer.data, tx)?;
+        for (input_idx, spent_output) in spent_outputs.iter().enumerate() {
+            if let Some(spent_output) = spent_output {
+                if spent_output.token_id != parse_data.token_id
+                    && spent_output.token != SlpToken::EMPTY
+                {
+                    culprits.input_idxs.push(input_idx as u32);
+                }
+            }
+        }
+        Ok(culprits)
+    }
+
     pub async fn broadcast_tx(&self, tx: &UnhashedTx, check_slp: bool) -> Result<Sha256d> {
         if check_slp {
-            self.check_no_slp_burn(tx)??;
+            self.check_slp_burn(tx)??;
         }
         let raw_tx = tx.ser();
         let result = self
             .indexer
-            .bitcoind
-            .cmd_text("sendrawtransaction", &[raw_tx.hex().into()])
+            .node_status()
+            .call(
+                self.indexer
+                    .bitcoind
+                    .cmd_text("sendrawtransaction", &[raw_tx.hex().into()]),
+            )
             .await;
         let report = match result {
             Ok(txid_hex) => return Ok(Sha256d::from_hex_be(&txid_hex)?),
             Err(report) => report,
         };
-        match report.downcast::<BitcoindError>()? {
-            BitcoindError::JsonRpcCode { message, .. } => Err(BitcoindRejectedTx(message).into()),
-            bitcoind_error => Err(bitcoind_error.into()),
+        match report.downcast_ref::<BitcoindError>() {
+            Some(BitcoindError::JsonRpcCode { message, .. }) => {
+                Err(BitcoindRejectedTx(message.clone()).into())
+            }
+            _ => Err(report),
         }
     }
 
@@ -86,12 +171,17 @@ impl<'a> Broadcast<'a> {
         check_slp: bool,
     ) -> Result<std::result::Result<(), BroadcastError>> {
         if check_slp {
-            let result = self.check_no_slp_burn(tx)?;
+            let result = self.check_slp_burn(tx)?;
             if result.is_err() {
                 return Ok(result);
             }
         }
-        if let Err(msg) = self.indexer.bitcoind.test_mempool_accept(&tx.ser()).await? {
+        let result = self
+            .indexer
+            .node_status()
+            .call(self.indexer.bitcoind.test_mempool_accept(&tx.ser()))
+            .await?;
+        if let Err(msg) = result {
             return Ok(Err(BroadcastError::BitcoindRejectedTx(msg)));
         }
         Ok(Ok(()))
@@ -1,7 +1,8 @@
 use bitcoinsuite_error::{ErrorMeta, Report};
 
 use crate::{
-    broadcast::BroadcastError, BlocksError, ScriptHistoryError, SlpIndexerError, UtxosError,
+    broadcast::BroadcastError, BlocksError, LokadHistoryError, NodeStatusError, ScriptHistoryError,
+    SlpIndexerError, TokensError, UtxosError,
 };
 
 pub fn report_to_error_meta(report: &Report) -> Option<&dyn ErrorMeta> {
@@ -9,10 +10,16 @@ pub fn report_to_error_meta(report: &Report) -> Option<&dyn ErrorMeta> {
         Some(err)
     } else if let Some(err) = report.downcast_ref::<BroadcastError>() {
         Some(err)
+    } else if let Some(err) = report.downcast_ref::<LokadHistoryError>() {
+        Some(err)
+    } else if let Some(err) = report.downcast_ref::<NodeStatusError>() {
+        Some(err)
     } else if let Some(err) = report.downcast_ref::<SlpIndexerError>() {
         Some(err)
     } else if let Some(err) = report.downcast_ref::<ScriptHistoryError>() {
         Some(err)
+    } else if let Some(err) = report.downcast_ref::<TokensError>() {
+        Some(err)
     } else if let Some(err) = report.downcast_ref::<UtxosError>() {
         Some(err)
     } else {
@@ -1,28 +1,40 @@
 use std::{
     collections::{HashMap, HashSet},
+    path::PathBuf,
     sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use bitcoinsuite_bitcoind::rpc_client::BitcoindRpcClient;
-use bitcoinsuite_bitcoind_nng::{BlockTx, MempoolTx, Message, PubInterface, RpcInterface};
+use bitcoinsuite_bitcoind_nng::{
+    BlockIdentifier, BlockTx, MempoolTx, Message, PubInterface, RpcInterface,
+};
 use bitcoinsuite_core::{
-    ecc::Ecc, BitcoinCode, Bytes, Hashed, Network, Script, Sha256d, UnhashedTx,
+    ecc::Ecc, BitcoinCode, Bytes, Hashed, Network, OutPoint, Script, Sha256d, UnhashedTx,
 };
 use bitcoinsuite_error::{ErrorMeta, Result};
+use bitcoinsuite_slp::TokenId;
 use chronik_rocksdb::{
     script_payloads, Block, BlockHeight, BlockTxs, IndexDb, IndexMemData, MempoolData,
-    MempoolSlpData, MempoolTxEntry, TransientBlockDataReader, TxEntry,
+    MempoolSlpData, MempoolTxEntry, ScriptTxsCacheStats, TransientBlockDataReader, TxEntry,
 };
 use thiserror::Error;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
 
 use crate::{
     broadcast::Broadcast,
-    subscribers::{SubscribeBlockMessage, SubscribeScriptMessage, Subscribers},
+    subscribers::{
+        SubscribeBlockMessage, SubscribeOutpointMessage, SubscribeScriptMessage,
+        SubscribeTokenMessage, Subscribers,
+    },
     txs::Txs,
-    Blocks, ScriptHistory, Tokens, Utxos,
+    BlockFiles, Blocks, BlocksError, LokadHistory, NodeStatus, ScriptHistory, Tokens, Utxos,
+    Verifier,
 };
 
+/// Default for how many consecutive blocks may be disconnected before the indexer gives up.
+pub const DEFAULT_MAX_REORG_DEPTH: usize = 100;
+
 pub struct SlpIndexer {
     pub(crate) db: IndexDb,
     pub(crate) bitcoind: BitcoindRpcClient,
@@ -31,7 +43,34 @@ pub struct SlpIndexer {
     pub(crate) data: IndexMemData,
     pub(crate) network: Network,
     pub(crate) ecc: Arc<dyn Ecc + Sync + Send>,
+    block_files: Option<BlockFiles>,
+    node_status: Arc<NodeStatus>,
     subscribers: Subscribers,
+    is_stalled: bool,
+    last_processed_at: Option<i64>,
+    max_reorg_depth: usize,
+    reorg_depth: usize,
+    pending_reorg: Option<PendingReorg>,
+    conflicting_mempool_txs: HashMap<Sha256d, Sha256d>,
+    tip_raw: Option<TipRaw>,
+}
+
+/// Serialized header and coinbase tx of the tip, kept in memory so they can be served without
+/// querying the node.
+#[derive(Debug, Clone)]
+pub struct TipRaw {
+    pub hash: Sha256d,
+    pub raw_header: Vec<u8>,
+    pub raw_coinbase_tx: Vec<u8>,
+}
+
+/// Reorg in progress, as found when disconnecting its first block.
+#[derive(Debug, Clone, Copy)]
+struct PendingReorg {
+    /// Height of the last block shared by the old and the new chain.
+    fork_height: BlockHeight,
+    /// Height of the node's tip once all blocks of the new chain are connected.
+    new_tip_height: BlockHeight,
 }
 
 #[derive(Debug, Error, ErrorMeta)]
@@ -50,6 +89,20 @@ pub enum SlpIndexerError {
     #[critical()]
     #[error("Unexpected plugin message: {0:?}")]
     UnexpectedPluginMessage(Message),
+
+    #[critical()]
+    #[error(
+        "Reorg too deep: disconnecting block {block_hash} would exceed the max reorg depth of \
+         {max_reorg_depth} blocks. The index might be corrupted; consider re-indexing."
+    )]
+    ReorgTooDeep {
+        block_hash: Sha256d,
+        max_reorg_depth: usize,
+    },
+
+    #[critical()]
+    #[error("Inconsistent index, block at height {0} doesn't exist")]
+    InconsistentNoSuchBlockHeight(BlockHeight),
 }
 
 impl SlpIndexer {
@@ -64,7 +117,7 @@ impl SlpIndexer {
     ) -> Result<Self> {
         db.check_db_version()?;
         pub_interface.subscribe("------------")?;
-        Ok(SlpIndexer {
+        let mut slp_indexer = SlpIndexer {
             db,
             bitcoind,
             rpc_interface,
@@ -72,13 +125,27 @@ impl SlpIndexer {
             data,
             network,
             ecc,
+            block_files: None,
+            node_status: Arc::new(NodeStatus::default()),
             subscribers: Subscribers::default(),
-        })
+            is_stalled: false,
+            last_processed_at: None,
+            max_reorg_depth: DEFAULT_MAX_REORG_DEPTH,
+            reorg_depth: 0,
+            pending_reorg: None,
+            conflicting_mempool_txs: HashMap::new(),
+            tip_raw: None,
+        };
+        slp_indexer.load_tip_raw()?;
+        Ok(slp_indexer)
     }
 
     /// returns whether Initial Block Download has finished and the index is sync'd
     pub async fn catchup_step(&mut self) -> Result<bool> {
-        let blockchain_info = self.bitcoind.cmd_json("getblockchaininfo", &[]).await?;
+        let blockchain_info = self
+            .node_status
+            .call(self.bitcoind.cmd_json("getblockchaininfo", &[]))
+            .await?;
         let tip = self.db.blocks()?.tip()?;
         let tip_ref = tip.as_ref();
         let index_height = tip_ref.map(|block| block.height).unwrap_or(-1);
@@ -210,6 +277,8 @@ impl SlpIndexer {
             }
             msg => return Err(SlpIndexerError::UnexpectedPluginMessage(msg).into()),
         }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        self.last_processed_at = Some(now.as_secs() as i64);
         Ok(())
     }
 
@@ -223,6 +292,11 @@ impl SlpIndexer {
         &self.bitcoind
     }
 
+    /// Reachability of bitcoind; calls to it should go through `NodeStatus::call`.
+    pub fn node_status(&self) -> &Arc<NodeStatus> {
+        &self.node_status
+    }
+
     pub fn db(&self) -> &IndexDb {
         &self.db
     }
@@ -247,6 +321,10 @@ impl SlpIndexer {
         ScriptHistory::new(self)
     }
 
+    pub fn lokad_history(&self) -> LokadHistory {
+        LokadHistory::new(self)
+    }
+
     pub fn utxos(&self) -> Utxos {
         Utxos::new(self)
     }
@@ -259,10 +337,186 @@ impl SlpIndexer {
         Broadcast::new(self)
     }
 
+    pub fn verifier(&self) -> Verifier {
+        Verifier::new(self)
+    }
+
     pub fn subscribers_mut(&mut self) -> &mut Subscribers {
         &mut self.subscribers
     }
 
+    /// Subscribe to the spend of an outpoint. If it's already spent, the spend is sent right away.
+    pub fn subscribe_to_outpoint(
+        &mut self,
+        outpoint: &OutPoint,
+    ) -> Result<broadcast::Receiver<SubscribeOutpointMessage>> {
+        let receiver = self.subscribers.subscribe_to_outpoint(outpoint);
+        if let Some(spending_input) = self.utxos().spent_by(outpoint)? {
+            let is_confirmed = self.db_mempool().tx(&spending_input.txid).is_none();
+            self.subscribers.broadcast_to_outpoint(
+                outpoint,
+                SubscribeOutpointMessage::Spent {
+                    spending_txid: spending_input.txid,
+                    input_idx: spending_input.out_idx,
+                    is_confirmed,
+                },
+            );
+        }
+        Ok(receiver)
+    }
+
+    /// Whether the index has been lagging behind the node for too long, as determined by an
+    /// external watchdog.
+    pub fn is_stalled(&self) -> bool {
+        self.is_stalled
+    }
+
+    /// Unix timestamp of when the last message from the node was processed; unlike the tip's
+    /// timestamp, this also advances with mempool txs, and tells a quiet chain from a frozen
+    /// indexer.
+    pub fn last_processed_at(&self) -> Option<i64> {
+        self.last_processed_at
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    pub fn set_stalled(&mut self, is_stalled: bool) {
+        self.is_stalled = is_stalled;
+    }
+
+    pub fn script_history_cache_stats(&self) -> ScriptTxsCacheStats {
+        self.data.script_txs_cache_stats()
+    }
+
+    /// Changes how many scripts' history sizes are cached while indexing.
+    pub fn resize_script_history_cache(&mut self, capacity: usize) {
+        self.data.resize_script_txs_cache(capacity);
+    }
+
+    /// Max number of blocks that may be disconnected in a row (i.e. without any block being
+    /// connected in between); exceeding it results in a `ReorgTooDeep` error.
+    pub fn set_max_reorg_depth(&mut self, max_reorg_depth: usize) {
+        self.max_reorg_depth = max_reorg_depth;
+    }
+
+    pub fn max_reorg_depth(&self) -> usize {
+        self.max_reorg_depth
+    }
+
+    /// Directory of the node's `blkXXXXX.dat` files; if set, raw txs are read from there
+    /// directly instead of via the node's RPC interface.
+    pub fn set_blocks_dir(&mut self, blocks_dir: PathBuf) {
+        self.block_files = Some(BlockFiles::new(blocks_dir));
+    }
+
+    /// Recomputes the stats of every indexed block missing them from the block loaded from the
+    /// node and writes them back, so one lost entry doesn't take down every request using it.
+    /// Runs on startup, before any request is served; returns the number of repaired blocks.
+    pub fn repair_block_stats(&mut self) -> Result<usize> {
+        let block_reader = self.db.blocks()?;
+        let block_stats_reader = self.db.block_stats()?;
+        let mut num_repaired = 0;
+        for height in 0..=block_reader.height()? {
+            if block_stats_reader.by_height(height)?.is_some() {
+                continue;
+            }
+            let block = block_reader
+                .by_height(height)?
+                .ok_or(SlpIndexerError::InconsistentNoSuchBlockHeight(height))?;
+            self.recompute_block_stats(&block)?;
+            println!(
+                "Repaired missing block stats for block {} at height {}",
+                block.hash, block.height,
+            );
+            num_repaired += 1;
+        }
+        Ok(num_repaired)
+    }
+
+    fn recompute_block_stats(&self, block: &Block) -> Result<()> {
+        let nng_block = self
+            .rpc_interface
+            .get_block(BlockIdentifier::Hash(block.hash.clone()))?;
+        let txs = nng_block
+            .txs
+            .iter()
+            .map(|block_tx| {
+                let mut raw_tx = Bytes::from_slice(&block_tx.tx.raw);
+                UnhashedTx::deser(&mut raw_tx).map_err(Into::into)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // Block stats only use the txs' positions and sizes, the other fields don't matter here
+        let block_txs = BlockTxs {
+            txs: nng_block
+                .txs
+                .iter()
+                .zip(&txs)
+                .map(|(block_tx, tx)| TxEntry {
+                    txid: block_tx.tx.txid.clone(),
+                    data_pos: block_tx.data_pos,
+                    tx_size: block_tx.tx.raw.len() as u32,
+                    undo_pos: block_tx.undo_pos,
+                    undo_size: block_tx.undo_size,
+                    time_first_seen: 0,
+                    is_coinbase: tx.inputs[0].prev_out.is_coinbase(),
+                })
+                .collect(),
+            block_height: block.height,
+        };
+        self.db
+            .insert_block_stats(block, &block_txs, &txs, |tx_pos, input_idx| {
+                &nng_block.txs[tx_pos + 1].tx.spent_coins.as_ref().unwrap()[input_idx].tx_output
+            })
+    }
+
+    /// Reads `size` bytes at `data_pos` of the node's block file `file_num`. Uses the block files
+    /// on disk if available, and falls back to the node's RPC interface otherwise.
+    pub(crate) fn block_slice(&self, file_num: u32, data_pos: u32, size: u32) -> Result<Vec<u8>> {
+        if let Some(block_files) = &self.block_files {
+            match block_files.read_slice(file_num, data_pos, size) {
+                Ok(data) => return Ok(data),
+                Err(err) => eprintln!(
+                    "Reading block file {} failed, falling back to RPC: {}",
+                    file_num, err,
+                ),
+            }
+        }
+        Ok(self
+            .rpc_interface
+            .get_block_slice(file_num, data_pos, size)?)
+    }
+
+    /// Serialized header and coinbase tx of the tip, None if nothing has been indexed yet.
+    pub fn tip_raw(&self) -> Option<&TipRaw> {
+        self.tip_raw.as_ref()
+    }
+
+    /// Reads the serialized header and coinbase tx of the tip from the node, for when they're
+    /// not part of a block message, i.e. on startup and after disconnecting a block.
+    fn load_tip_raw(&mut self) -> Result<()> {
+        let blocks = self.blocks();
+        let tip_raw = match blocks.tip()? {
+            Some(tip) => Some(TipRaw {
+                raw_header: blocks
+                    .raw_header(&tip)?
+                    .ok_or_else(|| BlocksError::InconsistentNoSuchRawHeader(tip.hash.clone()))?,
+                raw_coinbase_tx: blocks.raw_coinbase_tx(&tip)?,
+                hash: tip.hash,
+            }),
+            None => None,
+        };
+        self.tip_raw = tip_raw;
+        Ok(())
+    }
+
+    /// Txid of the confirmed tx the given mempool tx conflicts with, i.e. which spends one of
+    /// the same outputs as the mempool tx or one of its ancestors.
+    pub fn mempool_conflict(&self, txid: &Sha256d) -> Option<&Sha256d> {
+        self.conflicting_mempool_txs.get(txid)
+    }
+
     fn _block_txs(block: &bitcoinsuite_bitcoind_nng::Block) -> Result<Vec<UnhashedTx>> {
         block
             .txs
@@ -279,15 +533,10 @@ impl SlpIndexer {
         tip: Option<Block>,
         block: bitcoinsuite_bitcoind_nng::Block,
     ) -> Result<()> {
+        self.reorg_depth = 0;
         let next_height = tip.as_ref().map(|tip| tip.height + 1).unwrap_or(0);
         let txs = Self::_block_txs(&block)?;
-        Self::broadcast_block_msg(
-            &mut self.subscribers,
-            block.header.hash.clone(),
-            &txs,
-            &block.txs,
-            true,
-        );
+        Self::broadcast_block_txs(&mut self.subscribers, &txs, &block.txs, true);
         let db_block = Block {
             hash: block.header.hash.clone(),
             prev_hash: block.header.prev_hash,
@@ -332,6 +581,25 @@ impl SlpIndexer {
             txs: db_txs,
             block_height: next_height,
         };
+        let conflicting_txs = self
+            .db_mempool()
+            .conflicting_txs(block.txs.iter().map(|block_tx| &block_tx.tx.txid).zip(&txs));
+        let coinbase_outputs = txs[0].outputs.clone();
+        let fees = block
+            .txs
+            .iter()
+            .zip(&txs)
+            .skip(1)
+            .map(|(block_tx, tx)| {
+                let spent_coins = block_tx.tx.spent_coins.as_ref().unwrap();
+                let sum_input_sats = spent_coins
+                    .iter()
+                    .map(|spent_coin| spent_coin.tx_output.value)
+                    .sum::<i64>();
+                let sum_output_sats = tx.outputs.iter().map(|output| output.value).sum::<i64>();
+                sum_input_sats - sum_output_sats
+            })
+            .sum::<i64>();
         self.db.insert_block(
             &db_block,
             &db_block_txs,
@@ -341,6 +609,55 @@ impl SlpIndexer {
             },
             &mut self.data,
         )?;
+        self.tip_raw = Some(TipRaw {
+            hash: block.header.hash.clone(),
+            raw_header: block.header.raw.clone(),
+            raw_coinbase_tx: block.txs[0].tx.raw.clone(),
+        });
+        for (txid, confirmed_txid) in conflicting_txs {
+            if let Some(tx) = self.db.mempool(&self.data).tx(&txid) {
+                Self::broadcast_msg(
+                    &mut self.subscribers,
+                    SubscribeScriptMessage::Conflicting(txid.clone()),
+                    tx.spent_coins
+                        .iter()
+                        .map(|spent_coin| &spent_coin.tx_output.script),
+                    tx.tx.outputs.iter().map(|output| &output.script),
+                );
+            }
+            self.conflicting_mempool_txs.insert(txid, confirmed_txid);
+        }
+        if self.subscribers.has_token_subs() {
+            let tokens = self.tokens();
+            let token_txs = block
+                .txs
+                .iter()
+                .zip(&txs)
+                .map(|(block_tx, tx)| {
+                    let txid = &block_tx.tx.txid;
+                    Ok((txid.clone(), tokens.tx_token_ids(txid, tx)?))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            self.broadcast_token_txs(token_txs, true)?;
+        }
+        // Sent last, so subscribers get it after all other messages of the block
+        self.subscribers
+            .broadcast_to_blocks(SubscribeBlockMessage::BlockConnected {
+                block_hash: block.header.hash.clone(),
+                height: next_height,
+                subsidy: coinbase_outputs
+                    .iter()
+                    .map(|output| output.value)
+                    .sum::<i64>()
+                    - fees,
+                coinbase_outputs,
+                fees,
+            });
+        if let Some(reorg) = self.pending_reorg {
+            if reorg.new_tip_height == next_height {
+                self.finish_reorg(next_height);
+            }
+        }
         self.update_transient_data(next_height)?;
         println!(
             "Added block {} with {} txs, height {}",
@@ -354,15 +671,37 @@ impl SlpIndexer {
         tip: Option<Block>,
         block: bitcoinsuite_bitcoind_nng::Block,
     ) -> Result<()> {
-        let txs = Self::_block_txs(&block)?;
-        Self::broadcast_block_msg(
-            &mut self.subscribers,
-            block.header.hash.clone(),
-            &txs,
-            &block.txs,
-            false,
-        );
+        if self.reorg_depth >= self.max_reorg_depth {
+            return Err(SlpIndexerError::ReorgTooDeep {
+                block_hash: block.header.hash,
+                max_reorg_depth: self.max_reorg_depth,
+            }
+            .into());
+        }
+        self.reorg_depth += 1;
         let tip = tip.unwrap();
+        let reorg = match self.pending_reorg {
+            Some(reorg) => reorg,
+            None => {
+                let reorg = self.find_reorg(&tip)?;
+                self.subscribers
+                    .broadcast_to_blocks(SubscribeBlockMessage::ReorgStarted {
+                        from_height: reorg.fork_height + 1,
+                        to_height: tip.height,
+                    });
+                self.pending_reorg = Some(reorg);
+                reorg
+            }
+        };
+        let txs = Self::_block_txs(&block)?;
+        Self::broadcast_block_txs(&mut self.subscribers, &txs, &block.txs, false);
+        let disconnected_txids = block
+            .txs
+            .iter()
+            .map(|block_tx| &block_tx.tx.txid)
+            .collect::<HashSet<_>>();
+        self.conflicting_mempool_txs
+            .retain(|_, confirmed_txid| !disconnected_txids.contains(confirmed_txid));
         let txids_fn = |idx: usize| &block.txs[idx].tx.txid;
         self.db.delete_block(
             &block.header.hash,
@@ -375,6 +714,15 @@ impl SlpIndexer {
             &mut self.data,
         )?;
         self.db.transient_data_writer().delete_block(tip.height)?;
+        self.load_tip_raw()?;
+        self.subscribers
+            .broadcast_to_blocks(SubscribeBlockMessage::BlockDisconnected(
+                block.header.hash.clone(),
+            ));
+        // If the new chain has no blocks (yet), the reorg is done once back at the fork
+        if tip.height - 1 == reorg.fork_height && reorg.new_tip_height == reorg.fork_height {
+            self.finish_reorg(reorg.fork_height);
+        }
         println!(
             "Removed block {} via BlockDisconnected message",
             block.header.hash
@@ -382,6 +730,40 @@ impl SlpIndexer {
         Ok(())
     }
 
+    /// Compares the index with the node's active chain to find the span of the reorg which
+    /// starts by disconnecting `tip`. If the node is still receiving blocks of the new chain,
+    /// `ReorgFinished` is sent after the ones it had at this point.
+    fn find_reorg(&self, tip: &Block) -> Result<PendingReorg> {
+        let block_reader = self.db.blocks()?;
+        // The fork can't be below genesis, so clamping also keeps huge depths from wrapping
+        let max_reorg_depth = self.max_reorg_depth.min(tip.height as usize) as BlockHeight;
+        let min_fork_height = tip.height - max_reorg_depth;
+        let mut fork_height = tip.height - 1;
+        while fork_height >= min_fork_height {
+            let index_block = block_reader
+                .by_height(fork_height)?
+                .expect("Inconsistent db");
+            let node_blocks = self.rpc_interface.get_block_range(fork_height, 1)?;
+            match node_blocks.first() {
+                Some(node_block) if node_block.header.hash == index_block.hash => break,
+                _ => fork_height -= 1,
+            }
+        }
+        let new_blocks = self
+            .rpc_interface
+            .get_block_range(fork_height + 1, (max_reorg_depth + 1) as _)?;
+        Ok(PendingReorg {
+            fork_height,
+            new_tip_height: fork_height + new_blocks.len() as BlockHeight,
+        })
+    }
+
+    fn finish_reorg(&mut self, new_tip_height: BlockHeight) {
+        self.pending_reorg = None;
+        self.subscribers
+            .broadcast_to_blocks(SubscribeBlockMessage::ReorgFinished { new_tip_height });
+    }
+
     fn handle_tx_added_to_mempool(&mut self, mempool_tx: MempoolTx) -> Result<()> {
         let nng_tx = mempool_tx.tx;
         let mut raw_tx = Bytes::from_bytes(nng_tx.raw);
@@ -395,13 +777,19 @@ impl SlpIndexer {
                 .map(|spent_output| &spent_output.tx_output.script),
             tx.outputs.iter().map(|spent_output| &spent_output.script),
         );
+        Self::broadcast_outpoints_spent(&mut self.subscribers, &nng_tx.txid, &tx, false);
         let entry = MempoolTxEntry {
             tx,
             spent_coins,
             time_first_seen: mempool_tx.time,
         };
         self.db
-            .insert_mempool_tx(&mut self.data, nng_tx.txid, entry)?;
+            .insert_mempool_tx(&mut self.data, nng_tx.txid.clone(), entry)?;
+        if self.subscribers.has_token_subs() {
+            let entry = self.db_mempool().tx(&nng_tx.txid).unwrap();
+            let token_ids = self.tokens().tx_token_ids(&nng_tx.txid, &entry.tx)?;
+            self.broadcast_token_txs(vec![(nng_tx.txid, token_ids)], false)?;
+        }
         Ok(())
     }
 
@@ -417,6 +805,7 @@ impl SlpIndexer {
             );
         }
         self.db.remove_mempool_tx(&mut self.data, &txid)?;
+        self.conflicting_mempool_txs.remove(&txid);
         Ok(())
     }
 
@@ -438,18 +827,75 @@ impl SlpIndexer {
         }
     }
 
-    fn broadcast_block_msg(
+    fn broadcast_outpoints_spent(
+        subscribers: &mut Subscribers,
+        txid: &Sha256d,
+        tx: &UnhashedTx,
+        is_confirmed: bool,
+    ) {
+        for (input_idx, input) in tx.inputs.iter().enumerate() {
+            subscribers.broadcast_to_outpoint(
+                &input.prev_out,
+                SubscribeOutpointMessage::Spent {
+                    spending_txid: txid.clone(),
+                    input_idx: input_idx as u32,
+                    is_confirmed,
+                },
+            );
+        }
+    }
+
+    /// Sends the txs to the subscribers of the tokens they affect, followed by the updated
+    /// stats of each of these tokens.
+    fn broadcast_token_txs(
+        &mut self,
+        token_txs: Vec<(Sha256d, Vec<TokenId>)>,
+        is_confirmed: bool,
+    ) -> Result<()> {
+        let mut affected_token_ids = Vec::<TokenId>::new();
+        for (txid, token_ids) in token_txs {
+            for token_id in token_ids {
+                self.subscribers.broadcast_to_token(
+                    &token_id,
+                    match is_confirmed {
+                        true => SubscribeTokenMessage::Confirmed(txid.clone()),
+                        false => SubscribeTokenMessage::AddedToMempool(txid.clone()),
+                    },
+                );
+                if !affected_token_ids.contains(&token_id) {
+                    affected_token_ids.push(token_id);
+                }
+            }
+        }
+        for token_id in affected_token_ids {
+            let token_stats = self
+                .tokens()
+                .token_stats_by_token_id(&token_id)?
+                .unwrap_or_default();
+            let unconfirmed_delta = self
+                .db_mempool_slp()
+                .token_stats_delta(&token_id)
+                .cloned()
+                .unwrap_or_default();
+            self.subscribers.broadcast_to_token(
+                &token_id,
+                SubscribeTokenMessage::Stats {
+                    token_stats,
+                    unconfirmed_delta,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Sends `Confirmed` (or `Reorg`) to the subscribers of the scripts of the block's txs, and
+    /// `Spent` to the subscribers of the outpoints they spend.
+    fn broadcast_block_txs(
         subscribers: &mut Subscribers,
-        block_hash: Sha256d,
         txs: &[UnhashedTx],
         block_txs: &[BlockTx],
         is_confirmed: bool,
     ) {
-        subscribers.broadcast_to_blocks(if is_confirmed {
-            SubscribeBlockMessage::BlockConnected(block_hash)
-        } else {
-            SubscribeBlockMessage::BlockDisconnected(block_hash)
-        });
         for (tx, block_tx) in txs.iter().zip(block_txs) {
             let spent_scripts = block_tx.tx.spent_coins.iter().flat_map(|spent_coins| {
                 spent_coins
@@ -464,7 +910,10 @@ impl SlpIndexer {
                 },
                 spent_scripts,
                 tx.outputs.iter().map(|output| &output.script),
-            )
+            );
+            if is_confirmed {
+                Self::broadcast_outpoints_spent(subscribers, &block_tx.tx.txid, tx, true);
+            }
         }
     }
 
@@ -1,16 +1,24 @@
+mod block_files;
 mod blocks;
 pub mod broadcast;
 pub mod error;
 mod indexer;
+mod lokad_history;
+mod node_status;
 mod script_history;
 pub mod subscribers;
 mod tokens;
 mod txs;
 mod utxos;
+mod verifier;
 
+pub use crate::block_files::*;
 pub use crate::blocks::*;
 pub use crate::indexer::*;
+pub use crate::lokad_history::*;
+pub use crate::node_status::*;
 pub use crate::script_history::*;
 pub use crate::tokens::*;
 pub use crate::txs::*;
 pub use crate::utxos::*;
+pub use crate::verifier::*;
@@ -0,0 +1,62 @@
+use bitcoinsuite_error::{ErrorMeta, Result};
+use bitcoinsuite_slp::RichTx;
+use chronik_rocksdb::TxNum;
+use thiserror::Error;
+
+use crate::SlpIndexer;
+
+pub struct LokadHistory<'a> {
+    indexer: &'a SlpIndexer,
+}
+
+#[derive(Debug, Error, ErrorMeta)]
+pub enum LokadHistoryError {
+    #[invalid_user_input()]
+    #[error("LOKAD ID is not indexed: {0}")]
+    LokadIdNotIndexed(String),
+
+    #[critical()]
+    #[error("Inconsistent db, tx_num doesn't exist: {0}")]
+    InconsistentNoSuchBlockTxNum(TxNum),
+}
+
+use self::LokadHistoryError::*;
+
+impl<'a> LokadHistory<'a> {
+    pub fn new(indexer: &'a SlpIndexer) -> Self {
+        LokadHistory { indexer }
+    }
+
+    /// Confirmed txs with an OP_RETURN starting with `lokad_id`, most recent first.
+    pub fn rev_history_page(
+        &self,
+        lokad_id: &[u8],
+        page_num: usize,
+        page_size: usize,
+    ) -> Result<Vec<RichTx>> {
+        let lokad_txs = self.indexer.db().lokad_txs()?;
+        if !lokad_txs.is_indexed(lokad_id) {
+            return Err(LokadIdNotIndexed(hex::encode(lokad_id)).into());
+        }
+        let tx_reader = self.indexer.db().txs()?;
+        lokad_txs
+            .rev_page_txs(lokad_id, page_num, page_size)?
+            .into_iter()
+            .map(|tx_num| {
+                let block_tx = tx_reader
+                    .by_tx_num(tx_num)?
+                    .ok_or(InconsistentNoSuchBlockTxNum(tx_num))?;
+                self.indexer.txs().rich_block_tx(tx_num, &block_tx)
+            })
+            .collect()
+    }
+
+    pub fn rev_history_num_pages(&self, lokad_id: &[u8], page_size: usize) -> Result<usize> {
+        let lokad_txs = self.indexer.db().lokad_txs()?;
+        if !lokad_txs.is_indexed(lokad_id) {
+            return Err(LokadIdNotIndexed(hex::encode(lokad_id)).into());
+        }
+        let num_txs = lokad_txs.num_txs(lokad_id)?;
+        Ok((num_txs + page_size - 1) / page_size)
+    }
+}
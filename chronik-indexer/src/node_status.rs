@@ -0,0 +1,90 @@
+use std::{
+    future::Future,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use bitcoinsuite_bitcoind::BitcoindError;
+use bitcoinsuite_error::{ErrorMeta, Report, Result};
+use thiserror::Error;
+
+/// How long bitcoind calls fail fast after bitcoind was found unreachable, before it's tried again.
+pub const DEFAULT_NODE_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Circuit breaker for calls to bitcoind's JSON-RPC interface.
+///
+/// Once a call fails because bitcoind can't be reached (e.g. while it's restarting), further
+/// calls fail with `NodeUnavailable` right away instead of each waiting for a connection error,
+/// until `retry_interval` has passed; the next call then probes bitcoind again. Data served from
+/// the index alone is unaffected.
+#[derive(Debug)]
+pub struct NodeStatus {
+    retry_interval: Duration,
+    /// When a call last failed because bitcoind was unreachable, None if the last call reached it.
+    unreachable_at: Mutex<Option<Instant>>,
+}
+
+#[derive(Debug, Error, ErrorMeta)]
+pub enum NodeStatusError {
+    #[critical()]
+    #[error("Node unavailable: bitcoind can't be reached, try again later")]
+    NodeUnavailable,
+}
+
+use self::NodeStatusError::*;
+
+impl NodeStatus {
+    pub fn new(retry_interval: Duration) -> Self {
+        NodeStatus {
+            retry_interval,
+            unreachable_at: Mutex::new(None),
+        }
+    }
+
+    /// Whether the last call to bitcoind reached it.
+    pub fn is_reachable(&self) -> bool {
+        self.unreachable_at.lock().unwrap().is_none()
+    }
+
+    /// Runs the bitcoind call `call`, unless bitcoind was found unreachable within the retry
+    /// interval, and records whether it was reachable. Errors from bitcoind being unreachable
+    /// are wrapped in `NodeUnavailable`.
+    pub async fn call<T>(&self, call: impl Future<Output = Result<T>>) -> Result<T> {
+        if let Some(unreachable_at) = *self.unreachable_at.lock().unwrap() {
+            if unreachable_at.elapsed() < self.retry_interval {
+                return Err(NodeUnavailable.into());
+            }
+        }
+        let result = call.await;
+        match &result {
+            Err(report) if is_unreachable_error(report) => {
+                self.set_unreachable();
+                result.map_err(|report| report.wrap_err(NodeUnavailable))
+            }
+            _ => {
+                *self.unreachable_at.lock().unwrap() = None;
+                result
+            }
+        }
+    }
+
+    /// Opens the circuit breaker, as if a call to bitcoind just failed to reach it.
+    pub fn set_unreachable(&self) {
+        *self.unreachable_at.lock().unwrap() = Some(Instant::now());
+    }
+}
+
+impl Default for NodeStatus {
+    fn default() -> Self {
+        NodeStatus::new(DEFAULT_NODE_RETRY_INTERVAL)
+    }
+}
+
+/// Any bitcoind error other than a JSON-RPC error response means bitcoind couldn't be reached
+/// (e.g. connection refused); an error response means it's up but rejected the call.
+fn is_unreachable_error(report: &Report) -> bool {
+    !matches!(
+        report.downcast_ref::<BitcoindError>(),
+        None | Some(BitcoindError::JsonRpcCode { .. }),
+    )
+}
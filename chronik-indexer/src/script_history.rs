@@ -1,15 +1,80 @@
-use bitcoinsuite_core::Sha256d;
+use std::{collections::HashSet, ops::Range};
+
+use bitcoinsuite_core::{OutPoint, Script, Sha256d, TxOutput};
 use bitcoinsuite_error::{ErrorMeta, Result};
-use bitcoinsuite_slp::RichTx;
-use chronik_rocksdb::{PayloadPrefix, TxNum};
+use bitcoinsuite_slp::{RichTx, RichTxBlock, SlpToken, SlpTokenType, TokenId};
+use chronik_rocksdb::{script_payloads, BlockHeight, PayloadPrefix, ScriptPayload, TxNum};
 use thiserror::Error;
 
 use crate::SlpIndexer;
 
+/// Max. number of the script's txs in a height range scanned for spent outputs or token flows, as
+/// the txs of the whole range have to be loaded to paginate them.
+pub const MAX_SPENT_OUTPUTS_SCAN_TXS: usize = 5000;
+
 pub struct ScriptHistory<'a> {
     indexer: &'a SlpIndexer,
 }
 
+/// Overview of a script, including mempool txs and UTXOs.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ScriptSummary {
+    /// Sum of the values of all UTXOs
+    pub balance: i64,
+    pub num_txs: usize,
+    pub num_utxos: usize,
+    /// Block timestamp (or time first seen, if in the mempool) of the oldest tx
+    pub first_activity: Option<i64>,
+    /// Block timestamp (or time first seen, if in the mempool) of the most recent tx
+    pub last_activity: Option<i64>,
+    /// SLP tokens held in UTXOs, ordered by token ID
+    pub token_holdings: Vec<TokenHolding>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenHolding {
+    pub token_id: TokenId,
+    pub token_type: SlpTokenType,
+    /// Sum of the token amounts, in base units
+    pub amount: i128,
+    pub num_mint_batons: usize,
+}
+
+/// SLP token which appeared in a script's history.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptToken {
+    pub token_id: TokenId,
+    pub token_type: SlpTokenType,
+    /// Height of the block of the script's first tx of the token, `None` if only in the mempool
+    pub first_seen_height: Option<BlockHeight>,
+}
+
+/// Token amounts a confirmed tx moved into and out of a script, in base units. The net change of
+/// the script's balance of the token is `received - sent`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptTokenFlow {
+    pub txid: Sha256d,
+    pub block: RichTxBlock,
+    /// Sum of the token amounts of the tx's outputs to the script
+    pub received: i128,
+    /// Sum of the token amounts of the script's outputs spent by the tx, including burned ones
+    pub sent: i128,
+}
+
+/// Output of a script which was spent by a confirmed tx.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpentScriptOutput {
+    pub outpoint: OutPoint,
+    pub output: TxOutput,
+    pub slp_token: SlpToken,
+    /// Height of the block of the tx creating the output
+    pub created_height: BlockHeight,
+    pub spending_txid: Sha256d,
+    pub input_idx: u32,
+    /// Block of the spending tx
+    pub spending_block: RichTxBlock,
+}
+
 #[derive(Debug, Error, ErrorMeta)]
 pub enum ScriptHistoryError {
     #[critical()]
@@ -19,6 +84,21 @@ pub enum ScriptHistoryError {
     #[critical()]
     #[error("Inconsistent db, tx_num doesn't exist: {0}")]
     InconsistentNoSuchBlockTxNum(TxNum),
+
+    #[critical()]
+    #[error("Inconsistent db, block height doesn't exist: {0}")]
+    InconsistentNoSuchBlockHeight(BlockHeight),
+
+    #[critical()]
+    #[error("Inconsistent db, txid doesn't exist: {0}")]
+    InconsistentNoSuchBlockTx(Sha256d),
+
+    #[invalid_user_input()]
+    #[error(
+        "Too many txs in range: {0}, at most {} can be scanned, narrow the range",
+        MAX_SPENT_OUTPUTS_SCAN_TXS
+    )]
+    TooManyTxsInRange(usize),
 }
 
 use self::ScriptHistoryError::*;
@@ -93,6 +173,231 @@ impl<'a> ScriptHistory<'a> {
         Ok(page_txs)
     }
 
+    /// Confirmed tx history of blocks in `start_height..=end_height`, most recent first.
+    /// Mempool txs are never included, even if the range extends to the tip.
+    /// Returns the txs of the page and the total number of pages.
+    pub fn rev_history_page_in_height_range(
+        &self,
+        prefix: PayloadPrefix,
+        payload: &[u8],
+        start_height: BlockHeight,
+        end_height: BlockHeight,
+        history_page_num: usize,
+        history_page_size: usize,
+    ) -> Result<(Vec<RichTx>, usize)> {
+        let mut tx_nums =
+            self.block_tx_nums_in_height_range(prefix, payload, start_height, end_height)?;
+        tx_nums.reverse();
+        self.block_txs_page(tx_nums, history_page_num, history_page_size)
+    }
+
+    /// Like [`ScriptHistory::rev_history_page_in_height_range`], but oldest first, e.g. to
+    /// replay the confirmed txs since a given height in the order they were mined.
+    pub fn history_page_in_height_range(
+        &self,
+        prefix: PayloadPrefix,
+        payload: &[u8],
+        start_height: BlockHeight,
+        end_height: BlockHeight,
+        history_page_num: usize,
+        history_page_size: usize,
+    ) -> Result<(Vec<RichTx>, usize)> {
+        let tx_nums =
+            self.block_tx_nums_in_height_range(prefix, payload, start_height, end_height)?;
+        self.block_txs_page(tx_nums, history_page_num, history_page_size)
+    }
+
+    /// Outputs of the script spent by txs in blocks in `start_height..=end_height`, in the
+    /// order they were spent. Txs spending the script's outputs are part of its history, so only
+    /// the script's txs in the range are scanned, and the optional spends index isn't needed.
+    /// Returns the spent outputs of the page and the total number of pages.
+    pub fn spent_outputs_page_in_height_range(
+        &self,
+        prefix: PayloadPrefix,
+        payload: &[u8],
+        start_height: BlockHeight,
+        end_height: BlockHeight,
+        page_num: usize,
+        page_size: usize,
+    ) -> Result<(Vec<SpentScriptOutput>, usize)> {
+        let tx_nums =
+            self.block_tx_nums_in_height_range(prefix, payload, start_height, end_height)?;
+        if tx_nums.len() > MAX_SPENT_OUTPUTS_SCAN_TXS {
+            return Err(TooManyTxsInRange(tx_nums.len()).into());
+        }
+        let tx_reader = self.indexer.db().txs()?;
+        let mut spent_outputs = Vec::new();
+        for tx_num in tx_nums {
+            let block_tx = tx_reader
+                .by_tx_num(tx_num)?
+                .ok_or(InconsistentNoSuchBlockTxNum(tx_num))?;
+            let tx = self.indexer.txs().rich_block_tx(tx_num, &block_tx)?;
+            let spending_block = match &tx.block {
+                Some(block) => block,
+                None => continue,
+            };
+            for (input_idx, input) in tx.inputs().enumerate() {
+                let spent_coin = match input.spent_coin {
+                    Some(spent_coin) => spent_coin,
+                    None => continue,
+                };
+                let is_script_output =
+                    script_payloads(&spent_coin.tx_output.script)
+                        .iter()
+                        .any(|state| {
+                            state.payload.payload_prefix == prefix
+                                && state.payload.payload_data == payload
+                        });
+                if !is_script_output {
+                    continue;
+                }
+                let prev_out = &input.tx_input.prev_out;
+                let (_, created_tx) = tx_reader
+                    .tx_and_num_by_txid(&prev_out.txid)?
+                    .ok_or_else(|| InconsistentNoSuchBlockTx(prev_out.txid.clone()))?;
+                spent_outputs.push(SpentScriptOutput {
+                    outpoint: prev_out.clone(),
+                    output: spent_coin.tx_output.clone(),
+                    slp_token: input.slp_token,
+                    created_height: created_tx.block_height,
+                    spending_txid: tx.txid.clone(),
+                    input_idx: input_idx as u32,
+                    spending_block: spending_block.clone(),
+                });
+            }
+        }
+        let num_pages = (spent_outputs.len() + page_size - 1) / page_size;
+        let page = spent_outputs
+            .into_iter()
+            .skip(page_num.saturating_mul(page_size))
+            .take(page_size)
+            .collect();
+        Ok((page, num_pages))
+    }
+
+    /// Token flows of the script's txs in blocks in `start_height..=end_height` for the token,
+    /// in the order the txs were mined. Txs not changing the script's balance of the token
+    /// are left out; mint batons don't count. Returns the flows of the page and the total number
+    /// of pages.
+    #[allow(clippy::too_many_arguments)]
+    pub fn token_flows_page_in_height_range(
+        &self,
+        prefix: PayloadPrefix,
+        payload: &[u8],
+        token_id: &TokenId,
+        start_height: BlockHeight,
+        end_height: BlockHeight,
+        page_num: usize,
+        page_size: usize,
+    ) -> Result<(Vec<ScriptTokenFlow>, usize)> {
+        let tx_nums =
+            self.block_tx_nums_in_height_range(prefix, payload, start_height, end_height)?;
+        if tx_nums.len() > MAX_SPENT_OUTPUTS_SCAN_TXS {
+            return Err(TooManyTxsInRange(tx_nums.len()).into());
+        }
+        let is_script = |script: &Script| {
+            script_payloads(script).iter().any(|state| {
+                state.payload.payload_prefix == prefix && state.payload.payload_data == payload
+            })
+        };
+        let tx_reader = self.indexer.db().txs()?;
+        let mut flows = Vec::new();
+        for tx_num in tx_nums {
+            let block_tx = tx_reader
+                .by_tx_num(tx_num)?
+                .ok_or(InconsistentNoSuchBlockTxNum(tx_num))?;
+            let tx = self.indexer.txs().rich_block_tx(tx_num, &block_tx)?;
+            let block = match &tx.block {
+                Some(block) => block.clone(),
+                None => continue,
+            };
+            let is_tx_token = match &tx.slp_tx_data {
+                Some(slp_tx_data) => &slp_tx_data.token_id == token_id,
+                None => false,
+            };
+            let mut sent = 0;
+            for input in tx.inputs() {
+                match input.spent_coin {
+                    Some(spent_coin) if is_script(&spent_coin.tx_output.script) => {}
+                    _ => continue,
+                }
+                // Burned inputs aren't part of the tx's input tokens, so count them separately
+                let token = match input.slp_burn {
+                    Some(slp_burn) if &slp_burn.token_id == token_id => slp_burn.token,
+                    Some(_) => continue,
+                    None if is_tx_token => input.slp_token,
+                    None => continue,
+                };
+                if !token.is_mint_baton {
+                    sent += token.amount.base_amount();
+                }
+            }
+            let mut received = 0;
+            if is_tx_token {
+                for output in tx.outputs() {
+                    if is_script(&output.tx_output.script) && !output.slp_token.is_mint_baton {
+                        received += output.slp_token.amount.base_amount();
+                    }
+                }
+            }
+            if received == 0 && sent == 0 {
+                continue;
+            }
+            flows.push(ScriptTokenFlow {
+                txid: tx.txid.clone(),
+                block,
+                received,
+                sent,
+            });
+        }
+        let num_pages = (flows.len() + page_size - 1) / page_size;
+        let page = flows
+            .into_iter()
+            .skip(page_num.saturating_mul(page_size))
+            .take(page_size)
+            .collect();
+        Ok((page, num_pages))
+    }
+
+    fn block_tx_nums_in_height_range(
+        &self,
+        prefix: PayloadPrefix,
+        payload: &[u8],
+        start_height: BlockHeight,
+        end_height: BlockHeight,
+    ) -> Result<Vec<TxNum>> {
+        match self
+            .indexer
+            .blocks()
+            .tx_num_range(start_height, end_height)?
+        {
+            Some(tx_num_range) => self.block_tx_nums_in_range(prefix, payload, tx_num_range),
+            None => Ok(vec![]),
+        }
+    }
+
+    fn block_txs_page(
+        &self,
+        tx_nums: Vec<TxNum>,
+        history_page_num: usize,
+        history_page_size: usize,
+    ) -> Result<(Vec<RichTx>, usize)> {
+        let num_pages = (tx_nums.len() + history_page_size - 1) / history_page_size;
+        let tx_reader = self.indexer.db().txs()?;
+        let page_txs = tx_nums
+            .into_iter()
+            .skip(history_page_num * history_page_size)
+            .take(history_page_size)
+            .map(|tx_num| {
+                let block_tx = tx_reader
+                    .by_tx_num(tx_num)?
+                    .ok_or(InconsistentNoSuchBlockTxNum(tx_num))?;
+                self.indexer.txs().rich_block_tx(tx_num, &block_tx)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok((page_txs, num_pages))
+    }
+
     pub fn rev_history_num_pages(
         &self,
         prefix: PayloadPrefix,
@@ -118,6 +423,45 @@ impl<'a> ScriptHistory<'a> {
         Ok(db_script_txs.page_size() * (num_pages - 1) + last_page_size)
     }
 
+    /// Tx nums of the script within `tx_num_range`, in chronological order.
+    fn block_tx_nums_in_range(
+        &self,
+        prefix: PayloadPrefix,
+        payload: &[u8],
+        tx_num_range: Range<TxNum>,
+    ) -> Result<Vec<TxNum>> {
+        let db_script_txs = self.indexer.db().script_txs()?;
+        let num_pages = db_script_txs.num_pages_by_payload(prefix, payload)?;
+        // Pages are sorted by tx_num, so binary search the first page ending within the range
+        let mut first_page_num = 0;
+        let mut end_page_num = num_pages;
+        while first_page_num < end_page_num {
+            let mid_page_num = (first_page_num + end_page_num) / 2;
+            let page_tx_nums = db_script_txs.page_txs(mid_page_num as u32, prefix, payload)?;
+            match page_tx_nums.last() {
+                Some(&last_tx_num) if last_tx_num >= tx_num_range.start => {
+                    end_page_num = mid_page_num
+                }
+                _ => first_page_num = mid_page_num + 1,
+            }
+        }
+        let mut tx_nums = Vec::new();
+        for page_num in first_page_num..num_pages {
+            let page_tx_nums = db_script_txs.page_txs(page_num as u32, prefix, payload)?;
+            tx_nums.extend(
+                page_tx_nums
+                    .iter()
+                    .copied()
+                    .filter(|tx_num| tx_num_range.contains(tx_num)),
+            );
+            match page_tx_nums.last() {
+                Some(&last_tx_num) if last_tx_num < tx_num_range.end => {}
+                _ => break,
+            }
+        }
+        Ok(tx_nums)
+    }
+
     pub fn num_mempool_txs(&self, prefix: PayloadPrefix, payload: &[u8]) -> usize {
         self.indexer
             .db_mempool()
@@ -125,4 +469,132 @@ impl<'a> ScriptHistory<'a> {
             .map(|txs| txs.len())
             .unwrap_or_default()
     }
+
+    /// Summary of the script's history and UTXOs. Besides a few lookups for the history, this
+    /// loads all UTXOs of the script, so it's as expensive as `Utxos::utxos`.
+    pub fn summary(&self, script_payload: &ScriptPayload) -> Result<ScriptSummary> {
+        let prefix = script_payload.payload_prefix;
+        let payload = &script_payload.payload_data;
+        let mempool_txs = self.indexer.db_mempool().script_txs(prefix, payload);
+        let num_mempool_txs = mempool_txs.map(|txs| txs.len()).unwrap_or_default();
+        let num_block_txs = self.num_block_txs(prefix, payload)?;
+        let mut summary = ScriptSummary {
+            num_txs: num_mempool_txs + num_block_txs,
+            ..Default::default()
+        };
+        if num_block_txs > 0 {
+            let db_script_txs = self.indexer.db().script_txs()?;
+            let first_page = db_script_txs.page_txs(0, prefix, payload)?;
+            let last_page_num = ((num_block_txs - 1) / db_script_txs.page_size()) as u32;
+            let last_page = db_script_txs.page_txs(last_page_num, prefix, payload)?;
+            summary.first_activity = Some(self.block_tx_timestamp(first_page[0])?);
+            summary.last_activity = Some(self.block_tx_timestamp(*last_page.last().unwrap())?);
+        }
+        if let Some(mempool_txs) = mempool_txs {
+            if let Some(&(oldest_time, _)) = mempool_txs.iter().next() {
+                summary.first_activity.get_or_insert(oldest_time);
+            }
+            if let Some(&(newest_time, _)) = mempool_txs.iter().next_back() {
+                summary.last_activity = Some(newest_time);
+            }
+        }
+        let utxos = self.indexer.utxos().utxos(script_payload)?;
+        summary.num_utxos = utxos.len();
+        for utxo in &utxos {
+            summary.balance += utxo.output.value;
+            // Non-token outputs of valid SLP txs have an empty token, they aren't holdings
+            let slp_output = match &utxo.slp_output {
+                Some(slp_output) if slp_output.token != SlpToken::EMPTY => slp_output,
+                _ => continue,
+            };
+            let holding_idx = match summary
+                .token_holdings
+                .binary_search_by_key(&slp_output.token_id.token_id_be(), |holding| {
+                    holding.token_id.token_id_be()
+                }) {
+                Ok(holding_idx) => holding_idx,
+                Err(holding_idx) => {
+                    summary.token_holdings.insert(
+                        holding_idx,
+                        TokenHolding {
+                            token_id: slp_output.token_id.clone(),
+                            token_type: slp_output.token_type,
+                            amount: 0,
+                            num_mint_batons: 0,
+                        },
+                    );
+                    holding_idx
+                }
+            };
+            let holding = &mut summary.token_holdings[holding_idx];
+            holding.amount += slp_output.token.amount.base_amount();
+            if slp_output.token.is_mint_baton {
+                holding.num_mint_batons += 1;
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Tokens of all valid SLP txs in the script's history: first the confirmed ones, in the
+    /// order they first appeared, then the ones only seen in the mempool. This scans the
+    /// script's entire history, so it's slow for scripts with many txs.
+    pub fn token_history(&self, prefix: PayloadPrefix, payload: &[u8]) -> Result<Vec<ScriptToken>> {
+        let db_script_txs = self.indexer.db().script_txs()?;
+        let tx_reader = self.indexer.db().txs()?;
+        let slp_reader = self.indexer.db().slp()?;
+        let mut seen_token_ids = HashSet::new();
+        let mut tokens = Vec::new();
+        let num_pages = db_script_txs.num_pages_by_payload(prefix, payload)?;
+        for page_num in 0..num_pages {
+            for tx_num in db_script_txs.page_txs(page_num as u32, prefix, payload)? {
+                let slp_tx_data = match slp_reader.slp_data_by_tx_num(tx_num)? {
+                    Some(slp) => slp.slp_tx_data,
+                    None => continue,
+                };
+                if !seen_token_ids.insert(slp_tx_data.token_id.hash().clone()) {
+                    continue;
+                }
+                let block_tx = tx_reader
+                    .by_tx_num(tx_num)?
+                    .ok_or(InconsistentNoSuchBlockTxNum(tx_num))?;
+                tokens.push(ScriptToken {
+                    token_id: slp_tx_data.token_id,
+                    token_type: slp_tx_data.slp_token_type,
+                    first_seen_height: Some(block_tx.block_height),
+                });
+            }
+        }
+        if let Some(mempool_txs) = self.indexer.db_mempool().script_txs(prefix, payload) {
+            for (_, txid) in mempool_txs {
+                let slp_tx_data = match self.indexer.db_mempool_slp().slp_tx_data(txid) {
+                    Some(slp) => &slp.slp_tx_data,
+                    None => continue,
+                };
+                if seen_token_ids.insert(slp_tx_data.token_id.hash().clone()) {
+                    tokens.push(ScriptToken {
+                        token_id: slp_tx_data.token_id.clone(),
+                        token_type: slp_tx_data.slp_token_type,
+                        first_seen_height: None,
+                    });
+                }
+            }
+        }
+        Ok(tokens)
+    }
+
+    fn block_tx_timestamp(&self, tx_num: TxNum) -> Result<i64> {
+        let block_tx = self
+            .indexer
+            .db()
+            .txs()?
+            .by_tx_num(tx_num)?
+            .ok_or(InconsistentNoSuchBlockTxNum(tx_num))?;
+        let block = self
+            .indexer
+            .db()
+            .blocks()?
+            .by_height(block_tx.block_height)?
+            .ok_or(InconsistentNoSuchBlockHeight(block_tx.block_height))?;
+        Ok(block.timestamp)
+    }
 }
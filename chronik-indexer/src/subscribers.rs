@@ -1,7 +1,8 @@
 use std::collections::HashMap;
 
-use bitcoinsuite_core::Sha256d;
-use chronik_rocksdb::ScriptPayload;
+use bitcoinsuite_core::{OutPoint, Sha256d, TxOutput};
+use bitcoinsuite_slp::TokenId;
+use chronik_rocksdb::{BlockHeight, ScriptPayload, TokenStats};
 use tokio::sync::broadcast;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -10,20 +11,68 @@ pub enum SubscribeScriptMessage {
     RemovedFromMempool(Sha256d),
     Confirmed(Sha256d),
     Reorg(Sha256d),
+    Conflicting(Sha256d),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubscribeOutpointMessage {
+    Spent {
+        spending_txid: Sha256d,
+        input_idx: u32,
+        /// Whether the spending tx is in a block; mempool spends can still be replaced or
+        /// evicted, so the subscription stays open until the spend confirms.
+        is_confirmed: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubscribeTokenMessage {
+    AddedToMempool(Sha256d),
+    Confirmed(Sha256d),
+    /// Sent after the `AddedToMempool`/`Confirmed` messages of a mempool tx or block.
+    Stats {
+        /// Stats of the token, including the mempool
+        token_stats: TokenStats,
+        /// Part of `token_stats` caused by txs in the mempool
+        unconfirmed_delta: TokenStats,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SubscribeBlockMessage {
-    BlockConnected(Sha256d),
+    /// Sent after a block has been connected, with the reward claimed by its coinbase tx.
+    BlockConnected {
+        block_hash: Sha256d,
+        height: BlockHeight,
+        coinbase_outputs: Vec<TxOutput>,
+        /// Part of the coinbase output sats not paid by the fees of the block's txs.
+        subsidy: i64,
+        /// Input sats minus output sats of the block's non-coinbase txs.
+        fees: i64,
+    },
     BlockDisconnected(Sha256d),
+    /// Sent before the first `BlockDisconnected` of a reorg, which disconnects the blocks from
+    /// `from_height` up to `to_height` (inclusive).
+    ReorgStarted {
+        from_height: BlockHeight,
+        to_height: BlockHeight,
+    },
+    /// Sent after the last block of a reorg has been (re)connected.
+    ReorgFinished {
+        new_tip_height: BlockHeight,
+    },
 }
 
 const SCRIPT_CHANNEL_CAPACITY: usize = 16;
 const BLOCK_CHANNEL_CAPACITY: usize = 16;
+const OUTPOINT_CHANNEL_CAPACITY: usize = 4;
+const TOKEN_CHANNEL_CAPACITY: usize = 16;
 
 #[derive(Debug, Clone)]
 pub struct Subscribers {
     subs_script: HashMap<ScriptPayload, broadcast::Sender<SubscribeScriptMessage>>,
+    subs_outpoint: HashMap<OutPoint, broadcast::Sender<SubscribeOutpointMessage>>,
+    subs_token: HashMap<[u8; 32], broadcast::Sender<SubscribeTokenMessage>>,
     subs_block: broadcast::Sender<SubscribeBlockMessage>,
 }
 
@@ -51,6 +100,56 @@ impl Subscribers {
         }
     }
 
+    /// Subscribe to the spend of an outpoint. Mempool spends are sent as they come in; the
+    /// channel is closed after the spend confirms.
+    pub fn subscribe_to_outpoint(
+        &mut self,
+        outpoint: &OutPoint,
+    ) -> broadcast::Receiver<SubscribeOutpointMessage> {
+        match self.subs_outpoint.get(outpoint) {
+            Some(sender) => sender.subscribe(),
+            None => {
+                let (sender, receiver) = broadcast::channel(OUTPOINT_CHANNEL_CAPACITY);
+                self.subs_outpoint.insert(outpoint.clone(), sender);
+                receiver
+            }
+        }
+    }
+
+    /// Clean unsubscribe
+    pub fn unsubscribe_from_outpoint(&mut self, outpoint: &OutPoint) {
+        if let Some(sender) = self.subs_outpoint.get(outpoint) {
+            if sender.receiver_count() == 0 {
+                self.subs_outpoint.remove(outpoint);
+            }
+        }
+    }
+
+    pub fn subscribe_to_token(
+        &mut self,
+        token_id: &TokenId,
+    ) -> broadcast::Receiver<SubscribeTokenMessage> {
+        let token_id_be = token_id.token_id_be();
+        match self.subs_token.get(&token_id_be) {
+            Some(sender) => sender.subscribe(),
+            None => {
+                let (sender, receiver) = broadcast::channel(TOKEN_CHANNEL_CAPACITY);
+                self.subs_token.insert(token_id_be, sender);
+                receiver
+            }
+        }
+    }
+
+    /// Clean unsubscribe
+    pub fn unsubscribe_from_token(&mut self, token_id: &TokenId) {
+        let token_id_be = token_id.token_id_be();
+        if let Some(sender) = self.subs_token.get(&token_id_be) {
+            if sender.receiver_count() == 0 {
+                self.subs_token.remove(&token_id_be);
+            }
+        }
+    }
+
     pub fn subscribe_to_blocks(&self) -> broadcast::Receiver<SubscribeBlockMessage> {
         self.subs_block.subscribe()
     }
@@ -68,6 +167,39 @@ impl Subscribers {
         }
     }
 
+    pub(crate) fn broadcast_to_outpoint(
+        &mut self,
+        outpoint: &OutPoint,
+        msg: SubscribeOutpointMessage,
+    ) {
+        let SubscribeOutpointMessage::Spent { is_confirmed, .. } = msg;
+        if is_confirmed {
+            // Confirmed outpoints can only be spent once, so this unsubscribes everyone
+            if let Some(sender) = self.subs_outpoint.remove(outpoint) {
+                let _ = sender.send(msg);
+            }
+        } else if let Some(sender) = self.subs_outpoint.get(outpoint) {
+            // Unclean unsubscribe
+            if sender.send(msg).is_err() {
+                self.subs_outpoint.remove(outpoint);
+            }
+        }
+    }
+
+    pub(crate) fn has_token_subs(&self) -> bool {
+        !self.subs_token.is_empty()
+    }
+
+    pub(crate) fn broadcast_to_token(&mut self, token_id: &TokenId, msg: SubscribeTokenMessage) {
+        let token_id_be = token_id.token_id_be();
+        if let Some(sender) = self.subs_token.get(&token_id_be) {
+            // Unclean unsubscribe
+            if sender.send(msg).is_err() {
+                self.subs_token.remove(&token_id_be);
+            }
+        }
+    }
+
     pub(crate) fn broadcast_to_blocks(&mut self, msg: SubscribeBlockMessage) {
         if self.subs_block.receiver_count() > 0 {
             if let Err(err) = self.subs_block.send(msg) {
@@ -81,6 +213,8 @@ impl Default for Subscribers {
     fn default() -> Self {
         Subscribers {
             subs_script: Default::default(),
+            subs_outpoint: Default::default(),
+            subs_token: Default::default(),
             subs_block: broadcast::channel(BLOCK_CHANNEL_CAPACITY).0,
         }
     }
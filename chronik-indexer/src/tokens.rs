@@ -1,6 +1,8 @@
-use bitcoinsuite_error::Result;
-use bitcoinsuite_slp::TokenId;
-use chronik_rocksdb::TokenStats;
+use bitcoinsuite_core::{Sha256d, UnhashedTx};
+use bitcoinsuite_error::{ErrorMeta, Result};
+use bitcoinsuite_slp::{RichTx, SlpGenesisInfo, SlpTxData, SlpTxType, TokenId};
+use chronik_rocksdb::{BlockHeight, SlpStats, TokenHolder, TokenNum, TokenStats, TxNum};
+use thiserror::Error;
 
 use crate::SlpIndexer;
 
@@ -8,27 +10,547 @@ pub struct Tokens<'a> {
     indexer: &'a SlpIndexer,
 }
 
+/// Max number of decimals an SLP token can have.
+pub const MAX_TOKEN_DECIMALS: u32 = 9;
+
+/// Valid MINT tx of a token, either in the mempool or in a block.
+pub struct TokenMint {
+    pub tx: RichTx,
+    /// Sum of the token amounts of the tx's outputs, in base units
+    pub amount: i128,
+}
+
+/// Confirmed tx spending outputs of a token.
+pub struct TokenSpend {
+    pub tx: RichTx,
+    /// Whether the tx burned any of the token's outputs it spent
+    pub is_burn: bool,
+}
+
+/// Confirmed token, as listed by [`Tokens::tokens_page`].
+pub struct TokenListing {
+    pub token_id: TokenId,
+    pub genesis_info: SlpGenesisInfo,
+    /// Height of the block of the token's GENESIS tx
+    pub genesis_height: BlockHeight,
+}
+
+#[derive(Debug, Error, ErrorMeta)]
+pub enum TokensError {
+    #[critical()]
+    #[error("Inconsistent db, GENESIS tx doesn't exist: {0}")]
+    InconsistentNoSuchGenesisTx(Sha256d),
+
+    #[critical()]
+    #[error("Inconsistent db, tx_num doesn't exist: {0}")]
+    InconsistentNoSuchBlockTxNum(TxNum),
+
+    #[critical()]
+    #[error("Inconsistent db, txid doesn't exist in mempool: {0}")]
+    InconsistentNoSuchMempoolTx(Sha256d),
+
+    #[invalid_user_input()]
+    #[error("Unsupported token decimals: {0}, at most 9 are allowed")]
+    UnsupportedDecimals(u32),
+
+    #[invalid_user_input()]
+    #[error("Invalid token amount: {0:?}")]
+    InvalidTokenAmount(String),
+
+    #[invalid_user_input()]
+    #[error("Token amount {amount} has more than {decimals} decimals")]
+    TooManyDecimals { amount: String, decimals: u32 },
+
+    #[invalid_user_input()]
+    #[error("Token amount {0} exceeds the max of 2^64-1 base units")]
+    TokenAmountTooLarge(String),
+}
+
+use self::TokensError::*;
+
 impl<'a> Tokens<'a> {
     pub fn new(indexer: &'a SlpIndexer) -> Self {
         Tokens { indexer }
     }
 
     pub fn token_stats_by_token_id(&self, token_id: &TokenId) -> Result<Option<TokenStats>> {
+        let mut token_stats = self.token_stats_by_token_ids(std::slice::from_ref(token_id))?;
+        Ok(token_stats.remove(0))
+    }
+
+    /// Like `token_stats_by_token_id` for many tokens, opening the DB reader only once.
+    pub fn token_stats_by_token_ids(
+        &self,
+        token_ids: &[TokenId],
+    ) -> Result<Vec<Option<TokenStats>>> {
         let slp_reader = self.indexer.db.slp()?;
-        let db_token_stats = match slp_reader.token_num_by_id(token_id)? {
-            Some(token_num) => self.indexer.db.slp()?.token_stats_by_token_num(token_num)?,
-            None => None,
+        token_ids
+            .iter()
+            .map(|token_id| {
+                let db_token_stats = match slp_reader.token_num_by_id(token_id)? {
+                    Some(token_num) => slp_reader.token_stats_by_token_num(token_num)?,
+                    None => None,
+                };
+                let mempool_token_stats = self.indexer.db_mempool_slp().token_stats_delta(token_id);
+                match (db_token_stats, mempool_token_stats) {
+                    (None, None) => Ok(None),
+                    (None, Some(token_stats)) => Ok(Some(token_stats.clone())),
+                    (Some(token_stats), None) => Ok(Some(token_stats)),
+                    (Some(mut token_stats), Some(mempool_token_stats)) => {
+                        token_stats.add_delta(mempool_token_stats);
+                        Ok(Some(token_stats))
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Aggregate stats over all confirmed SLP txs; mempool txs aren't counted.
+    pub fn slp_stats(&self) -> Result<SlpStats> {
+        self.indexer.db.slp()?.slp_stats()
+    }
+
+    /// Tokens affected by the given mempool or confirmed tx: its own token, if it's a valid SLP
+    /// tx, and the tokens of the outputs it spends.
+    pub fn tx_token_ids(&self, txid: &Sha256d, tx: &UnhashedTx) -> Result<Vec<TokenId>> {
+        let own_token_id = match self.indexer.db_mempool_slp().slp_tx_data(txid) {
+            Some(valid_tx_data) => Some(valid_tx_data.slp_tx_data.token_id.clone()),
+            None => match self.indexer.db.txs()?.tx_num_by_txid(txid)? {
+                Some(tx_num) => self
+                    .indexer
+                    .db
+                    .slp()?
+                    .slp_data_by_tx_num(tx_num)?
+                    .map(|valid_tx_data| valid_tx_data.slp_tx_data.token_id),
+                None => None,
+            },
         };
-        let mempool_token_stats = self.indexer.db_mempool_slp().token_stats_delta(token_id);
-        match (db_token_stats, mempool_token_stats) {
-            (None, None) => Ok(None),
-            (None, Some(token_stats)) => Ok(Some(token_stats.clone())),
-            (Some(token_stats), None) => Ok(Some(token_stats)),
-            (Some(mut token_stats), Some(mempool_token_stats)) => {
-                token_stats.total_minted += mempool_token_stats.total_minted;
-                token_stats.total_burned += mempool_token_stats.total_burned;
-                Ok(Some(token_stats))
+        let spent_outputs = self.indexer.db.slp_spent_outputs(&self.indexer.data, tx)?;
+        let mut token_ids = Vec::new();
+        let spent_token_ids = spent_outputs
+            .into_iter()
+            .flatten()
+            .map(|spent_output| spent_output.token_id);
+        for token_id in own_token_id.into_iter().chain(spent_token_ids) {
+            if !token_ids.contains(&token_id) {
+                token_ids.push(token_id);
             }
         }
+        Ok(token_ids)
+    }
+
+    /// GENESIS txs of the `count` most recently created tokens, newest first, and the cursor
+    /// to pass as `before_token_num` to get the next page, if there are more tokens.
+    /// Tokens created in the mempool come first, as they don't have a token num yet; they're
+    /// only on the first page, i.e. without `before_token_num`.
+    pub fn recent_tokens(
+        &self,
+        before_token_num: Option<TokenNum>,
+        count: usize,
+    ) -> Result<(Vec<RichTx>, Option<TokenNum>)> {
+        let txs = self.indexer.txs();
+        let mut genesis_txs = Vec::new();
+        if before_token_num.is_none() {
+            genesis_txs = self
+                .indexer
+                .db_mempool_slp()
+                .genesis_txids()
+                .map(|txid| {
+                    txs.rich_tx_by_txid(txid)?
+                        .ok_or_else(|| InconsistentNoSuchGenesisTx(txid.clone()).into())
+                })
+                .collect::<Result<Vec<_>>>()?;
+            genesis_txs.sort_by(|a, b| {
+                b.time_first_seen
+                    .cmp(&a.time_first_seen)
+                    .then_with(|| a.txid.cmp(&b.txid))
+            });
+            genesis_txs.truncate(count);
+        }
+        let num_db_tokens = count - genesis_txs.len();
+        // Fetch one more token to tell whether there's a next page
+        let mut db_tokens = self
+            .indexer
+            .db
+            .slp()?
+            .recent_token_ids(before_token_num, num_db_tokens + 1)?;
+        let next_before_token_num = db_tokens
+            .get(num_db_tokens)
+            .map(|&(token_num, _)| token_num + 1);
+        db_tokens.truncate(num_db_tokens);
+        for (_, token_id) in db_tokens {
+            let txid = token_id.hash();
+            let genesis_tx = txs
+                .rich_tx_by_txid(txid)?
+                .ok_or_else(|| InconsistentNoSuchGenesisTx(txid.clone()))?;
+            genesis_txs.push(genesis_tx);
+        }
+        if genesis_txs.is_empty() {
+            // Otherwise, the cursor would lead back to the same empty page
+            return Ok((genesis_txs, None));
+        }
+        Ok((genesis_txs, next_before_token_num))
+    }
+
+    /// Confirmed tokens in the order they were created, starting at token num `start_token_num`,
+    /// and the token num the next page starts at, None if there are no more tokens. Tokens only
+    /// in the mempool aren't listed, as they don't have a token num yet.
+    pub fn tokens_page(
+        &self,
+        start_token_num: TokenNum,
+        count: usize,
+    ) -> Result<(Vec<TokenListing>, Option<TokenNum>)> {
+        // Fetch one more token to tell whether there's a next page
+        let mut db_tokens = self
+            .indexer
+            .db
+            .slp()?
+            .tokens_page(start_token_num, count.saturating_add(1))?;
+        let next_start_token_num = db_tokens.get(count).map(|&(token_num, _, _)| token_num);
+        db_tokens.truncate(count);
+        let tx_reader = self.indexer.db.txs()?;
+        let tokens = db_tokens
+            .into_iter()
+            .map(|(_, token_id, genesis_info)| {
+                let txid = token_id.hash();
+                let (_, genesis_tx) = tx_reader
+                    .tx_and_num_by_txid(txid)?
+                    .ok_or_else(|| InconsistentNoSuchGenesisTx(txid.clone()))?;
+                Ok(TokenListing {
+                    genesis_height: genesis_tx.block_height,
+                    token_id,
+                    genesis_info,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok((tokens, next_start_token_num))
+    }
+
+    /// Confirmed txs spending outputs of the token, most recent first.
+    pub fn rev_spends_page(
+        &self,
+        token_id: &TokenId,
+        page_num: usize,
+        page_size: usize,
+    ) -> Result<Vec<TokenSpend>> {
+        let slp_reader = self.indexer.db.slp()?;
+        let token_num = match slp_reader.token_num_by_id(token_id)? {
+            Some(token_num) => token_num,
+            None => return Ok(vec![]),
+        };
+        let tx_reader = self.indexer.db.txs()?;
+        slp_reader
+            .rev_page_token_spends(token_num, page_num, page_size)?
+            .into_iter()
+            .map(|tx_num| {
+                let block_tx = tx_reader
+                    .by_tx_num(tx_num)?
+                    .ok_or(InconsistentNoSuchBlockTxNum(tx_num))?;
+                let tx = self.indexer.txs().rich_block_tx(tx_num, &block_tx)?;
+                let is_burn = tx
+                    .slp_burns
+                    .iter()
+                    .flatten()
+                    .any(|burn| &burn.token_id == token_id);
+                Ok(TokenSpend { tx, is_burn })
+            })
+            .collect()
+    }
+
+    /// Page of the token's MINT txs and the total number of pages. Mempool MINTs come first,
+    /// then confirmed ones, each most recent first. Confirmed MINTs are found by filtering the
+    /// txs spending the token's outputs (every MINT spends a mint baton), so this scans all of
+    /// the token's spends.
+    pub fn rev_mints_page(
+        &self,
+        token_id: &TokenId,
+        page_num: usize,
+        page_size: usize,
+    ) -> Result<(Vec<TokenMint>, usize)> {
+        let txs = self.indexer.txs();
+        let mut mempool_txs = self
+            .indexer
+            .db_mempool_slp()
+            .mint_txids(token_id)
+            .map(|txid| {
+                txs.rich_tx_by_txid(txid)?
+                    .ok_or_else(|| InconsistentNoSuchMempoolTx(txid.clone()).into())
+            })
+            .collect::<Result<Vec<_>>>()?;
+        mempool_txs.sort_by(|a, b| {
+            b.time_first_seen
+                .cmp(&a.time_first_seen)
+                .then_with(|| a.txid.cmp(&b.txid))
+        });
+        let slp_reader = self.indexer.db.slp()?;
+        let mut block_tx_nums = Vec::new();
+        if let Some(token_num) = slp_reader.token_num_by_id(token_id)? {
+            for tx_num in slp_reader.rev_page_token_spends(token_num, 0, usize::MAX)? {
+                let is_mint = match slp_reader.slp_data_by_tx_num(tx_num)? {
+                    Some(slp) => {
+                        slp.slp_tx_data.slp_tx_type == SlpTxType::Mint
+                            && &slp.slp_tx_data.token_id == token_id
+                    }
+                    None => false,
+                };
+                if is_mint {
+                    block_tx_nums.push(tx_num);
+                }
+            }
+        }
+        let num_mints = mempool_txs.len() + block_tx_nums.len();
+        let page_start = page_num.saturating_mul(page_size);
+        let num_mempool_txs = mempool_txs.len();
+        let mut mint_txs = mempool_txs
+            .into_iter()
+            .skip(page_start)
+            .take(page_size)
+            .collect::<Vec<_>>();
+        let tx_reader = self.indexer.db.txs()?;
+        for tx_num in block_tx_nums
+            .into_iter()
+            .skip(page_start.saturating_sub(num_mempool_txs))
+            .take(page_size - mint_txs.len())
+        {
+            let block_tx = tx_reader
+                .by_tx_num(tx_num)?
+                .ok_or(InconsistentNoSuchBlockTxNum(tx_num))?;
+            mint_txs.push(txs.rich_block_tx(tx_num, &block_tx)?);
+        }
+        let mints = mint_txs
+            .into_iter()
+            .map(|tx| {
+                let amount = tx
+                    .slp_tx_data
+                    .as_ref()
+                    .map(|slp_tx_data| {
+                        slp_tx_data
+                            .output_tokens
+                            .iter()
+                            .map(|token| token.amount.base_amount())
+                            .sum()
+                    })
+                    .unwrap_or_default();
+                TokenMint { tx, amount }
+            })
+            .collect();
+        Ok((mints, (num_mints + page_size - 1) / page_size))
+    }
+
+    /// Page of the token's valid SLP txs (GENESIS, MINT, SEND and BURN) and the total number of
+    /// pages. Mempool txs come first, then confirmed ones, each most recent first; the GENESIS
+    /// is always last. Non-SLP txs burning the token aren't included, see
+    /// [`Tokens::rev_spends_page`] for those.
+    pub fn rev_history_page(
+        &self,
+        token_id: &TokenId,
+        page_num: usize,
+        page_size: usize,
+    ) -> Result<(Vec<RichTx>, usize)> {
+        let txs = self.indexer.txs();
+        let mut mempool_txs = self
+            .indexer
+            .db_mempool_slp()
+            .token_txids(token_id)
+            .map(|txid| {
+                txs.rich_tx_by_txid(txid)?
+                    .ok_or_else(|| InconsistentNoSuchMempoolTx(txid.clone()).into())
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // Txs can share their first seen time with the GENESIS, which still has to be last
+        mempool_txs.sort_by(|a, b| {
+            (&a.txid == token_id.hash())
+                .cmp(&(&b.txid == token_id.hash()))
+                .then_with(|| b.time_first_seen.cmp(&a.time_first_seen))
+                .then_with(|| a.txid.cmp(&b.txid))
+        });
+        let slp_reader = self.indexer.db.slp()?;
+        let token_num = slp_reader.token_num_by_id(token_id)?;
+        let num_block_txs = match token_num {
+            Some(token_num) => slp_reader.num_token_txs(token_num)?,
+            None => 0,
+        };
+        let num_txs = mempool_txs.len() + num_block_txs;
+        let page_start = page_num.saturating_mul(page_size);
+        let num_mempool_txs = mempool_txs.len();
+        let mut history_txs = mempool_txs
+            .into_iter()
+            .skip(page_start)
+            .take(page_size)
+            .collect::<Vec<_>>();
+        if let Some(token_num) = token_num {
+            let db_skip = page_start.saturating_sub(num_mempool_txs);
+            let db_take = page_size - history_txs.len();
+            let tx_reader = self.indexer.db.txs()?;
+            for tx_num in slp_reader
+                .rev_page_token_txs(token_num, 0, db_skip.saturating_add(db_take))?
+                .into_iter()
+                .skip(db_skip)
+            {
+                let block_tx = tx_reader
+                    .by_tx_num(tx_num)?
+                    .ok_or(InconsistentNoSuchBlockTxNum(tx_num))?;
+                history_txs.push(txs.rich_block_tx(tx_num, &block_tx)?);
+            }
+        }
+        Ok((history_txs, (num_txs + page_size - 1) / page_size))
+    }
+
+    /// Page of the scripts holding confirmed UTXOs of the token, largest amount first, and the
+    /// total number of pages. Mempool txs aren't taken into account.
+    pub fn holders_page(
+        &self,
+        token_id: &TokenId,
+        page_num: usize,
+        page_size: usize,
+    ) -> Result<(Vec<TokenHolder>, usize)> {
+        let slp_reader = self.indexer.db.slp()?;
+        let holders = match slp_reader.token_num_by_id(token_id)? {
+            Some(token_num) => slp_reader.token_holders(token_num)?,
+            None => vec![],
+        };
+        let num_pages = (holders.len() + page_size - 1) / page_size;
+        let holders = holders
+            .into_iter()
+            .skip(page_num.saturating_mul(page_size))
+            .take(page_size)
+            .collect();
+        Ok((holders, num_pages))
+    }
+
+    /// Number of scripts holding confirmed UTXOs of the token, i.e. the number of entries in
+    /// `holders_page` over all pages.
+    pub fn num_holders(&self, token_id: &TokenId) -> Result<usize> {
+        let slp_reader = self.indexer.db.slp()?;
+        match slp_reader.token_num_by_id(token_id)? {
+            Some(token_num) => Ok(slp_reader.token_holders(token_num)?.len()),
+            None => Ok(0),
+        }
+    }
+
+    /// Heights of the blocks of the token's first and last confirmed tx, i.e. its GENESIS and
+    /// the most recent tx spending its outputs. None if the token is only in the mempool or
+    /// doesn't exist; mempool txs don't count as activity.
+    pub fn active_heights(&self, token_id: &TokenId) -> Result<Option<(BlockHeight, BlockHeight)>> {
+        let tx_reader = self.indexer.db.txs()?;
+        let genesis_tx_num = match tx_reader.tx_num_by_txid(token_id.hash())? {
+            Some(genesis_tx_num) => genesis_tx_num,
+            None => return Ok(None),
+        };
+        let slp_reader = self.indexer.db.slp()?;
+        let last_spend_tx_num = match slp_reader.token_num_by_id(token_id)? {
+            Some(token_num) => slp_reader
+                .rev_page_token_spends(token_num, 0, 1)?
+                .first()
+                .copied(),
+            None => None,
+        };
+        let block_height = |tx_num| -> Result<BlockHeight> {
+            let block_tx = tx_reader
+                .by_tx_num(tx_num)?
+                .ok_or(InconsistentNoSuchBlockTxNum(tx_num))?;
+            Ok(block_tx.block_height)
+        };
+        let first_active_height = block_height(genesis_tx_num)?;
+        let last_active_height = match last_spend_tx_num {
+            Some(tx_num) => block_height(tx_num)?,
+            None => first_active_height,
+        };
+        Ok(Some((first_active_height, last_active_height)))
+    }
+
+    /// SLP data (token type and GENESIS info) of the token's GENESIS tx, without reading the tx
+    /// itself or the token's stats, or None if there's no such GENESIS tx.
+    pub fn genesis_slp_tx_data(&self, token_id: &TokenId) -> Result<Option<SlpTxData>> {
+        let slp_tx_data = match self.indexer.db_mempool_slp().slp_tx_data(token_id.hash()) {
+            Some(slp_data) => Some(slp_data.slp_tx_data.clone()),
+            None => match self.indexer.db().txs()?.tx_num_by_txid(token_id.hash())? {
+                Some(tx_num) => self
+                    .indexer
+                    .db()
+                    .slp()?
+                    .slp_data_by_tx_num(tx_num)?
+                    .map(|slp| slp.slp_tx_data),
+                None => None,
+            },
+        };
+        Ok(slp_tx_data
+            .filter(|slp_tx_data| matches!(slp_tx_data.slp_tx_type, SlpTxType::Genesis(_))))
+    }
+
+    /// Decimals of the token as set in its GENESIS, or None if there's no such GENESIS tx.
+    pub fn decimals(&self, token_id: &TokenId) -> Result<Option<u32>> {
+        let rich_tx = match self.indexer.txs().rich_tx_by_txid(token_id.hash())? {
+            Some(rich_tx) => rich_tx,
+            None => return Ok(None),
+        };
+        match rich_tx.slp_tx_data {
+            Some(slp_tx_data) => match slp_tx_data.slp_tx_type {
+                SlpTxType::Genesis(genesis_info) => Ok(Some(genesis_info.decimals)),
+                _ => Ok(None),
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub fn rev_spends_num_pages(&self, token_id: &TokenId, page_size: usize) -> Result<usize> {
+        let slp_reader = self.indexer.db.slp()?;
+        let num_spends = match slp_reader.token_num_by_id(token_id)? {
+            Some(token_num) => slp_reader.num_token_spends(token_num)?,
+            None => 0,
+        };
+        Ok((num_spends + page_size - 1) / page_size)
+    }
+}
+
+/// Converts a human-readable token amount like "1.5" into base units, i.e. shifts it left by
+/// `decimals` digits. Done on the decimal string, so there are no float rounding errors.
+/// Fractional digits beyond `decimals` must be zeros; anything finer than a base unit is rejected
+/// rather than rounded, so a wallet can't end up sending a different amount than displayed.
+pub fn parse_token_amount(amount: &str, decimals: u32) -> Result<u64> {
+    if decimals > MAX_TOKEN_DECIMALS {
+        return Err(UnsupportedDecimals(decimals).into());
+    }
+    let (int_part, frac_part) = match amount.split_once('.') {
+        Some((int_part, frac_part)) => (int_part, frac_part),
+        None => (amount, ""),
+    };
+    let is_digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+    if int_part.is_empty()
+        || !is_digits(int_part)
+        || (amount.contains('.') && frac_part.is_empty())
+        || !is_digits(frac_part)
+    {
+        return Err(InvalidTokenAmount(amount.to_string()).into());
+    }
+    let decimals = decimals as usize;
+    let (frac_part, excess_part) = frac_part.split_at(frac_part.len().min(decimals));
+    if excess_part.bytes().any(|digit| digit != b'0') {
+        return Err(TooManyDecimals {
+            amount: amount.to_string(),
+            decimals: decimals as u32,
+        }
+        .into());
+    }
+    let base_units = format!("{}{:0<width$}", int_part, frac_part, width = decimals);
+    // Only digits at this point, so parsing can only fail by overflowing
+    base_units
+        .parse()
+        .map_err(|_| TokenAmountTooLarge(amount.to_string()).into())
+}
+
+/// Formats an amount in base units as a human-readable amount with `decimals` digits after the
+/// decimal point, e.g. "1.50" for 150 base units with 2 decimals; the reverse of
+/// `parse_token_amount`.
+pub fn format_token_amount(base_units: u64, decimals: u32) -> Result<String> {
+    if decimals > MAX_TOKEN_DECIMALS {
+        return Err(UnsupportedDecimals(decimals).into());
+    }
+    let decimals = decimals as usize;
+    let digits = format!("{:0>width$}", base_units, width = decimals + 1);
+    if decimals == 0 {
+        return Ok(digits);
     }
+    let (int_part, frac_part) = digits.split_at(digits.len() - decimals);
+    Ok(format!("{}.{}", int_part, frac_part))
 }
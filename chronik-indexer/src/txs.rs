@@ -12,6 +12,15 @@ pub struct Txs<'a> {
     indexer: &'a SlpIndexer,
 }
 
+/// Position of a confirmed tx in the chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxLocation {
+    pub block: Block,
+    /// Position of the tx within its block, where the coinbase is at 0
+    pub index_in_block: usize,
+    pub tx_num: TxNum,
+}
+
 impl<'a> Txs<'a> {
     pub fn new(indexer: &'a SlpIndexer) -> Self {
         Txs { indexer }
@@ -74,7 +83,7 @@ impl<'a> Txs<'a> {
         let block = block_reader
             .by_height(block_tx.block_height)?
             .expect("Inconsistent db");
-        let raw_tx = self.indexer.rpc_interface.get_block_slice(
+        let raw_tx = self.indexer.block_slice(
             block.file_num,
             block_tx.entry.data_pos,
             block_tx.entry.tx_size,
@@ -157,6 +166,31 @@ impl<'a> Txs<'a> {
         })
     }
 
+    /// Block and position within it of a confirmed tx; None for mempool and unknown txs.
+    pub fn tx_location(&self, txid: &Sha256d) -> Result<Option<TxLocation>> {
+        let (tx_num, block_tx) = match self.indexer.db().txs()?.tx_and_num_by_txid(txid)? {
+            Some(tuple) => tuple,
+            None => return Ok(None),
+        };
+        let height = block_tx.block_height;
+        let block = self
+            .indexer
+            .db()
+            .blocks()?
+            .by_height(height)?
+            .expect("Inconsistent db");
+        let tx_num_range = self
+            .indexer
+            .blocks()
+            .tx_num_range(height, height)?
+            .expect("Inconsistent db");
+        Ok(Some(TxLocation {
+            block,
+            index_in_block: (tx_num - tx_num_range.start) as usize,
+            tx_num,
+        }))
+    }
+
     pub fn raw_tx_by_id(&self, txid: &Sha256d) -> Result<Option<Bytes>> {
         if let Some(entry) = self.indexer.db_mempool().tx(txid) {
             return Ok(Some(entry.tx.ser()));
@@ -170,7 +204,7 @@ impl<'a> Txs<'a> {
         let block = block_reader
             .by_height(block_tx.block_height)?
             .expect("Inconsistent db");
-        let raw_tx = self.indexer.rpc_interface.get_block_slice(
+        let raw_tx = self.indexer.block_slice(
             block.file_num,
             block_tx.entry.data_pos,
             block_tx.entry.tx_size,
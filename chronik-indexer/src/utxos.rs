@@ -1,9 +1,18 @@
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
+
 use bitcoinsuite_core::{BitcoinCode, Bytes, OutPoint, Sha256d, TxOutput, UnhashedTx};
 use bitcoinsuite_error::{ErrorMeta, Result};
 use bitcoinsuite_slp::{RichTxBlock, RichUtxo, SlpOutput};
-use chronik_rocksdb::{BlockHeight, ScriptPayload, TxNum, UtxoDelta};
+use chronik_rocksdb::{
+    BlockHeight, BlockTx, OutpointEntry, PayloadPrefix, ScriptPayload, TxNum, UtxoDelta,
+};
+use futures::Stream;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use thiserror::Error;
+use tokio::sync::RwLock;
 
 use crate::SlpIndexer;
 
@@ -23,6 +32,38 @@ pub enum UtxoStateVariant {
     Spent,
     NoSuchTx,
     NoSuchOutput,
+    /// The output exists and isn't spent in the mempool, but there's no spends index to tell
+    /// whether a block spent it.
+    Unknown,
+}
+
+/// Confirmed UTXO of a script, as streamed by [`export_utxos`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExportedUtxo {
+    pub script_payload: ScriptPayload,
+    pub outpoint: OutPoint,
+    pub value: i64,
+}
+
+/// Confirmed UTXO created before a given height, as returned by [`dormant_utxos`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DormantUtxo {
+    pub script_payload: ScriptPayload,
+    pub outpoint: OutPoint,
+    pub value: i64,
+    /// Height of the block of the tx which created the UTXO
+    pub height: BlockHeight,
+}
+
+/// Number of scripts whose UTXOs are loaded at once by [`export_utxos`].
+const EXPORT_UTXOS_NUM_SCRIPTS: usize = 1000;
+
+/// Confirmed tx looked up by `utxo_states`, shared by all queried outputs of the tx.
+struct ConfirmedTx {
+    block_tx: BlockTx,
+    spent_out_idxs: HashSet<u32>,
+    /// Only read from the block files once an output is found to be unspent.
+    num_outputs: Option<usize>,
 }
 
 #[derive(Debug, Error, ErrorMeta)]
@@ -78,7 +119,7 @@ impl<'a> Utxos<'a> {
                     .by_height(block_tx.block_height)?
                     .expect("Inconsistent db");
                 let output = if db_utxo.is_partial_script {
-                    let raw_tx = self.indexer.rpc_interface.get_block_slice(
+                    let raw_tx = self.indexer.block_slice(
                         block.file_num,
                         block_tx.entry.data_pos,
                         block_tx.entry.tx_size,
@@ -168,80 +209,346 @@ impl<'a> Utxos<'a> {
         Ok(utxos)
     }
 
-    pub fn utxo_state(&self, outpoint: &OutPoint) -> Result<UtxoState> {
-        let mempool = self.indexer.db_mempool();
-        let mut is_spent_in_mempool = false;
-        if let Some(spends) = mempool.spends(&outpoint.txid) {
-            if spends
-                .iter()
-                .any(|&(out_idx, _, _)| out_idx == outpoint.out_idx)
-            {
-                if mempool.tx(&outpoint.txid).is_some() {
-                    return Ok(UtxoState {
-                        height: None,
-                        state: UtxoStateVariant::Spent,
-                    });
+    /// Sum of the values of the script's UTXOs, including mempool ones. Unlike summing the
+    /// values returned by `utxos`, this doesn't read any txs, since the UTXO index stores the
+    /// value of each UTXO.
+    pub fn total_value(&self, script_payload: &ScriptPayload) -> Result<i64> {
+        let prefix = script_payload.payload_prefix;
+        let payload = &script_payload.payload_data;
+        let db_utxos = self.indexer.db().utxos()?.utxos(prefix, payload)?;
+        let mut total_value = db_utxos.iter().map(|db_utxo| db_utxo.value).sum::<i64>();
+        let mempool_delta = match self.indexer.db_mempool().utxos(prefix, payload) {
+            Some(mempool_delta) => mempool_delta,
+            None => return Ok(total_value),
+        };
+        if !mempool_delta.deletes.is_empty() {
+            let tx_reader = self.indexer.db().txs()?;
+            for outpoint in &mempool_delta.deletes {
+                let tx_num = match tx_reader.tx_num_by_txid(&outpoint.txid)? {
+                    Some(tx_num) => tx_num,
+                    None => continue,
+                };
+                let outpoint_entry = OutpointEntry {
+                    tx_num,
+                    out_idx: outpoint.out_idx,
+                };
+                if let Ok(idx) =
+                    db_utxos.binary_search_by(|db_utxo| db_utxo.outpoint.cmp(&outpoint_entry))
+                {
+                    total_value -= db_utxos[idx].value;
                 }
-                is_spent_in_mempool = true;
             }
         }
-        if !is_spent_in_mempool {
+        for outpoint in &mempool_delta.inserts {
+            let entry = self
+                .indexer
+                .db_mempool()
+                .tx(&outpoint.txid)
+                .ok_or_else(|| InconsistentNoSuchMempoolTx(outpoint.txid.clone()))?;
+            total_value += entry.tx.outputs[outpoint.out_idx as usize].value;
+        }
+        Ok(total_value)
+    }
+
+    pub fn utxo_state(&self, outpoint: &OutPoint) -> Result<UtxoState> {
+        let mut utxo_states = self.utxo_states(std::slice::from_ref(outpoint))?;
+        Ok(utxo_states.remove(0))
+    }
+
+    /// Like `utxo_state` for many outpoints at once, opening the DB readers only once and
+    /// looking up each confirmed tx only once, no matter how many of its outputs are queried.
+    pub fn utxo_states(&self, outpoints: &[OutPoint]) -> Result<Vec<UtxoState>> {
+        let mempool = self.indexer.db_mempool();
+        let tx_reader = self.indexer.db().txs()?;
+        let spends_reader = self.indexer.db().spends()?;
+        let block_reader = self.indexer.db().blocks()?;
+        let mut confirmed_txs = HashMap::<&Sha256d, Option<ConfirmedTx>>::new();
+        let mut utxo_states = Vec::with_capacity(outpoints.len());
+        for outpoint in outpoints {
+            let is_spent_in_mempool = mempool.spends(&outpoint.txid).map_or(false, |spends| {
+                spends
+                    .iter()
+                    .any(|&(out_idx, _, _)| out_idx == outpoint.out_idx)
+            });
             if let Some(tx) = mempool.tx(&outpoint.txid) {
-                if outpoint.out_idx as usize >= tx.tx.outputs.len() {
-                    return Ok(UtxoState {
+                let state = if is_spent_in_mempool {
+                    UtxoStateVariant::Spent
+                } else if outpoint.out_idx as usize >= tx.tx.outputs.len() {
+                    UtxoStateVariant::NoSuchOutput
+                } else {
+                    UtxoStateVariant::Unspent
+                };
+                utxo_states.push(UtxoState {
+                    height: None,
+                    state,
+                });
+                continue;
+            }
+            let confirmed_tx = match confirmed_txs.entry(&outpoint.txid) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let confirmed_tx = match tx_reader.tx_and_num_by_txid(&outpoint.txid)? {
+                        Some((tx_num, block_tx)) => Some(ConfirmedTx {
+                            block_tx,
+                            spent_out_idxs: spends_reader
+                                .spends_by_tx_num(tx_num)?
+                                .iter()
+                                .map(|spend| spend.out_idx)
+                                .collect(),
+                            num_outputs: None,
+                        }),
+                        None => None,
+                    };
+                    entry.insert(confirmed_tx)
+                }
+            };
+            let confirmed_tx = match confirmed_tx {
+                Some(confirmed_tx) => confirmed_tx,
+                None => {
+                    utxo_states.push(UtxoState {
                         height: None,
-                        state: UtxoStateVariant::NoSuchOutput,
+                        state: UtxoStateVariant::NoSuchTx,
                     });
+                    continue;
                 }
-                return Ok(UtxoState {
-                    height: None,
-                    state: UtxoStateVariant::Unspent,
+            };
+            let height = Some(confirmed_tx.block_tx.block_height);
+            if is_spent_in_mempool || confirmed_tx.spent_out_idxs.contains(&outpoint.out_idx) {
+                utxo_states.push(UtxoState {
+                    height,
+                    state: UtxoStateVariant::Spent,
                 });
+                continue;
             }
+            let num_outputs = match confirmed_tx.num_outputs {
+                Some(num_outputs) => num_outputs,
+                None => {
+                    let block_tx = &confirmed_tx.block_tx;
+                    let block = block_reader
+                        .by_height(block_tx.block_height)?
+                        .expect("Inconsistent db");
+                    let raw_tx = self.indexer.block_slice(
+                        block.file_num,
+                        block_tx.entry.data_pos,
+                        block_tx.entry.tx_size,
+                    )?;
+                    let tx = UnhashedTx::deser(&mut Bytes::from_bytes(raw_tx))?;
+                    confirmed_tx.num_outputs = Some(tx.outputs.len());
+                    tx.outputs.len()
+                }
+            };
+            let state = if outpoint.out_idx as usize >= num_outputs {
+                UtxoStateVariant::NoSuchOutput
+            } else if !self.indexer.db().has_spends_index() {
+                UtxoStateVariant::Unknown
+            } else {
+                UtxoStateVariant::Unspent
+            };
+            utxo_states.push(UtxoState { height, state });
         }
-        let tx_reader = self.indexer.db().txs()?;
-        let spends_reader = self.indexer.db().spends()?;
-        let (tx_num, block_tx) = match tx_reader.tx_and_num_by_txid(&outpoint.txid)? {
-            Some(tx) => tx,
-            None => {
-                return Ok(UtxoState {
-                    height: None,
-                    state: UtxoStateVariant::NoSuchTx,
-                })
+        Ok(utxo_states)
+    }
+
+    /// SLP data of the given output, if its tx is a valid SLP tx, either in the mempool or in
+    /// a block. Outputs without tokens of such a tx (e.g. the OP_RETURN) have an empty token.
+    pub fn slp_output(&self, outpoint: &OutPoint) -> Result<Option<SlpOutput>> {
+        let out_idx = outpoint.out_idx as usize;
+        let slp_tx_data = match self.indexer.db_mempool_slp().slp_tx_data(&outpoint.txid) {
+            Some(slp_data) => Some(slp_data.slp_tx_data.clone()),
+            None => match self.indexer.db().txs()?.tx_num_by_txid(&outpoint.txid)? {
+                Some(tx_num) => self
+                    .indexer
+                    .db()
+                    .slp()?
+                    .slp_data_by_tx_num(tx_num)?
+                    .map(|slp| slp.slp_tx_data),
+                None => None,
+            },
+        };
+        Ok(slp_tx_data.map(|slp_tx_data| SlpOutput {
+            tx_type: slp_tx_data.slp_tx_type.tx_type_variant(),
+            token_type: slp_tx_data.slp_token_type,
+            token: slp_tx_data
+                .output_tokens
+                .get(out_idx)
+                .cloned()
+                .unwrap_or_default(),
+            token_id: slp_tx_data.token_id,
+            group_token_id: slp_tx_data.group_token_id,
+        }))
+    }
+
+    /// Input spending the given outpoint (as spending txid and input index), either in the
+    /// mempool or in a block. Spends in a block are only found if the spends index is enabled.
+    pub fn spent_by(&self, outpoint: &OutPoint) -> Result<Option<OutPoint>> {
+        if let Some(spends) = self.indexer.db_mempool().spends(&outpoint.txid) {
+            let mempool_spend = spends
+                .iter()
+                .find(|&&(out_idx, _, _)| out_idx == outpoint.out_idx);
+            if let Some((_, txid, input_idx)) = mempool_spend {
+                return Ok(Some(OutPoint {
+                    txid: txid.clone(),
+                    out_idx: *input_idx,
+                }));
             }
+        }
+        let tx_reader = self.indexer.db().txs()?;
+        let tx_num = match tx_reader.tx_num_by_txid(&outpoint.txid)? {
+            Some(tx_num) => tx_num,
+            None => return Ok(None),
         };
-        if is_spent_in_mempool {
-            return Ok(UtxoState {
-                height: Some(block_tx.block_height),
-                state: UtxoStateVariant::Spent,
-            });
+        let spends = self.indexer.db().spends()?.spends_by_tx_num(tx_num)?;
+        match spends
+            .iter()
+            .find(|spend| spend.out_idx == outpoint.out_idx)
+        {
+            Some(spend) => Ok(Some(OutPoint {
+                txid: tx_reader
+                    .txid_by_tx_num(spend.tx_num)?
+                    .ok_or(InconsistentNoSuchTxNum(spend.tx_num))?,
+                out_idx: spend.input_idx,
+            })),
+            None => Ok(None),
         }
-        let spends = spends_reader.spends_by_tx_num(tx_num)?;
-        if spends.iter().any(|spend| spend.out_idx == outpoint.out_idx) {
-            return Ok(UtxoState {
-                height: Some(block_tx.block_height),
-                state: UtxoStateVariant::Spent,
-            });
+    }
+
+    /// UTXOs of up to `EXPORT_UTXOS_NUM_SCRIPTS` scripts with the given prefix, after the
+    /// payload `after`, and the payload of the last script loaded, to continue from. None if
+    /// there are no more scripts with that prefix.
+    fn export_page(
+        &self,
+        prefix: PayloadPrefix,
+        after: Option<&[u8]>,
+    ) -> Result<(Vec<ExportedUtxo>, Option<Vec<u8>>)> {
+        let scripts =
+            self.indexer
+                .db()
+                .utxos()?
+                .utxos_page(prefix, after, EXPORT_UTXOS_NUM_SCRIPTS)?;
+        let last_payload = scripts
+            .last()
+            .map(|(script_payload, _)| script_payload.payload_data.clone());
+        let tx_reader = self.indexer.db().txs()?;
+        let mut utxos = Vec::new();
+        for (script_payload, utxo_entries) in scripts {
+            for utxo_entry in utxo_entries {
+                let tx_num = utxo_entry.outpoint.tx_num;
+                let txid = tx_reader
+                    .txid_by_tx_num(tx_num)?
+                    .ok_or(InconsistentNoSuchTxNum(tx_num))?;
+                utxos.push(ExportedUtxo {
+                    script_payload: script_payload.clone(),
+                    outpoint: OutPoint {
+                        txid,
+                        out_idx: utxo_entry.outpoint.out_idx,
+                    },
+                    value: utxo_entry.value,
+                });
+            }
         }
-        let block_reader = self.indexer.db().blocks()?;
-        let block = block_reader
-            .by_height(block_tx.block_height)?
-            .expect("Inconsistent db");
-        let raw_tx = self.indexer.rpc_interface.get_block_slice(
-            block.file_num,
-            block_tx.entry.data_pos,
-            block_tx.entry.tx_size,
-        )?;
-        let tx = UnhashedTx::deser(&mut Bytes::from_bytes(raw_tx))?;
-        if outpoint.out_idx as usize >= tx.outputs.len() {
-            return Ok(UtxoState {
-                height: Some(block_tx.block_height),
-                state: UtxoStateVariant::NoSuchOutput,
-            });
+        Ok((utxos, last_payload))
+    }
+}
+
+/// Streams the confirmed UTXOs of every script with one of the given prefixes, in chunks of
+/// the UTXOs of up to 1000 scripts; mempool UTXOs aren't included. Like `export_blocks`, the
+/// indexer is only locked while a chunk is being loaded, so blocks indexed during the export
+/// end up in some chunks but not others, i.e. the result isn't a snapshot at a single height.
+pub fn export_utxos(
+    slp_indexer: Arc<RwLock<SlpIndexer>>,
+    prefixes: Vec<PayloadPrefix>,
+) -> impl Stream<Item = Result<Vec<ExportedUtxo>>> {
+    // Cursor is the index into `prefixes` and the payload of the last exported script
+    let cursor: Option<(usize, Option<Vec<u8>>)> = Some((0, None));
+    futures::stream::unfold(cursor, move |cursor| {
+        let slp_indexer = Arc::clone(&slp_indexer);
+        let prefixes = prefixes.clone();
+        async move {
+            let (mut prefix_idx, mut after) = cursor?;
+            let slp_indexer = slp_indexer.read().await;
+            loop {
+                let prefix = *prefixes.get(prefix_idx)?;
+                match slp_indexer.utxos().export_page(prefix, after.as_deref()) {
+                    Ok((utxos, Some(last_payload))) => {
+                        return Some((Ok(utxos), Some((prefix_idx, Some(last_payload)))))
+                    }
+                    Ok((_, None)) => {
+                        prefix_idx += 1;
+                        after = None;
+                    }
+                    Err(report) => return Some((Err(report), None)),
+                }
+            }
+        }
+    })
+}
+
+/// The `limit` oldest confirmed UTXOs created in blocks below `older_than_height`, oldest
+/// first. Scans the entire UTXO set, so this is for operators only; like `export_utxos`, the
+/// indexer is only locked while a page of scripts is scanned, so blocks indexed meanwhile may be
+/// seen partially. UTXOs indexed under multiple payloads (e.g. P2TR with state) are listed once.
+pub async fn dormant_utxos(
+    slp_indexer: Arc<RwLock<SlpIndexer>>,
+    older_than_height: BlockHeight,
+    limit: usize,
+) -> Result<Vec<DormantUtxo>> {
+    // UTXOs of txs from this tx_num onwards are too recent; above the tip, all are old enough
+    let end_tx_num = match slp_indexer
+        .read()
+        .await
+        .blocks()
+        .tx_num_range(older_than_height, older_than_height)?
+    {
+        Some(tx_num_range) => tx_num_range.start,
+        None => TxNum::MAX,
+    };
+    let mut oldest_utxos = BTreeMap::<OutpointEntry, (ScriptPayload, i64)>::new();
+    for prefix in PayloadPrefix::ALL {
+        let mut after = None;
+        loop {
+            let indexer = slp_indexer.read().await;
+            let scripts = indexer.db().utxos()?.utxos_page(
+                prefix,
+                after.as_deref(),
+                EXPORT_UTXOS_NUM_SCRIPTS,
+            )?;
+            after = match scripts.last() {
+                Some((script_payload, _)) => Some(script_payload.payload_data.clone()),
+                None => break,
+            };
+            for (script_payload, utxo_entries) in scripts {
+                for utxo_entry in utxo_entries {
+                    if utxo_entry.outpoint.tx_num >= end_tx_num {
+                        continue;
+                    }
+                    oldest_utxos
+                        .entry(utxo_entry.outpoint)
+                        .or_insert_with(|| (script_payload.clone(), utxo_entry.value));
+                    if oldest_utxos.len() > limit {
+                        let newest_outpoint = oldest_utxos.keys().next_back().cloned();
+                        oldest_utxos.remove(&newest_outpoint.unwrap());
+                    }
+                }
+            }
         }
-        Ok(UtxoState {
-            height: Some(block_tx.block_height),
-            state: UtxoStateVariant::Unspent,
-        })
     }
+    let indexer = slp_indexer.read().await;
+    let tx_reader = indexer.db().txs()?;
+    oldest_utxos
+        .into_iter()
+        .map(|(outpoint, (script_payload, value))| {
+            let block_tx = tx_reader
+                .by_tx_num(outpoint.tx_num)?
+                .ok_or(InconsistentNoSuchTxNum(outpoint.tx_num))?;
+            Ok(DormantUtxo {
+                script_payload,
+                outpoint: OutPoint {
+                    txid: block_tx.entry.txid,
+                    out_idx: outpoint.out_idx,
+                },
+                value,
+                height: block_tx.block_height,
+            })
+        })
+        .collect()
 }
@@ -0,0 +1,202 @@
+use bitcoinsuite_bitcoind_nng::BlockIdentifier;
+use bitcoinsuite_core::{BitcoinCode, Bytes, UnhashedTx};
+use bitcoinsuite_error::Result;
+use chronik_rocksdb::{Block, BlockHeight, BlockStats};
+
+use crate::SlpIndexer;
+
+/// Every how many blocks `verify_index` recomputes a block's stats from the node's block data.
+pub const DEFAULT_VERIFY_SAMPLE_INTERVAL: BlockHeight = 1000;
+
+pub struct Verifier<'a> {
+    indexer: &'a SlpIndexer,
+}
+
+/// Inconsistency found by `verify_index`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Discrepancy {
+    MissingBlock(BlockHeight),
+    MissingBlockStats(BlockHeight),
+    MissingFirstTxNum(BlockHeight),
+    /// Number of txs in the block stats differs from the number of txs in the tx index.
+    TxCountMismatch {
+        height: BlockHeight,
+        num_txs_stats: u64,
+        num_txs_indexed: u64,
+    },
+    /// Running total of the UTXO set value (outputs minus inputs of all blocks) became negative.
+    NegativeUtxoTotal {
+        height: BlockHeight,
+        utxo_total: i64,
+    },
+    /// Change of the UTXO set value of a sampled block, as recorded in its stats, differs from
+    /// the one recomputed from the node's block data.
+    UtxoDeltaMismatch {
+        height: BlockHeight,
+        delta_stats: i64,
+        delta_recomputed: i64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyReport {
+    pub num_blocks: usize,
+    pub num_sampled_blocks: usize,
+    /// Total value of the UTXO set (including burned outputs), summed up from the block stats.
+    pub utxo_total: i64,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+impl<'a> Verifier<'a> {
+    pub fn new(indexer: &'a SlpIndexer) -> Self {
+        Verifier { indexer }
+    }
+
+    /// Checks the consistency of the index from genesis to the tip, without modifying it.
+    /// Reads the block, block stats and tx index entries of every block, and additionally loads
+    /// every `sample_interval`-th block from the node to recompute its UTXO delta.
+    pub fn verify_index(&self, sample_interval: BlockHeight) -> Result<VerifyReport> {
+        let block_reader = self.indexer.db().blocks()?;
+        let block_stats_reader = self.indexer.db().block_stats()?;
+        let tx_reader = self.indexer.db().txs()?;
+        let tip_height = block_reader.height()?;
+        let mut report = VerifyReport {
+            num_blocks: 0,
+            num_sampled_blocks: 0,
+            utxo_total: 0,
+            discrepancies: Vec::new(),
+        };
+        let mut first_tx_num = tx_reader.first_tx_num_by_block(0)?;
+        for height in 0..=tip_height {
+            report.num_blocks += 1;
+            let next_first_tx_num = match height == tip_height {
+                true => tx_reader.last_tx_num()?.map(|last_tx_num| last_tx_num + 1),
+                false => tx_reader.first_tx_num_by_block(height + 1)?,
+            };
+            let block = block_reader.by_height(height)?;
+            let block_stats = block_stats_reader.by_height(height)?;
+            if block.is_none() {
+                report.discrepancies.push(Discrepancy::MissingBlock(height));
+            }
+            let block_stats = match block_stats {
+                Some(block_stats) => block_stats,
+                None => {
+                    report
+                        .discrepancies
+                        .push(Discrepancy::MissingBlockStats(height));
+                    first_tx_num = next_first_tx_num;
+                    continue;
+                }
+            };
+            match (first_tx_num, next_first_tx_num) {
+                (Some(first_tx_num), Some(next_first_tx_num)) => {
+                    let num_txs_indexed = next_first_tx_num - first_tx_num;
+                    if num_txs_indexed != block_stats.num_txs {
+                        report.discrepancies.push(Discrepancy::TxCountMismatch {
+                            height,
+                            num_txs_stats: block_stats.num_txs,
+                            num_txs_indexed,
+                        });
+                    }
+                }
+                (None, _) => report
+                    .discrepancies
+                    .push(Discrepancy::MissingFirstTxNum(height)),
+                // Reported in the next iteration
+                (Some(_), None) => {}
+            }
+            let delta_stats = utxo_delta(&block_stats);
+            report.utxo_total += delta_stats;
+            // Only report when the total turns negative, not for every block after
+            if report.utxo_total < 0 && report.utxo_total - delta_stats >= 0 {
+                report.discrepancies.push(Discrepancy::NegativeUtxoTotal {
+                    height,
+                    utxo_total: report.utxo_total,
+                });
+            }
+            if let Some(block) = &block {
+                if sample_interval > 0 && height % sample_interval == 0 {
+                    report.num_sampled_blocks += 1;
+                    let delta_recomputed = self.recompute_utxo_delta(block)?;
+                    if delta_recomputed != delta_stats {
+                        report.discrepancies.push(Discrepancy::UtxoDeltaMismatch {
+                            height,
+                            delta_stats,
+                            delta_recomputed,
+                        });
+                    }
+                }
+            }
+            if height % 10_000 == 0 {
+                println!("Verified index up to height {}", height);
+            }
+            first_tx_num = next_first_tx_num;
+        }
+        Ok(report)
+    }
+
+    fn recompute_utxo_delta(&self, block: &Block) -> Result<i64> {
+        let nng_block = self
+            .indexer
+            .rpc_interface
+            .get_block(BlockIdentifier::Hash(block.hash.clone()))?;
+        let mut delta = 0;
+        for (tx_idx, block_tx) in nng_block.txs.iter().enumerate() {
+            let tx = UnhashedTx::deser(&mut Bytes::from_slice(&block_tx.tx.raw))?;
+            delta += tx.outputs.iter().map(|output| output.value).sum::<i64>();
+            // Coinbase doesn't spend any coins
+            if tx_idx == 0 {
+                continue;
+            }
+            if let Some(spent_coins) = &block_tx.tx.spent_coins {
+                delta -= spent_coins
+                    .iter()
+                    .map(|spent_coin| spent_coin.tx_output.value)
+                    .sum::<i64>();
+            }
+        }
+        Ok(delta)
+    }
+}
+
+fn utxo_delta(block_stats: &BlockStats) -> i64 {
+    block_stats.sum_coinbase_output_sats + block_stats.sum_normal_output_sats
+        - block_stats.sum_input_sats
+}
+
+impl std::fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Discrepancy::MissingBlock(height) => write!(f, "Block {} missing", height),
+            Discrepancy::MissingBlockStats(height) => {
+                write!(f, "Block stats for block {} missing", height)
+            }
+            Discrepancy::MissingFirstTxNum(height) => {
+                write!(f, "First tx num of block {} missing", height)
+            }
+            Discrepancy::TxCountMismatch {
+                height,
+                num_txs_stats,
+                num_txs_indexed,
+            } => write!(
+                f,
+                "Block {} has {} txs according to its stats, but {} txs are indexed",
+                height, num_txs_stats, num_txs_indexed,
+            ),
+            Discrepancy::NegativeUtxoTotal { height, utxo_total } => write!(
+                f,
+                "UTXO set value became negative at block {}: {}",
+                height, utxo_total,
+            ),
+            Discrepancy::UtxoDeltaMismatch {
+                height,
+                delta_stats,
+                delta_recomputed,
+            } => write!(
+                f,
+                "UTXO delta of block {} is {} according to its stats, but recomputed as {}",
+                height, delta_stats, delta_recomputed,
+            ),
+        }
+    }
+}
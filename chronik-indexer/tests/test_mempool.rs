@@ -20,13 +20,13 @@ use bitcoinsuite_slp::{
 use bitcoinsuite_test_utils::bin_folder;
 use bitcoinsuite_test_utils_blockchain::build_tx;
 use chronik_indexer::{
-    broadcast::{BroadcastError, SlpBurns},
+    broadcast::{BroadcastError, SlpBurnPolicy, SlpBurns},
     subscribers::{SubscribeBlockMessage, SubscribeScriptMessage},
     SlpIndexer, UtxoState, UtxoStateVariant,
 };
 use chronik_rocksdb::{
-    BlockStats, Db, IndexDb, IndexMemData, MempoolTxEntry, PayloadPrefix, ScriptPayload,
-    ScriptTxsConf, TokenStats, TransientData,
+    BlockStats, Db, IndexDb, IndexMemData, LokadTxsConf, MempoolTxEntry, PayloadPrefix,
+    ScriptPayload, ScriptTxsConf, TokenStats, TransientData,
 };
 use pretty_assertions::{assert_eq, assert_ne};
 use tempdir::TempDir;
@@ -57,7 +57,7 @@ async fn test_mempool() -> Result<()> {
     let script_txs_conf = ScriptTxsConf { page_size: 7 };
     let db = Db::open(dir.path().join("index.rocksdb"))?;
     let transient_data = TransientData::open(&dir.path().join("transient.rocksdb"))?;
-    let db = IndexDb::new(db, transient_data, script_txs_conf);
+    let db = IndexDb::new(db, transient_data, script_txs_conf, LokadTxsConf::default());
     let bitcoind = instance.cli();
     let cache = IndexMemData::new(10);
     let mut slp_indexer = SlpIndexer::new(
@@ -101,8 +101,8 @@ async fn test_index_mempool(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli)
             let expected_hash = Sha256d::from_hex_be(hashes[i].as_str().unwrap())?;
             let msg = timeout(dt_timeout, blocks.recv()).await??;
             let actual_hash = match msg {
-                SubscribeBlockMessage::BlockConnected(hash) => hash,
-                SubscribeBlockMessage::BlockDisconnected(_) => unreachable!(),
+                SubscribeBlockMessage::BlockConnected { block_hash, .. } => block_hash,
+                msg => panic!("Unexpected message: {:?}", msg),
             };
             assert_eq!(expected_hash, actual_hash);
             i += 1;
@@ -113,6 +113,44 @@ async fn test_index_mempool(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli)
                 .rev_history_num_pages(P2SH, anyone_slice, 4)?,
             3,
         );
+        let page_heights = |start_height, end_height, page_num, page_size| -> Result<_> {
+            let (txs, num_pages) = slp_indexer
+                .script_history()
+                .rev_history_page_in_height_range(
+                    P2SH,
+                    anyone_slice,
+                    start_height,
+                    end_height,
+                    page_num,
+                    page_size,
+                )?;
+            let heights = txs
+                .iter()
+                .map(|tx| tx.block.as_ref().unwrap().height)
+                .collect::<Vec<_>>();
+            Ok((heights, num_pages))
+        };
+        assert_eq!(page_heights(3, 5, 0, 4)?, (vec![5, 4, 3], 1));
+        assert_eq!(page_heights(3, 5, 0, 2)?, (vec![5, 4], 2));
+        assert_eq!(page_heights(3, 5, 1, 2)?, (vec![3], 2));
+        assert_eq!(page_heights(0, 2, 0, 4)?, (vec![2, 1], 1));
+        // Range beyond the tip is clamped to the tip
+        assert_eq!(page_heights(9, 1000, 0, 4)?, (vec![10, 9], 1));
+        assert_eq!(page_heights(11, 1000, 0, 4)?, (vec![], 0));
+        // Oldest first
+        let (txs, num_pages) = slp_indexer.script_history().history_page_in_height_range(
+            P2SH,
+            anyone_slice,
+            3,
+            5,
+            0,
+            2,
+        )?;
+        let heights = txs
+            .iter()
+            .map(|tx| tx.block.as_ref().unwrap().height)
+            .collect::<Vec<_>>();
+        assert_eq!((heights, num_pages), (vec![3, 4], 2));
     }
 
     let burn_address = CashAddress::from_hash(BCHREG, AddressType::P2SH, ShaRmd160::new([0; 20]));
@@ -331,6 +369,28 @@ async fn test_index_mempool(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli)
             state: UtxoStateVariant::NoSuchOutput,
         },
     );
+    // Far out-of-range output of a mempool-only tx is still NoSuchOutput, not NoSuchTx
+    assert_eq!(
+        slp_indexer.utxos().utxo_state(&OutPoint {
+            txid: txid1.clone(),
+            out_idx: u32::MAX,
+        })?,
+        UtxoState {
+            height: None,
+            state: UtxoStateVariant::NoSuchOutput,
+        },
+    );
+    // Never-seen txid is NoSuchTx, even with a non-empty mempool
+    assert_eq!(
+        slp_indexer.utxos().utxo_state(&OutPoint {
+            txid: Sha256d::new([0x42; 32]),
+            out_idx: 0,
+        })?,
+        UtxoState {
+            height: None,
+            state: UtxoStateVariant::NoSuchTx,
+        },
+    );
 
     bitcoind.cmd_string("setmocktime", &["2100000001"])?;
     let (outpoint, value) = utxos.pop().unwrap();
@@ -459,6 +519,7 @@ async fn test_index_mempool(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli)
         Some(TokenStats {
             total_minted: 100,
             total_burned: 0,
+            supply_overflowed: false,
         }),
     );
 
@@ -518,6 +579,25 @@ async fn test_index_mempool(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli)
             .unwrap_err(),
         BroadcastError::InvalidSlpBurns(SlpBurns(burns.clone())),
     );
+    // tx3 burns 1 base token of the token it sends, which can be allowed explicitly
+    assert_eq!(
+        slp_indexer
+            .broadcast()
+            .with_burn_policy(SlpBurnPolicy::RejectAnyBurn)
+            .test_mempool_accept(&tx3, true)
+            .await?,
+        Err(BroadcastError::InvalidSlpBurns(SlpBurns(burns.clone()))),
+    );
+    for burn_policy in [SlpBurnPolicy::AllowExplicitBurn, SlpBurnPolicy::AllowAll] {
+        assert_eq!(
+            slp_indexer
+                .broadcast()
+                .with_burn_policy(burn_policy)
+                .test_mempool_accept(&tx3, true)
+                .await?,
+            Ok(()),
+        );
+    }
     let txid3 = slp_indexer.broadcast().broadcast_tx(&tx3, false).await?;
     let slp_tx_data3 = SlpValidTxData {
         slp_tx_data: SlpTxData {
@@ -669,6 +749,7 @@ async fn test_index_mempool(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli)
             ],
         )?;
     }
+    // Confirmed output spent by a mempool tx
     assert_eq!(
         slp_indexer.utxos().utxo_state(&outpoint)?,
         UtxoState {
@@ -676,6 +757,7 @@ async fn test_index_mempool(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli)
             state: UtxoStateVariant::Spent,
         },
     );
+    // Mempool output spent by another mempool tx
     assert_eq!(
         slp_indexer.utxos().utxo_state(&OutPoint {
             txid: txid1.clone(),
@@ -686,6 +768,18 @@ async fn test_index_mempool(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli)
             state: UtxoStateVariant::Spent,
         },
     );
+    // Out-of-range output of a mempool tx which has other outputs spent in the mempool
+    assert_eq!(
+        slp_indexer.utxos().utxo_state(&OutPoint {
+            txid: txid1.clone(),
+            out_idx: 2,
+        })?,
+        UtxoState {
+            height: None,
+            state: UtxoStateVariant::NoSuchOutput,
+        },
+    );
+    // Unspent mempool output, whose tx spends other mempool txs
     assert_eq!(
         slp_indexer.utxos().utxo_state(&OutPoint {
             txid: txid3.clone(),
@@ -696,11 +790,22 @@ async fn test_index_mempool(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli)
             state: UtxoStateVariant::Unspent,
         },
     );
+    assert_eq!(
+        slp_indexer.utxos().utxo_state(&OutPoint {
+            txid: txid3.clone(),
+            out_idx: 2,
+        })?,
+        UtxoState {
+            height: None,
+            state: UtxoStateVariant::NoSuchOutput,
+        },
+    );
     assert_eq!(
         slp_indexer.tokens().token_stats_by_token_id(&token_id)?,
         Some(TokenStats {
             total_minted: 100,
             total_burned: 1,
+            supply_overflowed: false,
         }),
     );
 
@@ -736,6 +841,7 @@ async fn test_index_mempool(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli)
     let block_tx = slp_indexer.db().txs()?.by_txid(&txid1)?.unwrap();
     assert_eq!(block_tx.entry.txid, txid1);
     assert_eq!(block_tx.entry.tx_size, tx1.raw().len() as u32);
+    assert_eq!(block_tx.entry.time_first_seen, 2_100_000_000);
     assert_eq!(block_tx.block_height, 111);
     assert_eq!(slp_indexer.db_mempool().tx(&txid1), None);
     assert!(slp_indexer.db_mempool().tx(&txid2).is_some());
@@ -843,10 +949,13 @@ async fn test_index_mempool(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli)
     assert_eq!(slp_indexer.db_mempool().tx(&txid1), None);
     assert_eq!(slp_indexer.db_mempool().tx(&txid2), None);
     assert_eq!(slp_indexer.db_mempool().tx(&txid3), None);
+    // The node evicted tx3 before connecting the block, so it's not flagged as conflicting
+    assert_eq!(slp_indexer.mempool_conflict(&txid3), None);
 
     let block_tx = slp_indexer.db().txs()?.by_txid(&txid2)?.unwrap();
     assert_eq!(block_tx.entry.txid, txid2);
     assert_eq!(block_tx.entry.tx_size, tx2.raw().len() as u32);
+    assert_eq!(block_tx.entry.time_first_seen, 2_100_000_001);
     assert_eq!(block_tx.block_height, 112);
 
     assert_eq!(slp_indexer.db().txs()?.by_txid(&txid3)?, None);
@@ -855,6 +964,8 @@ async fn test_index_mempool(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli)
     let block_tx = slp_indexer.db().txs()?.by_txid(&txid3_modified)?.unwrap();
     assert_eq!(block_tx.entry.txid, txid3_modified);
     assert_eq!(block_tx.entry.tx_size, tx3.raw().len() as u32);
+    // The modified tx3 never entered the mempool, so it has no first-seen time
+    assert_eq!(block_tx.entry.time_first_seen, 0);
     assert_eq!(block_tx.block_height, 112);
 
     assert_eq!(slp_indexer.txs().raw_tx_by_id(&txid1)?, Some(tx1.ser()));
@@ -905,7 +1016,7 @@ async fn test_index_mempool(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli)
         spent_coins[1].height = Some(111);
         spent_coins[2].height = Some(112);
     }
-    rich_tx3.time_first_seen = 0; // tx not first seen in mempool
+    rich_tx3.time_first_seen = 0; // modified tx3 was never in the mempool
     let spent_coins = rich_tx3.spent_coins.as_mut().unwrap();
     spent_coins[1].height = Some(111);
     spent_coins[2].height = Some(112);
@@ -982,6 +1093,7 @@ async fn test_index_mempool(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli)
         Some(TokenStats {
             total_minted: 100,
             total_burned: 1,
+            supply_overflowed: false,
         }),
     );
 
@@ -1052,6 +1164,124 @@ async fn test_index_mempool(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli)
     );
     assert_eq!(block_stats_reader.by_height(113)?, None);
 
+    // Mempool tx with a descendant, whose coin then gets spent by a different, mined tx
+    let (outpoint, value) = utxos.pop().unwrap();
+    let parent_tx = build_tx(
+        outpoint.clone(),
+        &anyone_script,
+        vec![TxOutput {
+            value: value - 10_000,
+            script: anyone_script.to_p2sh(),
+        }],
+    );
+    let parent_txid = slp_indexer
+        .broadcast()
+        .broadcast_tx(&parent_tx, true)
+        .await?;
+    slp_indexer.process_next_msg()?;
+    match timeout(dt_timeout, receiver.recv()).await?? {
+        SubscribeScriptMessage::AddedToMempool(txid) => assert_eq!(txid, parent_txid),
+        _ => panic!("Wrong message received"),
+    }
+    let child_tx = build_tx(
+        OutPoint {
+            txid: parent_txid.clone(),
+            out_idx: 0,
+        },
+        &anyone_script,
+        vec![TxOutput {
+            value: value - 20_000,
+            script: anyone_script.to_p2sh(),
+        }],
+    );
+    let child_txid = slp_indexer
+        .broadcast()
+        .broadcast_tx(&child_tx, true)
+        .await?;
+    slp_indexer.process_next_msg()?;
+    match timeout(dt_timeout, receiver.recv()).await?? {
+        SubscribeScriptMessage::AddedToMempool(txid) => assert_eq!(txid, child_txid),
+        _ => panic!("Wrong message received"),
+    }
+
+    let conflicting_tx = build_tx(
+        outpoint,
+        &anyone_script,
+        vec![TxOutput {
+            value: value - 30_000,
+            script: burn_address2.to_script(),
+        }],
+    )
+    .hashed();
+    let conflicting_txid = lotus_txid(conflicting_tx.unhashed_tx());
+    let tip = slp_indexer.db().blocks()?.tip()?.unwrap();
+    let coinbase_tx = build_lotus_coinbase(tip.height + 1, anyone_script.to_p2sh());
+    let coinbase_txid = lotus_txid(&coinbase_tx);
+    let block3 = build_lotus_block(
+        tip.hash.clone(),
+        tip.timestamp + 1,
+        tip.height + 1,
+        coinbase_tx.hashed(),
+        vec![conflicting_tx],
+        Sha256d::default(),
+        vec![],
+    );
+    let result = bitcoind.cmd_string("submitblock", &[&block3.ser().hex()])?;
+    assert_eq!(result, "");
+
+    // The node evicts both mempool txs, in no particular order, before connecting the block
+    let mut removed_txids = HashSet::new();
+    for _ in 0..2 {
+        slp_indexer.process_next_msg()?;
+        removed_txids.insert(match timeout(dt_timeout, receiver.recv()).await?? {
+            SubscribeScriptMessage::RemovedFromMempool(txid) => txid,
+            _ => panic!("Wrong message received"),
+        });
+    }
+    assert_eq!(
+        removed_txids,
+        [&parent_txid, &child_txid].into_iter().cloned().collect(),
+    );
+    slp_indexer.process_next_msg()?;
+    let mut subbed_txids = HashSet::new();
+    for _ in 0..2 {
+        subbed_txids.insert(match timeout(dt_timeout, receiver.recv()).await?? {
+            SubscribeScriptMessage::Confirmed(txid) => txid,
+            _ => panic!("Wrong message received"),
+        });
+    }
+    assert_eq!(
+        subbed_txids,
+        [&coinbase_txid, &conflicting_txid]
+            .into_iter()
+            .cloned()
+            .collect(),
+    );
+    assert_eq!(slp_indexer.db_mempool().tx(&parent_txid), None);
+    assert_eq!(slp_indexer.db_mempool().tx(&child_txid), None);
+    assert_eq!(slp_indexer.mempool_conflict(&parent_txid), None);
+    assert_eq!(slp_indexer.mempool_conflict(&child_txid), None);
+    assert_eq!(slp_indexer.txs().rich_tx_by_txid(&parent_txid)?, None);
+    assert_eq!(
+        slp_indexer.utxos().utxo_state(&OutPoint {
+            txid: parent_txid,
+            out_idx: 0,
+        })?,
+        UtxoState {
+            height: None,
+            state: UtxoStateVariant::NoSuchTx,
+        },
+    );
+    assert_eq!(
+        slp_indexer
+            .db()
+            .txs()?
+            .by_txid(&conflicting_txid)?
+            .unwrap()
+            .block_height,
+        113,
+    );
+
     Ok(())
 }
 
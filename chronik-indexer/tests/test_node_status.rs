@@ -0,0 +1,49 @@
+use std::{net::TcpListener, time::Duration};
+
+use bitcoinsuite_bitcoind::rpc_client::{BitcoindRpcClient, BitcoindRpcClientConf};
+use bitcoinsuite_error::Result;
+use chronik_indexer::{NodeStatus, NodeStatusError};
+use pretty_assertions::assert_eq;
+
+#[tokio::test]
+async fn test_node_status() -> Result<()> {
+    bitcoinsuite_error::install()?;
+    // Simulate an unreachable bitcoind with a port nothing listens on
+    let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+    let bitcoind = BitcoindRpcClient::new(BitcoindRpcClientConf {
+        url: format!("http://127.0.0.1:{}", port),
+        rpc_user: "user".to_string(),
+        rpc_pass: "pass".to_string(),
+    });
+    let node_status = NodeStatus::new(Duration::from_millis(200));
+    assert!(node_status.is_reachable());
+
+    let report = node_status
+        .call(bitcoind.cmd_json("getblockcount", &[]))
+        .await
+        .unwrap_err();
+    assert!(report.downcast_ref::<NodeStatusError>().is_some());
+    assert!(!node_status.is_reachable());
+
+    // Fails fast without making the call until the retry interval has passed
+    let report = node_status.call(async { Ok(()) }).await.unwrap_err();
+    assert_eq!(
+        report.to_string(),
+        "Node unavailable: bitcoind can't be reached, try again later",
+    );
+    assert!(!node_status.is_reachable());
+
+    // Afterwards, the next call probes bitcoind again, and succeeding closes the breaker
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    node_status.call(async { Ok(()) }).await?;
+    assert!(node_status.is_reachable());
+
+    // Errors not from bitcoind don't affect reachability
+    let result = node_status
+        .call(async { Err::<(), _>(NodeStatusError::NodeUnavailable.into()) })
+        .await;
+    assert!(result.is_err());
+    assert!(node_status.is_reachable());
+
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use std::{ffi::OsString, str::FromStr, sync::Arc, time::Duration};
+use std::{ffi::OsString, path::Path, str::FromStr, sync::Arc, time::Duration};
 
 use bitcoinsuite_bitcoind::{
     cli::BitcoinCli,
@@ -13,10 +13,12 @@ use bitcoinsuite_ecc_secp256k1::EccSecp256k1;
 use bitcoinsuite_error::Result;
 use bitcoinsuite_slp::{RichTxBlock, RichUtxo};
 use bitcoinsuite_test_utils::bin_folder;
-use chronik_indexer::SlpIndexer;
+use chronik_indexer::{
+    subscribers::SubscribeBlockMessage, BlockFiles, SlpIndexer, SlpIndexerError,
+};
 use chronik_rocksdb::{
-    BlockTx, Db, IndexDb, IndexMemData, OutpointEntry, PayloadPrefix, ScriptPayload, ScriptTxsConf,
-    ScriptTxsReader, TransientData, TxEntry, UtxoEntry, UtxosReader,
+    BlockTx, Db, IndexDb, IndexMemData, LokadTxsConf, OutpointEntry, PayloadPrefix, ScriptPayload,
+    ScriptTxsConf, ScriptTxsReader, TransientData, TxEntry, UtxoEntry, UtxosReader,
 };
 use pretty_assertions::assert_eq;
 use tempdir::TempDir;
@@ -27,6 +29,8 @@ async fn test_non_slp() -> Result<()> {
     let dir = TempDir::new("slp-indexer-test")?;
     let pub_url = format!("ipc://{}", dir.path().join("pub.pipe").to_string_lossy());
     let rpc_url = format!("ipc://{}", dir.path().join("rpc.pipe").to_string_lossy());
+    let blocks_dir = dir.path().join("blocks");
+    std::fs::create_dir(&blocks_dir)?;
     let conf = BitcoindConf::from_chain_regtest(
         bin_folder(),
         BitcoindChain::XPI,
@@ -37,6 +41,7 @@ async fn test_non_slp() -> Result<()> {
             OsString::from_str("-nngpubmsg=mempooltxadd")?,
             OsString::from_str("-nngpubmsg=mempooltxrem")?,
             OsString::from_str(&format!("-nngrpc={}", rpc_url))?,
+            OsString::from_str(&format!("-blocksdir={}", blocks_dir.to_string_lossy()))?,
         ],
     )?;
     let mut instance = BitcoindInstance::setup(conf)?;
@@ -46,7 +51,7 @@ async fn test_non_slp() -> Result<()> {
     let script_txs_conf = ScriptTxsConf { page_size: 1000 };
     let db = Db::open(dir.path().join("index.rocksdb"))?;
     let transient_data = TransientData::open(&dir.path().join("transient.rocksdb"))?;
-    let db = IndexDb::new(db, transient_data, script_txs_conf);
+    let db = IndexDb::new(db, transient_data, script_txs_conf, LokadTxsConf::default());
     let bitcoin_cli = instance.cli();
     let cache = IndexMemData::new(10);
     let mut slp_indexer = SlpIndexer::new(
@@ -59,8 +64,11 @@ async fn test_non_slp() -> Result<()> {
         Arc::new(EccSecp256k1::default()),
     )?;
     test_index_genesis(&mut slp_indexer, bitcoin_cli).await?;
-    test_get_out_of_ibd(&mut slp_indexer, bitcoin_cli).await?;
+    let node_blocks_dir = blocks_dir.join("regtest").join("blocks");
+    test_get_out_of_ibd(&mut slp_indexer, bitcoin_cli, &node_blocks_dir).await?;
     test_reorg_empty(&mut slp_indexer, bitcoin_cli).await?;
+    test_reorg_two_blocks(&mut slp_indexer, bitcoin_cli).await?;
+    test_reorg_too_deep(&mut slp_indexer, bitcoin_cli).await?;
     instance.cleanup()?;
     Ok(())
 }
@@ -180,7 +188,11 @@ async fn test_index_genesis(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli)
     Ok(())
 }
 
-async fn test_get_out_of_ibd(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli) -> Result<()> {
+async fn test_get_out_of_ibd(
+    slp_indexer: &mut SlpIndexer,
+    bitcoind: &BitcoinCli,
+    blocks_dir: &Path,
+) -> Result<()> {
     let prev_info = bitcoind.cmd_json("getblockchaininfo", &[])?;
     // generate block delayed
     let gen_handle = std::thread::spawn({
@@ -249,6 +261,30 @@ async fn test_get_out_of_ibd(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli
     assert!(slp_indexer.catchup_step().await?);
     slp_indexer.leave_catchup()?;
 
+    // index is consistent, sampling every block
+    let report = slp_indexer.verifier().verify_index(1)?;
+    assert_eq!(report.num_blocks, 2);
+    assert_eq!(report.num_sampled_blocks, 2);
+    assert_eq!(report.discrepancies, vec![]);
+
+    // raw tx read from the block files matches the one read via RPC
+    let raw_tx_rpc = slp_indexer.txs().raw_tx_by_id(&coinbase_txid)?.unwrap();
+    let block_tx = slp_indexer.db().txs()?.by_txid(&coinbase_txid)?.unwrap();
+    let block_files = BlockFiles::new(blocks_dir.to_path_buf());
+    assert_eq!(
+        block_files.read_slice(
+            tip.file_num,
+            block_tx.entry.data_pos,
+            block_tx.entry.tx_size,
+        )?,
+        raw_tx_rpc.to_vec(),
+    );
+    slp_indexer.set_blocks_dir(blocks_dir.to_path_buf());
+    assert_eq!(
+        slp_indexer.txs().raw_tx_by_id(&coinbase_txid)?.unwrap(),
+        raw_tx_rpc,
+    );
+
     Ok(())
 }
 
@@ -390,6 +426,112 @@ async fn test_reorg_empty(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli) -
     Ok(())
 }
 
+async fn test_reorg_two_blocks(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli) -> Result<()> {
+    // Depths that don't fit into a BlockHeight don't wrap around when looking for the fork
+    slp_indexer.set_max_reorg_depth(usize::MAX);
+    let script = Script::p2sh(&ShaRmd160::digest(Bytes::from_bytes(vec![0x52])));
+    let tip = slp_indexer.db().blocks()?.tip()?.unwrap();
+    let fork_block = slp_indexer
+        .db()
+        .blocks()?
+        .by_height(tip.height - 2)?
+        .unwrap();
+    // three new blocks replacing the last two blocks of the index
+    let mut prev_hash = fork_block.hash.clone();
+    let mut new_hashes = Vec::new();
+    let mut new_coinbase_outputs = Vec::new();
+    for offset in 1..=3 {
+        let height = fork_block.height + offset;
+        let coinbase = build_lotus_coinbase(height, script.clone());
+        let block = build_lotus_block(
+            prev_hash,
+            tip.timestamp + offset as i64,
+            height,
+            coinbase.clone().hashed(),
+            vec![],
+            Sha256d::default(),
+            vec![],
+        );
+        bitcoind.cmd_string("submitblock", &[&block.ser().hex()])?;
+        prev_hash = block.header.calc_hash();
+        new_hashes.push(prev_hash.clone());
+        new_coinbase_outputs.push(coinbase.outputs);
+    }
+    assert_eq!(
+        bitcoind.cmd_string("getbestblockhash", &[])?,
+        prev_hash.to_hex_be(),
+    );
+
+    let mut blocks = slp_indexer.subscribers_mut().subscribe_to_blocks();
+    // two BlockDisconnected, then three BlockConnected
+    for _ in 0..5 {
+        slp_indexer.process_next_msg()?;
+    }
+    // Blocks without other txs pay no fees, so the whole coinbase is subsidy
+    let block_connected = |idx: usize| {
+        let coinbase_outputs = new_coinbase_outputs[idx].clone();
+        SubscribeBlockMessage::BlockConnected {
+            block_hash: new_hashes[idx].clone(),
+            height: fork_block.height + idx as i32 + 1,
+            subsidy: coinbase_outputs.iter().map(|output| output.value).sum(),
+            coinbase_outputs,
+            fees: 0,
+        }
+    };
+    let mut msgs = Vec::new();
+    while let Ok(msg) = blocks.try_recv() {
+        msgs.push(msg);
+    }
+    assert_eq!(
+        msgs,
+        vec![
+            SubscribeBlockMessage::ReorgStarted {
+                from_height: tip.height - 1,
+                to_height: tip.height,
+            },
+            SubscribeBlockMessage::BlockDisconnected(tip.hash.clone()),
+            SubscribeBlockMessage::BlockDisconnected(tip.prev_hash.clone()),
+            block_connected(0),
+            block_connected(1),
+            block_connected(2),
+            SubscribeBlockMessage::ReorgFinished {
+                new_tip_height: tip.height + 1,
+            },
+        ],
+    );
+
+    Ok(())
+}
+
+async fn test_reorg_too_deep(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli) -> Result<()> {
+    slp_indexer.set_max_reorg_depth(1);
+    let tip = slp_indexer.db().blocks()?.tip()?.unwrap();
+    // Disconnects the tip and the block before it
+    bitcoind.cmd_string("invalidateblock", &[&tip.prev_hash.to_hex_be()])?;
+
+    // Disconnecting the tip is fine
+    slp_indexer.process_next_msg()?;
+    let new_tip = slp_indexer.db().blocks()?.tip()?.unwrap();
+    assert_eq!(new_tip.hash, tip.prev_hash);
+
+    // Disconnecting the next block exceeds the max reorg depth
+    let report = slp_indexer.process_next_msg().unwrap_err();
+    match report.downcast_ref::<SlpIndexerError>() {
+        Some(SlpIndexerError::ReorgTooDeep {
+            block_hash,
+            max_reorg_depth,
+        }) => {
+            assert_eq!(block_hash, &tip.prev_hash);
+            assert_eq!(*max_reorg_depth, 1);
+        }
+        _ => panic!("Expected ReorgTooDeep, got {:?}", report),
+    }
+    // Index is left untouched
+    assert_eq!(slp_indexer.db().blocks()?.tip()?.unwrap(), new_tip);
+
+    Ok(())
+}
+
 fn check_pages<const N: usize>(
     script_txs_reader: &ScriptTxsReader,
     prefix: PayloadPrefix,
@@ -12,16 +12,20 @@ use bitcoinsuite_core::{
 use bitcoinsuite_ecc_secp256k1::EccSecp256k1;
 use bitcoinsuite_error::Result;
 use bitcoinsuite_slp::{
-    genesis_opreturn, send_opreturn, RichTx, RichTxBlock, SlpAmount, SlpBurn, SlpError,
-    SlpGenesisInfo, SlpToken, SlpTokenType, SlpTxData, SlpTxType, TokenId,
+    genesis_opreturn, mint_opreturn, send_opreturn, RichTx, RichTxBlock, SlpAmount, SlpBurn,
+    SlpError, SlpGenesisInfo, SlpToken, SlpTokenType, SlpTxData, SlpTxType, TokenId,
 };
 use bitcoinsuite_test_utils::bin_folder;
 use bitcoinsuite_test_utils_blockchain::build_tx;
 use chronik_indexer::{
-    broadcast::{BroadcastError, SlpBurns},
+    broadcast::{BroadcastError, SlpBurnPolicy, SlpBurns, SlpCulprits},
+    subscribers::SubscribeTokenMessage,
     SlpIndexer,
 };
-use chronik_rocksdb::{Db, IndexDb, IndexMemData, PayloadPrefix, ScriptTxsConf, TransientData};
+use chronik_rocksdb::{
+    Db, IndexDb, IndexMemData, LokadTxsConf, PayloadPrefix, ScriptTxsConf, TokenStats,
+    TransientData,
+};
 use pretty_assertions::assert_eq;
 use tempdir::TempDir;
 
@@ -50,7 +54,7 @@ async fn test_slp() -> Result<()> {
     let script_txs_conf = ScriptTxsConf { page_size: 7 };
     let db = Db::open(dir.path().join("index.rocksdb"))?;
     let transient_data = TransientData::open(&dir.path().join("transient.rocksdb"))?;
-    let db = IndexDb::new(db, transient_data, script_txs_conf);
+    let db = IndexDb::new(db, transient_data, script_txs_conf, LokadTxsConf::default());
     let bitcoind = instance.cli();
     let cache = IndexMemData::new(10);
     let mut slp_indexer = SlpIndexer::new(
@@ -126,7 +130,13 @@ async fn test_index_slp(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli) ->
     let slp_error = slp_error.downcast::<BroadcastError>()?;
     assert_eq!(
         slp_error,
-        BroadcastError::InvalidSlpTx(SlpError::InvalidMintBatonIdx { actual: 1 }),
+        BroadcastError::InvalidSlpTx(
+            SlpError::InvalidMintBatonIdx { actual: 1 },
+            SlpCulprits {
+                input_idxs: vec![],
+                output_idxs: vec![1],
+            },
+        ),
     );
 
     let tx1 = build_tx(
@@ -210,6 +220,9 @@ async fn test_index_slp(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli) ->
     let txid2 = slp_indexer.broadcast().broadcast_tx(&tx2, true).await?;
     let token_id2 = TokenId::new(txid2.clone());
     slp_indexer.process_next_msg()?;
+    // Tokens only in the mempool have no activity heights
+    assert_eq!(slp_indexer.tokens().active_heights(&token_id1)?, None);
+    assert_eq!(slp_indexer.tokens().active_heights(&token_id2)?, None);
 
     let mut tx_burn = build_tx(
         OutPoint {
@@ -278,6 +291,22 @@ async fn test_index_slp(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli) ->
         ),
     );
 
+    // Burns of another token and of a mint baton are not explicit burns
+    let slp_error = slp_indexer
+        .broadcast()
+        .with_burn_policy(SlpBurnPolicy::AllowExplicitBurn)
+        .test_mempool_accept(&tx_burn, true)
+        .await?
+        .unwrap_err();
+    assert!(matches!(slp_error, BroadcastError::InvalidSlpBurns(_)));
+    // With all burns allowed, the SLP check passes and bitcoind has the final say
+    let result = slp_indexer
+        .broadcast()
+        .with_burn_policy(SlpBurnPolicy::AllowAll)
+        .test_mempool_accept(&tx_burn, true)
+        .await?;
+    assert!(matches!(result, Err(BroadcastError::BitcoindRejectedTx(_))));
+
     let broadcast_error = slp_indexer
         .broadcast()
         .broadcast_tx(&tx_burn, false)
@@ -291,6 +320,65 @@ async fn test_index_slp(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli) ->
         ),
     );
 
+    // SEND more than the inputs hold; no input or output in particular is at fault
+    let tx_overspend = build_tx(
+        OutPoint {
+            txid: txid1.clone(),
+            out_idx: 1,
+        },
+        &anyone_script,
+        vec![TxOutput {
+            value: 0,
+            script: send_opreturn(&token_id1, SlpTokenType::Fungible, &[SlpAmount::new(250)]),
+        }],
+    );
+    let slp_error = slp_indexer
+        .broadcast()
+        .broadcast_tx(&tx_overspend, true)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        slp_error.downcast::<BroadcastError>()?,
+        BroadcastError::InvalidSlpTx(
+            SlpError::OutputSumExceedInputSum {
+                input_sum: SlpAmount::new(200),
+                output_sum: SlpAmount::new(250),
+            },
+            SlpCulprits::default(),
+        ),
+    );
+
+    // SEND of token 2 spending an output of token 1
+    let tx_wrong_token = build_tx(
+        OutPoint {
+            txid: txid1.clone(),
+            out_idx: 1,
+        },
+        &anyone_script,
+        vec![TxOutput {
+            value: 0,
+            script: send_opreturn(&token_id2, SlpTokenType::Fungible, &[SlpAmount::new(100)]),
+        }],
+    );
+    let slp_error = slp_indexer
+        .broadcast()
+        .test_mempool_accept(&tx_wrong_token, true)
+        .await?
+        .unwrap_err();
+    assert_eq!(
+        slp_error,
+        BroadcastError::InvalidSlpTx(
+            SlpError::OutputSumExceedInputSum {
+                input_sum: SlpAmount::ZERO,
+                output_sum: SlpAmount::new(100),
+            },
+            SlpCulprits {
+                input_idxs: vec![0],
+                output_idxs: vec![],
+            },
+        ),
+    );
+
     // mine previous txs
     let block_hashes = bitcoind.cmd_json("generatetoaddress", &["1", burn_address.as_str()])?;
     slp_indexer.process_next_msg()?;
@@ -318,8 +406,25 @@ async fn test_index_slp(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli) ->
         ],
     );
 
+    let mut token_receiver = slp_indexer.subscribers_mut().subscribe_to_token(&token_id1);
     let txid3 = slp_indexer.broadcast().broadcast_tx(&tx3, true).await?;
     slp_indexer.process_next_msg()?;
+    let token1_stats = TokenStats {
+        total_minted: 200,
+        total_burned: 0,
+        supply_overflowed: false,
+    };
+    assert_eq!(
+        token_receiver.try_recv()?,
+        SubscribeTokenMessage::AddedToMempool(txid3.clone()),
+    );
+    assert_eq!(
+        token_receiver.try_recv()?,
+        SubscribeTokenMessage::Stats {
+            token_stats: token1_stats.clone(),
+            unconfirmed_delta: TokenStats::default(),
+        },
+    );
 
     let rich_tx3 = RichTx {
         tx: tx3.clone().hashed(),
@@ -355,6 +460,18 @@ async fn test_index_slp(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli) ->
     // Mine tx3
     let block_hashes = bitcoind.cmd_json("generatetoaddress", &["1", burn_address.as_str()])?;
     slp_indexer.process_next_msg()?;
+    assert_eq!(
+        token_receiver.try_recv()?,
+        SubscribeTokenMessage::Confirmed(txid3.clone()),
+    );
+    assert_eq!(
+        token_receiver.try_recv()?,
+        SubscribeTokenMessage::Stats {
+            token_stats: token1_stats,
+            unconfirmed_delta: TokenStats::default(),
+        },
+    );
+    std::mem::drop(token_receiver);
 
     // Invalidate last block
     let block_hash2 = block_hashes[0].as_str().unwrap();
@@ -412,5 +529,190 @@ async fn test_index_slp(slp_indexer: &mut SlpIndexer, bitcoind: &BitcoinCli) ->
         })
     );
 
+    // MINTs of token1 across blocks and the mempool; the SEND tx3 isn't listed
+    let build_mint_tx = |baton_outpoint: OutPoint, amount: u64, value: i64| {
+        build_tx(
+            baton_outpoint,
+            &anyone_script,
+            vec![
+                TxOutput {
+                    value: 0,
+                    script: mint_opreturn(&token_id1, SlpTokenType::Fungible, Some(2), amount),
+                },
+                TxOutput {
+                    value,
+                    script: anyone_script.to_p2sh(),
+                },
+                TxOutput {
+                    value,
+                    script: anyone_script.to_p2sh(),
+                },
+            ],
+        )
+    };
+    let mint_tx1 = build_mint_tx(
+        OutPoint {
+            txid: token_id1.hash().clone(),
+            out_idx: 2,
+        },
+        50,
+        3_000,
+    );
+    let mint_txid1 = slp_indexer
+        .broadcast()
+        .broadcast_tx(&mint_tx1, true)
+        .await?;
+    slp_indexer.process_next_msg()?;
+    bitcoind.cmd_json("generatetoaddress", &["1", burn_address.as_str()])?;
+    slp_indexer.process_next_msg()?;
+    let mint_tx2 = build_mint_tx(
+        OutPoint {
+            txid: mint_txid1.clone(),
+            out_idx: 2,
+        },
+        25,
+        1_000,
+    );
+    let mint_txid2 = slp_indexer
+        .broadcast()
+        .broadcast_tx(&mint_tx2, true)
+        .await?;
+    slp_indexer.process_next_msg()?;
+    // Mempool MINT first, then the confirmed one
+    let expected_mints = [(&mint_txid2, 25, false), (&mint_txid1, 50, true)];
+    check_mints(slp_indexer, &token_id1, 0, 10, &expected_mints, 1)?;
+    check_mints(slp_indexer, &token_id1, 1, 1, &expected_mints[1..], 2)?;
+    check_mints(slp_indexer, &token_id1, 2, 1, &[], 2)?;
+    // GENESIS at 111, SEND tx3 at 112, MINT mint_tx1 at 113; mint_tx2 in the mempool isn't counted
+    assert_eq!(
+        slp_indexer.tokens().active_heights(&token_id1)?,
+        Some((111, 113)),
+    );
+    bitcoind.cmd_json("generatetoaddress", &["1", burn_address.as_str()])?;
+    slp_indexer.process_next_msg()?;
+    let expected_mints = [(&mint_txid2, 25, true), (&mint_txid1, 50, true)];
+    check_mints(slp_indexer, &token_id1, 0, 10, &expected_mints, 1)?;
+    // token2 has never been minted after its GENESIS
+    check_mints(slp_indexer, &token_id2, 0, 10, &[], 0)?;
+    assert_eq!(
+        slp_indexer.tokens().active_heights(&token_id1)?,
+        Some((111, 114)),
+    );
+    // token2 has no confirmed spends, so it was last active at its GENESIS
+    assert_eq!(
+        slp_indexer.tokens().active_heights(&token_id2)?,
+        Some((111, 111)),
+    );
+
+    // The anyone script received token1 from the GENESIS, sent it all to recv1 in tx3, then
+    // received both MINTs; the GENESIS of token2 and the mint batons don't count
+    let expected_flows = [
+        (token_id1.hash(), 111, 200, 0),
+        (&txid3, 112, 0, 200),
+        (&mint_txid1, 113, 50, 0),
+        (&mint_txid2, 114, 25, 0),
+    ];
+    check_token_flows(
+        slp_indexer,
+        anyone_slice,
+        &token_id1,
+        (0, 200),
+        (0, 10),
+        &expected_flows,
+        1,
+    )?;
+    check_token_flows(
+        slp_indexer,
+        anyone_slice,
+        &token_id1,
+        (112, 113),
+        (0, 10),
+        &expected_flows[1..3],
+        1,
+    )?;
+    check_token_flows(
+        slp_indexer,
+        anyone_slice,
+        &token_id1,
+        (0, 200),
+        (1, 3),
+        &expected_flows[3..],
+        2,
+    )?;
+    check_token_flows(
+        slp_indexer,
+        recv1_hash.as_slice(),
+        &token_id1,
+        (0, 200),
+        (0, 10),
+        &[(&txid3, 112, 200, 0)],
+        1,
+    )?;
+    check_token_flows(
+        slp_indexer,
+        anyone_slice,
+        &token_id2,
+        (0, 200),
+        (0, 10),
+        &[(token_id2.hash(), 111, 300, 0)],
+        1,
+    )?;
+
+    Ok(())
+}
+
+/// Checks a page of token flows of a P2SH script as (txid, height, received, sent) and the
+/// number of pages.
+fn check_token_flows(
+    slp_indexer: &SlpIndexer,
+    payload: &[u8],
+    token_id: &TokenId,
+    (start_height, end_height): (i32, i32),
+    (page_num, page_size): (usize, usize),
+    expected_flows: &[(&Sha256d, i32, i128, i128)],
+    expected_num_pages: usize,
+) -> Result<()> {
+    let (flows, num_pages) = slp_indexer
+        .script_history()
+        .token_flows_page_in_height_range(
+            PayloadPrefix::P2SH,
+            payload,
+            token_id,
+            start_height,
+            end_height,
+            page_num,
+            page_size,
+        )?;
+    assert_eq!(
+        flows
+            .iter()
+            .map(|flow| (&flow.txid, flow.block.height, flow.received, flow.sent))
+            .collect::<Vec<_>>(),
+        expected_flows.to_vec(),
+    );
+    assert_eq!(num_pages, expected_num_pages);
+    Ok(())
+}
+
+/// Checks a page of MINTs as (txid, amount, is_confirmed) and the number of pages.
+fn check_mints(
+    slp_indexer: &SlpIndexer,
+    token_id: &TokenId,
+    page_num: usize,
+    page_size: usize,
+    expected_mints: &[(&Sha256d, i128, bool)],
+    expected_num_pages: usize,
+) -> Result<()> {
+    let (mints, num_pages) = slp_indexer
+        .tokens()
+        .rev_mints_page(token_id, page_num, page_size)?;
+    assert_eq!(
+        mints
+            .iter()
+            .map(|mint| (&mint.tx.txid, mint.amount, mint.tx.block.is_some()))
+            .collect::<Vec<_>>(),
+        expected_mints.to_vec(),
+    );
+    assert_eq!(num_pages, expected_num_pages);
     Ok(())
 }
@@ -0,0 +1,87 @@
+use bitcoinsuite_error::Result;
+use chronik_indexer::{format_token_amount, parse_token_amount, TokensError};
+use pretty_assertions::assert_eq;
+
+#[test]
+fn test_parse_token_amount() -> Result<()> {
+    assert_eq!(parse_token_amount("0", 0)?, 0);
+    assert_eq!(parse_token_amount("123", 0)?, 123);
+    assert_eq!(parse_token_amount("123", 2)?, 12300);
+    assert_eq!(parse_token_amount("1.5", 2)?, 150);
+    assert_eq!(parse_token_amount("1.05", 2)?, 105);
+    assert_eq!(parse_token_amount("0.01", 2)?, 1);
+    assert_eq!(parse_token_amount("007.10", 2)?, 710);
+    assert_eq!(parse_token_amount("0.000000001", 9)?, 1);
+    // Trailing zeros beyond the decimals don't change the amount
+    assert_eq!(parse_token_amount("1.500000", 2)?, 150);
+    assert_eq!(parse_token_amount("5.0", 0)?, 5);
+    // Largest amounts fitting into u64
+    assert_eq!(parse_token_amount("18446744073709551615", 0)?, u64::MAX);
+    assert_eq!(parse_token_amount("18446744073.709551615", 9)?, u64::MAX);
+    assert_eq!(
+        parse_token_amount("0000000000000000000000018446744073709551615", 0)?,
+        u64::MAX,
+    );
+
+    let check_err = |amount: &str, decimals: u32, expected: TokensError| {
+        let report = parse_token_amount(amount, decimals).unwrap_err();
+        let err = report.downcast::<TokensError>().unwrap();
+        assert_eq!(err.to_string(), expected.to_string());
+    };
+    // Amounts finer than a base unit are rejected, not rounded
+    for (amount, decimals) in [("1.005", 2), ("0.5", 0), ("1.0000000001", 9)] {
+        check_err(
+            amount,
+            decimals,
+            TokensError::TooManyDecimals {
+                amount: amount.to_string(),
+                decimals,
+            },
+        );
+    }
+    for (amount, decimals) in [
+        ("18446744073709551616", 0),
+        ("18446744073.709551616", 9),
+        ("18446744073709551615", 1),
+        ("99999999999999999999999999999", 0),
+    ] {
+        check_err(
+            amount,
+            decimals,
+            TokensError::TokenAmountTooLarge(amount.to_string()),
+        );
+    }
+    for amount in [
+        "", ".", ".5", "1.", "1.2.3", "-1", "+1", "1e5", " 1", "1,5", "1.-5",
+    ] {
+        check_err(
+            amount,
+            2,
+            TokensError::InvalidTokenAmount(amount.to_string()),
+        );
+    }
+    check_err("1", 10, TokensError::UnsupportedDecimals(10));
+    Ok(())
+}
+
+#[test]
+fn test_format_token_amount() -> Result<()> {
+    assert_eq!(format_token_amount(0, 0)?, "0");
+    assert_eq!(format_token_amount(123, 0)?, "123");
+    assert_eq!(format_token_amount(0, 2)?, "0.00");
+    assert_eq!(format_token_amount(1, 2)?, "0.01");
+    assert_eq!(format_token_amount(150, 2)?, "1.50");
+    assert_eq!(format_token_amount(12300, 2)?, "123.00");
+    assert_eq!(format_token_amount(1, 9)?, "0.000000001");
+    assert_eq!(format_token_amount(u64::MAX, 9)?, "18446744073.709551615");
+    assert!(format_token_amount(1, 10).is_err());
+
+    // Formatting and parsing are inverses
+    for decimals in 0..=9 {
+        for base_units in [0, 1, 10, 999, 1_000_000_007, u64::MAX] {
+            let display = format_token_amount(base_units, decimals)?;
+            assert_eq!(parse_token_amount(&display, decimals)?, base_units);
+        }
+    }
+    Ok(())
+}
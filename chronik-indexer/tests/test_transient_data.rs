@@ -11,7 +11,7 @@ use bitcoinsuite_error::Result;
 use bitcoinsuite_test_utils::bin_folder;
 use bitcoinsuite_test_utils_blockchain::{build_tx, setup_bitcoind_coins};
 use chronik_indexer::{run_transient_data_catchup, SlpIndexer};
-use chronik_rocksdb::{Db, IndexDb, IndexMemData, ScriptTxsConf, TransientData};
+use chronik_rocksdb::{Db, IndexDb, IndexMemData, LokadTxsConf, ScriptTxsConf, TransientData};
 use pretty_assertions::assert_eq;
 use tempdir::TempDir;
 use tokio::sync::RwLock;
@@ -41,7 +41,7 @@ async fn test_transient_data() -> Result<()> {
     let script_txs_conf = ScriptTxsConf { page_size: 7 };
     let db = Db::open(dir.path().join("index.rocksdb"))?;
     let transient_data = TransientData::open(&dir.path().join("transient.rocksdb"))?;
-    let db = IndexDb::new(db, transient_data, script_txs_conf);
+    let db = IndexDb::new(db, transient_data, script_txs_conf, LokadTxsConf::default());
     let bitcoind = instance.cli();
     let cache = IndexMemData::new(10);
     let mut slp_indexer = SlpIndexer::new(
@@ -223,7 +223,12 @@ async fn test_transient_data() -> Result<()> {
     let script_txs_conf = ScriptTxsConf { page_size: 7 };
     let reindex_db = Db::open(dir.path().join("reindex.rocksdb"))?;
     let transient_data = TransientData::open(&dir.path().join("transient.rocksdb"))?;
-    let db = IndexDb::new(reindex_db, transient_data, script_txs_conf);
+    let db = IndexDb::new(
+        reindex_db,
+        transient_data,
+        script_txs_conf,
+        LokadTxsConf::default(),
+    );
     let mut slp_indexer = SlpIndexer::new(
         db,
         instance.rpc_client().clone(),
@@ -1,13 +1,14 @@
 use std::time::Instant;
 
 use bitcoinsuite_core::{
-    build_bitcoin_block, build_bitcoin_coinbase, BitcoinCode, Bytes, Op, OutPoint, Script,
-    SequenceNo, Sha256d, TxInput, TxOutput, UnhashedTx,
+    build_bitcoin_block, build_bitcoin_coinbase, BitcoinCode, Bytes, Hashed, Op, OutPoint, Script,
+    SequenceNo, Sha256d, ShaRmd160, TxInput, TxOutput, UnhashedTx,
 };
 use bitcoinsuite_error::Result;
 use bitcoinsuite_test_utils_blockchain::build_tx;
 use chronik_rocksdb::{
-    Block, BlockTxs, Db, IndexDb, IndexMemData, ScriptTxsConf, TransientData, TxEntry,
+    Block, BlockTxs, Db, DbConf, IndexDb, IndexMemData, LokadTxsConf, PayloadPrefix, ScriptTxsConf,
+    TransientData, TxEntry,
 };
 use rand::{distributions::WeightedIndex, prelude::Distribution, Rng, SeedableRng};
 use tempdir::TempDir;
@@ -15,6 +16,9 @@ use tempdir::TempDir;
 fn main() -> Result<()> {
     let num_blocks = 200;
     let cache_size = 10_000;
+    let db_conf = DbConf {
+        payload_prefix_bloom: std::env::args().any(|arg| arg == "--payload-prefix-bloom"),
+    };
     let mut blocks = Vec::new();
 
     let anyone_script = Script::from_slice(&[0x51]);
@@ -180,11 +184,12 @@ fn main() -> Result<()> {
     bitcoinsuite_error::install()?;
     println!("Inserting blocks...");
     println!("Cache size: {}", cache_size);
+    println!("DB conf: {:?}", db_conf);
     let dir = TempDir::new("chronik-rocksdb-bench")?;
     let script_txs_conf = ScriptTxsConf { page_size: 1000 };
-    let db = Db::open(dir.path().join("index.rocksdb"))?;
+    let db = Db::open_with_conf(dir.path().join("index.rocksdb"), &db_conf)?;
     let transient_data = TransientData::open(&dir.path().join("transient.rocksdb"))?;
-    let db = IndexDb::new(db, transient_data, script_txs_conf);
+    let db = IndexDb::new(db, transient_data, script_txs_conf, LokadTxsConf::default());
     let mut data = IndexMemData::new(cache_size);
     let t = Instant::now();
     for (block_height, (block, block_spent_scripts)) in blocks.iter().enumerate() {
@@ -235,6 +240,24 @@ fn main() -> Result<()> {
     println!("{}", timings.script_txs_timings);
     println!("UTXOs:");
     println!("{}", timings.utxos_timings);
+    std::mem::drop(timings);
+
+    println!("Reading scripts...");
+    let script_txs_reader = db.script_txs()?;
+    let utxos_reader = db.utxos()?;
+    let t = Instant::now();
+    let mut num_reads = 0;
+    for script_counter in (100..=counter).step_by(10) {
+        let script = script_from_counter(script_counter);
+        let script_hash = ShaRmd160::digest(script.bytecode().clone());
+        let payload = script_hash.as_slice();
+        script_txs_reader.num_pages_by_payload(PayloadPrefix::P2SH, payload)?;
+        script_txs_reader.page_txs(0, PayloadPrefix::P2SH, payload)?;
+        utxos_reader.utxos(PayloadPrefix::P2SH, payload)?;
+        num_reads += 1;
+    }
+    let dt = t.elapsed();
+    println!("Read {} scripts, took {:?}", num_reads, dt);
 
     Ok(())
 }
@@ -1,10 +1,13 @@
-use std::path::Path;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use rocksdb::{ColumnFamily, ColumnFamilyDescriptor, Options, WriteBatch};
 
 use crate::{
-    BlockStatsWriter, BlockWriter, DbSchema, ScriptTxsWriter, SlpWriter, SpendsWriter, TxWriter,
-    UtxosWriter,
+    replica::Replica, BlockStatsWriter, BlockWriter, DbSchema, LokadTxsWriter, MetaWriter,
+    ScriptTxsWriter, SlpWriter, SpendsWriter, TxWriter, UtxosWriter,
 };
 use bitcoinsuite_error::{ErrorMeta, Result, WrapErr};
 use thiserror::Error;
@@ -12,7 +15,28 @@ use thiserror::Error;
 pub type CF = ColumnFamily;
 
 pub struct Db {
-    db: rocksdb::DB,
+    db: Arc<rocksdb::DB>,
+    replica: Option<Replica>,
+}
+
+/// Handle to compact a `Db` from another thread, e.g. in the background while indexing.
+#[derive(Clone)]
+pub struct DbCompactor {
+    db: Arc<rocksdb::DB>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct DbConf {
+    /// Use bloom filters for the CFs keyed by script payload (script_txs and utxos); script_txs
+    /// also gets a prefix extractor covering the whole script payload of its keys.
+    pub payload_prefix_bloom: bool,
+    /// Also apply every write batch to a second DB at this path, e.g. as a warm standby that can
+    /// be opened with `Db::open_read_only`. The replica is written after the primary, so it's not
+    /// atomic with it: if the process dies in between, the replica lags one batch behind and
+    /// has to be re-synced (e.g. by copying the primary). Each write also takes about twice as
+    /// long, which slows down initial sync. Opening fails if the replica isn't on the same
+    /// schema version and tip as the primary.
+    pub replica_path: Option<PathBuf>,
 }
 
 #[derive(Debug, Error, ErrorMeta)]
@@ -24,22 +48,56 @@ pub enum DbError {
     #[critical()]
     #[error("RocksDB error")]
     RocksDb,
+
+    #[critical()]
+    #[error("RocksDB error writing to the replica")]
+    RocksDbReplica,
 }
 
 use self::DbError::*;
 
 impl Db {
     pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::open_with_conf(path, &DbConf::default())
+    }
+
+    pub fn open_with_conf(path: impl AsRef<Path>, conf: &DbConf) -> Result<Self> {
+        let mut db = Self::open_with_cfs(path.as_ref(), Self::cf_descriptors(conf))?;
+        if let Some(replica_path) = &conf.replica_path {
+            let replica = Self::open_with_cfs(replica_path, Self::cf_descriptors(conf))?;
+            db.replica = Some(Replica::new(&db, path.as_ref(), replica)?);
+        }
+        Ok(db)
+    }
+
+    /// Opens a DB (e.g. a replica) read-only; it won't see writes made after opening it.
+    pub fn open_read_only(path: impl AsRef<Path>, conf: &DbConf) -> Result<Self> {
+        let db = rocksdb::DB::open_cf_descriptors_read_only(
+            &Options::default(),
+            path,
+            Self::cf_descriptors(conf),
+            false,
+        )
+        .wrap_err(RocksDb)?;
+        Ok(Db {
+            db: Arc::new(db),
+            replica: None,
+        })
+    }
+
+    fn cf_descriptors(conf: &DbConf) -> Vec<ColumnFamilyDescriptor> {
         let mut cfs = Vec::new();
         DbSchema::add_cfs(&mut cfs);
         BlockWriter::add_cfs(&mut cfs);
         BlockStatsWriter::add_cfs(&mut cfs);
         TxWriter::add_cfs(&mut cfs);
-        ScriptTxsWriter::add_cfs(&mut cfs);
-        UtxosWriter::add_cfs(&mut cfs);
+        ScriptTxsWriter::add_cfs(&mut cfs, conf);
+        UtxosWriter::add_cfs(&mut cfs, conf);
         SpendsWriter::add_cfs(&mut cfs);
         SlpWriter::add_cfs(&mut cfs);
-        Self::open_with_cfs(path, cfs)
+        LokadTxsWriter::add_cfs(&mut cfs);
+        MetaWriter::add_cfs(&mut cfs);
+        cfs
     }
 
     pub fn open_with_cfs(path: impl AsRef<Path>, cfs: Vec<ColumnFamilyDescriptor>) -> Result<Self> {
@@ -47,13 +105,22 @@ impl Db {
         db_options.create_if_missing(true);
         db_options.create_missing_column_families(true);
         let db = rocksdb::DB::open_cf_descriptors(&db_options, path, cfs).wrap_err(RocksDb)?;
-        Ok(Db { db })
+        Ok(Db {
+            db: Arc::new(db),
+            replica: None,
+        })
     }
 
     pub fn rocks(&self) -> &rocksdb::DB {
         &self.db
     }
 
+    pub fn compactor(&self) -> DbCompactor {
+        DbCompactor {
+            db: Arc::clone(&self.db),
+        }
+    }
+
     pub fn cf(&self, name: &str) -> Result<&CF> {
         Ok(self
             .db
@@ -66,6 +133,172 @@ impl Db {
     }
 
     pub fn write_batch(&self, batch: WriteBatch) -> Result<()> {
-        self.db.write(batch).wrap_err(RocksDb)
+        let replica_batch = match &self.replica {
+            Some(replica) => Some((replica, replica.replica_batch(&batch)?)),
+            None => None,
+        };
+        self.db.write(batch).wrap_err(RocksDb)?;
+        if let Some((replica, replica_batch)) = replica_batch {
+            replica.write(replica_batch)?;
+        }
+        Ok(())
+    }
+}
+
+impl DbCompactor {
+    /// Compacts the whole key range of every CF, one after another, calling `on_cf_compacted`
+    /// with the CF name after each. A freshly restored DB reads slowly until compacted.
+    /// Blocks until done; writes can continue meanwhile. The replica isn't compacted.
+    pub fn compact_all(&self, mut on_cf_compacted: impl FnMut(&str)) -> Result<()> {
+        let cf_names =
+            rocksdb::DB::list_cf(&Options::default(), self.db.path()).wrap_err(RocksDb)?;
+        for cf_name in &cf_names {
+            let cf = self
+                .db
+                .cf_handle(cf_name)
+                .ok_or_else(|| NoSuchColumnFamily(cf_name.clone()))?;
+            self.db.compact_range_cf(cf, None::<&[u8]>, None::<&[u8]>);
+            on_cf_compacted(cf_name);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoinsuite_error::Result;
+    use pretty_assertions::assert_eq;
+    use rocksdb::{ColumnFamilyDescriptor, Options, WriteBatch};
+
+    use crate::{blocks::CF_BLOCKS, Db, DbConf, DbSchema, ReplicaError, CF_LOKAD_TXS};
+
+    #[test]
+    fn test_replica() -> Result<()> {
+        bitcoinsuite_error::install()?;
+        let tempdir = tempdir::TempDir::new("slp-indexer-rocks--replica")?;
+        let replica_path = tempdir.path().join("replica");
+        let conf = DbConf {
+            replica_path: Some(replica_path.clone()),
+            ..Default::default()
+        };
+        let db = Db::open_with_conf(tempdir.path().join("primary"), &conf)?;
+        let mut batch = WriteBatch::default();
+        let cf = db.cf(CF_BLOCKS)?;
+        batch.put_cf(cf, b"key", b"value");
+        db.write_batch(batch)?;
+        assert_eq!(db.get(cf, b"key")?.as_deref(), Some(b"value".as_ref()));
+
+        let replica = Db::open_read_only(&replica_path, &conf)?;
+        let replica_cf = replica.cf(CF_BLOCKS)?;
+        assert_eq!(
+            replica.get(replica_cf, b"key")?.as_deref(),
+            Some(b"value".as_ref()),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_replica_cf_ids() -> Result<()> {
+        bitcoinsuite_error::install()?;
+        let tempdir = tempdir::TempDir::new("slp-indexer-rocks--replica-cf-ids")?;
+        let replica_path = tempdir.path().join("replica");
+        // Create the replica's CFs in another order, so their IDs differ from the primary's
+        Db::open_with_cfs(
+            &replica_path,
+            vec![ColumnFamilyDescriptor::new(
+                CF_LOKAD_TXS,
+                Options::default(),
+            )],
+        )?;
+        let conf = DbConf {
+            replica_path: Some(replica_path.clone()),
+            ..Default::default()
+        };
+        let db = Db::open_with_conf(tempdir.path().join("primary"), &conf)?;
+        let mut batch = WriteBatch::default();
+        batch.put_cf(db.cf(CF_BLOCKS)?, b"key", b"blocks");
+        batch.put_cf(db.cf(CF_LOKAD_TXS)?, b"key", b"lokad");
+        batch.delete_cf(db.cf(CF_LOKAD_TXS)?, b"deleted");
+        db.write_batch(batch)?;
+        std::mem::drop(db);
+
+        let replica = Db::open_read_only(&replica_path, &conf)?;
+        assert_eq!(
+            replica.get(replica.cf(CF_BLOCKS)?, b"key")?.as_deref(),
+            Some(b"blocks".as_ref()),
+        );
+        assert_eq!(
+            replica.get(replica.cf(CF_LOKAD_TXS)?, b"key")?.as_deref(),
+            Some(b"lokad".as_ref()),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_replica_out_of_sync() -> Result<()> {
+        bitcoinsuite_error::install()?;
+        let tempdir = tempdir::TempDir::new("slp-indexer-rocks--replica-out-of-sync")?;
+        let primary_path = tempdir.path().join("primary");
+        let replica_path = tempdir.path().join("replica");
+        // Primary set up without the replica
+        let db = Db::open(&primary_path)?;
+        DbSchema::new(&db)?.check_db_version()?;
+        std::mem::drop(db);
+
+        let conf = DbConf {
+            replica_path: Some(replica_path.clone()),
+            ..Default::default()
+        };
+        let err = match Db::open_with_conf(&primary_path, &conf) {
+            Ok(_) => panic!("Opened out-of-sync replica"),
+            Err(report) => report.downcast::<ReplicaError>()?,
+        };
+        assert!(matches!(
+            err,
+            ReplicaError::ReplicaOutOfSync {
+                field: "schema version",
+                ..
+            },
+        ));
+
+        // The schema version is written through the replica too
+        std::fs::remove_dir_all(&primary_path)?;
+        std::fs::remove_dir_all(&replica_path)?;
+        let db = Db::open_with_conf(&primary_path, &conf)?;
+        DbSchema::new(&db)?.check_db_version()?;
+        std::mem::drop(db);
+        let db = Db::open_with_conf(&primary_path, &conf)?;
+        DbSchema::new(&db)?.check_db_version()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_compact_all() -> Result<()> {
+        bitcoinsuite_error::install()?;
+        let tempdir = tempdir::TempDir::new("slp-indexer-rocks--compact")?;
+        let db = Db::open(tempdir.path())?;
+        let cf = db.cf(CF_BLOCKS)?;
+        for idx in 0u32..100 {
+            let mut batch = WriteBatch::default();
+            batch.put_cf(cf, idx.to_be_bytes(), b"value");
+            batch.delete_cf(cf, idx.wrapping_sub(1).to_be_bytes());
+            db.write_batch(batch)?;
+        }
+        let compactor = db.compactor();
+        let compacted_cfs = std::thread::spawn(move || -> Result<_> {
+            let mut compacted_cfs = Vec::new();
+            compactor.compact_all(|cf_name| compacted_cfs.push(cf_name.to_string()))?;
+            Ok(compacted_cfs)
+        })
+        .join()
+        .unwrap()?;
+        assert!(compacted_cfs.iter().any(|cf_name| cf_name == CF_BLOCKS));
+        assert!(compacted_cfs.iter().any(|cf_name| cf_name == "default"));
+        assert_eq!(
+            db.get(cf, 99u32.to_be_bytes())?.as_deref(),
+            Some(b"value".as_ref())
+        );
+        assert_eq!(db.get(cf, 98u32.to_be_bytes())?.as_deref(), None);
+        Ok(())
     }
 }
@@ -1,14 +1,14 @@
-use bitcoinsuite_error::{ErrorMeta, Result, WrapErr};
+use bitcoinsuite_error::{ErrorMeta, Result};
 use byteorder::BE;
-use rocksdb::ColumnFamilyDescriptor;
+use rocksdb::{ColumnFamilyDescriptor, WriteBatch};
 use thiserror::Error;
 use zerocopy::{AsBytes, U64};
 
-use crate::{data::interpret, Db, DbError, CF};
+use crate::{data::interpret, Db, CF};
 
 pub const CF_SCHEMA: &str = "schema";
 
-pub const DB_SCHEMA_VERSION: DbVersionNum = 100;
+pub const DB_SCHEMA_VERSION: DbVersionNum = 106;
 
 const FIELD_VERSION: &[u8] = b"version";
 
@@ -57,11 +57,17 @@ impl<'a> DbSchema<'a> {
         Ok(DbSchema { db, cf_schema })
     }
 
+    /// The schema version the DB is on, `None` if it hasn't been set yet.
+    pub fn version(&self) -> Result<Option<DbVersionNum>> {
+        match self.db.get(self.cf_schema, FIELD_VERSION)? {
+            Some(version_slice) => Ok(Some(interpret::<DbVersionNumZC>(&version_slice)?.get())),
+            None => Ok(None),
+        }
+    }
+
     pub fn check_db_version(&self) -> Result<()> {
-        let version_slice = self.db.get(self.cf_schema, FIELD_VERSION)?;
-        match version_slice {
-            Some(version_slice) => {
-                let version = interpret::<DbVersionNumZC>(&version_slice)?.get();
+        match self.version()? {
+            Some(version) => {
                 if version < DB_SCHEMA_VERSION {
                     return Err(DbTooOld {
                         actual: version,
@@ -78,11 +84,11 @@ impl<'a> DbSchema<'a> {
                 }
             }
             None => {
+                // Through a batch, so it's written to the replica too
                 let version = DbVersionNumZC::new(DB_SCHEMA_VERSION);
-                self.db
-                    .rocks()
-                    .put_cf(self.cf_schema, FIELD_VERSION, version.as_bytes())
-                    .wrap_err(DbError::RocksDb)?;
+                let mut batch = WriteBatch::default();
+                batch.put_cf(self.cf_schema, FIELD_VERSION, version.as_bytes());
+                self.db.write_batch(batch)?;
             }
         }
         Ok(())
@@ -5,16 +5,19 @@ use std::{
 
 use bitcoinsuite_core::{Sha256d, TxOutput, UnhashedTx};
 use bitcoinsuite_error::{ErrorMeta, Result};
-use bitcoinsuite_slp::{SlpError, SlpValidTxData};
+use bitcoinsuite_slp::{SlpError, SlpSpentOutput, SlpValidTxData};
 use rocksdb::WriteBatch;
+use serde::{de::DeserializeOwned, Serialize};
 use thiserror::Error;
 
 use crate::{
     input_tx_nums::fetch_input_tx_nums, Block, BlockHeight, BlockReader, BlockStatsReader,
-    BlockStatsWriter, BlockTxs, BlockWriter, Db, DbSchema, MempoolData, MempoolDeleteMode,
-    MempoolSlpData, MempoolTxEntry, MempoolWriter, ScriptTxsConf, ScriptTxsReader, ScriptTxsWriter,
-    ScriptTxsWriterCache, SlpReader, SlpWriter, SpendsReader, SpendsWriter, Timings, TransientData,
-    TransientDataWriter, TxReader, TxWriter, UtxosReader, UtxosWriter,
+    BlockStatsWriter, BlockTxs, BlockWriteSize, BlockWriteSizes, BlockWriter, Db, DbSchema,
+    LokadId, LokadTxsConf, LokadTxsReader, LokadTxsWriter, MempoolData, MempoolDeleteMode,
+    MempoolSlpData, MempoolTxEntry, MempoolWriter, MetaKey, MetaReader, MetaWriter, ScriptFilter,
+    ScriptPayload, ScriptTxsCacheStats, ScriptTxsConf, ScriptTxsReader, ScriptTxsWriter,
+    ScriptTxsWriterCache, SlpConf, SlpReader, SlpWriter, SpendsReader, SpendsWriter, Timings,
+    TransientData, TransientDataWriter, TxReader, TxWriter, UtxosReader, UtxosWriter,
 };
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
@@ -28,9 +31,27 @@ pub struct IndexDb {
     db: Db,
     transient_data: TransientData,
     timings: RwLock<IndexTimings>,
+    write_sizes: RwLock<BlockWriteSizes>,
     script_txs_conf: ScriptTxsConf,
+    lokad_txs_conf: LokadTxsConf,
+    script_filter: ScriptFilter,
+    slp_conf: SlpConf,
+    has_spends_index: bool,
 }
 
+/// Whether the index was built with the spends index, see `IndexDb::with_spends_index`.
+const META_HAS_SPENDS_INDEX: MetaKey<bool> = MetaKey::new("has_spends_index");
+
+/// Whether the index treats unknown SLP token types as non-SLP, see `IndexDb::with_slp_conf`.
+const META_IGNORE_UNKNOWN_TOKEN_TYPES: MetaKey<bool> = MetaKey::new("ignore_unknown_token_types");
+
+/// Sorted LOKAD IDs indexed in the lokad_txs CF, see `LokadTxsConf::lokad_ids`.
+const META_LOKAD_IDS: MetaKey<Vec<LokadId>> = MetaKey::new("lokad_ids");
+
+/// Sorted payloads watched by the index's script filter, None if it indexes every script; see
+/// `IndexDb::with_script_filter`.
+const META_WATCHED_PAYLOADS: MetaKey<Option<Vec<ScriptPayload>>> = MetaKey::new("watched_payloads");
+
 pub struct IndexMemData {
     script_txs_cache: ScriptTxsWriterCache,
     mempool: MempoolData,
@@ -42,22 +63,204 @@ pub enum IndexDbError {
     #[critical()]
     #[error("Unknown block")]
     UnknownBlock(Sha256d),
+
+    #[critical()]
+    #[error("Block {0} is indexed at height {1}, but height {2} is being disconnected")]
+    DisconnectHeightMismatch(Sha256d, BlockHeight, BlockHeight),
+
+    #[critical()]
+    #[error("No first tx num for block at height {0}")]
+    MissingFirstTxNum(BlockHeight),
+
+    #[critical()]
+    #[error(
+        "Index was built with has_spends_index = {indexed}, but it's configured as \
+         {configured}; changing it requires reindexing"
+    )]
+    SpendsIndexMismatch { indexed: bool, configured: bool },
+
+    #[critical()]
+    #[error(
+        "Index was built with another script filter (watch-only mode or watched scripts) than \
+         configured; changing it requires reindexing"
+    )]
+    ScriptFilterMismatch,
+
+    #[critical()]
+    #[error(
+        "Index was built with ignore_unknown_token_types = {indexed}, but it's configured as \
+         {configured}; changing it requires reindexing"
+    )]
+    SlpConfMismatch { indexed: bool, configured: bool },
+
+    #[critical()]
+    #[error(
+        "Index was built with lokad_ids = [{indexed}], but it's configured as [{configured}]; \
+         changing them requires reindexing"
+    )]
+    LokadIdsMismatch { indexed: String, configured: String },
 }
 
 use self::IndexDbError::*;
 
 impl IndexDb {
-    pub fn new(db: Db, transient_data: TransientData, script_txs_conf: ScriptTxsConf) -> Self {
+    pub fn new(
+        db: Db,
+        transient_data: TransientData,
+        script_txs_conf: ScriptTxsConf,
+        lokad_txs_conf: LokadTxsConf,
+    ) -> Self {
         IndexDb {
             db,
             transient_data,
             timings: Default::default(),
+            write_sizes: Default::default(),
             script_txs_conf,
+            lokad_txs_conf,
+            script_filter: ScriptFilter::All,
+            slp_conf: SlpConf::default(),
+            has_spends_index: true,
         }
     }
 
+    /// Only index the script history and UTXOs of the scripts allowed by `script_filter`.
+    /// Changing the filter of an existing index requires a reindex, `check_db_version` refuses
+    /// to open an index built with another filter.
+    pub fn with_script_filter(mut self, script_filter: ScriptFilter) -> Self {
+        self.script_filter = script_filter;
+        self
+    }
+
+    pub fn script_filter(&self) -> &ScriptFilter {
+        &self.script_filter
+    }
+
+    /// How SLP txs are indexed; changing it for an existing index requires a reindex,
+    /// `check_db_version` refuses to open an index built with another conf.
+    pub fn with_slp_conf(mut self, slp_conf: SlpConf) -> Self {
+        self.slp_conf = slp_conf;
+        self
+    }
+
+    pub fn slp_conf(&self) -> &SlpConf {
+        &self.slp_conf
+    }
+
+    /// Whether to maintain the spends CF, which maps confirmed outputs to the inputs spending
+    /// them. Changing it for an existing index requires a reindex, `check_db_version` refuses
+    /// to open an index built with the other setting.
+    pub fn with_spends_index(mut self, has_spends_index: bool) -> Self {
+        self.has_spends_index = has_spends_index;
+        self
+    }
+
+    pub fn has_spends_index(&self) -> bool {
+        self.has_spends_index
+    }
+
     pub fn check_db_version(&self) -> Result<()> {
-        DbSchema::new(&self.db)?.check_db_version()
+        DbSchema::new(&self.db)?.check_db_version()?;
+        self.check_spends_index()?;
+        self.check_script_filter()?;
+        self.check_slp_conf()?;
+        self.check_lokad_ids()
+    }
+
+    /// Fails if the index was built with another spends index setting.
+    fn check_spends_index(&self) -> Result<()> {
+        // Indexes from before the setting was recorded always have the spends index
+        let indexed = self.indexed_setting(&META_HAS_SPENDS_INDEX, &self.has_spends_index, true)?;
+        if indexed != self.has_spends_index {
+            return Err(SpendsIndexMismatch {
+                indexed,
+                configured: self.has_spends_index,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Fails if the index was built with another script filter.
+    fn check_script_filter(&self) -> Result<()> {
+        let configured = match &self.script_filter {
+            ScriptFilter::All => None,
+            ScriptFilter::Watched(payloads) => {
+                let mut payloads = payloads.iter().cloned().collect::<Vec<_>>();
+                payloads.sort_unstable();
+                Some(payloads)
+            }
+        };
+        // Indexes from before the setting was recorded always index every script
+        let indexed = self.indexed_setting(&META_WATCHED_PAYLOADS, &configured, None)?;
+        if indexed != configured {
+            return Err(ScriptFilterMismatch.into());
+        }
+        Ok(())
+    }
+
+    /// Fails if the index was built with another SLP conf.
+    fn check_slp_conf(&self) -> Result<()> {
+        let configured = self.slp_conf.ignore_unknown_token_types;
+        // Indexes from before the setting was recorded index unknown token types
+        let indexed = self.indexed_setting(&META_IGNORE_UNKNOWN_TOKEN_TYPES, &configured, false)?;
+        if indexed != configured {
+            return Err(SlpConfMismatch {
+                indexed,
+                configured,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Fails if the index was built with another set of LOKAD IDs.
+    fn check_lokad_ids(&self) -> Result<()> {
+        let mut configured = self
+            .lokad_txs_conf
+            .lokad_ids
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        configured.sort_unstable();
+        // Indexes from before the setting was recorded don't index any LOKAD IDs
+        let indexed = self.indexed_setting(&META_LOKAD_IDS, &configured, vec![])?;
+        if indexed != configured {
+            let join_hex = |lokad_ids: &[LokadId]| {
+                lokad_ids
+                    .iter()
+                    .map(hex::encode)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            return Err(LokadIdsMismatch {
+                indexed: join_hex(&indexed),
+                configured: join_hex(&configured),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Value of the setting `key` the index was built with. If it hasn't been recorded yet, the
+    /// index is either new and gets built with `configured`, or it's from before the setting
+    /// was recorded and was built with `legacy`; that value is recorded and returned.
+    fn indexed_setting<T: Serialize + DeserializeOwned + Clone>(
+        &self,
+        key: &MetaKey<T>,
+        configured: &T,
+        legacy: T,
+    ) -> Result<T> {
+        if let Some(indexed) = MetaReader::new(&self.db)?.get(key)? {
+            return Ok(indexed);
+        }
+        let indexed = match self.blocks()?.tip()? {
+            Some(_) => legacy,
+            None => configured.clone(),
+        };
+        let mut batch = WriteBatch::default();
+        MetaWriter::new(&self.db)?.put(&mut batch, key, &indexed)?;
+        self.db.write_batch(batch)?;
+        Ok(indexed)
     }
 
     pub fn blocks(&self) -> Result<BlockReader> {
@@ -88,10 +291,23 @@ impl IndexDb {
         SlpReader::new(&self.db)
     }
 
+    pub fn meta(&self) -> Result<MetaReader> {
+        MetaReader::new(&self.db)
+    }
+
+    pub fn lokad_txs(&self) -> Result<LokadTxsReader> {
+        LokadTxsReader::new(&self.db, self.lokad_txs_conf.clone())
+    }
+
     pub fn timings(&self) -> RwLockReadGuard<IndexTimings> {
         self.timings.read().unwrap()
     }
 
+    /// Bytes written to the DB by the most recently connected blocks.
+    pub fn block_write_sizes(&self) -> RwLockReadGuard<BlockWriteSizes> {
+        self.write_sizes.read().unwrap()
+    }
+
     pub fn mempool<'a>(&self, data: &'a IndexMemData) -> &'a MempoolData {
         &data.mempool
     }
@@ -110,6 +326,15 @@ impl IndexDb {
         data.mempool_slp.validate_slp_tx(txid, tx, &spent_outputs)
     }
 
+    /// SLP data of the outputs spent by `tx`, from the mempool or the DB.
+    pub fn slp_spent_outputs(
+        &self,
+        data: &IndexMemData,
+        tx: &UnhashedTx,
+    ) -> Result<Vec<Option<SlpSpentOutput>>> {
+        data.mempool_slp.collect_spent_outputs(&self.db, tx)
+    }
+
     pub fn insert_block<'b>(
         &self,
         block: &Block,
@@ -122,17 +347,21 @@ impl IndexDb {
         let block_writer = BlockWriter::new(&self.db)?;
         let block_stats_writer = BlockStatsWriter::new(&self.db)?;
         let tx_writer = TxWriter::new(&self.db)?;
-        let script_txs_writer = ScriptTxsWriter::new(&self.db, self.script_txs_conf.clone())?;
-        let utxo_writer = UtxosWriter::new(&self.db)?;
+        let script_txs_writer =
+            ScriptTxsWriter::new(&self.db, self.script_txs_conf.clone(), &self.script_filter)?;
+        let utxo_writer = UtxosWriter::new(&self.db, &self.script_filter)?;
         let spends_writer = SpendsWriter::new(&self.db)?;
-        let slp_writer = SlpWriter::new(&self.db)?;
+        let slp_writer = SlpWriter::new(&self.db, self.slp_conf.clone())?;
+        let lokad_txs_writer = LokadTxsWriter::new(&self.db, self.lokad_txs_conf.clone())?;
         let mut batch = WriteBatch::default();
+        let mut write_size = BlockWriteSize::new(block.height);
 
         let txids_fn = |idx: usize| &block_txs.txs[idx].txid;
 
         timings.timings.start_timer();
         block_writer.insert(&mut batch, block)?;
         timings.timings.stop_timer("blocks");
+        write_size.add_batch_size("blocks", &batch);
 
         timings.timings.start_timer();
         block_stats_writer.insert_block_txs(
@@ -143,10 +372,12 @@ impl IndexDb {
             &block_spent_output_fn,
         )?;
         timings.timings.stop_timer("block_stats");
+        write_size.add_batch_size("block_stats", &batch);
 
         timings.timings.start_timer();
         let first_tx_num = tx_writer.insert_block_txs(&mut batch, block_txs)?;
         timings.timings.stop_timer("txs");
+        write_size.add_batch_size("txs", &batch);
 
         timings.timings.start_timer();
         let input_tx_nums = fetch_input_tx_nums(&self.db, first_tx_num, txids_fn, txs)?;
@@ -162,6 +393,7 @@ impl IndexDb {
         )?;
         timings.timings.stop_timer("outputs");
         timings.script_txs_timings.add(&script_txs_timings);
+        write_size.add_batch_size("script_txs", &batch);
 
         timings.timings.start_timer();
         let utxos_timings = utxo_writer.insert_block_txs(
@@ -174,18 +406,29 @@ impl IndexDb {
         )?;
         timings.timings.stop_timer("utxos");
         timings.utxos_timings.add(&utxos_timings);
+        write_size.add_batch_size("utxos", &batch);
 
-        timings.timings.start_timer();
-        spends_writer.insert_block_txs(&mut batch, first_tx_num, txs, &input_tx_nums)?;
-        timings.timings.stop_timer("spends");
+        if self.has_spends_index {
+            timings.timings.start_timer();
+            spends_writer.insert_block_txs(&mut batch, first_tx_num, txs, &input_tx_nums)?;
+            timings.timings.stop_timer("spends");
+            write_size.add_batch_size("spends", &batch);
+        }
 
         timings.timings.start_timer();
         slp_writer.insert_block_txs(&mut batch, first_tx_num, txs, txids_fn, &input_tx_nums)?;
         timings.timings.stop_timer("slp");
+        write_size.add_batch_size("slp", &batch);
+
+        timings.timings.start_timer();
+        lokad_txs_writer.insert_block_txs(&mut batch, first_tx_num, txs)?;
+        timings.timings.stop_timer("lokad_txs");
+        write_size.add_batch_size("lokad_txs", &batch);
 
         timings.timings.start_timer();
         self.db.write_batch(batch)?;
         timings.timings.stop_timer("insert");
+        self.write_sizes.write().unwrap().add_block(write_size);
 
         let mempool_txids = block_txs
             .txs
@@ -197,6 +440,8 @@ impl IndexDb {
             db: &self.db,
             mempool: &mut data.mempool,
             mempool_slp: &mut data.mempool_slp,
+            script_filter: &self.script_filter,
+            slp_conf: &self.slp_conf,
         };
         mempool_writer.delete_mempool_mined_txs(mempool_txids)?;
 
@@ -216,21 +461,28 @@ impl IndexDb {
         let block_stats_writer = BlockStatsWriter::new(&self.db)?;
         let tx_writer = TxWriter::new(&self.db)?;
         let conf = ScriptTxsConf { page_size: 1000 };
-        let script_txs_writer = ScriptTxsWriter::new(&self.db, conf)?;
-        let utxo_writer = UtxosWriter::new(&self.db)?;
+        let script_txs_writer = ScriptTxsWriter::new(&self.db, conf, &self.script_filter)?;
+        let utxo_writer = UtxosWriter::new(&self.db, &self.script_filter)?;
         let spends_writer = SpendsWriter::new(&self.db)?;
-        let slp_writer = SlpWriter::new(&self.db)?;
+        let slp_writer = SlpWriter::new(&self.db, self.slp_conf.clone())?;
+        let lokad_txs_writer = LokadTxsWriter::new(&self.db, self.lokad_txs_conf.clone())?;
         let tx_reader = TxReader::new(&self.db)?;
-        let first_tx_num = tx_reader.first_tx_num_by_block(height)?.unwrap();
-        let input_tx_nums = fetch_input_tx_nums(&self.db, first_tx_num, &txids_fn, txs)?;
-        let mut batch = WriteBatch::default();
-        block_writer.delete_by_hash(&mut batch, block_hash)?;
         let block = self
             .blocks()?
             .by_hash(block_hash)?
             .ok_or_else(|| UnknownBlock(block_hash.clone()))?;
+        if block.height != height {
+            return Err(DisconnectHeightMismatch(block_hash.clone(), block.height, height).into());
+        }
+        // Genesis has first_tx_num 0, so only a missing entry signals an inconsistent index
+        let first_tx_num = tx_reader
+            .first_tx_num_by_block(height)?
+            .ok_or(MissingFirstTxNum(height))?;
+        let input_tx_nums = fetch_input_tx_nums(&self.db, first_tx_num, &txids_fn, txs)?;
+        let mut batch = WriteBatch::default();
+        block_writer.delete_by_hash(&mut batch, block_hash)?;
         block_stats_writer.delete_by_height(&mut batch, height)?;
-        tx_writer.delete_block_txs(&mut batch, block.height)?;
+        tx_writer.delete_block_txs(&mut batch, height)?;
         script_txs_writer.delete_block_txs(
             &mut batch,
             first_tx_num,
@@ -243,10 +495,51 @@ impl IndexDb {
             first_tx_num,
             &txids_fn,
             txs,
+            &block_spent_output_fn,
+        )?;
+        if self.has_spends_index {
+            spends_writer.delete_block_txs(&mut batch, first_tx_num, txs, &input_tx_nums)?;
+        }
+        slp_writer.delete_block_txs(
+            &mut batch,
+            first_tx_num,
+            txs,
+            &txids_fn,
+            &input_tx_nums,
+            &block_spent_output_fn,
+        )?;
+        lokad_txs_writer.delete_block_txs(&mut batch, first_tx_num, txs)?;
+        self.db.write_batch(batch)?;
+        self.write_sizes.write().unwrap().remove_block(height);
+        Ok(())
+    }
+
+    /// Writes only the block stats of an already indexed block, e.g. to restore missing stats.
+    pub fn insert_block_stats<'b>(
+        &self,
+        block: &Block,
+        block_txs: &BlockTxs,
+        txs: &[UnhashedTx],
+        block_spent_output_fn: impl Fn(/*tx_idx:*/ usize, /*out_idx:*/ usize) -> &'b TxOutput,
+    ) -> Result<()> {
+        let block_stats_writer = BlockStatsWriter::new(&self.db)?;
+        let mut batch = WriteBatch::default();
+        block_stats_writer.insert_block_txs(
+            &mut batch,
+            block,
+            txs,
+            block_txs,
             block_spent_output_fn,
         )?;
-        spends_writer.delete_block_txs(&mut batch, first_tx_num, txs, &input_tx_nums)?;
-        slp_writer.delete_block_txs(&mut batch, first_tx_num, txs, &txids_fn, &input_tx_nums)?;
+        self.db.write_batch(batch)?;
+        Ok(())
+    }
+
+    /// Deletes only the block stats at `height`, leaving the rest of the block indexed.
+    pub fn delete_block_stats(&self, height: BlockHeight) -> Result<()> {
+        let block_stats_writer = BlockStatsWriter::new(&self.db)?;
+        let mut batch = WriteBatch::default();
+        block_stats_writer.delete_by_height(&mut batch, height)?;
         self.db.write_batch(batch)?;
         Ok(())
     }
@@ -289,6 +582,8 @@ impl IndexDb {
             db: &self.db,
             mempool: &mut data.mempool,
             mempool_slp: &mut data.mempool_slp,
+            script_filter: &self.script_filter,
+            slp_conf: &self.slp_conf,
         }
     }
 }
@@ -301,4 +596,139 @@ impl IndexMemData {
             mempool_slp: MempoolSlpData::default(),
         }
     }
+
+    pub fn script_txs_cache_stats(&self) -> ScriptTxsCacheStats {
+        self.script_txs_cache.stats()
+    }
+
+    pub fn resize_script_txs_cache(&mut self, capacity: usize) {
+        self.script_txs_cache.resize(capacity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoinsuite_error::Result;
+
+    use std::collections::HashSet;
+
+    use crate::{
+        Db, IndexDb, IndexDbError, LokadTxsConf, PayloadPrefix, ScriptFilter, ScriptPayload,
+        ScriptTxsConf, SlpConf, TransientData,
+    };
+
+    fn open_index_db(dir: &std::path::Path, has_spends_index: bool) -> Result<IndexDb> {
+        let db = Db::open(dir.join("index.rocksdb"))?;
+        let transient_data = TransientData::open(&dir.join("transient.rocksdb"))?;
+        let script_txs_conf = ScriptTxsConf { page_size: 7 };
+        Ok(
+            IndexDb::new(db, transient_data, script_txs_conf, LokadTxsConf::default())
+                .with_spends_index(has_spends_index),
+        )
+    }
+
+    #[test]
+    fn test_spends_index_mismatch() -> Result<()> {
+        bitcoinsuite_error::install()?;
+        let tempdir = tempdir::TempDir::new("slp-indexer-rocks--spends-index")?;
+        // A new index records the setting, so reopening it with the same setting works
+        open_index_db(tempdir.path(), false)?.check_db_version()?;
+        open_index_db(tempdir.path(), false)?.check_db_version()?;
+        let err = open_index_db(tempdir.path(), true)?
+            .check_db_version()
+            .unwrap_err()
+            .downcast::<IndexDbError>()?;
+        assert!(matches!(
+            err,
+            IndexDbError::SpendsIndexMismatch {
+                indexed: false,
+                configured: true,
+            },
+        ));
+        Ok(())
+    }
+    #[test]
+    fn test_script_filter_mismatch() -> Result<()> {
+        bitcoinsuite_error::install()?;
+        let tempdir = tempdir::TempDir::new("slp-indexer-rocks--script-filter")?;
+        let watched = |payload_data: Vec<u8>| {
+            ScriptFilter::Watched(HashSet::from([ScriptPayload {
+                payload_prefix: PayloadPrefix::P2PKH,
+                payload_data,
+            }]))
+        };
+        open_index_db(tempdir.path(), true)?
+            .with_script_filter(watched(vec![1; 20]))
+            .check_db_version()?;
+        open_index_db(tempdir.path(), true)?
+            .with_script_filter(watched(vec![1; 20]))
+            .check_db_version()?;
+        for script_filter in [ScriptFilter::All, watched(vec![2; 20])] {
+            let err = open_index_db(tempdir.path(), true)?
+                .with_script_filter(script_filter)
+                .check_db_version()
+                .unwrap_err()
+                .downcast::<IndexDbError>()?;
+            assert!(matches!(err, IndexDbError::ScriptFilterMismatch));
+        }
+        Ok(())
+    }
+    #[test]
+    fn test_slp_conf_mismatch() -> Result<()> {
+        bitcoinsuite_error::install()?;
+        let tempdir = tempdir::TempDir::new("slp-indexer-rocks--slp-conf")?;
+        let slp_conf = |ignore_unknown_token_types| SlpConf {
+            ignore_unknown_token_types,
+        };
+        open_index_db(tempdir.path(), true)?
+            .with_slp_conf(slp_conf(true))
+            .check_db_version()?;
+        open_index_db(tempdir.path(), true)?
+            .with_slp_conf(slp_conf(true))
+            .check_db_version()?;
+        let err = open_index_db(tempdir.path(), true)?
+            .with_slp_conf(slp_conf(false))
+            .check_db_version()
+            .unwrap_err()
+            .downcast::<IndexDbError>()?;
+        assert!(matches!(
+            err,
+            IndexDbError::SlpConfMismatch {
+                indexed: true,
+                configured: false,
+            },
+        ));
+        Ok(())
+    }
+    #[test]
+    fn test_lokad_ids_mismatch() -> Result<()> {
+        bitcoinsuite_error::install()?;
+        let tempdir = tempdir::TempDir::new("slp-indexer-rocks--lokad-ids")?;
+        let check_lokad_ids = |lokad_ids: &[&[u8]]| -> Result<()> {
+            let db = Db::open(tempdir.path().join("index.rocksdb"))?;
+            let transient_data = TransientData::open(&tempdir.path().join("transient.rocksdb"))?;
+            let lokad_txs_conf = LokadTxsConf {
+                lokad_ids: lokad_ids.iter().map(|lokad_id| lokad_id.to_vec()).collect(),
+                ..LokadTxsConf::default()
+            };
+            IndexDb::new(
+                db,
+                transient_data,
+                ScriptTxsConf { page_size: 7 },
+                lokad_txs_conf,
+            )
+            .check_db_version()
+        };
+        check_lokad_ids(&[b"SLP\0", &[0x6d, 0x02]])?;
+        check_lokad_ids(&[&[0x6d, 0x02], b"SLP\0"])?;
+        let err = check_lokad_ids(&[&[0x6d, 0x02]])
+            .unwrap_err()
+            .downcast::<IndexDbError>()?;
+        assert_eq!(
+            err.to_string(),
+            "Index was built with lokad_ids = [534c5000, 6d02], but it's configured as [6d02]; \
+             changing them requires reindexing",
+        );
+        Ok(())
+    }
 }
@@ -6,11 +6,14 @@ mod db_schema;
 mod index;
 mod indexdb;
 mod input_tx_nums;
+mod lokad_txs;
 mod mempool;
 mod mempool_data;
 mod mempool_slp_data;
 mod merge_ops;
+mod meta;
 mod outpoint_data;
+mod replica;
 mod script_payload;
 mod script_txs;
 mod slp;
@@ -20,16 +23,20 @@ mod timings;
 mod transient_data;
 mod txs;
 mod utxos;
+mod write_sizes;
 
 pub use crate::block_stats::*;
 pub use crate::blocks::*;
 pub use crate::db::*;
 pub use crate::db_schema::*;
 pub use crate::indexdb::*;
+pub use crate::lokad_txs::*;
 pub use crate::mempool::*;
 pub use crate::mempool_data::*;
 pub use crate::mempool_slp_data::*;
+pub use crate::meta::*;
 pub use crate::outpoint_data::OutpointEntry;
+pub use crate::replica::ReplicaError;
 pub use crate::script_payload::*;
 pub use crate::script_txs::*;
 pub use crate::slp::*;
@@ -39,6 +46,7 @@ pub use crate::timings::*;
 pub use crate::transient_data::*;
 pub use crate::txs::*;
 pub use crate::utxos::*;
+pub use crate::write_sizes::*;
 
 pub mod proto {
     include!(concat!(env!("OUT_DIR"), "/chronik_db.rs"));
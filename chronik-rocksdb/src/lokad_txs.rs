@@ -0,0 +1,381 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use bitcoinsuite_core::{Script, UnhashedTx};
+use bitcoinsuite_error::Result;
+use rocksdb::{ColumnFamilyDescriptor, Direction, IteratorMode, Options, WriteBatch};
+use zerocopy::AsBytes;
+
+use crate::{
+    data::interpret_slice,
+    merge_ops::{
+        full_merge_ordered_list, partial_merge_ordered_list, PREFIX_DELETE, PREFIX_INSERT,
+    },
+    Db, TxNum, TxNumOrd, TxNumZC, CF,
+};
+
+pub const CF_LOKAD_TXS: &str = "lokad_txs";
+
+/// Number of tx nums per page of the lokad_txs CF.
+pub const DEFAULT_LOKAD_TXS_PAGE_SIZE: usize = 1000;
+
+type LokadPageNum = u32;
+const PAGE_NUM_SIZE: usize = std::mem::size_of::<LokadPageNum>();
+
+/*
+lokad_txs:
+len(lokad_id) | lokad_id | page_num -> [tx_num]
+Like script_txs, every page but the last one holds exactly `page_size` tx nums, so the number of
+txs follows from the last page and any page can be looked up directly.
+*/
+
+const OP_RETURN: u8 = 0x6a;
+const OP_PUSHDATA1: u8 = 0x4c;
+
+/// Protocol identifier of an OP_RETURN output, i.e. the first pushop after the OP_RETURN.
+/// E.g. b"SLP\0" for SLP or [0x6d, 0x02] for a memo.cash post.
+pub type LokadId = Vec<u8>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LokadTxsConf {
+    /// Which LOKAD IDs to index; if empty, no OP_RETURN data is indexed.
+    pub lokad_ids: HashSet<LokadId>,
+    pub page_size: usize,
+}
+
+pub struct LokadTxsWriter<'a> {
+    db: &'a Db,
+    cf_lokad_txs: &'a CF,
+    conf: LokadTxsConf,
+}
+
+pub struct LokadTxsReader<'a> {
+    db: &'a Db,
+    cf_lokad_txs: &'a CF,
+    conf: LokadTxsConf,
+}
+
+impl Default for LokadTxsConf {
+    fn default() -> Self {
+        LokadTxsConf {
+            lokad_ids: HashSet::new(),
+            page_size: DEFAULT_LOKAD_TXS_PAGE_SIZE,
+        }
+    }
+}
+
+impl<'a> LokadTxsWriter<'a> {
+    pub fn add_cfs(columns: &mut Vec<ColumnFamilyDescriptor>) {
+        let mut options = Options::default();
+        options.set_merge_operator(
+            "slp-indexer-rocks.MergeLokadTxs",
+            full_merge_ordered_list::<TxNumOrd>,
+            partial_merge_ordered_list::<TxNumOrd>,
+        );
+        columns.push(ColumnFamilyDescriptor::new(CF_LOKAD_TXS, options));
+    }
+
+    pub fn new(db: &'a Db, conf: LokadTxsConf) -> Result<Self> {
+        let cf_lokad_txs = db.cf(CF_LOKAD_TXS)?;
+        Ok(LokadTxsWriter {
+            db,
+            cf_lokad_txs,
+            conf,
+        })
+    }
+
+    pub fn insert_block_txs(
+        &self,
+        batch: &mut WriteBatch,
+        first_tx_num: TxNum,
+        txs: &[UnhashedTx],
+    ) -> Result<()> {
+        for (lokad_id, tx_nums) in self.prepare_lokad_tx_nums(first_tx_num, txs) {
+            let start_num_txs = num_txs(self.db, self.cf_lokad_txs, &self.conf, &lokad_id)?;
+            for (new_tx_idx, tx_num) in tx_nums.into_iter().enumerate() {
+                let page_num = (start_num_txs + new_tx_idx) / self.conf.page_size;
+                let mut value = TxNumZC::new(tx_num).as_bytes().to_vec();
+                value.insert(0, PREFIX_INSERT);
+                batch.merge_cf(
+                    self.cf_lokad_txs,
+                    key_for_lokad_page(&lokad_id, page_num as LokadPageNum),
+                    value,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub fn delete_block_txs(
+        &self,
+        batch: &mut WriteBatch,
+        first_tx_num: TxNum,
+        txs: &[UnhashedTx],
+    ) -> Result<()> {
+        for (lokad_id, tx_nums) in self.prepare_lokad_tx_nums(first_tx_num, txs) {
+            let num_txs = num_txs(self.db, self.cf_lokad_txs, &self.conf, &lokad_id)?;
+            let start_num_txs = num_txs - tx_nums.len();
+            for (new_tx_idx, tx_num) in tx_nums.into_iter().enumerate() {
+                let page_num = (start_num_txs + new_tx_idx) / self.conf.page_size;
+                let mut value = TxNumZC::new(tx_num).as_bytes().to_vec();
+                value.insert(0, PREFIX_DELETE);
+                batch.merge_cf(
+                    self.cf_lokad_txs,
+                    key_for_lokad_page(&lokad_id, page_num as LokadPageNum),
+                    value,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn prepare_lokad_tx_nums(
+        &self,
+        first_tx_num: TxNum,
+        txs: &[UnhashedTx],
+    ) -> BTreeMap<LokadId, BTreeSet<TxNum>> {
+        let mut lokad_tx_nums = BTreeMap::<_, BTreeSet<TxNum>>::new();
+        if self.conf.lokad_ids.is_empty() {
+            return lokad_tx_nums;
+        }
+        for (tx_idx, tx) in txs.iter().enumerate() {
+            let tx_num = first_tx_num + tx_idx as u64;
+            for output in &tx.outputs {
+                if let Some(lokad_id) = parse_lokad_id(&output.script) {
+                    if self.conf.lokad_ids.contains(lokad_id) {
+                        lokad_tx_nums
+                            .entry(lokad_id.to_vec())
+                            .or_default()
+                            .insert(tx_num);
+                    }
+                }
+            }
+        }
+        lokad_tx_nums
+    }
+}
+
+impl<'a> LokadTxsReader<'a> {
+    pub fn new(db: &'a Db, conf: LokadTxsConf) -> Result<Self> {
+        let cf_lokad_txs = db.cf(CF_LOKAD_TXS)?;
+        Ok(LokadTxsReader {
+            db,
+            cf_lokad_txs,
+            conf,
+        })
+    }
+
+    pub fn is_indexed(&self, lokad_id: &[u8]) -> bool {
+        self.conf.lokad_ids.contains(lokad_id)
+    }
+
+    pub fn num_txs(&self, lokad_id: &[u8]) -> Result<usize> {
+        num_txs(self.db, self.cf_lokad_txs, &self.conf, lokad_id)
+    }
+
+    /// Tx nums in reverse order, i.e. most recent first and oldest last. Only reads the stored
+    /// pages overlapping the requested one.
+    pub fn rev_page_txs(
+        &self,
+        lokad_id: &[u8],
+        page_num: usize,
+        page_size: usize,
+    ) -> Result<Vec<TxNum>> {
+        let num_txs = self.num_txs(lokad_id)?;
+        // Range of the requested txs in chronological order
+        let end = num_txs.saturating_sub(page_num.saturating_mul(page_size));
+        let start = end.saturating_sub(page_size);
+        if start == end {
+            return Ok(vec![]);
+        }
+        let mut tx_nums = Vec::with_capacity(end - start);
+        for db_page_num in start / self.conf.page_size..=(end - 1) / self.conf.page_size {
+            let page_start = db_page_num * self.conf.page_size;
+            let page_txs = self.page_txs(lokad_id, db_page_num as LokadPageNum)?;
+            let page_end = page_start + page_txs.len();
+            if start.max(page_start) >= end.min(page_end) {
+                continue;
+            }
+            tx_nums.extend_from_slice(
+                &page_txs[start.max(page_start) - page_start..end.min(page_end) - page_start],
+            );
+        }
+        tx_nums.reverse();
+        Ok(tx_nums)
+    }
+
+    fn page_txs(&self, lokad_id: &[u8], page_num: LokadPageNum) -> Result<Vec<TxNum>> {
+        let value = match self
+            .db
+            .get(self.cf_lokad_txs, key_for_lokad_page(lokad_id, page_num))?
+        {
+            Some(value) => value,
+            None => return Ok(vec![]),
+        };
+        Ok(interpret_slice::<TxNumZC>(&value)?
+            .iter()
+            .map(|tx_num| tx_num.get())
+            .collect())
+    }
+}
+
+/// Number of indexed txs of `lokad_id`, from the size of its last non-empty page.
+fn num_txs(db: &Db, cf_lokad_txs: &CF, conf: &LokadTxsConf, lokad_id: &[u8]) -> Result<usize> {
+    let prefix = key_prefix_for_lokad_id(lokad_id);
+    let last_key = key_for_lokad_page(lokad_id, LokadPageNum::MAX);
+    let iterator = db.rocks().iterator_cf(
+        cf_lokad_txs,
+        IteratorMode::From(&last_key, Direction::Reverse),
+    );
+    for (key, value) in iterator {
+        if !key.starts_with(&prefix) || key.len() != prefix.len() + PAGE_NUM_SIZE {
+            break;
+        }
+        // Pages emptied by disconnected blocks stay around as empty values
+        if value.is_empty() {
+            continue;
+        }
+        let page_num = LokadPageNum::from_be_bytes(key[prefix.len()..].try_into().unwrap());
+        let num_page_txs = interpret_slice::<TxNumZC>(&value)?.len();
+        return Ok(page_num as usize * conf.page_size + num_page_txs);
+    }
+    Ok(0)
+}
+
+/// Returns the LOKAD ID of an OP_RETURN script, if it starts with a pushop.
+pub fn parse_lokad_id(script: &Script) -> Option<&[u8]> {
+    let bytecode: &[u8] = script.bytecode();
+    if bytecode.first() != Some(&OP_RETURN) {
+        return None;
+    }
+    let (data_start, len) = match *bytecode.get(1)? {
+        opcode @ 0x01..=0x4b => (2, opcode as usize),
+        OP_PUSHDATA1 => (3, *bytecode.get(2)? as usize),
+        _ => return None,
+    };
+    bytecode.get(data_start..data_start + len)
+}
+
+fn key_prefix_for_lokad_id(lokad_id: &[u8]) -> Vec<u8> {
+    [[lokad_id.len() as u8].as_ref(), lokad_id].concat()
+}
+
+fn key_for_lokad_page(lokad_id: &[u8], page_num: LokadPageNum) -> Vec<u8> {
+    [
+        key_prefix_for_lokad_id(lokad_id).as_slice(),
+        page_num.to_be_bytes().as_ref(),
+    ]
+    .concat()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{parse_lokad_id, Db, LokadTxsConf, LokadTxsReader, LokadTxsWriter};
+    use bitcoinsuite_core::{Script, ShaRmd160, TxInput, TxOutput, UnhashedTx};
+    use bitcoinsuite_error::Result;
+    use pretty_assertions::assert_eq;
+    use rocksdb::WriteBatch;
+
+    const MEMO_POST: [u8; 2] = [0x6d, 0x02];
+
+    fn make_tx(output_scripts: &[Script]) -> UnhashedTx {
+        UnhashedTx {
+            version: 1,
+            inputs: vec![TxInput::default()],
+            outputs: output_scripts
+                .iter()
+                .map(|script| TxOutput {
+                    value: 0,
+                    script: script.clone(),
+                })
+                .collect(),
+            lock_time: 0,
+        }
+    }
+
+    #[test]
+    fn test_parse_lokad_id() -> Result<()> {
+        let memo_script = Script::from_slice(&[0x6a, 0x02, 0x6d, 0x02, 0x03, b'h', b'e', b'y']);
+        assert_eq!(parse_lokad_id(&memo_script), Some(&MEMO_POST[..]));
+        let slp_script = Script::from_slice(&[0x6a, 0x04, b'S', b'L', b'P', 0x00, 0x01, 0x01]);
+        assert_eq!(parse_lokad_id(&slp_script), Some(&b"SLP\0"[..]));
+        let pushdata1_script = Script::from_slice(&[0x6a, 0x4c, 0x02, 0x6d, 0x02]);
+        assert_eq!(parse_lokad_id(&pushdata1_script), Some(&MEMO_POST[..]));
+        assert_eq!(parse_lokad_id(&Script::from_slice(&[0x6a])), None);
+        assert_eq!(
+            parse_lokad_id(&Script::from_slice(&[0x6a, 0x02, 0x6d])),
+            None
+        );
+        assert_eq!(parse_lokad_id(&Script::from_slice(&[0x6a, 0x51])), None);
+        assert_eq!(
+            parse_lokad_id(&Script::p2pkh(&ShaRmd160::new([1; 20]))),
+            None,
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_lokad_txs() -> Result<()> {
+        bitcoinsuite_error::install()?;
+        let tempdir = tempdir::TempDir::new("slp-indexer-rocks--lokad_txs")?;
+        let db = Db::open(tempdir.path())?;
+        let conf = LokadTxsConf {
+            lokad_ids: [MEMO_POST.to_vec()].into_iter().collect(),
+            page_size: 2,
+        };
+        let writer = LokadTxsWriter::new(&db, conf.clone())?;
+        let reader = LokadTxsReader::new(&db, conf)?;
+        let memo_script = Script::from_slice(&[0x6a, 0x02, 0x6d, 0x02, 0x03, b'h', b'e', b'y']);
+        let slp_script = Script::from_slice(&[0x6a, 0x04, b'S', b'L', b'P', 0x00, 0x01, 0x01]);
+        let p2pkh_script = Script::p2pkh(&ShaRmd160::new([1; 20]));
+        let block1 = vec![
+            make_tx(&[p2pkh_script.clone()]),
+            make_tx(&[memo_script.clone(), p2pkh_script.clone()]),
+            make_tx(&[slp_script.clone(), p2pkh_script.clone()]),
+        ];
+        let block2 = vec![
+            make_tx(&[p2pkh_script]),
+            make_tx(&[memo_script.clone(), memo_script.clone()]),
+            make_tx(&[memo_script, slp_script]),
+        ];
+
+        assert!(reader.is_indexed(&MEMO_POST));
+        assert!(!reader.is_indexed(b"SLP\0"));
+        assert_eq!(reader.num_txs(&MEMO_POST)?, 0);
+        assert_eq!(reader.rev_page_txs(&MEMO_POST, 0, 10)?, vec![]);
+
+        let mut batch = WriteBatch::default();
+        writer.insert_block_txs(&mut batch, 0, &block1)?;
+        db.write_batch(batch)?;
+        assert_eq!(reader.num_txs(&MEMO_POST)?, 1);
+        assert_eq!(reader.rev_page_txs(&MEMO_POST, 0, 10)?, vec![1]);
+        // Not configured, so not indexed
+        assert_eq!(reader.num_txs(b"SLP\0")?, 0);
+
+        let mut batch = WriteBatch::default();
+        writer.insert_block_txs(&mut batch, 3, &block2)?;
+        db.write_batch(batch)?;
+        assert_eq!(reader.num_txs(&MEMO_POST)?, 3);
+        assert_eq!(reader.rev_page_txs(&MEMO_POST, 0, 10)?, vec![5, 4, 1]);
+        assert_eq!(reader.rev_page_txs(&MEMO_POST, 0, 2)?, vec![5, 4]);
+        assert_eq!(reader.rev_page_txs(&MEMO_POST, 1, 2)?, vec![1]);
+        assert_eq!(reader.rev_page_txs(&MEMO_POST, 2, 2)?, vec![]);
+        // Pages of other sizes than the stored ones
+        assert_eq!(reader.rev_page_txs(&MEMO_POST, 1, 1)?, vec![4]);
+        assert_eq!(reader.rev_page_txs(&MEMO_POST, 0, 3)?, vec![5, 4, 1]);
+
+        let mut batch = WriteBatch::default();
+        writer.delete_block_txs(&mut batch, 3, &block2)?;
+        db.write_batch(batch)?;
+        assert_eq!(reader.num_txs(&MEMO_POST)?, 1);
+        assert_eq!(reader.rev_page_txs(&MEMO_POST, 0, 10)?, vec![1]);
+
+        // The emptied page gets filled again
+        let mut batch = WriteBatch::default();
+        writer.insert_block_txs(&mut batch, 3, &block2)?;
+        db.write_batch(batch)?;
+        assert_eq!(reader.num_txs(&MEMO_POST)?, 3);
+        assert_eq!(reader.rev_page_txs(&MEMO_POST, 0, 10)?, vec![5, 4, 1]);
+
+        Ok(())
+    }
+}
@@ -4,12 +4,16 @@ use bitcoinsuite_core::Sha256d;
 use bitcoinsuite_error::{ErrorMeta, Result};
 use thiserror::Error;
 
-use crate::{Db, MempoolData, MempoolDeleteMode, MempoolSlpData, MempoolTxEntry};
+use crate::{
+    Db, MempoolData, MempoolDeleteMode, MempoolSlpData, MempoolTxEntry, ScriptFilter, SlpConf,
+};
 
 pub struct MempoolWriter<'a> {
     pub db: &'a Db,
     pub mempool: &'a mut MempoolData,
     pub mempool_slp: &'a mut MempoolSlpData,
+    pub script_filter: &'a ScriptFilter,
+    pub slp_conf: &'a SlpConf,
 }
 
 #[derive(Debug, Error, ErrorMeta)]
@@ -28,14 +32,21 @@ use self::MempoolError::*;
 impl<'a> MempoolWriter<'a> {
     pub fn insert_mempool_tx(&mut self, txid: Sha256d, entry: MempoolTxEntry) -> Result<()> {
         self.mempool_slp
-            .insert_mempool_tx(self.db, &txid, &entry.tx)?;
-        self.mempool
-            .insert_mempool_tx(txid, entry.tx, entry.spent_coins, entry.time_first_seen)?;
+            .insert_mempool_tx(self.db, self.slp_conf, &txid, &entry.tx)?;
+        self.mempool.insert_mempool_tx(
+            txid,
+            entry.tx,
+            entry.spent_coins,
+            entry.time_first_seen,
+            self.script_filter,
+        )?;
         Ok(())
     }
 
     pub fn delete_mempool_tx(&mut self, txid: &Sha256d, mode: MempoolDeleteMode) -> Result<()> {
-        let tx = self.mempool.delete_mempool_tx(txid, mode)?;
+        let tx = self
+            .mempool
+            .delete_mempool_tx(txid, mode, self.script_filter)?;
         self.mempool_slp.delete_mempool_tx(self.db, txid, &tx)?;
         Ok(())
     }
@@ -114,7 +125,7 @@ mod tests {
 
     use crate::{
         input_tx_nums::fetch_input_tx_nums, BlockTxs, Db, MempoolData, MempoolSlpData,
-        MempoolTxEntry, MempoolWriter, SlpWriter, TxEntry, TxWriter,
+        MempoolTxEntry, MempoolWriter, ScriptFilter, SlpConf, SlpWriter, TxEntry, TxWriter,
     };
 
     #[test]
@@ -123,7 +134,7 @@ mod tests {
         let tempdir = tempdir::TempDir::new("slp-indexer-rocks--mempool")?;
         let db = Db::open(tempdir.path())?;
         let tx_writer = TxWriter::new(&db)?;
-        let slp_writer = SlpWriter::new(&db)?;
+        let slp_writer = SlpWriter::new(&db, SlpConf::default())?;
         let token_id = TokenId::new(make_hash(4));
         let (block_txids, block_txs) = make_block([
             make_tx((1, [(0, 0xffff_ffff)], 5), Script::default()),
@@ -181,6 +192,8 @@ mod tests {
             db: &db,
             mempool: &mut mempool,
             mempool_slp: &mut mempool_slp,
+            script_filter: &ScriptFilter::All,
+            slp_conf: &SlpConf::default(),
         };
         let mempool_batch = [
             make_tx((10, [(2, 0)], 3), Script::default()),
@@ -4,7 +4,7 @@ use bitcoinsuite_core::{Bytes, Coin, OutPoint, Sha256d, UnhashedTx};
 use bitcoinsuite_error::{ErrorMeta, Result};
 use thiserror::Error;
 
-use crate::{script_payload::script_payloads, PayloadPrefix};
+use crate::{PayloadPrefix, ScriptFilter};
 
 #[derive(Debug, PartialEq, Eq, Default)]
 pub struct MempoolData {
@@ -77,13 +77,14 @@ impl MempoolData {
         tx: UnhashedTx,
         spent_coins: Vec<Coin>,
         time_first_seen: i64,
+        script_filter: &ScriptFilter,
     ) -> Result<()> {
         for (out_idx, output) in tx.outputs.iter().enumerate() {
             let outpoint = OutPoint {
                 txid: txid.clone(),
                 out_idx: out_idx as u32,
             };
-            for script_payload in script_payloads(&output.script) {
+            for script_payload in script_filter.script_payloads(&output.script) {
                 let script_payload = script_payload.payload.into_vec();
                 let script_payload = Bytes::from_bytes(script_payload);
                 {
@@ -110,7 +111,7 @@ impl MempoolData {
             }
         }
         for (input_idx, (input, spent_coin)) in tx.inputs.iter().zip(&spent_coins).enumerate() {
-            for script_payload in script_payloads(&spent_coin.tx_output.script) {
+            for script_payload in script_filter.script_payloads(&spent_coin.tx_output.script) {
                 let script_payload = script_payload.payload.into_vec();
                 let script_payload = Bytes::from_bytes(script_payload);
                 {
@@ -163,6 +164,7 @@ impl MempoolData {
         &mut self,
         txid: &Sha256d,
         mode: MempoolDeleteMode,
+        script_filter: &ScriptFilter,
     ) -> Result<UnhashedTx> {
         let MempoolTxEntry {
             tx,
@@ -173,7 +175,7 @@ impl MempoolData {
             None => return Err(NoSuchTx(txid.clone()).into()),
         };
         for (input_idx, (input, spent_coin)) in tx.inputs.iter().zip(&spent_coins).enumerate() {
-            for script_payload in script_payloads(&spent_coin.tx_output.script) {
+            for script_payload in script_filter.script_payloads(&spent_coin.tx_output.script) {
                 let script_payload = script_payload.payload.into_vec();
                 let script_payload = Bytes::from_bytes(script_payload);
                 if let Some(txs) = self.script_txs.get_mut(&script_payload) {
@@ -217,7 +219,7 @@ impl MempoolData {
                 txid: txid.clone(),
                 out_idx: out_idx as u32,
             };
-            for script_payload in script_payloads(&output.script) {
+            for script_payload in script_filter.script_payloads(&output.script) {
                 let script_payload = script_payload.payload.into_vec();
                 let script_payload = Bytes::from_bytes(script_payload);
                 if let Some(txs) = self.script_txs.get_mut(&script_payload) {
@@ -226,7 +228,22 @@ impl MempoolData {
                         self.script_txs.remove(&script_payload);
                     }
                 }
+                let is_spent_in_mempool = self.is_spent_in_mempool(&outpoint);
                 let delta = match mode {
+                    // The node can evict a tx before its descendants (e.g. after a conflicting
+                    // tx got mined). Outputs still spent by a mempool tx are then kept as
+                    // deletes, which are cleared once that spending tx is removed as well.
+                    MempoolDeleteMode::Remove if is_spent_in_mempool => {
+                        if !self.utxos.contains_key(&script_payload) {
+                            self.utxos
+                                .insert(script_payload.clone(), UtxoDelta::default());
+                        }
+                        let delta = self.utxos.get_mut(&script_payload).expect("Impossible");
+                        if !delta.deletes.insert(outpoint.clone()) {
+                            return Err(UtxoAlreadySpent(outpoint).into());
+                        }
+                        delta
+                    }
                     MempoolDeleteMode::Remove => {
                         let delta = self
                             .utxos
@@ -280,6 +297,47 @@ impl MempoolData {
     pub fn spends(&self, txid: &Sha256d) -> Option<&BTreeSet<(u32, Sha256d, u32)>> {
         self.spends.get(txid)
     }
+
+    fn is_spent_in_mempool(&self, outpoint: &OutPoint) -> bool {
+        self.spends.get(&outpoint.txid).map_or(false, |spends| {
+            spends
+                .iter()
+                .any(|&(out_idx, _, _)| out_idx == outpoint.out_idx)
+        })
+    }
+
+    /// Mempool txs that double-spend an input of one of `confirmed_txs`, including all their
+    /// descendants, mapped to the txid of the confirmed tx they conflict with.
+    pub fn conflicting_txs<'b>(
+        &self,
+        confirmed_txs: impl IntoIterator<Item = (&'b Sha256d, &'b UnhashedTx)>,
+    ) -> HashMap<Sha256d, Sha256d> {
+        let mut conflicts = HashMap::new();
+        for (confirmed_txid, confirmed_tx) in confirmed_txs {
+            let mut stack = Vec::new();
+            for input in &confirmed_tx.inputs {
+                let spends = match self.spends.get(&input.prev_out.txid) {
+                    Some(spends) => spends,
+                    None => continue,
+                };
+                for (out_idx, spending_txid, _) in spends {
+                    if *out_idx == input.prev_out.out_idx && spending_txid != confirmed_txid {
+                        stack.push(spending_txid);
+                    }
+                }
+            }
+            while let Some(txid) = stack.pop() {
+                if conflicts.contains_key(txid) {
+                    continue;
+                }
+                conflicts.insert(txid.clone(), confirmed_txid.clone());
+                if let Some(spends) = self.spends.get(txid) {
+                    stack.extend(spends.iter().map(|(_, spending_txid, _)| spending_txid));
+                }
+            }
+        }
+        conflicts
+    }
 }
 
 impl UtxoDelta {
@@ -294,7 +352,7 @@ impl UtxoDelta {
 
 #[cfg(test)]
 mod tests {
-    use std::collections::BTreeSet;
+    use std::collections::{BTreeSet, HashMap};
 
     use bitcoinsuite_core::{
         ecc::PubKey, Coin, OutPoint, Script, Sha256d, ShaRmd160, TxInput, TxOutput, UnhashedTx,
@@ -304,6 +362,7 @@ mod tests {
 
     use crate::{
         mempool_data::UtxoDelta, MempoolData, MempoolDeleteMode, MempoolTxEntry, PayloadPrefix,
+        ScriptFilter,
     };
 
     #[test]
@@ -329,7 +388,13 @@ mod tests {
         let txid1 = make_hash(10);
         let tx1 = make_tx([(1, 4)], [&script2]);
         let spent_scripts1 = vec![script1.clone()];
-        mempool.insert_mempool_tx(txid1.clone(), tx1.clone(), make_spents(&spent_scripts1), 90)?;
+        mempool.insert_mempool_tx(
+            txid1.clone(),
+            tx1.clone(),
+            make_spents(&spent_scripts1),
+            90,
+            &ScriptFilter::All,
+        )?;
         check_tx(&mempool, &txid1, &tx1, &spent_scripts1, 90);
         check_outputs(&mempool, P2PKH, &payload2, [(90, &txid1)]);
         check_utxos(&mempool, P2PKH, &payload1, [], [(&txid0, 4)]);
@@ -340,7 +405,13 @@ mod tests {
         let txid2 = make_hash(11);
         let tx2 = make_tx([(1, 5), (10, 0)], [&script1, &script4]);
         let spent_scripts2 = vec![script3, script2];
-        mempool.insert_mempool_tx(txid2.clone(), tx2.clone(), make_spents(&spent_scripts2), 91)?;
+        mempool.insert_mempool_tx(
+            txid2.clone(),
+            tx2.clone(),
+            make_spents(&spent_scripts2),
+            91,
+            &ScriptFilter::All,
+        )?;
         check_tx(&mempool, &txid2, &tx2, &spent_scripts2, 91);
         check_outputs(&mempool, P2PKH, &payload1, [(90, &txid1), (91, &txid2)]);
         check_outputs(&mempool, P2PKH, &payload2, [(90, &txid1), (91, &txid2)]);
@@ -355,7 +426,7 @@ mod tests {
         check_spends_absent(&mempool, &txid2);
 
         // Remove tx 2
-        mempool.delete_mempool_tx(&txid2, MempoolDeleteMode::Remove)?;
+        mempool.delete_mempool_tx(&txid2, MempoolDeleteMode::Remove, &ScriptFilter::All)?;
         check_tx(&mempool, &txid1, &tx1, &spent_scripts1, 90);
         check_tx_absent(&mempool, &txid2);
         check_outputs(&mempool, P2PKH, &payload1, [(90, &txid1)]);
@@ -369,18 +440,36 @@ mod tests {
         check_spends(&mempool, &txid0, [(4, &txid1, 0)]);
         check_spends_absent(&mempool, &txid2);
 
-        mempool.delete_mempool_tx(&txid1, MempoolDeleteMode::Remove)?;
+        mempool.delete_mempool_tx(&txid1, MempoolDeleteMode::Remove, &ScriptFilter::All)?;
         assert_eq!(mempool, MempoolData::default());
 
         // Add txs back in
-        mempool.insert_mempool_tx(txid1.clone(), tx1.clone(), make_spents(&spent_scripts1), 90)?;
-        mempool.insert_mempool_tx(txid2.clone(), tx2.clone(), make_spents(&spent_scripts2), 91)?;
+        mempool.insert_mempool_tx(
+            txid1.clone(),
+            tx1.clone(),
+            make_spents(&spent_scripts1),
+            90,
+            &ScriptFilter::All,
+        )?;
+        mempool.insert_mempool_tx(
+            txid2.clone(),
+            tx2.clone(),
+            make_spents(&spent_scripts2),
+            91,
+            &ScriptFilter::All,
+        )?;
 
         // Add tx 3
         let txid3 = make_hash(12);
         let tx3 = make_tx([(1, 6), (1, 7), (11, 0)], [&script5, &script6]);
         let spent_scripts3 = vec![script7, script1.clone(), script1];
-        mempool.insert_mempool_tx(txid3.clone(), tx3.clone(), make_spents(&spent_scripts3), 92)?;
+        mempool.insert_mempool_tx(
+            txid3.clone(),
+            tx3.clone(),
+            make_spents(&spent_scripts3),
+            92,
+            &ScriptFilter::All,
+        )?;
         check_tx(&mempool, &txid3, &tx3, &spent_scripts3, 92);
         check_outputs(
             &mempool,
@@ -418,18 +507,36 @@ mod tests {
         check_spends_absent(&mempool, &txid3);
 
         // Delete txs in mempool eviction order
-        mempool.delete_mempool_tx(&txid3, MempoolDeleteMode::Remove)?;
-        mempool.delete_mempool_tx(&txid2, MempoolDeleteMode::Remove)?;
-        mempool.delete_mempool_tx(&txid1, MempoolDeleteMode::Remove)?;
+        mempool.delete_mempool_tx(&txid3, MempoolDeleteMode::Remove, &ScriptFilter::All)?;
+        mempool.delete_mempool_tx(&txid2, MempoolDeleteMode::Remove, &ScriptFilter::All)?;
+        mempool.delete_mempool_tx(&txid1, MempoolDeleteMode::Remove, &ScriptFilter::All)?;
         assert_eq!(mempool, MempoolData::default());
 
         // Add txs back in
-        mempool.insert_mempool_tx(txid1.clone(), tx1, make_spents(&spent_scripts1), 90)?;
-        mempool.insert_mempool_tx(txid2.clone(), tx2.clone(), make_spents(&spent_scripts2), 91)?;
-        mempool.insert_mempool_tx(txid3.clone(), tx3.clone(), make_spents(&spent_scripts3), 92)?;
+        mempool.insert_mempool_tx(
+            txid1.clone(),
+            tx1,
+            make_spents(&spent_scripts1),
+            90,
+            &ScriptFilter::All,
+        )?;
+        mempool.insert_mempool_tx(
+            txid2.clone(),
+            tx2.clone(),
+            make_spents(&spent_scripts2),
+            91,
+            &ScriptFilter::All,
+        )?;
+        mempool.insert_mempool_tx(
+            txid3.clone(),
+            tx3.clone(),
+            make_spents(&spent_scripts3),
+            92,
+            &ScriptFilter::All,
+        )?;
 
         // Delete txs in block mined order
-        mempool.delete_mempool_tx(&txid1, MempoolDeleteMode::Mined)?;
+        mempool.delete_mempool_tx(&txid1, MempoolDeleteMode::Mined, &ScriptFilter::All)?;
         check_tx_absent(&mempool, &txid1);
         check_tx(&mempool, &txid2, &tx2, &spent_scripts2, 91);
         check_tx(&mempool, &txid3, &tx3, &spent_scripts3, 92);
@@ -456,7 +563,7 @@ mod tests {
         check_spends(&mempool, &txid1, [(0, &txid2, 1)]);
         check_spends(&mempool, &txid2, [(0, &txid3, 2)]);
 
-        mempool.delete_mempool_tx(&txid2, MempoolDeleteMode::Mined)?;
+        mempool.delete_mempool_tx(&txid2, MempoolDeleteMode::Mined, &ScriptFilter::All)?;
         check_tx_absent(&mempool, &txid1);
         check_tx_absent(&mempool, &txid2);
         check_outputs(&mempool, P2PKH, &payload1, [(92, &txid3)]);
@@ -478,9 +585,138 @@ mod tests {
         check_spends_absent(&mempool, &txid1);
         check_spends(&mempool, &txid2, [(0, &txid3, 2)]);
 
-        mempool.delete_mempool_tx(&txid3, MempoolDeleteMode::Mined)?;
+        mempool.delete_mempool_tx(&txid3, MempoolDeleteMode::Mined, &ScriptFilter::All)?;
+        assert_eq!(mempool, MempoolData::default());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mempool_conflicting_txs() -> Result<()> {
+        bitcoinsuite_error::install()?;
+        let mut mempool = MempoolData::default();
+        let script = Script::p2pkh(&ShaRmd160::new([1; 20]));
+
+        // tx1 spends 1:4, tx2 spends tx1:0, tx3 spends 1:5
+        let txid1 = make_hash(10);
+        let tx1 = make_tx([(1, 4)], [&script]);
+        mempool.insert_mempool_tx(
+            txid1.clone(),
+            tx1.clone(),
+            make_spents(&[script.clone()]),
+            90,
+            &ScriptFilter::All,
+        )?;
+        let txid2 = make_hash(11);
+        let tx2 = make_tx([(10, 0)], [&script]);
+        mempool.insert_mempool_tx(
+            txid2.clone(),
+            tx2,
+            make_spents(&[script.clone()]),
+            91,
+            &ScriptFilter::All,
+        )?;
+        let txid3 = make_hash(12);
+        let tx3 = make_tx([(1, 5)], [&script]);
+        mempool.insert_mempool_tx(
+            txid3.clone(),
+            tx3,
+            make_spents(&[script.clone()]),
+            92,
+            &ScriptFilter::All,
+        )?;
+
+        // tx1 itself being confirmed is not a conflict
+        assert_eq!(mempool.conflicting_txs([(&txid1, &tx1)]), HashMap::new());
+
+        // Confirmed tx spending 1:4 conflicts with tx1 and its descendant tx2
+        let confirmed_txid = make_hash(20);
+        let confirmed_tx = make_tx([(1, 3), (1, 4)], [&script]);
+        assert_eq!(
+            mempool.conflicting_txs([(&confirmed_txid, &confirmed_tx)]),
+            [
+                (txid1, confirmed_txid.clone()),
+                (txid2, confirmed_txid.clone()),
+            ]
+            .into_iter()
+            .collect::<HashMap<_, _>>(),
+        );
+
+        // Unrelated confirmed tx doesn't conflict with anything
+        let unrelated_tx = make_tx([(1, 6)], [&script]);
+        assert_eq!(
+            mempool.conflicting_txs([(&confirmed_txid, &unrelated_tx)]),
+            HashMap::new(),
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mempool_remove_conflicting_txs() -> Result<()> {
+        use PayloadPrefix::*;
+        bitcoinsuite_error::install()?;
+        let (script1, payload1) = (Script::p2pkh(&ShaRmd160::new([1; 20])), [1; 20]);
+        let (script2, payload2) = (Script::p2pkh(&ShaRmd160::new([2; 20])), [2; 20]);
+        let txid0 = make_hash(1);
+        // tx1 spends the confirmed 1:4, tx2 spends tx1:0
+        let txid1 = make_hash(10);
+        let tx1 = make_tx([(1, 4)], [&script2]);
+        let txid2 = make_hash(11);
+        let tx2 = make_tx([(10, 0)], [&script1]);
+        let insert_txs = |mempool: &mut MempoolData| -> Result<()> {
+            mempool.insert_mempool_tx(
+                txid1.clone(),
+                tx1.clone(),
+                make_spents(&[script1.clone()]),
+                90,
+                &ScriptFilter::All,
+            )?;
+            mempool.insert_mempool_tx(
+                txid2.clone(),
+                tx2.clone(),
+                make_spents(&[script2.clone()]),
+                91,
+                &ScriptFilter::All,
+            )
+        };
+
+        // A block mines another tx spending 1:4, so the node evicts tx1 and tx2, descendant first
+        let mut mempool = MempoolData::default();
+        insert_txs(&mut mempool)?;
+        mempool.delete_mempool_tx(&txid2, MempoolDeleteMode::Remove, &ScriptFilter::All)?;
+        mempool.delete_mempool_tx(&txid1, MempoolDeleteMode::Remove, &ScriptFilter::All)?;
+        assert_eq!(mempool, MempoolData::default());
+
+        // Same, but evicting tx1 first; tx1:0 stays deleted until tx2 is gone too
+        insert_txs(&mut mempool)?;
+        mempool.delete_mempool_tx(&txid1, MempoolDeleteMode::Remove, &ScriptFilter::All)?;
+        check_tx_absent(&mempool, &txid1);
+        check_tx(&mempool, &txid2, &tx2, &[script2.clone()], 91);
+        check_outputs(&mempool, P2PKH, &payload1, [(91, &txid2)]);
+        check_outputs(&mempool, P2PKH, &payload2, [(91, &txid2)]);
+        check_utxos(&mempool, P2PKH, &payload1, [(&txid2, 0)], []);
+        check_utxos(&mempool, P2PKH, &payload2, [], [(&txid1, 0)]);
+        check_spends_absent(&mempool, &txid0);
+        check_spends(&mempool, &txid1, [(0, &txid2, 0)]);
+        mempool.delete_mempool_tx(&txid2, MempoolDeleteMode::Remove, &ScriptFilter::All)?;
         assert_eq!(mempool, MempoolData::default());
 
+        // tx1 gets mined, then tx2 is evicted because another tx spending tx1:0 got mined
+        insert_txs(&mut mempool)?;
+        mempool.delete_mempool_tx(&txid1, MempoolDeleteMode::Mined, &ScriptFilter::All)?;
+        check_utxos(&mempool, P2PKH, &payload2, [], [(&txid1, 0)]);
+        mempool.delete_mempool_tx(&txid2, MempoolDeleteMode::Remove, &ScriptFilter::All)?;
+        assert_eq!(mempool, MempoolData::default());
+
+        // Removing an output that's neither a mempool UTXO nor spent in the mempool still fails
+        insert_txs(&mut mempool)?;
+        mempool.delete_mempool_tx(&txid2, MempoolDeleteMode::Remove, &ScriptFilter::All)?;
+        mempool.utxos.clear();
+        assert!(mempool
+            .delete_mempool_tx(&txid1, MempoolDeleteMode::Remove, &ScriptFilter::All)
+            .is_err());
+
         Ok(())
     }
 
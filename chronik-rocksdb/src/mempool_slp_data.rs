@@ -3,10 +3,13 @@ use std::collections::HashMap;
 use bitcoinsuite_core::{Sha256d, UnhashedTx};
 use bitcoinsuite_error::Result;
 use bitcoinsuite_slp::{
-    parse_slp_tx, validate_slp_tx, SlpError, SlpSpentOutput, SlpTxType, SlpValidTxData, TokenId,
+    parse_slp_tx, validate_slp_tx, SlpError, SlpParseData, SlpSpentOutput, SlpTxType,
+    SlpValidTxData, TokenId,
 };
 
-use crate::{is_ignored_error, Db, SlpReader, TokenStats, TxReader};
+use crate::{
+    add_saturating, is_ignored_error, sub_saturating, Db, SlpConf, SlpReader, TokenStats, TxReader,
+};
 
 #[derive(Debug, Default)]
 pub struct MempoolSlpData {
@@ -16,16 +19,29 @@ pub struct MempoolSlpData {
 }
 
 impl MempoolSlpData {
-    pub fn insert_mempool_tx(&mut self, db: &Db, txid: &Sha256d, tx: &UnhashedTx) -> Result<()> {
+    pub fn insert_mempool_tx(
+        &mut self,
+        db: &Db,
+        slp_conf: &SlpConf,
+        txid: &Sha256d,
+        tx: &UnhashedTx,
+    ) -> Result<()> {
         let spent_outputs = self.collect_spent_outputs(db, tx)?;
-        let adder = |a: &mut i128, b: i128| *a += b;
-        match self.validate_slp_tx(txid, tx, &spent_outputs)? {
-            Ok(valid_tx_data) => {
-                self.update_token_stats(Some(&valid_tx_data), &spent_outputs, adder);
+        // Like SlpWriter::insert_block_txs, ignored token types are skipped before validation,
+        // so they're neither valid nor invalid SLP, even if they would fail validation
+        let validation = match parse_slp_tx(txid, tx) {
+            Ok(parse_data) if slp_conf.is_ignored_token_type(parse_data.slp_token_type) => None,
+            Ok(parse_data) => Some(validate_parsed_slp_tx(parse_data, &spent_outputs)),
+            Err(slp_error) => Some(Err(slp_error)),
+        };
+        match validation {
+            Some(Ok(valid_tx_data)) => {
+                self.update_token_stats(Some(&valid_tx_data), &spent_outputs, add_saturating);
                 self.valid_slp_txs.insert(txid.clone(), valid_tx_data);
             }
-            Err(slp_error) => {
-                self.update_token_stats(None, &spent_outputs, adder);
+            None => self.update_token_stats(None, &spent_outputs, add_saturating),
+            Some(Err(slp_error)) => {
+                self.update_token_stats(None, &spent_outputs, add_saturating);
                 if !is_ignored_error(&slp_error) {
                     self.invalid_slp_txs.insert(txid.clone(), slp_error);
                 }
@@ -36,12 +52,11 @@ impl MempoolSlpData {
 
     pub fn delete_mempool_tx(&mut self, db: &Db, txid: &Sha256d, tx: &UnhashedTx) -> Result<()> {
         let spent_outputs = self.collect_spent_outputs(db, tx)?;
-        let subber = |a: &mut i128, b: i128| *a -= b;
         match self.valid_slp_txs.remove(txid) {
             Some(valid_tx_data) => {
-                self.update_token_stats(Some(&valid_tx_data), &spent_outputs, subber)
+                self.update_token_stats(Some(&valid_tx_data), &spent_outputs, sub_saturating)
             }
-            None => self.update_token_stats(None, &spent_outputs, subber),
+            None => self.update_token_stats(None, &spent_outputs, sub_saturating),
         }
         self.invalid_slp_txs.remove(txid);
         Ok(())
@@ -57,16 +72,7 @@ impl MempoolSlpData {
             Ok(parse_data) => parse_data,
             Err(slp_error) => return Ok(Err(slp_error)),
         };
-        match validate_slp_tx(
-            parse_data,
-            &spent_outputs
-                .iter()
-                .map(|spent_output| spent_output.as_ref())
-                .collect::<Vec<_>>(),
-        ) {
-            Ok(valid_tx_data) => Ok(Ok(valid_tx_data)),
-            Err(slp_error) => Ok(Err(slp_error)),
-        }
+        Ok(validate_parsed_slp_tx(parse_data, spent_outputs))
     }
 
     pub fn collect_spent_outputs(
@@ -114,7 +120,7 @@ impl MempoolSlpData {
         &mut self,
         valid_tx_data: Option<&SlpValidTxData>,
         spent_outputs: &[Option<SlpSpentOutput>],
-        op: impl Fn(&mut i128, i128),
+        op: impl Fn(i128, i128) -> (i128, bool),
     ) {
         fn update_token(
             this: &mut MempoolSlpData,
@@ -126,18 +132,31 @@ impl MempoolSlpData {
                 .entry(token_id.token_id_be())
                 .or_default();
             f(token_stats);
+            // Once overflowed, the delta is kept around so the flag isn't lost
             if token_stats == &TokenStats::default() {
                 this.token_stats_delta.remove(token_id.as_slice_be());
             }
         }
+        fn update_supply(
+            total: &mut i128,
+            supply_overflowed: &mut bool,
+            amount: i128,
+            op: &impl Fn(i128, i128) -> (i128, bool),
+        ) {
+            let (new_total, overflowed) = op(*total, amount);
+            *total = new_total;
+            *supply_overflowed |= overflowed;
+        }
         match valid_tx_data {
             // SEND has the correct burns computed
             Some(slp) if slp.slp_tx_data.slp_tx_type == SlpTxType::Send => {
                 for burn in slp.slp_burns.iter().flatten() {
                     update_token(self, &burn.token_id, |token_stats| {
-                        op(
+                        update_supply(
                             &mut token_stats.total_burned,
+                            &mut token_stats.supply_overflowed,
                             burn.token.amount.base_amount(),
+                            &op,
                         );
                     });
                 }
@@ -147,9 +166,11 @@ impl MempoolSlpData {
             _ => {
                 for spent_output in spent_outputs.iter().flatten() {
                     update_token(self, &spent_output.token_id, |token_stats| {
-                        op(
+                        update_supply(
                             &mut token_stats.total_burned,
+                            &mut token_stats.supply_overflowed,
                             spent_output.token.amount.base_amount(),
+                            &op,
                         );
                     });
                 }
@@ -163,7 +184,12 @@ impl MempoolSlpData {
         if let SlpTxType::Genesis(_) | SlpTxType::Mint = &slp_tx_data.slp_tx_type {
             for token in &slp_tx_data.output_tokens {
                 update_token(self, &slp_tx_data.token_id, |token_stats| {
-                    op(&mut token_stats.total_minted, token.amount.base_amount());
+                    update_supply(
+                        &mut token_stats.total_minted,
+                        &mut token_stats.supply_overflowed,
+                        token.amount.base_amount(),
+                        &op,
+                    );
                 });
             }
         }
@@ -180,6 +206,46 @@ impl MempoolSlpData {
     pub fn token_stats_delta(&self, token_id: &TokenId) -> Option<&TokenStats> {
         self.token_stats_delta.get(token_id.as_slice_be())
     }
+
+    /// Txids of the valid GENESIS txs in the mempool, in arbitrary order.
+    pub fn genesis_txids(&self) -> impl Iterator<Item = &Sha256d> {
+        self.valid_slp_txs
+            .iter()
+            .filter(|(_, slp)| matches!(slp.slp_tx_data.slp_tx_type, SlpTxType::Genesis(_)))
+            .map(|(txid, _)| txid)
+    }
+
+    /// Txids of the valid MINT txs of the token in the mempool, in arbitrary order.
+    pub fn mint_txids<'a>(&'a self, token_id: &'a TokenId) -> impl Iterator<Item = &'a Sha256d> {
+        self.valid_slp_txs
+            .iter()
+            .filter(move |(_, slp)| {
+                slp.slp_tx_data.slp_tx_type == SlpTxType::Mint
+                    && &slp.slp_tx_data.token_id == token_id
+            })
+            .map(|(txid, _)| txid)
+    }
+
+    /// Txids of all valid SLP txs of the token in the mempool, in arbitrary order.
+    pub fn token_txids<'a>(&'a self, token_id: &'a TokenId) -> impl Iterator<Item = &'a Sha256d> {
+        self.valid_slp_txs
+            .iter()
+            .filter(move |(_, slp)| &slp.slp_tx_data.token_id == token_id)
+            .map(|(txid, _)| txid)
+    }
+}
+
+fn validate_parsed_slp_tx(
+    parse_data: SlpParseData,
+    spent_outputs: &[Option<SlpSpentOutput>],
+) -> std::result::Result<SlpValidTxData, SlpError> {
+    validate_slp_tx(
+        parse_data,
+        &spent_outputs
+            .iter()
+            .map(|spent_output| spent_output.as_ref())
+            .collect::<Vec<_>>(),
+    )
 }
 
 #[cfg(test)]
@@ -194,8 +260,8 @@ mod tests {
     use rocksdb::WriteBatch;
 
     use crate::{
-        input_tx_nums::fetch_input_tx_nums, BlockTxs, Db, MempoolSlpData, SlpReader, SlpWriter,
-        TokenStats, TxEntry, TxWriter,
+        input_tx_nums::fetch_input_tx_nums, BlockTxs, Db, MempoolSlpData, SlpConf, SlpReader,
+        SlpWriter, TokenStats, TxEntry, TxWriter,
     };
 
     #[test]
@@ -203,7 +269,7 @@ mod tests {
         let tempdir = tempdir::TempDir::new("slp-indexer-rocks--utxos")?;
         let db = Db::open(tempdir.path())?;
         let tx_writer = TxWriter::new(&db)?;
-        let slp_writer = SlpWriter::new(&db)?;
+        let slp_writer = SlpWriter::new(&db, SlpConf::default())?;
         let slp_reader = SlpReader::new(&db)?;
         let token_id = TokenId::new(make_hash(2));
         let (block_txids, block_txs) = make_block([
@@ -261,6 +327,7 @@ mod tests {
             Some(TokenStats {
                 total_minted: 13,
                 total_burned: 0,
+                supply_overflowed: false,
             }),
         );
 
@@ -273,7 +340,7 @@ mod tests {
                 &[SlpAmount::new(1), SlpAmount::new(2)],
             ),
         );
-        slp_mempool.insert_mempool_tx(&db, &txid0, &tx0)?;
+        slp_mempool.insert_mempool_tx(&db, &SlpConf::default(), &txid0, &tx0)?;
         assert_eq!(slp_mempool.slp_tx_error(&txid0), None);
         assert_eq!(
             slp_mempool.slp_tx_data(&txid0),
@@ -294,7 +361,7 @@ mod tests {
             (11, [(10, 1), (3, 2)], 2),
             send_opreturn(&token_id, SlpTokenType::Fungible, &[SlpAmount::new(9)]),
         );
-        slp_mempool.insert_mempool_tx(&db, &txid1, &tx1)?;
+        slp_mempool.insert_mempool_tx(&db, &SlpConf::default(), &txid1, &tx1)?;
         assert_eq!(slp_mempool.slp_tx_data(&txid1), None);
         assert_eq!(
             slp_mempool.slp_tx_error(&txid1),
@@ -308,6 +375,7 @@ mod tests {
             Some(&TokenStats {
                 total_minted: 0,
                 total_burned: 8,
+                supply_overflowed: false,
             }),
         );
 
@@ -321,7 +389,7 @@ mod tests {
         assert_eq!(slp_mempool.slp_tx_error(&txid0), None);
 
         let (txid0, tx0) = make_tx((10, [(3, 1)], 2), Script::opreturn(&[b"SLP\0"]));
-        slp_mempool.insert_mempool_tx(&db, &txid0, &tx0)?;
+        slp_mempool.insert_mempool_tx(&db, &SlpConf::default(), &txid0, &tx0)?;
         assert_eq!(slp_mempool.slp_tx_data(&txid0), None);
         assert_eq!(
             slp_mempool.slp_tx_error(&txid0),
@@ -335,11 +403,12 @@ mod tests {
             Some(&TokenStats {
                 total_minted: 0,
                 total_burned: 3,
+                supply_overflowed: false,
             }),
         );
 
         let (txid1, tx1) = make_tx((11, [(3, 2)], 2), Script::from_slice(b"\x04SLP\0\x01"));
-        slp_mempool.insert_mempool_tx(&db, &txid1, &tx1)?;
+        slp_mempool.insert_mempool_tx(&db, &SlpConf::default(), &txid1, &tx1)?;
         assert_eq!(slp_mempool.slp_tx_data(&txid1), None);
         assert_eq!(slp_mempool.slp_tx_error(&txid1), None);
         assert_eq!(
@@ -347,6 +416,7 @@ mod tests {
             Some(&TokenStats {
                 total_minted: 0,
                 total_burned: 10,
+                supply_overflowed: false,
             }),
         );
 
@@ -359,7 +429,7 @@ mod tests {
                 1000,
             ),
         );
-        slp_mempool.insert_mempool_tx(&db, &txid2, &tx2)?;
+        slp_mempool.insert_mempool_tx(&db, &SlpConf::default(), &txid2, &tx2)?;
         let token_id2 = TokenId::new(txid2.clone());
         assert_eq!(slp_mempool.slp_tx_error(&txid2), None);
         assert_eq!(
@@ -385,6 +455,7 @@ mod tests {
             Some(&TokenStats {
                 total_minted: 1000,
                 total_burned: 0,
+                supply_overflowed: false,
             }),
         );
 
@@ -392,7 +463,7 @@ mod tests {
             (13, [(12, 2)], 3),
             mint_opreturn(&token_id2, SlpTokenType::Fungible, Some(2), 400),
         );
-        slp_mempool.insert_mempool_tx(&db, &txid3, &tx3)?;
+        slp_mempool.insert_mempool_tx(&db, &SlpConf::default(), &txid3, &tx3)?;
         assert_eq!(slp_mempool.slp_tx_error(&txid3), None);
         assert_eq!(
             slp_mempool.slp_tx_data(&txid3),
@@ -417,6 +488,7 @@ mod tests {
             Some(&TokenStats {
                 total_minted: 1400,
                 total_burned: 0,
+                supply_overflowed: false,
             }),
         );
 
@@ -428,7 +500,7 @@ mod tests {
                 &[SlpAmount::new(1), SlpAmount::new(2)],
             ),
         );
-        slp_mempool.insert_mempool_tx(&db, &txid4, &tx4)?;
+        slp_mempool.insert_mempool_tx(&db, &SlpConf::default(), &txid4, &tx4)?;
         assert_eq!(slp_mempool.slp_tx_error(&txid4), None);
         assert_eq!(
             slp_mempool.slp_tx_data(&txid4),
@@ -456,6 +528,7 @@ mod tests {
             Some(&TokenStats {
                 total_minted: 1400,
                 total_burned: 1000,
+                supply_overflowed: false,
             }),
         );
 
@@ -467,6 +540,7 @@ mod tests {
             Some(&TokenStats {
                 total_minted: 0,
                 total_burned: 3,
+                supply_overflowed: false,
             })
         );
 
@@ -483,6 +557,7 @@ mod tests {
             Some(&TokenStats {
                 total_minted: 400,
                 total_burned: 1000,
+                supply_overflowed: false,
             })
         );
 
@@ -494,6 +569,7 @@ mod tests {
             Some(&TokenStats {
                 total_minted: 0,
                 total_burned: 1000,
+                supply_overflowed: false,
             })
         );
 
@@ -505,6 +581,91 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_slp_mempool_chained() -> Result<()> {
+        let tempdir = tempdir::TempDir::new("slp-indexer-rocks--slp-mempool-chained")?;
+        let db = Db::open(tempdir.path())?;
+        let slp_conf = SlpConf {
+            ignore_unknown_token_types: true,
+        };
+        let mut slp_mempool = MempoolSlpData::default();
+
+        // GENESIS and a SEND of the new token, both only in the mempool
+        let (txid0, tx0) = make_tx(
+            (20, [(1, 0)], 2),
+            genesis_opreturn(
+                &SlpGenesisInfo::default(),
+                SlpTokenType::Fungible,
+                None,
+                100,
+            ),
+        );
+        slp_mempool.insert_mempool_tx(&db, &slp_conf, &txid0, &tx0)?;
+        let token_id = TokenId::new(txid0.clone());
+        let (txid1, tx1) = make_tx(
+            (21, [(20, 1)], 3),
+            send_opreturn(
+                &token_id,
+                SlpTokenType::Fungible,
+                &[SlpAmount::new(60), SlpAmount::new(40)],
+            ),
+        );
+        slp_mempool.insert_mempool_tx(&db, &slp_conf, &txid1, &tx1)?;
+        assert_eq!(slp_mempool.slp_tx_error(&txid1), None);
+        assert_eq!(
+            slp_mempool.slp_tx_data(&txid1),
+            Some(&SlpValidTxData {
+                slp_tx_data: SlpTxData {
+                    input_tokens: vec![SlpToken::amount(100)],
+                    output_tokens: vec![
+                        SlpToken::EMPTY,
+                        SlpToken::amount(60),
+                        SlpToken::amount(40),
+                    ],
+                    slp_token_type: SlpTokenType::Fungible,
+                    slp_tx_type: SlpTxType::Send,
+                    token_id: token_id.clone(),
+                    group_token_id: None,
+                },
+                slp_burns: vec![None],
+            })
+        );
+        assert_eq!(
+            slp_mempool.token_stats_delta(&token_id),
+            Some(&TokenStats {
+                total_minted: 100,
+                total_burned: 0,
+                supply_overflowed: false,
+            }),
+        );
+
+        // Tx of an ignored token type is non-SLP, like in a block, and burns its SLP inputs
+        let (txid2, tx2) = make_tx(
+            (22, [(21, 1)], 2),
+            Script::opreturn(&[b"SLP\0", b"\xff", b"INCOGNITO"]),
+        );
+        slp_mempool.insert_mempool_tx(&db, &slp_conf, &txid2, &tx2)?;
+        assert_eq!(slp_mempool.slp_tx_data(&txid2), None);
+        assert_eq!(slp_mempool.slp_tx_error(&txid2), None);
+        assert_eq!(
+            slp_mempool.token_stats_delta(&token_id),
+            Some(&TokenStats {
+                total_minted: 100,
+                total_burned: 60,
+                supply_overflowed: false,
+            }),
+        );
+
+        slp_mempool.delete_mempool_tx(&db, &txid2, &tx2)?;
+        slp_mempool.delete_mempool_tx(&db, &txid1, &tx1)?;
+        slp_mempool.delete_mempool_tx(&db, &txid0, &tx0)?;
+        assert_eq!(slp_mempool.slp_tx_data(&txid0), None);
+        assert_eq!(slp_mempool.slp_tx_data(&txid1), None);
+        assert_eq!(slp_mempool.token_stats_delta(&token_id), None);
+
+        Ok(())
+    }
+
     fn make_block<const N: usize>(
         txs: [(Sha256d, UnhashedTx); N],
     ) -> (Vec<Sha256d>, Vec<UnhashedTx>) {
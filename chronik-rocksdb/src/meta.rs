@@ -0,0 +1,201 @@
+use std::marker::PhantomData;
+
+use bitcoinsuite_error::{ErrorMeta, Result, WrapErr};
+use rocksdb::{ColumnFamilyDescriptor, Options, WriteBatch};
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+use crate::{Db, CF};
+
+pub const CF_META: &str = "meta";
+
+/// Name of a value stored in the meta CF, typed by the value stored under it.
+///
+/// Meant for small scalars of features (e.g. running totals or timestamps), so they don't each
+/// need their own CF. Values are serialized using bincode, so changing `T` of an existing key
+/// requires a schema version bump.
+#[derive(Debug)]
+pub struct MetaKey<T> {
+    name: &'static str,
+    value: PhantomData<fn() -> T>,
+}
+
+pub struct MetaWriter<'a> {
+    cf_meta: &'a CF,
+}
+
+pub struct MetaReader<'a> {
+    db: &'a Db,
+}
+
+#[derive(Debug, Error, ErrorMeta)]
+pub enum MetaError {
+    #[critical()]
+    #[error("Inconsistent db, invalid value for meta key {0}")]
+    InvalidMetaValue(&'static str),
+
+    #[critical()]
+    #[error("Cannot serialize value for meta key {0}")]
+    CannotSerializeMetaValue(&'static str),
+}
+
+use self::MetaError::*;
+
+impl<T> MetaKey<T> {
+    pub const fn new(name: &'static str) -> Self {
+        MetaKey {
+            name,
+            value: PhantomData,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl<'a> MetaWriter<'a> {
+    pub fn add_cfs(columns: &mut Vec<ColumnFamilyDescriptor>) {
+        columns.push(ColumnFamilyDescriptor::new(CF_META, Options::default()));
+    }
+
+    pub fn new(db: &'a Db) -> Result<Self> {
+        let cf_meta = db.cf(CF_META)?;
+        Ok(MetaWriter { cf_meta })
+    }
+
+    /// Sets `key` to `value` as part of `batch`, so it's written atomically with the rest of the
+    /// batch, e.g. with the block whose connecting changed the value. Disconnecting that block
+    /// has to set the value back accordingly.
+    pub fn put<T: Serialize>(
+        &self,
+        batch: &mut WriteBatch,
+        key: &MetaKey<T>,
+        value: &T,
+    ) -> Result<()> {
+        let value = bincode::serialize(value).wrap_err(CannotSerializeMetaValue(key.name))?;
+        batch.put_cf(self.cf_meta, key.name.as_bytes(), value);
+        Ok(())
+    }
+
+    pub fn delete<T>(&self, batch: &mut WriteBatch, key: &MetaKey<T>) {
+        batch.delete_cf(self.cf_meta, key.name.as_bytes());
+    }
+}
+
+impl<'a> MetaReader<'a> {
+    pub fn new(db: &'a Db) -> Result<Self> {
+        db.cf(CF_META)?;
+        Ok(MetaReader { db })
+    }
+
+    /// Value of `key`, None if it has never been set (or was deleted).
+    pub fn get<T: DeserializeOwned>(&self, key: &MetaKey<T>) -> Result<Option<T>> {
+        let value = match self.db.get(self.cf_meta(), key.name.as_bytes())? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let value = bincode::deserialize(&value).wrap_err(InvalidMetaValue(key.name))?;
+        Ok(Some(value))
+    }
+
+    fn cf_meta(&self) -> &CF {
+        self.db.cf(CF_META).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoinsuite_error::Result;
+    use pretty_assertions::assert_eq;
+    use rocksdb::WriteBatch;
+    use serde::{Deserialize, Serialize};
+
+    use crate::{Db, MetaError, MetaKey, MetaReader, MetaWriter};
+
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    struct Totals {
+        num_txs: u64,
+        sum_sats: i64,
+    }
+
+    const KEY_HEIGHT: MetaKey<i32> = MetaKey::new("height");
+    const KEY_NAME: MetaKey<String> = MetaKey::new("name");
+    const KEY_FEATURES: MetaKey<Vec<String>> = MetaKey::new("features");
+    const KEY_TOTALS: MetaKey<Totals> = MetaKey::new("totals");
+
+    #[test]
+    fn test_meta() -> Result<()> {
+        bitcoinsuite_error::install()?;
+        let tempdir = tempdir::TempDir::new("slp-indexer-rocks--meta")?;
+        let db = Db::open(tempdir.path())?;
+        let writer = MetaWriter::new(&db)?;
+        let reader = MetaReader::new(&db)?;
+        assert_eq!(reader.get(&KEY_HEIGHT)?, None);
+        assert_eq!(reader.get(&KEY_TOTALS)?, None);
+
+        let totals = Totals {
+            num_txs: 3,
+            sum_sats: -5000,
+        };
+        let mut batch = WriteBatch::default();
+        writer.put(&mut batch, &KEY_HEIGHT, &100)?;
+        writer.put(&mut batch, &KEY_NAME, &"chronik".to_string())?;
+        writer.put(
+            &mut batch,
+            &KEY_FEATURES,
+            &vec!["a".to_string(), "b".to_string()],
+        )?;
+        writer.put(&mut batch, &KEY_TOTALS, &totals)?;
+        db.write_batch(batch)?;
+        assert_eq!(reader.get(&KEY_HEIGHT)?, Some(100));
+        assert_eq!(reader.get(&KEY_NAME)?, Some("chronik".to_string()));
+        assert_eq!(
+            reader.get(&KEY_FEATURES)?,
+            Some(vec!["a".to_string(), "b".to_string()]),
+        );
+        assert_eq!(reader.get(&KEY_TOTALS)?, Some(totals.clone()));
+
+        // Connecting a block updates the totals along with its other writes
+        let mut batch = WriteBatch::default();
+        let new_totals = Totals {
+            num_txs: 5,
+            sum_sats: 1000,
+        };
+        writer.put(&mut batch, &KEY_TOTALS, &new_totals)?;
+        writer.put(&mut batch, &KEY_HEIGHT, &101)?;
+        db.write_batch(batch)?;
+        assert_eq!(reader.get(&KEY_TOTALS)?, Some(new_totals));
+
+        // Disconnecting it sets them back
+        let mut batch = WriteBatch::default();
+        writer.put(&mut batch, &KEY_TOTALS, &totals)?;
+        writer.put(&mut batch, &KEY_HEIGHT, &100)?;
+        db.write_batch(batch)?;
+        assert_eq!(reader.get(&KEY_TOTALS)?, Some(totals.clone()));
+        assert_eq!(reader.get(&KEY_HEIGHT)?, Some(100));
+
+        // A batch that's never written leaves the values untouched
+        let mut batch = WriteBatch::default();
+        writer.put(&mut batch, &KEY_HEIGHT, &102)?;
+        std::mem::drop(batch);
+        assert_eq!(reader.get(&KEY_HEIGHT)?, Some(100));
+
+        let mut batch = WriteBatch::default();
+        writer.delete(&mut batch, &KEY_NAME);
+        db.write_batch(batch)?;
+        assert_eq!(reader.get(&KEY_NAME)?, None);
+
+        // Reading a value with the wrong type fails instead of returning garbage
+        let key_height_as_totals = MetaKey::<Totals>::new("height");
+        let err = reader
+            .get(&key_height_as_totals)
+            .unwrap_err()
+            .downcast::<MetaError>()?;
+        assert_eq!(
+            err.to_string(),
+            "Inconsistent db, invalid value for meta key height",
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,183 @@
+use std::{collections::HashMap, path::Path};
+
+use bitcoinsuite_error::{ErrorMeta, Result, WrapErr};
+use rocksdb::{Options, WriteBatch};
+use thiserror::Error;
+
+use crate::{BlockReader, Db, DbError, DbSchema, CF};
+
+/// Size of the header of a serialized `WriteBatch` (sequence number and count).
+const BATCH_HEADER_SIZE: usize = 12;
+
+// Record tags of a serialized `WriteBatch`, see RocksDB's `db/dbformat.h`.
+const TAG_DELETION: u8 = 0x0;
+const TAG_VALUE: u8 = 0x1;
+const TAG_MERGE: u8 = 0x2;
+const TAG_CF_DELETION: u8 = 0x4;
+const TAG_CF_VALUE: u8 = 0x5;
+const TAG_CF_MERGE: u8 = 0x6;
+
+/// Second DB every batch is also written to, see `DbConf::replica_path`.
+pub(crate) struct Replica {
+    db: Db,
+    /// Names of the primary's CFs by ID. CF IDs are assigned in creation order, so they differ
+    /// between the two DBs if CFs were added to the primary over several versions; batches are
+    /// therefore rebuilt for the replica by CF name.
+    primary_cf_names: HashMap<u32, String>,
+}
+
+#[derive(Debug, Error, ErrorMeta)]
+pub enum ReplicaError {
+    #[critical()]
+    #[error(
+        "Replica {field} differs from the index: {replica} vs. {primary}. Re-create the replica \
+         by copying the index while Chronik is stopped."
+    )]
+    ReplicaOutOfSync {
+        field: &'static str,
+        replica: String,
+        primary: String,
+    },
+
+    #[critical()]
+    #[error("Malformed write batch: {0}")]
+    MalformedWriteBatch(&'static str),
+
+    #[critical()]
+    #[error("Write batch uses unknown column family ID {0}")]
+    UnknownColumnFamilyId(u32),
+}
+
+use self::ReplicaError::*;
+
+enum BatchOp<'b> {
+    Put(&'b [u8], &'b [u8]),
+    Delete(&'b [u8]),
+    Merge(&'b [u8], &'b [u8]),
+}
+
+impl Replica {
+    /// Uses `db` as replica of `primary`, refusing if it's on another schema version or tip.
+    pub(crate) fn new(primary: &Db, primary_path: &Path, db: Db) -> Result<Self> {
+        let primary_version = DbSchema::new(primary)?.version()?;
+        let replica_version = DbSchema::new(&db)?.version()?;
+        if primary_version != replica_version {
+            return Err(ReplicaOutOfSync {
+                field: "schema version",
+                replica: format!("{:?}", replica_version),
+                primary: format!("{:?}", primary_version),
+            }
+            .into());
+        }
+        let primary_tip = BlockReader::new(primary)?.tip()?.map(|block| block.hash);
+        let replica_tip = BlockReader::new(&db)?.tip()?.map(|block| block.hash);
+        if primary_tip != replica_tip {
+            return Err(ReplicaOutOfSync {
+                field: "tip",
+                replica: format!("{:?}", replica_tip),
+                primary: format!("{:?}", primary_tip),
+            }
+            .into());
+        }
+        let cf_names =
+            rocksdb::DB::list_cf(&Options::default(), primary_path).wrap_err(DbError::RocksDb)?;
+        let mut primary_cf_names = HashMap::new();
+        for cf_name in cf_names {
+            let cf_id = cf_id(primary.cf(&cf_name)?)?;
+            primary_cf_names.insert(cf_id, cf_name);
+        }
+        Ok(Replica {
+            db,
+            primary_cf_names,
+        })
+    }
+
+    /// Rebuilds `batch` of the primary for the replica, without writing it yet.
+    pub(crate) fn replica_batch(&self, batch: &WriteBatch) -> Result<WriteBatch> {
+        let mut replica_batch = WriteBatch::default();
+        for record in parse_batch(batch.data())? {
+            let (cf_id, op) = record?;
+            let cf_name = self
+                .primary_cf_names
+                .get(&cf_id)
+                .ok_or(UnknownColumnFamilyId(cf_id))?;
+            let cf = self.db.cf(cf_name)?;
+            match op {
+                BatchOp::Put(key, value) => replica_batch.put_cf(cf, key, value),
+                BatchOp::Delete(key) => replica_batch.delete_cf(cf, key),
+                BatchOp::Merge(key, value) => replica_batch.merge_cf(cf, key, value),
+            }
+        }
+        Ok(replica_batch)
+    }
+
+    pub(crate) fn write(&self, batch: WriteBatch) -> Result<()> {
+        self.db
+            .rocks()
+            .write(batch)
+            .wrap_err(DbError::RocksDbReplica)
+    }
+}
+
+/// ID of `cf`, read back from a batch with a single write to it.
+fn cf_id(cf: &CF) -> Result<u32> {
+    let mut batch = WriteBatch::default();
+    batch.put_cf(cf, [], []);
+    let mut records = parse_batch(batch.data())?;
+    match records.next() {
+        Some(record) => Ok(record?.0),
+        None => Err(MalformedWriteBatch("missing record").into()),
+    }
+}
+
+/// Iterates the `(cf_id, op)` records of a serialized `WriteBatch`, see RocksDB's
+/// `ReadRecordFromWriteBatch`.
+fn parse_batch(data: &[u8]) -> Result<impl Iterator<Item = Result<(u32, BatchOp<'_>)>>> {
+    let mut data = data
+        .get(BATCH_HEADER_SIZE..)
+        .ok_or(MalformedWriteBatch("missing header"))?;
+    Ok(std::iter::from_fn(move || {
+        let (&tag, rest) = data.split_first()?;
+        data = rest;
+        Some(parse_record(tag, &mut data))
+    }))
+}
+
+fn parse_record<'b>(tag: u8, data: &mut &'b [u8]) -> Result<(u32, BatchOp<'b>)> {
+    let cf_id = match tag {
+        TAG_CF_VALUE | TAG_CF_DELETION | TAG_CF_MERGE => read_varint32(data)?,
+        _ => 0,
+    };
+    let op = match tag {
+        TAG_VALUE | TAG_CF_VALUE => BatchOp::Put(read_slice(data)?, read_slice(data)?),
+        TAG_DELETION | TAG_CF_DELETION => BatchOp::Delete(read_slice(data)?),
+        TAG_MERGE | TAG_CF_MERGE => BatchOp::Merge(read_slice(data)?, read_slice(data)?),
+        _ => return Err(MalformedWriteBatch("unsupported record type").into()),
+    };
+    Ok((cf_id, op))
+}
+
+fn read_varint32(data: &mut &[u8]) -> Result<u32> {
+    let mut result = 0u32;
+    for shift in (0..35).step_by(7) {
+        let (&byte, rest) = data
+            .split_first()
+            .ok_or(MalformedWriteBatch("truncated varint"))?;
+        *data = rest;
+        result |= u32::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(result);
+        }
+    }
+    Err(MalformedWriteBatch("varint too long").into())
+}
+
+fn read_slice<'b>(data: &mut &'b [u8]) -> Result<&'b [u8]> {
+    let len = read_varint32(data)? as usize;
+    if data.len() < len {
+        return Err(MalformedWriteBatch("truncated slice").into());
+    }
+    let (slice, rest) = data.split_at(len);
+    *data = rest;
+    Ok(slice)
+}
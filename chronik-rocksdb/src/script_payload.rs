@@ -1,6 +1,10 @@
+use std::collections::HashSet;
+
 use bitcoinsuite_core::{ecc::PubKey, Hashed, Script, ScriptVariant, ShaRmd160};
+use rocksdb::{BlockBasedOptions, Options};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum PayloadPrefix {
     Other = 0,
     P2PK = 1,
@@ -11,7 +15,19 @@ pub enum PayloadPrefix {
     P2TRState = 6,
 }
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+impl PayloadPrefix {
+    pub const ALL: [PayloadPrefix; 7] = [
+        PayloadPrefix::Other,
+        PayloadPrefix::P2PK,
+        PayloadPrefix::P2PKLegacy,
+        PayloadPrefix::P2PKH,
+        PayloadPrefix::P2SH,
+        PayloadPrefix::P2TRCommitment,
+        PayloadPrefix::P2TRState,
+    ];
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct ScriptPayload {
     pub payload_prefix: PayloadPrefix,
     pub payload_data: Vec<u8>,
@@ -25,6 +41,26 @@ pub struct ScriptPayloadState {
     pub is_partial: bool,
 }
 
+/// Which scripts get indexed in the script_txs and utxos CFs (and the mempool equivalents).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptFilter {
+    /// Index every script.
+    All,
+    /// Watch-only mode: Only index scripts with one of the given payloads.
+    Watched(HashSet<ScriptPayload>),
+}
+
+/// Bits per key of the bloom filters of CFs keyed by script payload.
+const PAYLOAD_BLOOM_BITS_PER_KEY: i32 = 10;
+
+/// Configures bloom filters for a CF keyed by `prefix || payload_data`. They cover whole keys,
+/// and also the prefixes of the CF's prefix extractor, if it has one.
+pub(crate) fn set_payload_bloom_options(options: &mut Options) {
+    let mut block_options = BlockBasedOptions::default();
+    block_options.set_bloom_filter(PAYLOAD_BLOOM_BITS_PER_KEY, false);
+    options.set_block_based_table_factory(&block_options);
+}
+
 pub fn script_payloads(script: &Script) -> Vec<ScriptPayloadState> {
     use PayloadPrefix::*;
     match script.parse_variant() {
@@ -98,6 +134,28 @@ pub fn script_payloads(script: &Script) -> Vec<ScriptPayloadState> {
     }
 }
 
+impl ScriptFilter {
+    pub fn is_indexed(&self, payload: &ScriptPayload) -> bool {
+        match self {
+            ScriptFilter::All => true,
+            ScriptFilter::Watched(payloads) => payloads.contains(payload),
+        }
+    }
+
+    /// Like `script_payloads`, but only returns payloads that are indexed.
+    pub fn script_payloads(&self, script: &Script) -> Vec<ScriptPayloadState> {
+        let mut payloads = script_payloads(script);
+        payloads.retain(|payload_state| self.is_indexed(&payload_state.payload));
+        payloads
+    }
+}
+
+impl Default for ScriptFilter {
+    fn default() -> Self {
+        ScriptFilter::All
+    }
+}
+
 impl ScriptPayload {
     pub fn into_vec(self) -> Vec<u8> {
         let mut script_payload = self.payload_data;
@@ -3,7 +3,9 @@ use std::collections::{BTreeSet, HashMap};
 use bitcoinsuite_core::{TxOutput, UnhashedTx};
 use bitcoinsuite_error::Result;
 use lru::LruCache;
-use rocksdb::{ColumnFamilyDescriptor, Direction, IteratorMode, Options, WriteBatch};
+use rocksdb::{
+    ColumnFamilyDescriptor, Direction, IteratorMode, Options, SliceTransform, WriteBatch,
+};
 use zerocopy::AsBytes;
 
 use crate::{
@@ -11,8 +13,8 @@ use crate::{
     merge_ops::{
         full_merge_ordered_list, partial_merge_ordered_list, PREFIX_DELETE, PREFIX_INSERT,
     },
-    script_payload::{script_payloads, PayloadPrefix},
-    Db, Timings, TxNum, TxNumOrd, TxNumZC, CF,
+    script_payload::{set_payload_bloom_options, PayloadPrefix},
+    Db, DbConf, ScriptFilter, Timings, TxNum, TxNumOrd, TxNumZC, CF,
 };
 
 pub const CF_SCRIPT_TXS: &str = "script_txs";
@@ -29,6 +31,7 @@ pub struct ScriptTxsWriter<'a> {
     db: &'a Db,
     cf_script_txs: &'a CF,
     conf: ScriptTxsConf,
+    script_filter: &'a ScriptFilter,
 }
 
 pub struct ScriptTxsReader<'a> {
@@ -40,25 +43,48 @@ pub struct ScriptTxsReader<'a> {
 pub struct ScriptTxsWriterCache {
     capacity: usize,
     num_txs_by_script: LruCache<Vec<u8>, u32>,
+    num_hits: u64,
+    num_misses: u64,
+}
+
+/// Usage of the script history cache since startup, to tune its capacity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ScriptTxsCacheStats {
+    pub capacity: usize,
+    pub num_entries: usize,
+    pub num_hits: u64,
+    pub num_misses: u64,
 }
 
 impl<'a> ScriptTxsWriter<'a> {
-    pub fn add_cfs(columns: &mut Vec<ColumnFamilyDescriptor>) {
+    pub fn add_cfs(columns: &mut Vec<ColumnFamilyDescriptor>, conf: &DbConf) {
         let mut options = Options::default();
         options.set_merge_operator(
             "slp-indexer-rocks.MergeScriptTxs",
             full_merge_ordered_list::<TxNumOrd>,
             partial_merge_ordered_list::<TxNumOrd>,
         );
+        if conf.payload_prefix_bloom {
+            // The prefix is the key without its page num, i.e. the whole `prefix || payload_data`,
+            // so lookups of a script's pages can skip every file without that script.
+            options.set_prefix_extractor(SliceTransform::create(
+                "slp-indexer-rocks.ScriptTxsPayload",
+                script_payload_of_key,
+                Some(key_has_page_num),
+            ));
+            options.set_memtable_prefix_bloom_ratio(0.1);
+            set_payload_bloom_options(&mut options);
+        }
         columns.push(ColumnFamilyDescriptor::new(CF_SCRIPT_TXS, options));
     }
 
-    pub fn new(db: &'a Db, conf: ScriptTxsConf) -> Result<Self> {
+    pub fn new(db: &'a Db, conf: ScriptTxsConf, script_filter: &'a ScriptFilter) -> Result<Self> {
         let cf_script_txs = db.cf(CF_SCRIPT_TXS)?;
         Ok(ScriptTxsWriter {
             db,
             cf_script_txs,
             conf,
+            script_filter,
         })
     }
 
@@ -71,7 +97,12 @@ impl<'a> ScriptTxsWriter<'a> {
         script_txs_cache: &mut ScriptTxsWriterCache,
     ) -> Result<Timings> {
         let timings = Timings::default();
-        let payload_tx_nums = prepare_tx_nums_by_payload(first_tx_num, txs, block_spent_output_fn);
+        let payload_tx_nums = prepare_tx_nums_by_payload(
+            first_tx_num,
+            txs,
+            block_spent_output_fn,
+            self.script_filter,
+        );
         for (script_payload, tx_nums) in payload_tx_nums {
             let start_num_txs = script_txs_cache.get_num_txs_by_payload(
                 self.db,
@@ -100,7 +131,12 @@ impl<'a> ScriptTxsWriter<'a> {
         block_spent_output_fn: impl Fn(/*tx_idx:*/ usize, /*out_idx:*/ usize) -> &'b TxOutput,
         script_txs_cache: &mut ScriptTxsWriterCache,
     ) -> Result<()> {
-        let payload_tx_nums = prepare_tx_nums_by_payload(first_tx_num, txs, block_spent_output_fn);
+        let payload_tx_nums = prepare_tx_nums_by_payload(
+            first_tx_num,
+            txs,
+            block_spent_output_fn,
+            self.script_filter,
+        );
         for (script_payload, tx_nums) in payload_tx_nums {
             let start_num_txs = script_txs_cache.get_num_txs_by_payload(
                 self.db,
@@ -127,16 +163,25 @@ fn key_for_script_payload(script_payload: &[u8], page_num: u32) -> Vec<u8> {
     [script_payload, page_num.to_be_bytes().as_ref()].concat()
 }
 
+fn script_payload_of_key(key: &[u8]) -> &[u8] {
+    &key[..key.len() - PAGE_NUM_SIZE]
+}
+
+fn key_has_page_num(key: &[u8]) -> bool {
+    key.len() > PAGE_NUM_SIZE
+}
+
 fn prepare_tx_nums_by_payload<'b>(
     first_tx_num: TxNum,
     txs: &[UnhashedTx],
     block_spent_output_fn: impl Fn(/*tx_idx:*/ usize, /*out_idx:*/ usize) -> &'b TxOutput,
+    script_filter: &ScriptFilter,
 ) -> HashMap<Vec<u8>, BTreeSet<TxNum>> {
     let mut payload_tx_nums = HashMap::<_, BTreeSet<TxNum>>::new();
     for (tx_idx, tx) in txs.iter().enumerate() {
         let tx_num = first_tx_num + tx_idx as u64;
         for output in &tx.outputs {
-            for script_payload in script_payloads(&output.script) {
+            for script_payload in script_filter.script_payloads(&output.script) {
                 let script_payload = script_payload.payload.into_vec();
                 let tx_nums = payload_tx_nums.entry(script_payload).or_default();
                 tx_nums.insert(tx_num);
@@ -149,7 +194,7 @@ fn prepare_tx_nums_by_payload<'b>(
         let tx_pos = tx_idx - 1;
         for input_idx in 0..tx.inputs.len() {
             let spent_output = block_spent_output_fn(tx_pos, input_idx);
-            for script_payload in script_payloads(&spent_output.script) {
+            for script_payload in script_filter.script_payloads(&spent_output.script) {
                 let script_payload = script_payload.payload.into_vec();
                 let tx_nums = payload_tx_nums.entry(script_payload).or_default();
                 tx_nums.insert(tx_num);
@@ -179,9 +224,11 @@ impl<'a> ScriptTxsReader<'a> {
         payload_data: &[u8],
     ) -> Result<usize> {
         let script_payload = [[prefix as u8].as_ref(), payload_data].concat();
+        // Seek to a full key, so it has the same prefix as the keys of this script
+        let first_key = key_for_script_payload(&script_payload, 0);
         let iterator = self.db.rocks().iterator_cf(
             self.cf_script_txs,
-            IteratorMode::From(&script_payload, Direction::Forward),
+            IteratorMode::From(&first_key, Direction::Forward),
         );
         let num_pages = iterator
             .take_while(|(key, _)| {
@@ -217,6 +264,23 @@ impl ScriptTxsWriterCache {
         ScriptTxsWriterCache {
             capacity,
             num_txs_by_script: LruCache::new(capacity),
+            num_hits: 0,
+            num_misses: 0,
+        }
+    }
+
+    /// Changes the capacity, evicting the least recently used entries if it shrinks.
+    pub fn resize(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.num_txs_by_script.resize(capacity);
+    }
+
+    pub fn stats(&self) -> ScriptTxsCacheStats {
+        ScriptTxsCacheStats {
+            capacity: self.capacity,
+            num_entries: self.num_txs_by_script.len(),
+            num_hits: self.num_hits,
+            num_misses: self.num_misses,
         }
     }
 
@@ -229,8 +293,10 @@ impl ScriptTxsWriterCache {
     ) -> Result<u32> {
         if self.capacity > 0 {
             if let Some(&num_txs) = self.num_txs_by_script.get(payload) {
+                self.num_hits += 1;
                 return Ok(num_txs);
             }
+            self.num_misses += 1;
         }
         let last_key = key_for_script_payload(payload, std::u32::MAX);
         let mut iterator = db
@@ -277,8 +343,9 @@ impl ScriptTxsWriterCache {
 #[cfg(test)]
 mod test {
     use crate::{
-        script_txs::key_for_script_payload, Db, PayloadPrefix, ScriptTxsConf, ScriptTxsReader,
-        ScriptTxsWriter, ScriptTxsWriterCache, TxNum, TxNumZC,
+        script_payload::script_payloads, script_txs::key_for_script_payload, Db, DbConf,
+        PayloadPrefix, ScriptFilter, ScriptTxsCacheStats, ScriptTxsConf, ScriptTxsReader,
+        ScriptTxsWriter, ScriptTxsWriterCache, TxNum, TxNumZC, CF_SCRIPT_TXS,
     };
     use bitcoinsuite_core::{
         ecc::PubKey, OutPoint, Script, Sha256d, ShaRmd160, TxInput, TxOutput, UnhashedTx,
@@ -290,13 +357,130 @@ mod test {
 
     #[test]
     fn test_scripts() -> Result<()> {
+        check_scripts(&DbConf::default())
+    }
+
+    #[test]
+    fn test_scripts_payload_prefix_bloom() -> Result<()> {
+        check_scripts(&DbConf {
+            payload_prefix_bloom: true,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_scripts_watch_only() -> Result<()> {
         use PayloadPrefix::*;
         bitcoinsuite_error::install()?;
-        let tempdir = tempdir::TempDir::new("slp-indexer-rocks--scripts")?;
+        let tempdir = tempdir::TempDir::new("slp-indexer-rocks--scripts-watch-only")?;
         let db = Db::open(tempdir.path())?;
         let mut cache = ScriptTxsWriterCache::with_capacity(4);
         let conf = ScriptTxsConf { page_size: 4 };
-        let script_txs_writer = ScriptTxsWriter::new(&db, conf.clone())?;
+        let (script1, payload1) = (Script::p2pkh(&ShaRmd160::new([1; 20])), [1; 20]);
+        let (script2, payload2) = (Script::p2sh(&ShaRmd160::new([2; 20])), [2; 20]);
+        let script_filter = ScriptFilter::Watched(
+            script_payloads(&script1)
+                .into_iter()
+                .map(|payload_state| payload_state.payload)
+                .collect(),
+        );
+        let script_txs_writer = ScriptTxsWriter::new(&db, conf.clone(), &script_filter)?;
+        let script_txs_reader = ScriptTxsReader::new(&db, conf)?;
+        let make_output = |script: &Script| TxOutput {
+            value: 0,
+            script: script.clone(),
+        };
+        let txs = [
+            UnhashedTx {
+                outputs: vec![make_output(&script1), make_output(&script2)],
+                ..Default::default()
+            },
+            UnhashedTx {
+                inputs: vec![TxInput {
+                    prev_out: OutPoint {
+                        txid: Sha256d::new([0; 32]),
+                        out_idx: 1,
+                    },
+                    ..Default::default()
+                }],
+                outputs: vec![make_output(&script2)],
+                ..Default::default()
+            },
+            UnhashedTx {
+                inputs: vec![TxInput {
+                    prev_out: OutPoint {
+                        txid: Sha256d::new([0; 32]),
+                        out_idx: 0,
+                    },
+                    ..Default::default()
+                }],
+                outputs: vec![make_output(&script2)],
+                ..Default::default()
+            },
+        ];
+        let spent_outputs = [make_output(&script2), make_output(&script1)];
+        let mut batch = WriteBatch::default();
+        script_txs_writer.insert_block_txs(
+            &mut batch,
+            0,
+            &txs,
+            |tx_pos, _| &spent_outputs[tx_pos],
+            &mut cache,
+        )?;
+        db.write_batch(batch)?;
+        // Only the watched script has a history
+        check_pages(&script_txs_reader, P2PKH, &payload1, [&[0, 2]])?;
+        check_pages(&script_txs_reader, P2SH, &payload2, [])?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_script_txs_cache_stats() -> Result<()> {
+        bitcoinsuite_error::install()?;
+        let tempdir = tempdir::TempDir::new("slp-indexer-rocks--script-txs-cache-stats")?;
+        let db = Db::open(tempdir.path())?;
+        let cf = db.cf(CF_SCRIPT_TXS)?;
+        let conf = ScriptTxsConf { page_size: 4 };
+        let mut cache = ScriptTxsWriterCache::with_capacity(2);
+        for payload in [[1u8], [1], [2], [3], [1]] {
+            assert_eq!(cache.get_num_txs_by_payload(&db, cf, &conf, &payload)?, 0);
+        }
+        // [3] evicted [1], so the last lookup missed again
+        assert_eq!(
+            cache.stats(),
+            ScriptTxsCacheStats {
+                capacity: 2,
+                num_entries: 2,
+                num_hits: 1,
+                num_misses: 4,
+            },
+        );
+        cache.resize(1);
+        assert_eq!(cache.get_num_txs_by_payload(&db, cf, &conf, &[1])?, 0);
+        assert_eq!(
+            cache.stats(),
+            ScriptTxsCacheStats {
+                capacity: 1,
+                num_entries: 1,
+                num_hits: 2,
+                num_misses: 4,
+            },
+        );
+        // A disabled cache doesn't count lookups
+        let mut cache = ScriptTxsWriterCache::with_capacity(0);
+        cache.get_num_txs_by_payload(&db, cf, &conf, &[1])?;
+        assert_eq!(cache.stats(), ScriptTxsCacheStats::default());
+        Ok(())
+    }
+
+    fn check_scripts(db_conf: &DbConf) -> Result<()> {
+        use PayloadPrefix::*;
+        bitcoinsuite_error::install()?;
+        let tempdir = tempdir::TempDir::new("slp-indexer-rocks--scripts")?;
+        let db = Db::open_with_conf(tempdir.path(), db_conf)?;
+        let mut cache = ScriptTxsWriterCache::with_capacity(4);
+        let conf = ScriptTxsConf { page_size: 4 };
+        let script_txs_writer = ScriptTxsWriter::new(&db, conf.clone(), &ScriptFilter::All)?;
         let script_txs_reader = ScriptTxsReader::new(&db, conf)?;
         let r = &script_txs_reader;
         let (script1, payload1) = (Script::p2pkh(&ShaRmd160::new([1; 20])), [1; 20]);
@@ -1,6 +1,6 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 
-use bitcoinsuite_core::{OutPoint, Sha256d, UnhashedTx};
+use bitcoinsuite_core::{OutPoint, Sha256d, TxOutput, UnhashedTx};
 use bitcoinsuite_error::{ErrorMeta, Result};
 use bitcoinsuite_slp::{
     parse_slp_tx, SlpAmount, SlpBurn, SlpError, SlpGenesisInfo, SlpParseData, SlpSpentOutput,
@@ -11,14 +11,14 @@ use rayon::iter::{
     Either, IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator,
     ParallelIterator,
 };
-use rocksdb::{ColumnFamilyDescriptor, IteratorMode, Options, WriteBatch};
+use rocksdb::{ColumnFamilyDescriptor, Direction, IteratorMode, Options, WriteBatch};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use zerocopy::{AsBytes, FromBytes, Unaligned, I128, U32};
+use zerocopy::{AsBytes, FromBytes, Unaligned, I128, U32, U64};
 
 use crate::{
-    data::interpret, validate_slp_batch, BatchSlpTx, Db, OutpointEntry, SlpInvalidTxData,
-    SlpValidHashMap, TxNum, TxNumZC, CF,
+    data::interpret, script_payloads, validate_slp_batch_partial, BatchError, BatchSlpTx, Db,
+    OutpointEntry, ScriptPayload, SlpInvalidTxData, SlpValidHashMap, TxNum, TxNumZC, CF,
 };
 
 pub const CF_SLP_TOKEN_ID_BY_NUM: &str = "slp_token_id_by_num";
@@ -27,12 +27,34 @@ pub const CF_SLP_TOKEN_METADATA: &str = "slp_token_metadata";
 pub const CF_SLP_TX_DATA: &str = "slp_tx_data";
 pub const CF_SLP_TX_INVALID_MESSAGE: &str = "slp_tx_invalid_message";
 pub const CF_SLP_TOKEN_STATS: &str = "slp_token_stats";
+pub const CF_SLP_STATS: &str = "slp_stats";
+pub const CF_SLP_TOKEN_SPENDS: &str = "slp_token_spends";
+pub const CF_SLP_UTXOS_BY_TOKEN: &str = "slp_utxos_by_token";
+pub const CF_SLP_TOKEN_TXS: &str = "slp_token_txs";
 
-type TokenNum = u32;
+/*
+slp_token_spends:
+token_num | tx_num -> ()
+
+slp_utxos_by_token:
+token_num | tx_num | out_idx -> (token, script payload)
+
+slp_token_txs:
+token_num | tx_num -> ()
+*/
+
+const SLP_STATS_KEY: &[u8] = b"stats";
+
+/// Invalid message of SLP txs that couldn't be validated because they're part of, or depend on,
+/// a cycle of txs within their block.
+const TX_CYCLE_MESSAGE: &str = "Tx is part of or depends on a cycle of txs";
+
+pub type TokenNum = u32;
 type TokenNumZC = U32<BE>;
 
 pub struct SlpWriter<'a> {
     db: &'a Db,
+    conf: SlpConf,
 }
 
 pub struct SlpReader<'a> {
@@ -46,6 +68,41 @@ struct TokenStatsData {
     total_minted: I128<LE>,
     // Total number of coins burned (in any way)
     total_burned: I128<LE>,
+    // 1 if total_minted or total_burned ever overflowed, 0 otherwise
+    supply_overflowed: u8,
+}
+
+#[derive(Debug, Clone, FromBytes, AsBytes, Unaligned, PartialEq, Eq, Default)]
+#[repr(C)]
+struct SlpStatsData {
+    num_genesis_txs: U64<LE>,
+    num_mint_txs: U64<LE>,
+    num_send_txs: U64<LE>,
+    num_burn_txs: U64<LE>,
+    // Number of tokens per token type, so we know which types are in use
+    num_fungible_tokens: U64<LE>,
+    num_nft1_group_tokens: U64<LE>,
+    num_nft1_child_tokens: U64<LE>,
+}
+
+/// Aggregate stats over all valid SLP txs in the index, maintained while indexing.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SlpStats {
+    pub num_tokens: u64,
+    // Number of token types (fungible, NFT1 group, NFT1 child) with at least one token
+    pub num_token_types: u64,
+    pub num_genesis_txs: u64,
+    pub num_mint_txs: u64,
+    pub num_send_txs: u64,
+    pub num_burn_txs: u64,
+}
+
+/// Script holding confirmed UTXOs of a token, with the sum of their token amounts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenHolder {
+    pub script_payload: ScriptPayload,
+    // Sum of the token amounts, in base units; mint batons don't count
+    pub amount: i128,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
@@ -54,6 +111,53 @@ pub struct TokenStats {
     pub total_minted: i128,
     // Total number of coins burned (in any way)
     pub total_burned: i128,
+    // Whether total_minted or total_burned ever overflowed and got saturated.
+    // Once set, this stays set, even if the overflowing block gets disconnected again.
+    pub supply_overflowed: bool,
+}
+
+/// Minted or burned amount of a token accumulated over a batch of txs.
+#[derive(Debug, Clone, Copy, Default)]
+struct SupplyDelta {
+    amount: i128,
+    overflowed: bool,
+}
+
+impl TokenStats {
+    /// Adds the stats of `delta` (e.g. from the mempool), saturating on overflow.
+    pub fn add_delta(&mut self, delta: &TokenStats) {
+        let (total_minted, minted_overflowed) =
+            add_saturating(self.total_minted, delta.total_minted);
+        let (total_burned, burned_overflowed) =
+            add_saturating(self.total_burned, delta.total_burned);
+        self.total_minted = total_minted;
+        self.total_burned = total_burned;
+        self.supply_overflowed |= delta.supply_overflowed || minted_overflowed || burned_overflowed;
+    }
+}
+
+impl SupplyDelta {
+    fn add(&mut self, amount: i128) {
+        let (amount, overflowed) = add_saturating(self.amount, amount);
+        self.amount = amount;
+        self.overflowed |= overflowed;
+    }
+}
+
+/// Adds `b` to `a`, saturating on overflow. Also returns whether it overflowed.
+pub(crate) fn add_saturating(a: i128, b: i128) -> (i128, bool) {
+    match a.checked_add(b) {
+        Some(sum) => (sum, false),
+        None => (a.saturating_add(b), true),
+    }
+}
+
+/// Subtracts `b` from `a`, saturating on overflow. Also returns whether it overflowed.
+pub(crate) fn sub_saturating(a: i128, b: i128) -> (i128, bool) {
+    match a.checked_sub(b) {
+        Some(diff) => (diff, false),
+        None => (a.saturating_sub(b), true),
+    }
 }
 
 struct SlpInputToken<'t> {
@@ -75,6 +179,10 @@ pub enum SlpWriterError {
     #[error("Inconsistent CF_SLP_TOKEN_ID_BY_NUM, token ID {0:?} not found")]
     InconsistentDbTokenNumById(TokenId),
 
+    #[critical()]
+    #[error("Inconsistent CF_SLP_TOKEN_METADATA, token num {0} not found")]
+    InconsistentDbTokenMetadataByNum(TokenNum),
+
     #[critical()]
     #[error("Inconsistent CF_SLP_TX_DATA, tx {0} has unknown token num {1}")]
     InconsistentDbNoSuchTokenNum(TxNum, TokenNum),
@@ -130,6 +238,25 @@ struct SerSlpTxEntry {
     slp_burns: Vec<Option<SerSlpBurn>>,
 }
 
+#[derive(Deserialize, Serialize, Clone)]
+struct SerSlpUtxo {
+    token: SerSlpToken,
+    script_payload: ScriptPayload,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SlpConf {
+    /// Treat txs of unknown token types as non-SLP, instead of indexing them as `Unknown`.
+    pub ignore_unknown_token_types: bool,
+}
+
+impl SlpConf {
+    /// Whether txs of `token_type` are treated as non-SLP.
+    pub fn is_ignored_token_type(&self, token_type: SlpTokenType) -> bool {
+        self.ignore_unknown_token_types && token_type == SlpTokenType::Unknown
+    }
+}
+
 impl<'a> SlpWriter<'a> {
     pub fn add_cfs(columns: &mut Vec<ColumnFamilyDescriptor>) {
         columns.push(ColumnFamilyDescriptor::new(
@@ -156,15 +283,35 @@ impl<'a> SlpWriter<'a> {
             CF_SLP_TOKEN_STATS,
             Options::default(),
         ));
+        columns.push(ColumnFamilyDescriptor::new(
+            CF_SLP_STATS,
+            Options::default(),
+        ));
+        columns.push(ColumnFamilyDescriptor::new(
+            CF_SLP_TOKEN_SPENDS,
+            Options::default(),
+        ));
+        columns.push(ColumnFamilyDescriptor::new(
+            CF_SLP_UTXOS_BY_TOKEN,
+            Options::default(),
+        ));
+        columns.push(ColumnFamilyDescriptor::new(
+            CF_SLP_TOKEN_TXS,
+            Options::default(),
+        ));
     }
 
-    pub fn new(db: &'a Db) -> Result<Self> {
+    pub fn new(db: &'a Db, conf: SlpConf) -> Result<Self> {
         db.cf(CF_SLP_TOKEN_ID_BY_NUM)?;
         db.cf(CF_SLP_TOKEN_NUM_BY_ID)?;
         db.cf(CF_SLP_TOKEN_METADATA)?;
         db.cf(CF_SLP_TX_DATA)?;
         db.cf(CF_SLP_TX_INVALID_MESSAGE)?;
-        Ok(SlpWriter { db })
+        db.cf(CF_SLP_STATS)?;
+        db.cf(CF_SLP_TOKEN_SPENDS)?;
+        db.cf(CF_SLP_UTXOS_BY_TOKEN)?;
+        db.cf(CF_SLP_TOKEN_TXS)?;
+        Ok(SlpWriter { db, conf })
     }
 
     pub fn insert_block_txs<'b>(
@@ -175,7 +322,7 @@ impl<'a> SlpWriter<'a> {
         txid_fn: impl Fn(usize) -> &'b Sha256d + Send + Sync,
         input_tx_nums: &[Vec<TxNum>],
     ) -> Result<()> {
-        let (parsed_slp_txs, invalid_parsed_slp_txs) = Self::parse_block_slp_txs(txs, &txid_fn);
+        let (parsed_slp_txs, invalid_parsed_slp_txs) = self.parse_block_slp_txs(txs, &txid_fn);
         let next_token_num = self.get_next_token_num()?;
         // Short-circuit for block without any SLP txs, and if there's no tokens yet
         if parsed_slp_txs.is_empty() && invalid_parsed_slp_txs.is_empty() && next_token_num == 0 {
@@ -193,11 +340,28 @@ impl<'a> SlpWriter<'a> {
             .map(|(tx_idx, batch_tx)| (first_tx_num + tx_idx as TxNum, batch_tx))
             .collect();
         // Validate SLP batch
-        let (valid_slp_txs, invalid_slp_txs) = validate_slp_batch(batch_txs, known_slp_outputs)?;
+        let (valid_slp_txs, invalid_slp_txs, batch_error) =
+            validate_slp_batch_partial(batch_txs, known_slp_outputs);
+        // Txs within a valid block can't form a cycle, but a malformed block mustn't halt
+        // indexing, so index those txs as invalid instead
+        let cycle_tx_nums = match batch_error {
+            Some(BatchError::FoundTxCycle(tx_nums)) => {
+                eprintln!(
+                    "{} SLP txs in block starting at tx_num {} form a cycle, indexing them as \
+                     invalid",
+                    tx_nums.len(),
+                    first_tx_num,
+                );
+                tx_nums
+            }
+            None => HashSet::new(),
+        };
         // Insert new tokens
         let mut token_num_by_id = self.insert_new_tokens(batch, valid_slp_txs.values())?;
         // Insert SLP txs
         self.insert_new_valid_txs(batch, valid_slp_txs.iter(), &mut token_num_by_id)?;
+        // Count new SLP txs and tokens
+        self.update_slp_stats(batch, valid_slp_txs.values(), u64::saturating_add)?;
         // Insert token stats
         self.update_token_stats(
             batch,
@@ -207,16 +371,44 @@ impl<'a> SlpWriter<'a> {
             input_tx_nums,
             &spent_slp_outputs,
             &mut token_num_by_id,
-            |a, b| a + b,
+            add_saturating,
+        )?;
+        // Index which txs spent which tokens
+        self.update_token_spends(
+            batch,
+            first_tx_num,
+            txs,
+            &valid_slp_txs,
+            input_tx_nums,
+            &spent_slp_outputs,
+            &mut token_num_by_id,
+            true,
+        )?;
+        // Index the token UTXOs created and spent
+        self.insert_slp_utxos(
+            batch,
+            first_tx_num,
+            txs,
+            &valid_slp_txs,
+            input_tx_nums,
+            &spent_slp_outputs,
+            &mut token_num_by_id,
         )?;
         // Insert invalid SLP txs
-        self.insert_new_invalid_txs(batch, first_tx_num, invalid_parsed_slp_txs, invalid_slp_txs);
+        self.insert_new_invalid_txs(
+            batch,
+            first_tx_num,
+            invalid_parsed_slp_txs,
+            invalid_slp_txs,
+            cycle_tx_nums,
+        );
         Ok(())
     }
 
-    /// Parse txs, split into valid and invalid (skip non-SLP)
+    /// Parse txs, split into valid and invalid (skip non-SLP and ignored token types)
     #[allow(clippy::type_complexity)]
     fn parse_block_slp_txs<'b>(
+        &self,
         txs: &[UnhashedTx],
         txid_fn: &(impl Fn(usize) -> &'b Sha256d + Send + Sync),
     ) -> (Vec<(usize, SlpParseData)>, Vec<(usize, SlpError)>) {
@@ -225,7 +417,12 @@ impl<'a> SlpWriter<'a> {
             .filter_map(|(tx_idx, tx)| {
                 let txid = txid_fn(tx_idx);
                 match parse_slp_tx(txid, tx) {
-                    Ok(slp_parse) => Some((tx_idx, Ok(slp_parse))),
+                    Ok(slp_parse) => {
+                        match self.conf.is_ignored_token_type(slp_parse.slp_token_type) {
+                            true => None,
+                            false => Some((tx_idx, Ok(slp_parse))),
+                        }
+                    }
                     Err(err) => match is_ignored_error(&err) {
                         true => None,
                         false => {
@@ -394,38 +591,14 @@ impl<'a> SlpWriter<'a> {
         input_tx_nums: &[Vec<TxNum>],
         spent_slp_outputs: &[Vec<Option<SlpSpentOutput>>],
         token_num_by_id: &mut HashMap<[u8; 32], TokenNum>,
-        op: impl Fn(i128, i128) -> i128,
+        op: impl Fn(i128, i128) -> (i128, bool),
     ) -> Result<()> {
         let mut minted = HashMap::new();
         let mut burned = HashMap::new();
         for (tx_idx, tx) in txs.iter().enumerate() {
             let tx_num = first_tx_num + tx_idx as TxNum;
-            let slp_token_inputs = match tx_idx {
-                0 => vec![None],
-                _ => tx
-                    .inputs
-                    .iter()
-                    .zip(&input_tx_nums[tx_idx - 1])
-                    .zip(&spent_slp_outputs[tx_idx - 1])
-                    .map(
-                        |((input, &input_tx_num), spent_slp_output)| match spent_slp_output {
-                            Some(spent_slp_output) => Some(SlpInputToken {
-                                token_id: &spent_slp_output.token_id,
-                                token: &spent_slp_output.token,
-                            }),
-                            None => valid_txs.get(&input_tx_num).and_then(|slp| {
-                                Some(SlpInputToken {
-                                    token_id: &slp.slp_tx_data.token_id,
-                                    token: slp
-                                        .slp_tx_data
-                                        .output_tokens
-                                        .get(input.prev_out.out_idx as usize)?,
-                                })
-                            }),
-                        },
-                    )
-                    .collect::<Vec<_>>(),
-            };
+            let slp_token_inputs =
+                slp_token_inputs(tx_idx, tx, valid_txs, input_tx_nums, spent_slp_outputs);
             let valid_slp_tx = valid_txs.get(&tx_num);
             self.calc_token_supply_delta(&mut minted, &mut burned, &slp_token_inputs, valid_slp_tx);
         }
@@ -443,14 +616,20 @@ impl<'a> SlpWriter<'a> {
                 Some(token_stats_data) => interpret::<TokenStatsData>(&token_stats_data)?.clone(),
                 None => TokenStatsData::default(),
             };
-            if let Some(&mint_amount) = minted.get(token_id.as_slice_be()) {
-                let new_total_minted = op(token_stats_data.total_minted.get(), mint_amount);
+            let mut supply_overflowed = token_stats_data.supply_overflowed != 0;
+            if let Some(mint_delta) = minted.get(token_id.as_slice_be()) {
+                let (new_total_minted, overflowed) =
+                    op(token_stats_data.total_minted.get(), mint_delta.amount);
                 token_stats_data.total_minted = new_total_minted.into();
+                supply_overflowed |= overflowed || mint_delta.overflowed;
             }
-            if let Some(&burn_amount) = burned.get(token_id.as_slice_be()) {
-                let new_total_burned = op(token_stats_data.total_burned.get(), burn_amount);
+            if let Some(burn_delta) = burned.get(token_id.as_slice_be()) {
+                let (new_total_burned, overflowed) =
+                    op(token_stats_data.total_burned.get(), burn_delta.amount);
                 token_stats_data.total_burned = new_total_burned.into();
+                supply_overflowed |= overflowed || burn_delta.overflowed;
             }
+            token_stats_data.supply_overflowed = supply_overflowed as u8;
             batch.put_cf(
                 self.cf_slp_token_stats(),
                 token_num_zc.as_bytes(),
@@ -460,10 +639,241 @@ impl<'a> SlpWriter<'a> {
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
+    fn update_token_spends(
+        &self,
+        batch: &mut WriteBatch,
+        first_tx_num: TxNum,
+        txs: &[UnhashedTx],
+        valid_txs: &SlpValidHashMap,
+        input_tx_nums: &[Vec<TxNum>],
+        spent_slp_outputs: &[Vec<Option<SlpSpentOutput>>],
+        token_num_by_id: &mut HashMap<[u8; 32], TokenNum>,
+        is_insert: bool,
+    ) -> Result<()> {
+        let null_token = TokenId::new(Sha256d::new([0; 32]));
+        for (tx_idx, tx) in txs.iter().enumerate().skip(1) {
+            let tx_num = first_tx_num + tx_idx as TxNum;
+            let mut spent_token_nums = BTreeSet::new();
+            for input_token in
+                slp_token_inputs(tx_idx, tx, valid_txs, input_tx_nums, spent_slp_outputs)
+                    .into_iter()
+                    .flatten()
+            {
+                let token = input_token.token;
+                if input_token.token_id == &null_token
+                    || (token.amount == SlpAmount::ZERO && !token.is_mint_baton)
+                {
+                    continue;
+                }
+                let token_num = self
+                    .get_token_num_by_token_id(token_num_by_id, input_token.token_id)?
+                    .ok_or_else(|| InconsistentTokenNumById(input_token.token_id.clone()))?;
+                spent_token_nums.insert(token_num);
+            }
+            for token_num in spent_token_nums {
+                let key = key_for_token_tx(token_num, tx_num);
+                match is_insert {
+                    true => batch.put_cf(self.cf_slp_token_spends(), key, b""),
+                    false => batch.delete_cf(self.cf_slp_token_spends(), key),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the token outputs spent by the block's txs from slp_utxos_by_token and adds the
+    /// token outputs they create, tx by tx, so outputs spent within the block end up removed.
+    #[allow(clippy::too_many_arguments)]
+    fn insert_slp_utxos(
+        &self,
+        batch: &mut WriteBatch,
+        first_tx_num: TxNum,
+        txs: &[UnhashedTx],
+        valid_txs: &SlpValidHashMap,
+        input_tx_nums: &[Vec<TxNum>],
+        spent_slp_outputs: &[Vec<Option<SlpSpentOutput>>],
+        token_num_by_id: &mut HashMap<[u8; 32], TokenNum>,
+    ) -> Result<()> {
+        for (tx_idx, tx) in txs.iter().enumerate() {
+            let tx_num = first_tx_num + tx_idx as TxNum;
+            let slp_token_inputs =
+                slp_token_inputs(tx_idx, tx, valid_txs, input_tx_nums, spent_slp_outputs);
+            for (input_idx, input_token) in slp_token_inputs.into_iter().enumerate() {
+                let token_num = match input_token {
+                    Some(input_token) => self.slp_utxo_token_num(
+                        token_num_by_id,
+                        input_token.token_id,
+                        input_token.token,
+                    )?,
+                    None => None,
+                };
+                if let Some(token_num) = token_num {
+                    let key = key_for_slp_utxo(
+                        token_num,
+                        input_tx_nums[tx_idx - 1][input_idx],
+                        tx.inputs[input_idx].prev_out.out_idx,
+                    );
+                    batch.delete_cf(self.cf_slp_utxos_by_token(), key);
+                }
+            }
+            if let Some(valid_tx) = valid_txs.get(&tx_num) {
+                let slp_tx_data = &valid_tx.slp_tx_data;
+                for (out_idx, (token, output)) in slp_tx_data
+                    .output_tokens
+                    .iter()
+                    .zip(&tx.outputs)
+                    .enumerate()
+                {
+                    let token_num =
+                        self.slp_utxo_token_num(token_num_by_id, &slp_tx_data.token_id, token)?;
+                    if let Some(token_num) = token_num {
+                        self.put_slp_utxo(batch, token_num, tx_num, out_idx as u32, token, output)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reverts `insert_slp_utxos`, going through the block's txs backwards: removes the token
+    /// outputs the txs created and adds back the token outputs they spent.
+    #[allow(clippy::too_many_arguments)]
+    fn delete_slp_utxos<'b>(
+        &self,
+        batch: &mut WriteBatch,
+        first_tx_num: TxNum,
+        txs: &[UnhashedTx],
+        valid_txs: &SlpValidHashMap,
+        input_tx_nums: &[Vec<TxNum>],
+        spent_slp_outputs: &[Vec<Option<SlpSpentOutput>>],
+        token_num_by_id: &mut HashMap<[u8; 32], TokenNum>,
+        block_spent_output_fn: impl Fn(/*tx_pos:*/ usize, /*out_idx:*/ usize) -> &'b TxOutput,
+    ) -> Result<()> {
+        for (tx_idx, tx) in txs.iter().enumerate().rev() {
+            let tx_num = first_tx_num + tx_idx as TxNum;
+            if let Some(valid_tx) = valid_txs.get(&tx_num) {
+                let slp_tx_data = &valid_tx.slp_tx_data;
+                for (out_idx, token) in slp_tx_data.output_tokens.iter().enumerate() {
+                    let token_num =
+                        self.slp_utxo_token_num(token_num_by_id, &slp_tx_data.token_id, token)?;
+                    if let Some(token_num) = token_num {
+                        let key = key_for_slp_utxo(token_num, tx_num, out_idx as u32);
+                        batch.delete_cf(self.cf_slp_utxos_by_token(), key);
+                    }
+                }
+            }
+            let slp_token_inputs =
+                slp_token_inputs(tx_idx, tx, valid_txs, input_tx_nums, spent_slp_outputs);
+            for (input_idx, input_token) in slp_token_inputs.into_iter().enumerate() {
+                let input_token = match input_token {
+                    Some(input_token) => input_token,
+                    None => continue,
+                };
+                let token_num = self.slp_utxo_token_num(
+                    token_num_by_id,
+                    input_token.token_id,
+                    input_token.token,
+                )?;
+                if let Some(token_num) = token_num {
+                    self.put_slp_utxo(
+                        batch,
+                        token_num,
+                        input_tx_nums[tx_idx - 1][input_idx],
+                        tx.inputs[input_idx].prev_out.out_idx,
+                        input_token.token,
+                        block_spent_output_fn(tx_idx - 1, input_idx),
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Token num of an output with `token`, or None if it doesn't hold any tokens and therefore
+    /// isn't indexed in slp_utxos_by_token.
+    fn slp_utxo_token_num(
+        &self,
+        token_num_by_id: &mut HashMap<[u8; 32], TokenNum>,
+        token_id: &TokenId,
+        token: &SlpToken,
+    ) -> Result<Option<TokenNum>> {
+        if token.amount == SlpAmount::ZERO && !token.is_mint_baton {
+            return Ok(None);
+        }
+        self.get_token_num_by_token_id(token_num_by_id, token_id)
+    }
+
+    /// Outputs are attributed to the first payload of their script, e.g. the commitment of
+    /// P2TR outputs; outputs without any payload aren't indexed.
+    fn put_slp_utxo(
+        &self,
+        batch: &mut WriteBatch,
+        token_num: TokenNum,
+        tx_num: TxNum,
+        out_idx: u32,
+        token: &SlpToken,
+        output: &TxOutput,
+    ) -> Result<()> {
+        let script_payload = match script_payloads(&output.script).into_iter().next() {
+            Some(payload_state) => payload_state.payload,
+            None => return Ok(()),
+        };
+        let ser_utxo = bincode::serialize(&SerSlpUtxo {
+            token: SerSlpToken::from_token(token),
+            script_payload,
+        })?;
+        batch.put_cf(
+            self.cf_slp_utxos_by_token(),
+            key_for_slp_utxo(token_num, tx_num, out_idx),
+            ser_utxo,
+        );
+        Ok(())
+    }
+
+    fn update_slp_stats<'b>(
+        &self,
+        batch: &mut WriteBatch,
+        valid_slp_txs: impl Iterator<Item = &'b SlpValidTxData>,
+        op: impl Fn(u64, u64) -> u64,
+    ) -> Result<()> {
+        let stats_data = self.db.get(self.cf_slp_stats(), SLP_STATS_KEY)?;
+        let mut stats_data = match stats_data {
+            Some(stats_data) => interpret::<SlpStatsData>(&stats_data)?.clone(),
+            None => SlpStatsData::default(),
+        };
+        let mut has_changed = false;
+        for slp_tx in valid_slp_txs {
+            let slp_tx_data = &slp_tx.slp_tx_data;
+            let num_txs = match slp_tx_data.slp_tx_type {
+                SlpTxType::Genesis(_) => &mut stats_data.num_genesis_txs,
+                SlpTxType::Mint => &mut stats_data.num_mint_txs,
+                SlpTxType::Send => &mut stats_data.num_send_txs,
+                SlpTxType::Burn(_) => &mut stats_data.num_burn_txs,
+                SlpTxType::Unknown => continue,
+            };
+            num_txs.set(op(num_txs.get(), 1));
+            has_changed = true;
+            if let SlpTxType::Genesis(_) = slp_tx_data.slp_tx_type {
+                let num_tokens = match slp_tx_data.slp_token_type {
+                    SlpTokenType::Fungible => &mut stats_data.num_fungible_tokens,
+                    SlpTokenType::Nft1Group => &mut stats_data.num_nft1_group_tokens,
+                    SlpTokenType::Nft1Child => &mut stats_data.num_nft1_child_tokens,
+                    SlpTokenType::Unknown => continue,
+                };
+                num_tokens.set(op(num_tokens.get(), 1));
+            }
+        }
+        if has_changed {
+            batch.put_cf(self.cf_slp_stats(), SLP_STATS_KEY, stats_data.as_bytes());
+        }
+        Ok(())
+    }
+
     fn calc_token_supply_delta(
         &self,
-        minted: &mut HashMap<[u8; 32], i128>,
-        burned: &mut HashMap<[u8; 32], i128>,
+        minted: &mut HashMap<[u8; 32], SupplyDelta>,
+        burned: &mut HashMap<[u8; 32], SupplyDelta>,
         slp_token_inputs: &[Option<SlpInputToken<'_>>],
         valid_slp_tx: Option<&SlpValidTxData>,
     ) {
@@ -475,8 +885,8 @@ impl<'a> SlpWriter<'a> {
                     if burn.token.amount == SlpAmount::ZERO || burn.token_id == null_token {
                         continue;
                     }
-                    let burned_amount = burned.entry(burn.token_id.token_id_be()).or_default();
-                    *burned_amount += burn.token.amount.base_amount();
+                    let burned_delta = burned.entry(burn.token_id.token_id_be()).or_default();
+                    burned_delta.add(burn.token.amount.base_amount());
                 }
                 return; // SEND never mints
             }
@@ -489,10 +899,10 @@ impl<'a> SlpWriter<'a> {
                     {
                         continue;
                     }
-                    let burned_amount = burned
+                    let burned_delta = burned
                         .entry(spent_output.token_id.token_id_be())
                         .or_default();
-                    *burned_amount += spent_output.token.amount.base_amount();
+                    burned_delta.add(spent_output.token.amount.base_amount());
                 }
             }
         };
@@ -504,10 +914,10 @@ impl<'a> SlpWriter<'a> {
         // GENESIS and MINT can mint
         if let SlpTxType::Genesis(_) | SlpTxType::Mint = &slp_tx_data.slp_tx_type {
             for token in &slp_tx_data.output_tokens {
-                let minted_amount = minted
+                let minted_delta = minted
                     .entry(slp_tx_data.token_id.token_id_be())
                     .or_default();
-                *minted_amount += token.amount.base_amount();
+                minted_delta.add(token.amount.base_amount());
             }
         }
     }
@@ -543,6 +953,13 @@ impl<'a> SlpWriter<'a> {
                 ),
                 None => None,
             };
+            if let Some(token_num) = token_num {
+                batch.put_cf(
+                    self.cf_slp_token_txs(),
+                    key_for_token_tx(token_num, tx_num),
+                    b"",
+                );
+            }
             let mut slp_burns = Vec::with_capacity(slp_tx.slp_burns.len());
             for slp_burn in &slp_tx.slp_burns {
                 match slp_burn {
@@ -589,20 +1006,24 @@ impl<'a> SlpWriter<'a> {
         first_tx_num: TxNum,
         invalid_parsed_slp_txs: impl IntoIterator<Item = (usize, SlpError)>,
         invalid_slp_txs: impl IntoIterator<Item = (TxNum, SlpInvalidTxData)>,
+        cycle_tx_nums: impl IntoIterator<Item = TxNum>,
     ) {
-        let mut insert = |tx_num: TxNum, slp_error: &SlpError| {
+        let mut insert = |tx_num: TxNum, message: &str| {
             batch.put_cf(
                 self.cf_slp_tx_invalid_message(),
                 TxNumZC::new(tx_num).as_bytes(),
-                slp_error.to_string().as_bytes(),
+                message.as_bytes(),
             );
         };
         for (tx_idx, slp_error) in invalid_parsed_slp_txs {
             let tx_num = first_tx_num + tx_idx as TxNum;
-            insert(tx_num, &slp_error);
+            insert(tx_num, &slp_error.to_string());
         }
         for (tx_num, invalid_tx_data) in invalid_slp_txs {
-            insert(tx_num, &invalid_tx_data.slp_error);
+            insert(tx_num, &invalid_tx_data.slp_error.to_string());
+        }
+        for tx_num in cycle_tx_nums {
+            insert(tx_num, TX_CYCLE_MESSAGE);
         }
     }
 
@@ -613,6 +1034,7 @@ impl<'a> SlpWriter<'a> {
         txs: &[UnhashedTx],
         txid_fn: impl Fn(usize) -> &'b Sha256d + Send + Sync,
         input_tx_nums: &[Vec<TxNum>],
+        block_spent_output_fn: impl Fn(/*tx_pos:*/ usize, /*out_idx:*/ usize) -> &'b TxOutput,
     ) -> Result<()> {
         let (delete_valid_txs, delete_invalid_txs): (Vec<Result<(_, _)>>, Vec<TxNum>) = txs
             .par_iter()
@@ -621,9 +1043,17 @@ impl<'a> SlpWriter<'a> {
                 let tx_num = first_tx_num + tx_idx as TxNum;
                 let txid = txid_fn(tx_idx);
                 match parse_slp_tx(txid, tx) {
-                    Ok(slp_parse_data) => Some(Either::Left(
-                        self.fetch_delete_data(tx_num, &slp_parse_data),
-                    )),
+                    Ok(slp_parse_data) => {
+                        match self
+                            .conf
+                            .is_ignored_token_type(slp_parse_data.slp_token_type)
+                        {
+                            true => None,
+                            false => Some(Either::Left(
+                                self.fetch_delete_data(tx_num, &slp_parse_data),
+                            )),
+                        }
+                    }
                     Err(err) => match is_ignored_error(&err) {
                         true => None,
                         false => Some(Either::Right(tx_num)),
@@ -636,11 +1066,16 @@ impl<'a> SlpWriter<'a> {
         let delete_valid_txs = delete_valid_txs.into_iter().collect::<Result<Vec<_>>>()?;
         let mut valid_slp_txs = HashMap::with_capacity(delete_valid_txs.len());
         let mut token_num_by_id = HashMap::new();
+        let mut deleted_token_nums = Vec::new();
         for (tx_num, delete_token) in delete_valid_txs {
             let tx_num_zc = TxNumZC::new(tx_num);
             batch.delete_cf(self.cf_slp_tx_data(), tx_num_zc.as_bytes());
             batch.delete_cf(self.cf_slp_tx_invalid_message(), tx_num_zc.as_bytes());
             if let Some((delete_token_num, delete_slp)) = delete_token {
+                batch.delete_cf(
+                    self.cf_slp_token_txs(),
+                    key_for_token_tx(delete_token_num, tx_num),
+                );
                 if matches!(delete_slp.slp_tx_data.slp_tx_type, SlpTxType::Genesis(_)) {
                     let delete_token_num_zc = TokenNumZC::new(delete_token_num);
                     batch.delete_cf(
@@ -652,6 +1087,7 @@ impl<'a> SlpWriter<'a> {
                         self.cf_slp_token_num_by_id(),
                         delete_slp.slp_tx_data.token_id.as_slice_be(),
                     );
+                    deleted_token_nums.push(delete_token_num_zc);
                 }
                 token_num_by_id
                     .entry(delete_slp.slp_tx_data.token_id.token_id_be())
@@ -667,8 +1103,33 @@ impl<'a> SlpWriter<'a> {
             input_tx_nums,
             &spent_slp_outputs,
             &mut token_num_by_id,
-            |a, b| a - b,
+            sub_saturating,
+        )?;
+        // Stats of tokens whose GENESIS got disconnected are gone entirely, not just zeroed
+        for token_num_zc in deleted_token_nums {
+            batch.delete_cf(self.cf_slp_token_stats(), token_num_zc.as_bytes());
+        }
+        self.update_token_spends(
+            batch,
+            first_tx_num,
+            txs,
+            &valid_slp_txs,
+            input_tx_nums,
+            &spent_slp_outputs,
+            &mut token_num_by_id,
+            false,
+        )?;
+        self.delete_slp_utxos(
+            batch,
+            first_tx_num,
+            txs,
+            &valid_slp_txs,
+            input_tx_nums,
+            &spent_slp_outputs,
+            &mut token_num_by_id,
+            block_spent_output_fn,
         )?;
+        self.update_slp_stats(batch, valid_slp_txs.values(), u64::saturating_sub)?;
         for tx_num in delete_invalid_txs {
             let tx_num = TxNumZC::new(tx_num);
             batch.delete_cf(self.cf_slp_tx_invalid_message(), tx_num.as_bytes());
@@ -747,14 +1208,35 @@ impl<'a> SlpWriter<'a> {
     fn cf_slp_token_stats(&self) -> &CF {
         self.db.cf(CF_SLP_TOKEN_STATS).unwrap()
     }
+
+    fn cf_slp_stats(&self) -> &CF {
+        self.db.cf(CF_SLP_STATS).unwrap()
+    }
+
+    fn cf_slp_token_spends(&self) -> &CF {
+        self.db.cf(CF_SLP_TOKEN_SPENDS).unwrap()
+    }
+
+    fn cf_slp_utxos_by_token(&self) -> &CF {
+        self.db.cf(CF_SLP_UTXOS_BY_TOKEN).unwrap()
+    }
+
+    fn cf_slp_token_txs(&self) -> &CF {
+        self.db.cf(CF_SLP_TOKEN_TXS).unwrap()
+    }
 }
 
 impl<'a> SlpReader<'a> {
     pub fn new(db: &'a Db) -> Result<Self> {
+        let _ = db.cf(CF_SLP_TOKEN_ID_BY_NUM)?;
         let _ = db.cf(CF_SLP_TOKEN_METADATA)?;
         let _ = db.cf(CF_SLP_TOKEN_NUM_BY_ID)?;
         let _ = db.cf(CF_SLP_TX_DATA)?;
         let _ = db.cf(CF_SLP_TX_INVALID_MESSAGE)?;
+        let _ = db.cf(CF_SLP_STATS)?;
+        let _ = db.cf(CF_SLP_TOKEN_SPENDS)?;
+        let _ = db.cf(CF_SLP_UTXOS_BY_TOKEN)?;
+        let _ = db.cf(CF_SLP_TOKEN_TXS)?;
         Ok(SlpReader { db })
     }
 
@@ -779,6 +1261,61 @@ impl<'a> SlpReader<'a> {
         Ok(Some(token_num))
     }
 
+    /// Token nums and IDs of the `count` most recently created tokens (highest token nums
+    /// first), only considering tokens with a token num below `before_token_num`, if given.
+    pub fn recent_token_ids(
+        &self,
+        before_token_num: Option<TokenNum>,
+        count: usize,
+    ) -> Result<Vec<(TokenNum, TokenId)>> {
+        let start_key;
+        let mode = match before_token_num {
+            None => IteratorMode::End,
+            Some(0) => return Ok(vec![]),
+            Some(before_token_num) => {
+                start_key = TokenNumZC::new(before_token_num - 1);
+                IteratorMode::From(start_key.as_bytes(), Direction::Reverse)
+            }
+        };
+        let iterator = self
+            .db
+            .rocks()
+            .iterator_cf(self.cf_slp_token_id_by_num(), mode);
+        iterator
+            .take(count)
+            .map(|(token_num, token_id)| {
+                Ok((
+                    interpret::<TokenNumZC>(&token_num)?.get(),
+                    TokenId::from_slice_be(&token_id)?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Token nums, IDs and GENESIS info of up to `count` tokens, in the order they were created
+    /// (lowest token nums first), starting at token num `start_token_num`.
+    pub fn tokens_page(
+        &self,
+        start_token_num: TokenNum,
+        count: usize,
+    ) -> Result<Vec<(TokenNum, TokenId, SlpGenesisInfo)>> {
+        let start_key = TokenNumZC::new(start_token_num);
+        let iterator = self.db.rocks().iterator_cf(
+            self.cf_slp_token_id_by_num(),
+            IteratorMode::From(start_key.as_bytes(), Direction::Forward),
+        );
+        iterator
+            .take(count)
+            .map(|(token_num, token_id)| {
+                let token_num = interpret::<TokenNumZC>(&token_num)?.get();
+                let genesis_info = self
+                    .token_by_token_num(token_num)?
+                    .ok_or(InconsistentDbTokenMetadataByNum(token_num))?;
+                Ok((token_num, TokenId::from_slice_be(&token_id)?, genesis_info))
+            })
+            .collect()
+    }
+
     pub fn slp_data_by_tx_num(&self, tx_num: TxNum) -> Result<Option<SlpValidTxData>> {
         let tx_num = TxNumZC::new(tx_num);
         let slp_tx_data = match self.db.get(self.cf_slp_tx_data(), tx_num.as_bytes())? {
@@ -879,9 +1416,128 @@ impl<'a> SlpReader<'a> {
         Ok(Some(TokenStats {
             total_burned: token_stats_data.total_burned.get(),
             total_minted: token_stats_data.total_minted.get(),
+            supply_overflowed: token_stats_data.supply_overflowed != 0,
         }))
     }
 
+    /// Number of confirmed txs spending outputs of the token.
+    pub fn num_token_spends(&self, token_num: TokenNum) -> Result<usize> {
+        Ok(self.num_token_tx_nums(self.cf_slp_token_spends(), token_num))
+    }
+
+    /// Tx nums of confirmed txs spending outputs of the token, most recent first.
+    pub fn rev_page_token_spends(
+        &self,
+        token_num: TokenNum,
+        page_num: usize,
+        page_size: usize,
+    ) -> Result<Vec<TxNum>> {
+        self.rev_page_token_tx_nums(self.cf_slp_token_spends(), token_num, page_num, page_size)
+    }
+
+    /// Number of confirmed valid SLP txs of the token (GENESIS, MINT, SEND and BURN).
+    pub fn num_token_txs(&self, token_num: TokenNum) -> Result<usize> {
+        Ok(self.num_token_tx_nums(self.cf_slp_token_txs(), token_num))
+    }
+
+    /// Tx nums of confirmed valid SLP txs of the token, most recent first, so the GENESIS is
+    /// always last.
+    pub fn rev_page_token_txs(
+        &self,
+        token_num: TokenNum,
+        page_num: usize,
+        page_size: usize,
+    ) -> Result<Vec<TxNum>> {
+        self.rev_page_token_tx_nums(self.cf_slp_token_txs(), token_num, page_num, page_size)
+    }
+
+    fn num_token_tx_nums(&self, cf: &CF, token_num: TokenNum) -> usize {
+        let prefix = TokenNumZC::new(token_num);
+        let iterator = self.db.rocks().iterator_cf(
+            cf,
+            IteratorMode::From(prefix.as_bytes(), Direction::Forward),
+        );
+        iterator
+            .take_while(|(key, _)| key.starts_with(prefix.as_bytes()))
+            .count()
+    }
+
+    fn rev_page_token_tx_nums(
+        &self,
+        cf: &CF,
+        token_num: TokenNum,
+        page_num: usize,
+        page_size: usize,
+    ) -> Result<Vec<TxNum>> {
+        let prefix = TokenNumZC::new(token_num);
+        let last_key = key_for_token_tx(token_num, TxNum::MAX);
+        let iterator = self
+            .db
+            .rocks()
+            .iterator_cf(cf, IteratorMode::From(&last_key, Direction::Reverse));
+        iterator
+            .take_while(|(key, _)| key.starts_with(prefix.as_bytes()))
+            .skip(page_num * page_size)
+            .take(page_size)
+            .map(|(key, _)| Ok(interpret::<TxNumZC>(&key[prefix.as_bytes().len()..])?.get()))
+            .collect()
+    }
+
+    /// Scripts holding confirmed UTXOs of the token, largest amount first (ties ordered by
+    /// script payload). Scripts holding only mint batons aren't included.
+    pub fn token_holders(&self, token_num: TokenNum) -> Result<Vec<TokenHolder>> {
+        let prefix = TokenNumZC::new(token_num);
+        let iterator = self.db.rocks().iterator_cf(
+            self.cf_slp_utxos_by_token(),
+            IteratorMode::From(prefix.as_bytes(), Direction::Forward),
+        );
+        let mut amounts = HashMap::<ScriptPayload, i128>::new();
+        for (_, ser_utxo) in iterator.take_while(|(key, _)| key.starts_with(prefix.as_bytes())) {
+            let utxo = bincode::deserialize::<SerSlpUtxo>(&ser_utxo)?;
+            if let SerSlpToken::Amount(amount) = utxo.token {
+                *amounts.entry(utxo.script_payload).or_default() += amount as i128;
+            }
+        }
+        let mut holders = amounts
+            .into_iter()
+            .map(|(script_payload, amount)| TokenHolder {
+                script_payload,
+                amount,
+            })
+            .collect::<Vec<_>>();
+        holders.sort_by(|a, b| {
+            b.amount
+                .cmp(&a.amount)
+                .then_with(|| a.script_payload.cmp(&b.script_payload))
+        });
+        Ok(holders)
+    }
+
+    pub fn slp_stats(&self) -> Result<SlpStats> {
+        let stats_data = self.db.get(self.cf_slp_stats(), SLP_STATS_KEY)?;
+        let stats_data = match &stats_data {
+            Some(stats_data) => interpret::<SlpStatsData>(stats_data)?.clone(),
+            None => SlpStatsData::default(),
+        };
+        let num_tokens_by_type = [
+            stats_data.num_fungible_tokens.get(),
+            stats_data.num_nft1_group_tokens.get(),
+            stats_data.num_nft1_child_tokens.get(),
+        ];
+        Ok(SlpStats {
+            num_tokens: num_tokens_by_type.iter().sum(),
+            num_token_types: num_tokens_by_type.iter().filter(|&&num| num > 0).count() as u64,
+            num_genesis_txs: stats_data.num_genesis_txs.get(),
+            num_mint_txs: stats_data.num_mint_txs.get(),
+            num_send_txs: stats_data.num_send_txs.get(),
+            num_burn_txs: stats_data.num_burn_txs.get(),
+        })
+    }
+
+    fn cf_slp_token_id_by_num(&self) -> &CF {
+        self.db.cf(CF_SLP_TOKEN_ID_BY_NUM).unwrap()
+    }
+
     fn cf_slp_token_num_by_id(&self) -> &CF {
         self.db.cf(CF_SLP_TOKEN_NUM_BY_ID).unwrap()
     }
@@ -901,6 +1557,22 @@ impl<'a> SlpReader<'a> {
     fn cf_slp_token_stats(&self) -> &CF {
         self.db.cf(CF_SLP_TOKEN_STATS).unwrap()
     }
+
+    fn cf_slp_stats(&self) -> &CF {
+        self.db.cf(CF_SLP_STATS).unwrap()
+    }
+
+    fn cf_slp_token_spends(&self) -> &CF {
+        self.db.cf(CF_SLP_TOKEN_SPENDS).unwrap()
+    }
+
+    fn cf_slp_utxos_by_token(&self) -> &CF {
+        self.db.cf(CF_SLP_UTXOS_BY_TOKEN).unwrap()
+    }
+
+    fn cf_slp_token_txs(&self) -> &CF {
+        self.db.cf(CF_SLP_TOKEN_TXS).unwrap()
+    }
 }
 
 impl Default for SerSlpToken {
@@ -925,6 +1597,60 @@ impl SerSlpToken {
     }
 }
 
+/// Token spent by each input of the tx at `tx_idx`, either from the db or from a valid SLP tx
+/// earlier in the same block.
+fn slp_token_inputs<'b>(
+    tx_idx: usize,
+    tx: &UnhashedTx,
+    valid_txs: &'b SlpValidHashMap,
+    input_tx_nums: &[Vec<TxNum>],
+    spent_slp_outputs: &'b [Vec<Option<SlpSpentOutput>>],
+) -> Vec<Option<SlpInputToken<'b>>> {
+    match tx_idx {
+        0 => vec![None],
+        _ => tx
+            .inputs
+            .iter()
+            .zip(&input_tx_nums[tx_idx - 1])
+            .zip(&spent_slp_outputs[tx_idx - 1])
+            .map(
+                |((input, &input_tx_num), spent_slp_output)| match spent_slp_output {
+                    Some(spent_slp_output) => Some(SlpInputToken {
+                        token_id: &spent_slp_output.token_id,
+                        token: &spent_slp_output.token,
+                    }),
+                    None => valid_txs.get(&input_tx_num).and_then(|slp| {
+                        Some(SlpInputToken {
+                            token_id: &slp.slp_tx_data.token_id,
+                            token: slp
+                                .slp_tx_data
+                                .output_tokens
+                                .get(input.prev_out.out_idx as usize)?,
+                        })
+                    }),
+                },
+            )
+            .collect(),
+    }
+}
+
+fn key_for_token_tx(token_num: TokenNum, tx_num: TxNum) -> Vec<u8> {
+    [
+        TokenNumZC::new(token_num).as_bytes(),
+        TxNumZC::new(tx_num).as_bytes(),
+    ]
+    .concat()
+}
+
+fn key_for_slp_utxo(token_num: TokenNum, tx_num: TxNum, out_idx: u32) -> Vec<u8> {
+    [
+        TokenNumZC::new(token_num).as_bytes(),
+        TxNumZC::new(tx_num).as_bytes(),
+        U32::<BE>::new(out_idx).as_bytes(),
+    ]
+    .concat()
+}
+
 fn get_token_id_by_token_num(db: &Db, token_num: TokenNum) -> Result<TokenId> {
     let token_id = db
         .get(
@@ -949,18 +1675,31 @@ pub fn is_ignored_error(slp_error: &SlpError) -> bool {
 
 #[cfg(test)]
 mod tests {
-    use bitcoinsuite_core::{Hashed, OutPoint, Script, Sha256d, TxInput, TxOutput, UnhashedTx};
+    use std::collections::HashMap;
+
+    use bitcoinsuite_core::{
+        Hashed, OutPoint, Script, Sha256d, ShaRmd160, TxInput, TxOutput, UnhashedTx,
+    };
     use bitcoinsuite_error::Result;
     use bitcoinsuite_slp::{
-        genesis_opreturn, mint_opreturn, send_opreturn, SlpAmount, SlpBurn, SlpError,
+        genesis_opreturn, mint_opreturn, parse_slp_tx, send_opreturn, SlpAmount, SlpBurn, SlpError,
         SlpGenesisInfo, SlpToken, SlpTokenType, SlpTxData, SlpTxType, TokenId,
     };
     use pretty_assertions::assert_eq;
-    use rocksdb::WriteBatch;
+    use rocksdb::{IteratorMode, WriteBatch};
+    use zerocopy::AsBytes;
 
     use crate::{
-        input_tx_nums::fetch_input_tx_nums, BlockHeight, BlockTxs, Db, SlpReader, SlpWriter,
-        TokenStats, TxEntry, TxNum, TxWriter,
+        input_tx_nums::fetch_input_tx_nums, BlockHeight, BlockTxs, Db, PayloadPrefix,
+        ScriptPayload, SlpConf, SlpReader, SlpStats, SlpWriter, TokenHolder, TokenStats, TxEntry,
+        TxNum, TxWriter,
+    };
+
+    use super::{
+        add_saturating, is_ignored_error, sub_saturating, TokenNumZC, TokenStatsData,
+        CF_SLP_TOKEN_ID_BY_NUM, CF_SLP_TOKEN_METADATA, CF_SLP_TOKEN_NUM_BY_ID, CF_SLP_TOKEN_SPENDS,
+        CF_SLP_TOKEN_STATS, CF_SLP_TOKEN_TXS, CF_SLP_TX_DATA, CF_SLP_TX_INVALID_MESSAGE,
+        CF_SLP_UTXOS_BY_TOKEN,
     };
 
     enum Outcome {
@@ -1338,11 +2077,18 @@ mod tests {
         let tempdir = tempdir::TempDir::new("slp-indexer-rocks--utxos")?;
         let db = Db::open(tempdir.path())?;
         let tx_writer = TxWriter::new(&db)?;
-        let slp_writer = SlpWriter::new(&db)?;
+        let slp_writer = SlpWriter::new(&db, SlpConf::default())?;
         let slp_reader = SlpReader::new(&db)?;
+        // Token outputs of the test txs all have the default script
+        let spent_output = TxOutput::default();
         let mut first_tx_num = 0;
         let mut previous_token_stats: Option<Vec<(TokenId, TokenStats)>> = None;
+        let mut token_ids = Vec::new();
+        let mut slp_stats = SlpStats::default();
+        let mut token_types = Vec::new();
+        let mut connected_blocks = Vec::new();
         for (block_height, (txids, txs, outcomes, token_stats)) in blocks.into_iter().enumerate() {
+            let previous_slp_stats = slp_stats.clone();
             let mut batch = WriteBatch::default();
             let input_tx_nums = fetch_input_tx_nums(&db, first_tx_num, |idx| &txids[idx], &txs)?;
             slp_writer.insert_block_txs(
@@ -1393,6 +2139,7 @@ mod tests {
                             "Mismatch genesis data for token ID {}",
                             txid
                         );
+                        token_ids.push(TokenId::new(txid.clone()));
                     }
                 }
                 match outcome {
@@ -1442,6 +2189,78 @@ mod tests {
                     }
                 }
             }
+            // Verify recent tokens; order within a block is arbitrary
+            let recent_tokens = slp_reader.recent_token_ids(None, token_ids.len() + 1)?;
+            let mut recent_token_ids = recent_tokens
+                .iter()
+                .map(|(_, token_id)| token_id.clone())
+                .collect::<Vec<_>>();
+            recent_token_ids.sort_by_key(TokenId::token_id_be);
+            let mut expected_token_ids = token_ids.clone();
+            expected_token_ids.sort_by_key(TokenId::token_id_be);
+            assert_eq!(recent_token_ids, expected_token_ids);
+            assert_eq!(slp_reader.recent_token_ids(None, 0)?, vec![]);
+            // Paging through the tokens with cursors lists every token exactly once
+            for page_size in 1..=2 {
+                let mut paged_tokens = Vec::new();
+                let mut before_token_num = None;
+                loop {
+                    let page = slp_reader.recent_token_ids(before_token_num, page_size)?;
+                    let (last_token_num, _) = match page.last() {
+                        Some(last) => last.clone(),
+                        None => break,
+                    };
+                    paged_tokens.extend(page);
+                    before_token_num = Some(last_token_num);
+                }
+                assert_eq!(paged_tokens, recent_tokens);
+            }
+            // Listing all tokens gives the same tokens oldest first, along with their GENESIS info
+            let all_tokens = slp_reader.tokens_page(0, token_ids.len() + 1)?;
+            assert_eq!(
+                all_tokens
+                    .iter()
+                    .map(|(token_num, token_id, _)| (*token_num, token_id.clone()))
+                    .collect::<Vec<_>>(),
+                recent_tokens.iter().rev().cloned().collect::<Vec<_>>(),
+            );
+            for (token_num, _, genesis_info) in &all_tokens {
+                assert_eq!(
+                    slp_reader.token_by_token_num(*token_num)?.as_ref(),
+                    Some(genesis_info),
+                );
+            }
+            let mut paged_tokens = Vec::new();
+            let mut start_token_num = 0;
+            loop {
+                let page = slp_reader.tokens_page(start_token_num, 2)?;
+                match page.last() {
+                    Some((last_token_num, _, _)) => start_token_num = last_token_num + 1,
+                    None => break,
+                }
+                paged_tokens.extend(page);
+            }
+            assert_eq!(paged_tokens, all_tokens);
+            // Verify SLP stats
+            for outcome in &outcomes {
+                if let Outcome::Valid(slp_data) | Outcome::ValidBurn(slp_data, _) = outcome {
+                    match &slp_data.slp_tx_type {
+                        SlpTxType::Genesis(_) => {
+                            slp_stats.num_genesis_txs += 1;
+                            slp_stats.num_tokens += 1;
+                            if !token_types.contains(&slp_data.slp_token_type) {
+                                token_types.push(slp_data.slp_token_type);
+                                slp_stats.num_token_types += 1;
+                            }
+                        }
+                        SlpTxType::Mint => slp_stats.num_mint_txs += 1,
+                        SlpTxType::Send => slp_stats.num_send_txs += 1,
+                        SlpTxType::Burn(_) => slp_stats.num_burn_txs += 1,
+                        SlpTxType::Unknown => {}
+                    }
+                }
+            }
+            assert_eq!(slp_reader.slp_stats()?, slp_stats);
             // Verify token stats
             for (token_id, expected_stats) in &token_stats {
                 let token_num = slp_reader.token_num_by_id(token_id)?.unwrap();
@@ -1461,6 +2280,7 @@ mod tests {
                 &txs,
                 |idx| &txids[idx],
                 &input_tx_nums,
+                |_, _| &spent_output,
             )?;
             db.write_batch(batch)?;
             for (tx_idx, outcome) in outcomes.iter().enumerate() {
@@ -1491,6 +2311,8 @@ mod tests {
                 }
             }
             previous_token_stats = Some(token_stats);
+            // Disconnecting decrements the SLP stats again
+            assert_eq!(slp_reader.slp_stats()?, previous_slp_stats);
             // Add block back in again before continuing
             let mut batch = WriteBatch::default();
             slp_writer.insert_block_txs(
@@ -1501,8 +2323,596 @@ mod tests {
                 &input_tx_nums,
             )?;
             db.write_batch(batch)?;
-            first_tx_num += txids.len() as TxNum;
+            let num_txs = txids.len() as TxNum;
+            connected_blocks.push((first_tx_num, txids, txs, input_tx_nums));
+            first_tx_num += num_txs;
         }
+        // Rewind the entire chain down to and including genesis
+        for (first_tx_num, txids, txs, input_tx_nums) in connected_blocks.iter().rev() {
+            let mut batch = WriteBatch::default();
+            slp_writer.delete_block_txs(
+                &mut batch,
+                *first_tx_num,
+                txs,
+                |idx| &txids[idx],
+                input_tx_nums,
+                |_, _| &spent_output,
+            )?;
+            db.write_batch(batch)?;
+        }
+        assert_eq!(slp_reader.slp_stats()?, SlpStats::default());
+        assert_eq!(
+            slp_reader.recent_token_ids(None, token_ids.len() + 1)?,
+            vec![]
+        );
+        assert_eq!(slp_reader.tokens_page(0, token_ids.len() + 1)?, vec![]);
+        for token_id in &token_ids {
+            assert_eq!(slp_reader.token_num_by_id(token_id)?, None);
+        }
+        // No leftover entries, e.g. zeroed stats of tokens that don't exist anymore
+        for cf_name in [
+            CF_SLP_TOKEN_ID_BY_NUM,
+            CF_SLP_TOKEN_NUM_BY_ID,
+            CF_SLP_TOKEN_METADATA,
+            CF_SLP_TX_DATA,
+            CF_SLP_TX_INVALID_MESSAGE,
+            CF_SLP_TOKEN_STATS,
+            CF_SLP_TOKEN_SPENDS,
+            CF_SLP_UTXOS_BY_TOKEN,
+            CF_SLP_TOKEN_TXS,
+        ] {
+            let mut iterator = db.rocks().iterator_cf(db.cf(cf_name)?, IteratorMode::Start);
+            assert!(iterator.next().is_none(), "{} not empty", cf_name);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_slp_writer_unknown_token_type() -> Result<()> {
+        bitcoinsuite_error::install()?;
+        for ignore_unknown_token_types in [false, true] {
+            let tempdir = tempdir::TempDir::new("slp-indexer-rocks--unknown-token-type")?;
+            let db = Db::open(tempdir.path())?;
+            let conf = SlpConf {
+                ignore_unknown_token_types,
+            };
+            let slp_writer = SlpWriter::new(&db, conf)?;
+            let slp_reader = SlpReader::new(&db)?;
+            let spent_output = TxOutput::default();
+            let (txids, txs): (Vec<_>, Vec<_>) = [
+                make_tx(
+                    (1, [(0, 0xffff_ffff)], 2),
+                    Script::default(),
+                    Outcome::NotSlp,
+                ),
+                // SLP tx of unknown token type 0xff
+                make_tx(
+                    (2, [(1, 1)], 2),
+                    Script::opreturn(&[b"SLP\0", b"\xff", b"INCOGNITO"]),
+                    Outcome::NotSlp,
+                ),
+            ]
+            .into_iter()
+            .map(|(txid, tx, _)| (txid, tx))
+            .unzip();
+            let input_tx_nums = fetch_input_tx_nums(&db, 0, |idx| &txids[idx], &txs)?;
+            let mut batch = WriteBatch::default();
+            slp_writer.insert_block_txs(&mut batch, 0, &txs, |idx| &txids[idx], &input_tx_nums)?;
+            db.write_batch(batch)?;
+            let slp_data = slp_reader.slp_data_by_tx_num(1)?;
+            match ignore_unknown_token_types {
+                true => assert_eq!(slp_data, None),
+                false => assert_eq!(
+                    slp_data.unwrap().slp_tx_data.slp_token_type,
+                    SlpTokenType::Unknown,
+                ),
+            }
+            assert_eq!(slp_reader.slp_invalid_message_tx_num(1)?, None);
+            assert_eq!(slp_reader.slp_stats()?, SlpStats::default());
+            let mut batch = WriteBatch::default();
+            slp_writer.delete_block_txs(
+                &mut batch,
+                0,
+                &txs,
+                |idx| &txids[idx],
+                &input_tx_nums,
+                |_, _| &spent_output,
+            )?;
+            db.write_batch(batch)?;
+            assert_eq!(slp_reader.slp_data_by_tx_num(1)?, None);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_slp_writer_tx_cycle() -> Result<()> {
+        bitcoinsuite_error::install()?;
+        let tempdir = tempdir::TempDir::new("slp-indexer-rocks--tx-cycle")?;
+        let db = Db::open(tempdir.path())?;
+        let slp_writer = SlpWriter::new(&db, SlpConf::default())?;
+        let slp_reader = SlpReader::new(&db)?;
+        let spent_output = TxOutput::default();
+        let token_id = TokenId::new(make_hash(2));
+        let send_script =
+            || send_opreturn(&token_id, SlpTokenType::Fungible, &[SlpAmount::new(10)]);
+        // Malformed block where txs 3 and 4 spend each other, and tx 5 depends on the cycle
+        let (txids, txs): (Vec<_>, Vec<_>) = [
+            make_tx(
+                (1, [(0, 0xffff_ffff)], 2),
+                Script::default(),
+                Outcome::NotSlp,
+            ),
+            make_tx(
+                (2, [(1, 1)], 2),
+                genesis_opreturn(&SlpGenesisInfo::default(), SlpTokenType::Fungible, None, 10),
+                Outcome::NotSlp,
+            ),
+            make_tx((3, [(4, 1)], 2), send_script(), Outcome::NotSlp),
+            make_tx((4, [(3, 1)], 2), send_script(), Outcome::NotSlp),
+            make_tx((5, [(4, 1)], 2), send_script(), Outcome::NotSlp),
+        ]
+        .into_iter()
+        .map(|(txid, tx, _)| (txid, tx))
+        .unzip();
+        let input_tx_nums = fetch_input_tx_nums(&db, 0, |idx| &txids[idx], &txs)?;
+        let mut batch = WriteBatch::default();
+        slp_writer.insert_block_txs(&mut batch, 0, &txs, |idx| &txids[idx], &input_tx_nums)?;
+        db.write_batch(batch)?;
+        // Txs outside the cycle are still validated
+        assert!(slp_reader.slp_data_by_tx_num(1)?.is_some());
+        assert_eq!(slp_reader.slp_invalid_message_tx_num(1)?, None);
+        for tx_num in 2..=4 {
+            assert_eq!(slp_reader.slp_data_by_tx_num(tx_num)?, None);
+            assert_eq!(
+                slp_reader.slp_invalid_message_tx_num(tx_num)?.as_deref(),
+                Some(super::TX_CYCLE_MESSAGE),
+            );
+        }
+        let mut batch = WriteBatch::default();
+        slp_writer.delete_block_txs(
+            &mut batch,
+            0,
+            &txs,
+            |idx| &txids[idx],
+            &input_tx_nums,
+            |_, _| &spent_output,
+        )?;
+        db.write_batch(batch)?;
+        for tx_num in 1..=4 {
+            assert_eq!(slp_reader.slp_data_by_tx_num(tx_num)?, None);
+            assert_eq!(slp_reader.slp_invalid_message_tx_num(tx_num)?, None);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_token_spends() -> Result<()> {
+        bitcoinsuite_error::install()?;
+        let tempdir = tempdir::TempDir::new("slp-indexer-rocks--token-spends")?;
+        let db = Db::open(tempdir.path())?;
+        let tx_writer = TxWriter::new(&db)?;
+        let slp_writer = SlpWriter::new(&db, SlpConf::default())?;
+        let slp_reader = SlpReader::new(&db)?;
+        let spent_output = TxOutput::default();
+        let token_id = TokenId::new(make_hash(2));
+        let block1 = vec![
+            make_tx(
+                (1, [(0, 0xffff_ffff)], 3),
+                Script::default(),
+                Outcome::NotSlp,
+            ),
+            make_tx(
+                (2, [(1, 1)], 3),
+                genesis_opreturn(
+                    &SlpGenesisInfo::default(),
+                    SlpTokenType::Fungible,
+                    Some(2),
+                    10,
+                ),
+                Outcome::NotSlp,
+            ),
+            // SEND spending the GENESIS output in the same block
+            make_tx(
+                (3, [(2, 1)], 2),
+                send_opreturn(&token_id, SlpTokenType::Fungible, &[SlpAmount::new(10)]),
+                Outcome::NotSlp,
+            ),
+        ];
+        let block2 = vec![
+            make_tx(
+                (11, [(0, 0xffff_ffff)], 2),
+                Script::default(),
+                Outcome::NotSlp,
+            ),
+            // Non-SLP tx burning the SEND output
+            make_tx((12, [(3, 1)], 2), Script::default(), Outcome::NotSlp),
+            // MINT spending the mint baton
+            make_tx(
+                (13, [(2, 2)], 3),
+                mint_opreturn(&token_id, SlpTokenType::Fungible, Some(2), 5),
+                Outcome::NotSlp,
+            ),
+            // Spends a non-token output of the GENESIS and the coinbase
+            make_tx(
+                (14, [(2, 0), (11, 1)], 2),
+                Script::default(),
+                Outcome::NotSlp,
+            ),
+        ];
+        let mut first_tx_num = 0;
+        let mut block2_data = None;
+        for (block_height, block) in [block1, block2].into_iter().enumerate() {
+            let (txids, txs): (Vec<_>, Vec<_>) =
+                block.into_iter().map(|(txid, tx, _)| (txid, tx)).unzip();
+            let input_tx_nums = fetch_input_tx_nums(&db, first_tx_num, |idx| &txids[idx], &txs)?;
+            let mut batch = WriteBatch::default();
+            slp_writer.insert_block_txs(
+                &mut batch,
+                first_tx_num,
+                &txs,
+                |idx| &txids[idx],
+                &input_tx_nums,
+            )?;
+            tx_writer.insert_block_txs(
+                &mut batch,
+                &BlockTxs {
+                    txs: txids
+                        .iter()
+                        .map(|txid| TxEntry {
+                            txid: txid.clone(),
+                            ..Default::default()
+                        })
+                        .collect(),
+                    block_height: block_height as BlockHeight,
+                },
+            )?;
+            db.write_batch(batch)?;
+            let token_num = slp_reader.token_num_by_id(&token_id)?.unwrap();
+            if block_height == 0 {
+                assert_eq!(slp_reader.num_token_spends(token_num)?, 1);
+                assert_eq!(slp_reader.rev_page_token_spends(token_num, 0, 10)?, vec![2]);
+                assert_eq!(slp_reader.rev_page_token_txs(token_num, 0, 10)?, vec![2, 1]);
+            }
+            let num_txs = txids.len() as TxNum;
+            block2_data = Some((first_tx_num, txids, txs, input_tx_nums));
+            first_tx_num += num_txs;
+        }
+        let token_num = slp_reader.token_num_by_id(&token_id)?.unwrap();
+        assert_eq!(slp_reader.num_token_spends(token_num)?, 3);
+        assert_eq!(
+            slp_reader.rev_page_token_spends(token_num, 0, 10)?,
+            vec![5, 4, 2],
+        );
+        assert_eq!(
+            slp_reader.rev_page_token_spends(token_num, 0, 2)?,
+            vec![5, 4]
+        );
+        assert_eq!(slp_reader.rev_page_token_spends(token_num, 1, 2)?, vec![2]);
+        assert_eq!(slp_reader.rev_page_token_spends(token_num, 2, 2)?, vec![]);
+        // Token txs only include valid SLP txs, not the non-SLP burn, with the GENESIS last
+        assert_eq!(slp_reader.num_token_txs(token_num)?, 3);
+        assert_eq!(
+            slp_reader.rev_page_token_txs(token_num, 0, 10)?,
+            vec![5, 2, 1],
+        );
+        assert_eq!(slp_reader.rev_page_token_txs(token_num, 1, 2)?, vec![1]);
+
+        // Disconnecting the block removes its spends again
+        let (first_tx_num, txids, txs, input_tx_nums) = block2_data.unwrap();
+        let mut batch = WriteBatch::default();
+        slp_writer.delete_block_txs(
+            &mut batch,
+            first_tx_num,
+            &txs,
+            |idx| &txids[idx],
+            &input_tx_nums,
+            |_, _| &spent_output,
+        )?;
+        db.write_batch(batch)?;
+        assert_eq!(slp_reader.num_token_spends(token_num)?, 1);
+        assert_eq!(slp_reader.rev_page_token_spends(token_num, 0, 10)?, vec![2]);
+        assert_eq!(slp_reader.num_token_txs(token_num)?, 2);
+        assert_eq!(slp_reader.rev_page_token_txs(token_num, 0, 10)?, vec![2, 1]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_token_holders() -> Result<()> {
+        bitcoinsuite_error::install()?;
+        let tempdir = tempdir::TempDir::new("slp-indexer-rocks--token-holders")?;
+        let db = Db::open(tempdir.path())?;
+        let tx_writer = TxWriter::new(&db)?;
+        let slp_writer = SlpWriter::new(&db, SlpConf::default())?;
+        let slp_reader = SlpReader::new(&db)?;
+        let token_id = TokenId::new(make_hash(2));
+        let script = |byte: u8| Script::p2pkh(&ShaRmd160::new([byte; 20]));
+        let holder = |byte: u8, amount: i128| TokenHolder {
+            script_payload: ScriptPayload {
+                payload_prefix: PayloadPrefix::P2PKH,
+                payload_data: vec![byte; 20],
+            },
+            amount,
+        };
+        let mut block1 = vec![
+            make_tx(
+                (1, [(0, 0xffff_ffff)], 3),
+                Script::default(),
+                Outcome::NotSlp,
+            ),
+            // GENESIS with the mint baton going to script 4
+            make_tx(
+                (2, [(1, 1)], 3),
+                genesis_opreturn(
+                    &SlpGenesisInfo::default(),
+                    SlpTokenType::Fungible,
+                    Some(2),
+                    10,
+                ),
+                Outcome::NotSlp,
+            ),
+            // SEND splitting the GENESIS output between scripts 2 and 1
+            make_tx(
+                (3, [(2, 1)], 3),
+                send_opreturn(
+                    &token_id,
+                    SlpTokenType::Fungible,
+                    &[SlpAmount::new(6), SlpAmount::new(4)],
+                ),
+                Outcome::NotSlp,
+            ),
+        ];
+        block1[1].1.outputs[1].script = script(1);
+        block1[1].1.outputs[2].script = script(4);
+        block1[2].1.outputs[1].script = script(2);
+        block1[2].1.outputs[2].script = script(1);
+        let mut block2 = vec![
+            make_tx(
+                (11, [(0, 0xffff_ffff)], 2),
+                Script::default(),
+                Outcome::NotSlp,
+            ),
+            // MINT spending the mint baton, to script 3
+            make_tx(
+                (12, [(2, 2)], 3),
+                mint_opreturn(&token_id, SlpTokenType::Fungible, Some(2), 5),
+                Outcome::NotSlp,
+            ),
+            // Non-SLP tx burning the tokens of script 1
+            make_tx((13, [(3, 2)], 2), Script::default(), Outcome::NotSlp),
+        ];
+        block2[1].1.outputs[1].script = script(3);
+        block2[1].1.outputs[2].script = script(3);
+        let mut outputs = HashMap::new();
+        let mut first_tx_num = 0;
+        let mut block2_data = None;
+        for (block_height, block) in [block1, block2].into_iter().enumerate() {
+            let (txids, txs): (Vec<_>, Vec<_>) =
+                block.into_iter().map(|(txid, tx, _)| (txid, tx)).unzip();
+            for (txid, tx) in txids.iter().zip(&txs) {
+                for (out_idx, output) in tx.outputs.iter().enumerate() {
+                    let outpoint = OutPoint {
+                        txid: txid.clone(),
+                        out_idx: out_idx as u32,
+                    };
+                    outputs.insert(outpoint, output.clone());
+                }
+            }
+            let input_tx_nums = fetch_input_tx_nums(&db, first_tx_num, |idx| &txids[idx], &txs)?;
+            let mut batch = WriteBatch::default();
+            slp_writer.insert_block_txs(
+                &mut batch,
+                first_tx_num,
+                &txs,
+                |idx| &txids[idx],
+                &input_tx_nums,
+            )?;
+            tx_writer.insert_block_txs(
+                &mut batch,
+                &BlockTxs {
+                    txs: txids
+                        .iter()
+                        .map(|txid| TxEntry {
+                            txid: txid.clone(),
+                            ..Default::default()
+                        })
+                        .collect(),
+                    block_height: block_height as BlockHeight,
+                },
+            )?;
+            db.write_batch(batch)?;
+            let token_num = slp_reader.token_num_by_id(&token_id)?.unwrap();
+            if block_height == 0 {
+                // Script 4 only holds the mint baton, which isn't part of any balance
+                assert_eq!(
+                    slp_reader.token_holders(token_num)?,
+                    vec![holder(2, 6), holder(1, 4)],
+                );
+            }
+            let num_txs = txids.len() as TxNum;
+            block2_data = Some((first_tx_num, txids, txs, input_tx_nums));
+            first_tx_num += num_txs;
+        }
+        let token_num = slp_reader.token_num_by_id(&token_id)?.unwrap();
+        assert_eq!(
+            slp_reader.token_holders(token_num)?,
+            vec![holder(2, 6), holder(3, 5)],
+        );
+
+        // Disconnecting the block adds back the token outputs it spent
+        let (first_tx_num, txids, txs, input_tx_nums) = block2_data.unwrap();
+        let spent_outputs = txs
+            .iter()
+            .skip(1)
+            .map(|tx| {
+                tx.inputs
+                    .iter()
+                    .map(|input| outputs[&input.prev_out].clone())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let mut batch = WriteBatch::default();
+        slp_writer.delete_block_txs(
+            &mut batch,
+            first_tx_num,
+            &txs,
+            |idx| &txids[idx],
+            &input_tx_nums,
+            |tx_pos, input_idx| &spent_outputs[tx_pos][input_idx],
+        )?;
+        db.write_batch(batch)?;
+        assert_eq!(
+            slp_reader.token_holders(token_num)?,
+            vec![holder(2, 6), holder(1, 4)],
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_slp_tx_multiple_opreturns() -> Result<()> {
+        let slp_script =
+            genesis_opreturn(&SlpGenesisInfo::default(), SlpTokenType::Fungible, None, 10);
+        let data_script = Script::opreturn(&[b"data"]);
+        let txid = make_hash(1);
+        let (_, mut tx, _) = make_tx((1, [(0, 0)], 3), slp_script.clone(), Outcome::NotSlp);
+        // Additional OP_RETURN after the SLP OP_RETURN doesn't affect parsing
+        tx.outputs[2].script = data_script.clone();
+        let parse_data = parse_slp_tx(&txid, &tx)?;
+        assert!(matches!(parse_data.slp_tx_type, SlpTxType::Genesis(_)));
+        assert_eq!(
+            parse_data.output_tokens[..2],
+            [SlpToken::EMPTY, SlpToken::amount(10)],
+        );
+        assert!(parse_data.output_tokens[2..]
+            .iter()
+            .all(|token| *token == SlpToken::EMPTY));
+        // SLP OP_RETURN is only recognized in the first output
+        tx.outputs[0].script = data_script;
+        tx.outputs[2].script = slp_script;
+        let slp_error = parse_slp_tx(&txid, &tx).unwrap_err();
+        assert!(is_ignored_error(&slp_error), "{:?}", slp_error);
+        Ok(())
+    }
+
+    #[test]
+    fn test_token_stats_overflow() -> Result<()> {
+        assert_eq!(add_saturating(1, 2), (3, false));
+        assert_eq!(add_saturating(i128::MAX - 1, 2), (i128::MAX, true));
+        assert_eq!(sub_saturating(i128::MIN + 1, 2), (i128::MIN, true));
+
+        bitcoinsuite_error::install()?;
+        let tempdir = tempdir::TempDir::new("slp-indexer-rocks--token-stats-overflow")?;
+        let db = Db::open(tempdir.path())?;
+        let tx_writer = TxWriter::new(&db)?;
+        let slp_writer = SlpWriter::new(&db, SlpConf::default())?;
+        let slp_reader = SlpReader::new(&db)?;
+        let spent_output = TxOutput::default();
+        let token_id = TokenId::new(make_hash(2));
+        let genesis_block = [
+            make_tx(
+                (1, [(0, 0xffff_ffff)], 3),
+                Script::default(),
+                Outcome::NotSlp,
+            ),
+            make_tx(
+                (2, [(1, 1)], 3),
+                genesis_opreturn(
+                    &SlpGenesisInfo::default(),
+                    SlpTokenType::Fungible,
+                    Some(2),
+                    u64::MAX,
+                ),
+                Outcome::NotSlp,
+            ),
+        ];
+        let mint_block = [
+            make_tx(
+                (11, [(0, 0xffff_ffff)], 3),
+                Script::default(),
+                Outcome::NotSlp,
+            ),
+            make_tx(
+                (12, [(2, 2)], 3),
+                mint_opreturn(&token_id, SlpTokenType::Fungible, Some(2), u64::MAX),
+                Outcome::NotSlp,
+            ),
+        ];
+        let mut first_tx_num = 0;
+        let mut mint_block_data = None;
+        for (block_height, block) in [genesis_block, mint_block].into_iter().enumerate() {
+            let (txids, txs): (Vec<_>, Vec<_>) =
+                block.into_iter().map(|(txid, tx, _)| (txid, tx)).unzip();
+            let input_tx_nums = fetch_input_tx_nums(&db, first_tx_num, |idx| &txids[idx], &txs)?;
+            let mut batch = WriteBatch::default();
+            slp_writer.insert_block_txs(
+                &mut batch,
+                first_tx_num,
+                &txs,
+                |idx| &txids[idx],
+                &input_tx_nums,
+            )?;
+            tx_writer.insert_block_txs(
+                &mut batch,
+                &BlockTxs {
+                    txs: txids
+                        .iter()
+                        .map(|txid| TxEntry {
+                            txid: txid.clone(),
+                            ..Default::default()
+                        })
+                        .collect(),
+                    block_height: block_height as BlockHeight,
+                },
+            )?;
+            db.write_batch(batch)?;
+            mint_block_data = Some((first_tx_num, txids, txs, input_tx_nums));
+            first_tx_num += 2;
+            if block_height == 0 {
+                // Pretend lots of MINTs happened already, so the next one overflows
+                let token_num = slp_reader.token_num_by_id(&token_id)?.unwrap();
+                let token_stats_data = TokenStatsData {
+                    total_minted: (i128::MAX - 1).into(),
+                    total_burned: 0.into(),
+                    supply_overflowed: 0,
+                };
+                let mut batch = WriteBatch::default();
+                batch.put_cf(
+                    slp_writer.cf_slp_token_stats(),
+                    TokenNumZC::new(token_num).as_bytes(),
+                    token_stats_data.as_bytes(),
+                );
+                db.write_batch(batch)?;
+            }
+        }
+        let token_num = slp_reader.token_num_by_id(&token_id)?.unwrap();
+        assert_eq!(
+            slp_reader.token_stats_by_token_num(token_num)?,
+            Some(TokenStats {
+                total_minted: i128::MAX,
+                total_burned: 0,
+                supply_overflowed: true,
+            }),
+        );
+
+        // Disconnecting the MINT block keeps the flag
+        let (first_tx_num, txids, txs, input_tx_nums) = mint_block_data.unwrap();
+        let mut batch = WriteBatch::default();
+        slp_writer.delete_block_txs(
+            &mut batch,
+            first_tx_num,
+            &txs,
+            |idx| &txids[idx],
+            &input_tx_nums,
+            |_, _| &spent_output,
+        )?;
+        db.write_batch(batch)?;
+        assert_eq!(
+            slp_reader.token_stats_by_token_num(token_num)?,
+            Some(TokenStats {
+                total_minted: i128::MAX - u64::MAX as i128,
+                total_burned: 0,
+                supply_overflowed: true,
+            }),
+        );
         Ok(())
     }
 
@@ -1529,6 +2939,7 @@ mod tests {
                     TokenStats {
                         total_minted: mint,
                         total_burned: burn,
+                        supply_overflowed: false,
                     },
                 )
             })
@@ -31,9 +31,21 @@ pub type SlpValidHashMap = HashMap<TxNum, SlpValidTxData>;
 pub type SlpInvalidHashMap = HashMap<TxNum, SlpInvalidTxData>;
 
 pub fn validate_slp_batch(
+    txs: HashMap<TxNum, BatchSlpTx>,
+    known_slp_outputs: HashMap<OutpointEntry, Option<SlpSpentOutput>>,
+) -> Result<(SlpValidHashMap, SlpInvalidHashMap), BatchError> {
+    match validate_slp_batch_partial(txs, known_slp_outputs) {
+        (valid_results, invalid_results, None) => Ok((valid_results, invalid_results)),
+        (_, _, Some(err)) => Err(err),
+    }
+}
+
+/// Like `validate_slp_batch`, but if some txs form a cycle, the other txs are still validated.
+/// The txs of the cycle, and txs depending on them, are in neither result, but in the error.
+pub fn validate_slp_batch_partial(
     mut txs: HashMap<TxNum, BatchSlpTx>,
     mut known_slp_outputs: HashMap<OutpointEntry, Option<SlpSpentOutput>>,
-) -> Result<(SlpValidHashMap, SlpInvalidHashMap), BatchError> {
+) -> (SlpValidHashMap, SlpInvalidHashMap, Option<BatchError>) {
     let mut valid_results = HashMap::new();
     let mut invalid_results = HashMap::new();
     let tx_nums = txs.keys().copied().collect::<HashSet<_>>();
@@ -131,10 +143,11 @@ pub fn validate_slp_batch(
             }
         }
         if next_round.is_empty() {
-            return Ok((valid_results, invalid_results));
+            return (valid_results, invalid_results, None);
         }
         if is_only_orphans {
-            return Err(BatchError::FoundTxCycle(next_round.into_keys().collect()));
+            let err = BatchError::FoundTxCycle(next_round.into_keys().collect());
+            return (valid_results, invalid_results, Some(err));
         }
         txs = next_round;
     }
@@ -302,6 +302,31 @@ impl<'a> TxReader<'a> {
         Ok(Some(tx_num.get()))
     }
 
+    /// Txid of the tx at position `index` within the block at `block_height`, where the coinbase
+    /// is at 0. None if the block doesn't exist or has no tx at `index`.
+    pub fn txid_by_block_and_index(
+        &self,
+        block_height: BlockHeight,
+        index: usize,
+    ) -> Result<Option<Sha256d>> {
+        let first_tx_num = match self.first_tx_num_by_block(block_height)? {
+            Some(first_tx_num) => first_tx_num,
+            None => return Ok(None),
+        };
+        let end_tx_num = match self.first_tx_num_by_block(block_height + 1)? {
+            Some(next_first_tx_num) => next_first_tx_num,
+            None => match self.last_tx_num()? {
+                Some(last_tx_num) => last_tx_num + 1,
+                None => return Ok(None),
+            },
+        };
+        let tx_num = first_tx_num + index as TxNum;
+        if tx_num >= end_tx_num {
+            return Ok(None);
+        }
+        self.txid_by_tx_num(tx_num)
+    }
+
     pub fn last_tx_num(&self) -> Result<Option<TxNum>> {
         let mut iter = self
             .db
@@ -448,6 +473,21 @@ mod test {
             assert_eq!(tx_reader.first_tx_num_by_block(1)?, Some(1));
             assert_eq!(tx_reader.first_tx_num_by_block(2)?, None);
             assert_eq!(tx_reader.last_tx_num()?, Some(2));
+            assert_eq!(
+                tx_reader.txid_by_block_and_index(0, 0)?,
+                Some(Sha256d::new([1; 32])),
+            );
+            assert_eq!(tx_reader.txid_by_block_and_index(0, 1)?, None);
+            assert_eq!(
+                tx_reader.txid_by_block_and_index(1, 0)?,
+                Some(Sha256d::new([2; 32])),
+            );
+            assert_eq!(
+                tx_reader.txid_by_block_and_index(1, 1)?,
+                Some(Sha256d::new([3; 32])),
+            );
+            assert_eq!(tx_reader.txid_by_block_and_index(1, 2)?, None);
+            assert_eq!(tx_reader.txid_by_block_and_index(2, 0)?, None);
             assert_eq!(tx_reader.by_txid(&Sha256d::new([0; 32]))?, None);
             assert_eq!(tx_reader.tx_num_by_txid(&Sha256d::new([0; 32]))?, None);
             assert_eq!(
@@ -482,7 +522,7 @@ mod test {
                 tx_reader.by_txid(&Sha256d::new([1; 32]))?,
                 Some(block_tx1.clone())
             );
-            assert_eq!(tx_reader.by_tx_num(0)?, Some(block_tx1));
+            assert_eq!(tx_reader.by_tx_num(0)?, Some(block_tx1.clone()));
             assert_eq!(tx_reader.by_txid(&Sha256d::new([2; 32]))?, None);
             assert_eq!(tx_reader.by_tx_num(1)?, None);
             assert_eq!(tx_reader.by_txid(&Sha256d::new([3; 32]))?, None);
@@ -548,6 +588,36 @@ mod test {
             assert_eq!(tx_reader.tx_num_by_txid(&Sha256d::new([103; 32]))?, Some(2));
             assert_eq!(tx_reader.by_tx_num(2)?, Some(block_tx3));
         }
+        {
+            // Delete remaining block, leaving no txs at all
+            let mut batch = WriteBatch::default();
+            tx_writer.delete_block_txs(&mut batch, 1)?;
+            db.write_batch(batch)?;
+            assert_eq!(tx_reader.first_tx_num_by_block(0)?, None);
+            assert_eq!(tx_reader.first_tx_num_by_block(1)?, None);
+            assert_eq!(tx_reader.last_tx_num()?, None);
+            assert_eq!(tx_reader.by_txid(&Sha256d::new([102; 32]))?, None);
+            assert_eq!(tx_reader.by_txid(&Sha256d::new([103; 32]))?, None);
+            assert_eq!(tx_reader.by_tx_num(1)?, None);
+            assert_eq!(tx_reader.by_tx_num(2)?, None);
+            // Deleting a block that has no txs is an error, not a silent no-op
+            let mut batch = WriteBatch::default();
+            assert!(tx_writer.delete_block_txs(&mut batch, 0).is_err());
+        }
+        {
+            // Re-inserting genesis after a full rewind starts again at tx_num 0
+            let block_txs = BlockTxs {
+                block_height: 0,
+                txs: vec![block_tx1.entry.clone()],
+            };
+            let mut batch = WriteBatch::default();
+            let first_tx_num = tx_writer.insert_block_txs(&mut batch, &block_txs)?;
+            db.write_batch(batch)?;
+            assert_eq!(first_tx_num, 0);
+            assert_eq!(tx_reader.first_tx_num_by_block(0)?, Some(0));
+            assert_eq!(tx_reader.last_tx_num()?, Some(0));
+            assert_eq!(tx_reader.by_txid(&Sha256d::new([1; 32]))?, Some(block_tx1));
+        }
         Ok(())
     }
 }
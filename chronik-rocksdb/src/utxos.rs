@@ -7,13 +7,14 @@ use bitcoinsuite_core::{OutPoint, Sha256d, TxOutput, UnhashedTx};
 use bitcoinsuite_error::{ErrorMeta, Result};
 use byteorder::LE;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
-use rocksdb::{ColumnFamilyDescriptor, Options, WriteBatch};
+use rocksdb::{ColumnFamilyDescriptor, Direction, IteratorMode, Options, WriteBatch};
 use thiserror::Error;
 use zerocopy::{AsBytes, FromBytes, Unaligned, U32, U64};
 
 use crate::{
-    data::interpret_slice, outpoint_data::OutpointData, script_payload::script_payloads, Db,
-    OutpointEntry, PayloadPrefix, Timings, TxNum, TxReader, CF,
+    data::interpret_slice, outpoint_data::OutpointData, script_payload::set_payload_bloom_options,
+    Db, DbConf, OutpointEntry, PayloadPrefix, ScriptFilter, ScriptPayload, Timings, TxNum,
+    TxReader, CF,
 };
 
 pub const CF_UTXOS: &str = "utxos";
@@ -28,6 +29,7 @@ script -> [(tx_num, out_idx, field)]
 
 pub struct UtxosWriter<'a> {
     db: &'a Db,
+    script_filter: &'a ScriptFilter,
 }
 
 pub struct UtxosReader<'a> {
@@ -72,14 +74,19 @@ fn _assert_utxos_writer_send_sync() {
 fn _assert_send_sync<T: Send + Sync>(_: impl Fn(T)) {}
 
 impl<'a> UtxosWriter<'a> {
-    pub fn add_cfs(columns: &mut Vec<ColumnFamilyDescriptor>) {
-        let options = Options::default();
+    pub fn add_cfs(columns: &mut Vec<ColumnFamilyDescriptor>, conf: &DbConf) {
+        let mut options = Options::default();
+        if conf.payload_prefix_bloom {
+            // No prefix extractor, as the keys are the bare script payloads and scripts are
+            // iterated across payloads, e.g. in `utxos_page`.
+            set_payload_bloom_options(&mut options);
+        }
         columns.push(ColumnFamilyDescriptor::new(CF_UTXOS, options));
     }
 
-    pub fn new(db: &'a Db) -> Result<Self> {
+    pub fn new(db: &'a Db, script_filter: &'a ScriptFilter) -> Result<Self> {
         let _ = db.cf(CF_UTXOS)?;
-        Ok(UtxosWriter { db })
+        Ok(UtxosWriter { db, script_filter })
     }
 
     pub fn insert_block_txs<'b>(
@@ -101,7 +108,7 @@ impl<'a> UtxosWriter<'a> {
             let txid = txids_fn(tx_idx);
             new_tx_nums.insert(txid.clone(), tx_num);
             for (out_idx, output) in tx.outputs.iter().enumerate() {
-                for script_payload_state in script_payloads(&output.script) {
+                for script_payload_state in self.script_filter.script_payloads(&output.script) {
                     let script_payload = script_payload_state.payload.into_vec();
                     let outpoints = output_outpoints.entry(script_payload).or_insert(vec![]);
                     outpoints.push(UtxoEntry {
@@ -151,7 +158,7 @@ impl<'a> UtxosWriter<'a> {
                 .enumerate()
             {
                 let spent_output = block_spent_output_fn(tx_pos, input_idx);
-                for script_payload in script_payloads(&spent_output.script) {
+                for script_payload in self.script_filter.script_payloads(&spent_output.script) {
                     let script_payload = script_payload.payload.into_vec();
                     let outpoints = input_outpoints.entry(script_payload).or_insert(vec![]);
                     outpoints.push((spent_tx_num, input.prev_out.out_idx));
@@ -238,7 +245,8 @@ impl<'a> UtxosWriter<'a> {
                         .tx_num_by_txid(&input.prev_out.txid)?
                         .ok_or_else(|| UnknownInputSpent(input.prev_out.clone()))?,
                 };
-                for script_payload_state in script_payloads(&spent_output.script) {
+                for script_payload_state in self.script_filter.script_payloads(&spent_output.script)
+                {
                     let script_payload = script_payload_state.payload.into_vec();
                     update_map_or_db_entry(
                         self.db,
@@ -265,7 +273,7 @@ impl<'a> UtxosWriter<'a> {
         let mut tx_num = first_tx_num;
         for tx in txs {
             for (out_idx, output) in tx.outputs.iter().enumerate() {
-                for script_payload in script_payloads(&output.script) {
+                for script_payload in self.script_filter.script_payloads(&output.script) {
                     let script_payload = script_payload.payload.into_vec();
                     update_map_or_db_entry(
                         self.db,
@@ -322,6 +330,39 @@ impl<'a> UtxosReader<'a> {
             .collect();
         Ok(entries)
     }
+
+    /// Up to `max_scripts` scripts with the given prefix and their UTXOs, ordered by payload,
+    /// starting after `after` (exclusive), or at the first script if None. Only iterates within
+    /// a single prefix.
+    pub fn utxos_page(
+        &self,
+        prefix: PayloadPrefix,
+        after: Option<&[u8]>,
+        max_scripts: usize,
+    ) -> Result<Vec<(ScriptPayload, Vec<UtxoEntry>)>> {
+        let start_key = [[prefix as u8].as_ref(), after.unwrap_or_default()].concat();
+        let iterator = self.db.rocks().iterator_cf(
+            self.cf_utxos,
+            IteratorMode::From(&start_key, Direction::Forward),
+        );
+        iterator
+            .take_while(|(key, _)| key.first() == Some(&(prefix as u8)))
+            .filter(|(key, _)| after.is_none() || key[..] != start_key[..])
+            .take(max_scripts)
+            .map(|(key, value)| {
+                let entries = interpret_slice::<UtxoData>(&value)?
+                    .iter()
+                    .cloned()
+                    .map(Into::into)
+                    .collect();
+                let script_payload = ScriptPayload {
+                    payload_prefix: prefix,
+                    payload_data: key[1..].to_vec(),
+                };
+                Ok((script_payload, entries))
+            })
+            .collect()
+    }
 }
 
 fn update_map_or_db_entry<'a>(
@@ -392,15 +433,15 @@ impl PartialOrd for UtxoData {
 mod test {
     use crate::{
         input_tx_nums::fetch_input_tx_nums, utxos::UtxoData, BlockHeight, BlockTxs, Db,
-        OutpointEntry, PayloadPrefix, TxEntry, TxNum, TxWriter, UtxoEntry, UtxosReader,
-        UtxosWriter,
+        OutpointEntry, PayloadPrefix, ScriptFilter, TxEntry, TxNum, TxWriter, UtxoEntry,
+        UtxosReader, UtxosWriter,
     };
     use bitcoinsuite_core::{
         ecc::PubKey, OutPoint, Script, Sha256d, ShaRmd160, TxInput, TxOutput, UnhashedTx,
     };
     use bitcoinsuite_error::Result;
     use pretty_assertions::{assert_eq, assert_ne};
-    use rocksdb::WriteBatch;
+    use rocksdb::{IteratorMode, WriteBatch};
     use zerocopy::AsBytes;
 
     #[test]
@@ -410,7 +451,7 @@ mod test {
         let tempdir = tempdir::TempDir::new("slp-indexer-rocks--utxos")?;
         let db = Db::open(tempdir.path())?;
         let tx_writer = TxWriter::new(&db)?;
-        let utxo_writer = UtxosWriter::new(&db)?;
+        let utxo_writer = UtxosWriter::new(&db, &ScriptFilter::All)?;
         let utxo_reader = UtxosReader::new(&db)?;
         let (script1, payload1) = (Script::p2pkh(&ShaRmd160::new([1; 20])), [1; 20]);
         let (script2, payload2) = (Script::p2pkh(&ShaRmd160::new([2; 20])), [2; 20]);
@@ -551,6 +592,18 @@ mod test {
             connect_block(0)?;
             check_utxos(&utxo_reader, P2PKH, &payload1, [(0, 0)], false)?;
             check_utxos(&utxo_reader, P2PKH, &payload2, [(0, 1)], false)?;
+            // Page through all scripts of a prefix
+            let page_payloads = |after: Option<&[u8]>, max_scripts| -> Result<Vec<Vec<u8>>> {
+                Ok(utxo_reader
+                    .utxos_page(P2PKH, after, max_scripts)?
+                    .into_iter()
+                    .map(|(script_payload, _)| script_payload.payload_data)
+                    .collect())
+            };
+            assert_eq!(page_payloads(None, 1)?, vec![payload1.to_vec()]);
+            assert_eq!(page_payloads(Some(&payload1), 10)?, vec![payload2.to_vec()]);
+            assert_eq!(page_payloads(Some(&payload2), 10)?, Vec::<Vec<u8>>::new());
+            assert!(utxo_reader.utxos_page(P2SH, None, 10)?.is_empty());
         }
         {
             // Connect block 1
@@ -622,6 +675,85 @@ mod test {
             check_utxos(&utxo_reader, P2TRCommitment, &payload7, [], true)?;
             check_utxos(&utxo_reader, P2TRState, &payload8, [], true)?;
         }
+        {
+            // Disconnect block 1 and 0, rewinding all the way to before genesis
+            disconnect_block(1)?;
+            disconnect_block(0)?;
+            check_utxos(&utxo_reader, P2PKH, &payload1, [], false)?;
+            check_utxos(&utxo_reader, P2PKH, &payload2, [], false)?;
+            check_utxos(&utxo_reader, P2SH, &payload3, [], false)?;
+            check_utxos(&utxo_reader, P2SH, &payload4, [], false)?;
+            let mut iterator = utxo_reader
+                .db
+                .rocks()
+                .iterator_cf(utxo_reader.cf_utxos, IteratorMode::Start);
+            assert!(iterator.next().is_none());
+        }
+        {
+            // Reconnecting after the rewind starts again at tx_num 0
+            connect_block(0)?;
+            check_utxos(&utxo_reader, P2PKH, &payload1, [(0, 0)], false)?;
+            check_utxos(&utxo_reader, P2PKH, &payload2, [(0, 1)], false)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_opreturn_outputs() -> Result<()> {
+        bitcoinsuite_error::install()?;
+        let tempdir = tempdir::TempDir::new("slp-indexer-rocks--utxos-opreturn")?;
+        let db = Db::open(tempdir.path())?;
+        let utxo_writer = UtxosWriter::new(&db, &ScriptFilter::All)?;
+        let utxo_reader = UtxosReader::new(&db)?;
+        let txid = Sha256d::new([0; 32]);
+        // SLP OP_RETURN first, followed by another OP_RETURN carrying data
+        let tx = UnhashedTx {
+            version: 1,
+            inputs: vec![TxInput::default()],
+            outputs: vec![
+                TxOutput {
+                    value: 0,
+                    script: Script::opreturn(&[b"SLP\0", b"\x01", b"GENESIS"]),
+                },
+                TxOutput {
+                    value: 1,
+                    script: Script::p2pkh(&ShaRmd160::new([1; 20])),
+                },
+                TxOutput {
+                    value: 0,
+                    script: Script::opreturn(&[b"data"]),
+                },
+            ],
+            lock_time: 0,
+        };
+        let mut batch = WriteBatch::default();
+        utxo_writer.insert_block_txs(
+            &mut batch,
+            0,
+            |_| &txid,
+            &[tx],
+            |_, _| unreachable!(),
+            &[],
+        )?;
+        db.write_batch(batch)?;
+        check_utxos(
+            &utxo_reader,
+            PayloadPrefix::P2PKH,
+            &[1; 20],
+            [(0, 1)],
+            false,
+        )?;
+        // Neither OP_RETURN output has a UTXO entry
+        let keys = utxo_reader
+            .db
+            .rocks()
+            .iterator_cf(utxo_reader.cf_utxos, IteratorMode::Start)
+            .map(|(key, _)| key.to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec![[[PayloadPrefix::P2PKH as u8].as_ref(), &[1; 20]].concat()]
+        );
         Ok(())
     }
 
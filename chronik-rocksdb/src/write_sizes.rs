@@ -0,0 +1,136 @@
+use std::collections::VecDeque;
+
+use rocksdb::WriteBatch;
+
+use crate::BlockHeight;
+
+/// Number of most recently connected blocks `BlockWriteSizes` keeps.
+pub const NUM_BLOCK_WRITE_SIZES: usize = 1000;
+
+/// Bytes of the write batches of the most recently connected blocks, for estimating how fast
+/// the DB grows. Only kept in memory, so it only covers blocks connected since startup.
+/// RocksDB's own overhead (WAL, compaction, compression) isn't accounted for.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BlockWriteSizes {
+    blocks: VecDeque<BlockWriteSize>,
+}
+
+/// Bytes written for a single block, split by the writers (i.e. groups of CFs) writing them.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BlockWriteSize {
+    pub height: BlockHeight,
+    pub sizes: Vec<(&'static str, usize)>,
+}
+
+impl BlockWriteSize {
+    pub fn new(height: BlockHeight) -> Self {
+        BlockWriteSize {
+            height,
+            sizes: Vec::new(),
+        }
+    }
+
+    /// Attributes everything `batch` grew by since the last call to the writer `name`.
+    pub fn add_batch_size(&mut self, name: &'static str, batch: &WriteBatch) {
+        let size = batch.size_in_bytes().saturating_sub(self.total());
+        self.sizes.push((name, size));
+    }
+
+    pub fn total(&self) -> usize {
+        self.sizes.iter().map(|&(_, size)| size).sum()
+    }
+}
+
+impl BlockWriteSizes {
+    pub fn add_block(&mut self, block_write_size: BlockWriteSize) {
+        if self.blocks.len() == NUM_BLOCK_WRITE_SIZES {
+            self.blocks.pop_front();
+        }
+        self.blocks.push_back(block_write_size);
+    }
+
+    /// Forgets the write size of the disconnected block at `height`, if it's kept.
+    pub fn remove_block(&mut self, height: BlockHeight) {
+        self.blocks.retain(|block| block.height != height);
+    }
+
+    pub fn num_blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Average bytes written per block, 0 if no blocks have been connected yet.
+    pub fn avg_bytes_per_block(&self) -> f64 {
+        if self.blocks.is_empty() {
+            return 0.0;
+        }
+        let total = self.blocks.iter().map(BlockWriteSize::total).sum::<usize>();
+        total as f64 / self.blocks.len() as f64
+    }
+
+    /// Average bytes written per block by each writer, in the order they write.
+    pub fn avg_bytes_per_writer(&self) -> Vec<(&'static str, f64)> {
+        let mut totals = Vec::<(&'static str, usize)>::new();
+        for block in &self.blocks {
+            for &(name, size) in &block.sizes {
+                match totals
+                    .iter_mut()
+                    .find(|(total_name, _)| *total_name == name)
+                {
+                    Some((_, total)) => *total += size,
+                    None => totals.push((name, size)),
+                }
+            }
+        }
+        totals
+            .into_iter()
+            .map(|(name, total)| (name, total as f64 / self.blocks.len() as f64))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use rocksdb::WriteBatch;
+
+    use crate::{BlockWriteSize, BlockWriteSizes, NUM_BLOCK_WRITE_SIZES};
+
+    #[test]
+    fn test_block_write_sizes() {
+        let mut batch = WriteBatch::default();
+        let mut block_write_size = BlockWriteSize::new(0);
+        batch.put(b"key", b"value");
+        block_write_size.add_batch_size("a", &batch);
+        batch.put(b"key2", b"value2");
+        block_write_size.add_batch_size("b", &batch);
+        block_write_size.add_batch_size("c", &batch);
+        let [(_, size_a), (_, size_b), (_, size_c)]: [(&str, usize); 3] =
+            block_write_size.sizes.clone().try_into().unwrap();
+        assert!(size_b > 0);
+        assert_eq!(size_c, 0);
+        assert_eq!(size_a + size_b, batch.size_in_bytes());
+
+        let mut write_sizes = BlockWriteSizes::default();
+        assert_eq!(write_sizes.avg_bytes_per_block(), 0.0);
+        let block_size = |height, size| BlockWriteSize {
+            height,
+            sizes: vec![("a", size), ("b", 2 * size)],
+        };
+        write_sizes.add_block(block_size(0, 10));
+        write_sizes.add_block(block_size(1, 20));
+        assert_eq!(write_sizes.num_blocks(), 2);
+        assert_eq!(write_sizes.avg_bytes_per_block(), 45.0);
+        assert_eq!(
+            write_sizes.avg_bytes_per_writer(),
+            vec![("a", 15.0), ("b", 30.0)],
+        );
+        write_sizes.remove_block(1);
+        assert_eq!(write_sizes.avg_bytes_per_block(), 30.0);
+
+        // Only keeps the most recent blocks
+        for height in 0..NUM_BLOCK_WRITE_SIZES as i32 + 5 {
+            write_sizes.add_block(block_size(height, 1));
+        }
+        assert_eq!(write_sizes.num_blocks(), NUM_BLOCK_WRITE_SIZES);
+    }
+}